@@ -0,0 +1,212 @@
+//! Access tokens for the ESP's HTTP and WebSocket server.
+//!
+//! A client logs in once with the pairing password and gets its own token, which the ESP keeps in
+//! NVS. After that the client sends the token as `Authorization: Bearer <token>`. Browsers can't
+//! set headers on WebSockets, so the token can also be sent in the query string as `?token=<token>`.
+//!
+//! The checks are here instead of in `esp` so that they can be tested on a normal computer.
+use serde::{Deserialize, Serialize};
+
+pub const TOKEN_QUERY_PARAMETER: &str = "token";
+/// The number of random bytes in a token. The token itself is hex, so it's twice as long.
+pub const TOKEN_BYTES: usize = 16;
+/// When a client logs in and there are already this many tokens, the oldest one is forgotten
+pub const MAX_TOKENS: usize = 16;
+
+/// A token that the ESP keeps in NVS
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct AuthToken {
+    /// So that you can tell which client the token is for
+    pub client_name: String,
+    pub token: String,
+}
+
+/// Body of `POST /login`
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct LoginRequest {
+    pub password: String,
+    pub client_name: String,
+}
+
+/// Response of a successful `POST /login`
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct LoginResponse {
+    pub token: String,
+}
+
+/// Compares without returning early, so that the time it takes doesn't tell how much of a
+/// guessed secret was right
+pub fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    if a.len() != b.len() {
+        return false;
+    }
+    a.iter()
+        .zip(b)
+        .fold(0, |difference, (a, b)| difference | (a ^ b))
+        == 0
+}
+
+/// Gets the token out of an `Authorization` header value
+pub fn bearer_token(authorization: &str) -> Option<&str> {
+    let (scheme, token) = authorization.trim().split_once(' ')?;
+    if scheme.eq_ignore_ascii_case("Bearer") {
+        Some(token.trim()).filter(|token| !token.is_empty())
+    } else {
+        None
+    }
+}
+
+/// Gets the token out of a query string like `a=b&token=abc`
+pub fn query_token(query: &str) -> Option<&str> {
+    query.split('&').find_map(|pair| {
+        pair.split_once('=')
+            .filter(|(key, value)| *key == TOKEN_QUERY_PARAMETER && !value.is_empty())
+            .map(|(_, value)| value)
+    })
+}
+
+/// Gets the token from a request. The `Authorization` header is used if it's there.
+pub fn request_token<'a>(
+    authorization: Option<&'a str>,
+    query: Option<&'a str>,
+) -> Option<&'a str> {
+    authorization
+        .and_then(bearer_token)
+        .or_else(|| query.and_then(query_token))
+}
+
+/// Returns the index of the matching token
+pub fn find_token(tokens: &[AuthToken], presented: &str) -> Option<usize> {
+    // Check every token, so that the time it takes doesn't tell which one matched
+    tokens
+        .iter()
+        .enumerate()
+        .fold(None, |found, (index, token)| {
+            match constant_time_eq(token.token.as_bytes(), presented.as_bytes()) {
+                true => Some(index),
+                false => found,
+            }
+        })
+}
+
+pub fn is_authorized(
+    tokens: &[AuthToken],
+    authorization: Option<&str>,
+    query: Option<&str>,
+) -> bool {
+    request_token(authorization, query).is_some_and(|token| find_token(tokens, token).is_some())
+}
+
+pub fn is_correct_password(expected: &str, given: &str) -> bool {
+    // An empty password would let anyone in
+    !expected.is_empty() && constant_time_eq(expected.as_bytes(), given.as_bytes())
+}
+
+/// Makes a token out of random bytes
+pub fn token_from_bytes(bytes: [u8; TOKEN_BYTES]) -> String {
+    bytes.iter().map(|byte| format!("{byte:02x}")).collect()
+}
+
+/// Adds a token, forgetting the oldest ones if there are too many
+pub fn add_token(tokens: &mut Vec<AuthToken>, token: AuthToken) {
+    tokens.push(token);
+    if tokens.len() > MAX_TOKENS {
+        tokens.drain(..tokens.len() - MAX_TOKENS);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tokens() -> Vec<AuthToken> {
+        vec![
+            AuthToken {
+                client_name: "phone".into(),
+                token: "aaaa".into(),
+            },
+            AuthToken {
+                client_name: "computer".into(),
+                token: "bbbb".into(),
+            },
+        ]
+    }
+
+    #[test]
+    fn bearer_header_is_parsed() {
+        assert_eq!(bearer_token("Bearer abc"), Some("abc"));
+        assert_eq!(bearer_token("bearer  abc "), Some("abc"));
+        assert_eq!(bearer_token("Basic abc"), None);
+        assert_eq!(bearer_token("Bearer "), None);
+        assert_eq!(bearer_token("abc"), None);
+    }
+
+    #[test]
+    fn query_token_is_parsed() {
+        assert_eq!(query_token("token=abc"), Some("abc"));
+        assert_eq!(query_token("a=b&token=abc&c=d"), Some("abc"));
+        assert_eq!(query_token("tokens=abc"), None);
+        assert_eq!(query_token("token="), None);
+        assert_eq!(query_token(""), None);
+    }
+
+    #[test]
+    fn header_is_preferred_over_query() {
+        assert_eq!(
+            request_token(Some("Bearer header"), Some("token=query")),
+            Some("header")
+        );
+        assert_eq!(request_token(None, Some("token=query")), Some("query"));
+        assert_eq!(
+            request_token(Some("Basic x"), Some("token=query")),
+            Some("query")
+        );
+        assert_eq!(request_token(None, None), None);
+    }
+
+    #[test]
+    fn only_known_tokens_are_authorized() {
+        let tokens = tokens();
+        assert!(is_authorized(&tokens, Some("Bearer aaaa"), None));
+        assert!(is_authorized(&tokens, None, Some("token=bbbb")));
+        assert!(!is_authorized(&tokens, Some("Bearer aaab"), None));
+        assert!(!is_authorized(&tokens, Some("Bearer aaa"), None));
+        assert!(!is_authorized(&tokens, None, None));
+        assert!(!is_authorized(&[], Some("Bearer aaaa"), None));
+        assert_eq!(find_token(&tokens, "bbbb"), Some(1));
+    }
+
+    #[test]
+    fn password_is_checked() {
+        assert!(is_correct_password("hunter2", "hunter2"));
+        assert!(!is_correct_password("hunter2", "hunter3"));
+        assert!(!is_correct_password("hunter2", ""));
+        assert!(!is_correct_password("", ""));
+    }
+
+    #[test]
+    fn tokens_are_hex() {
+        let token = token_from_bytes([
+            0x00, 0x0f, 0xff, 0x10, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12,
+        ]);
+        assert_eq!(token, "000fff100102030405060708090a0b0c");
+        assert_eq!(token.len(), TOKEN_BYTES * 2);
+    }
+
+    #[test]
+    fn oldest_tokens_are_forgotten() {
+        let mut tokens = Vec::new();
+        for i in 0..MAX_TOKENS + 2 {
+            add_token(
+                &mut tokens,
+                AuthToken {
+                    client_name: i.to_string(),
+                    token: i.to_string(),
+                },
+            );
+        }
+        assert_eq!(tokens.len(), MAX_TOKENS);
+        assert_eq!(tokens[0].token, "2");
+        assert_eq!(tokens[MAX_TOKENS - 1].token, (MAX_TOKENS + 1).to_string());
+    }
+}
@@ -0,0 +1,449 @@
+//! Which Bluetooth devices turn on the computer.
+//!
+//! Many controllers use random addresses, so a device can be matched by its name, a service it
+//! advertises, or its manufacturer data instead of its address. A minimum RSSI keeps a phone in
+//! another room from waking the computer, and a time window keeps it from happening at night.
+//!
+//! Devices can also be found with a discovery scan, so that users can pick them from a list.
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+use crate::schedule::{LocalTime, Weekdays};
+
+/// The part of the Bluetooth base UUID after the first 32 bits
+const BASE_UUID: u128 = 0x0000_1000_8000_0080_5F9B_34FB;
+/// The longest a discovery scan can be
+pub const MAX_DISCOVERY_DURATION: Duration = Duration::from_secs(30);
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BleUuid {
+    Uuid16(u16),
+    Uuid32(u32),
+    /// Most significant byte first, the way UUIDs are written
+    Uuid128([u8; 16]),
+}
+
+impl BleUuid {
+    /// 16 and 32 bit UUIDs are short for 128 bit UUIDs, so this is used to compare them
+    pub fn to_u128(self) -> u128 {
+        match self {
+            Self::Uuid16(uuid) => (uuid as u128) << 96 | BASE_UUID,
+            Self::Uuid32(uuid) => (uuid as u128) << 96 | BASE_UUID,
+            Self::Uuid128(bytes) => u128::from_be_bytes(bytes),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum BleAddressType {
+    /// The same every time
+    #[default]
+    Public,
+    /// Can change, like every time the device turns on
+    Random,
+}
+
+/// What a device sent while scanning
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Default)]
+pub struct BleAdvertisement {
+    /// In the order the ESP keeps it, which is the opposite of how it's written
+    pub address: [u8; 6],
+    pub address_type: BleAddressType,
+    pub name: Option<String>,
+    pub service_uuids: Vec<BleUuid>,
+    /// Starts with the company ID, least significant byte first
+    pub manufacturer_data: Option<Vec<u8>>,
+    /// In dBm
+    pub rssi: i8,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub enum DeviceMatcher {
+    Address([u8; 6]),
+    /// `*` matches any characters. Case doesn't matter.
+    Name(String),
+    ServiceUuid(BleUuid),
+    /// The data must start with `prefix` after the company ID
+    ManufacturerData {
+        company_id: u16,
+        prefix: Vec<u8>,
+    },
+}
+
+impl DeviceMatcher {
+    pub fn matches(&self, advertisement: &BleAdvertisement) -> bool {
+        match self {
+            Self::Address(address) => advertisement.address == *address,
+            Self::Name(pattern) => advertisement
+                .name
+                .as_ref()
+                .is_some_and(|name| name_matches(pattern, name)),
+            Self::ServiceUuid(uuid) => advertisement
+                .service_uuids
+                .iter()
+                .any(|advertised| advertised.to_u128() == uuid.to_u128()),
+            Self::ManufacturerData { company_id, prefix } => {
+                match advertisement.manufacturer_data.as_deref() {
+                    Some([low, high, data @ ..]) => {
+                        u16::from_le_bytes([*low, *high]) == *company_id && data.starts_with(prefix)
+                    }
+                    _ => false,
+                }
+            }
+        }
+    }
+}
+
+fn name_matches(pattern: &str, name: &str) -> bool {
+    let pattern = pattern.to_lowercase();
+    let name = name.to_lowercase();
+    let mut parts = pattern.split('*');
+    // There's always a first part, even if it's empty
+    let first = parts.next().unwrap_or_default();
+    let Some(mut rest) = name.strip_prefix(first) else {
+        return false;
+    };
+    let parts = parts.collect::<Vec<_>>();
+    let Some((last, middle)) = parts.split_last() else {
+        // No `*`
+        return rest.is_empty();
+    };
+    for part in middle {
+        match rest.find(part) {
+            Some(index) => rest = &rest[index + part.len()..],
+            None => return false,
+        }
+    }
+    rest.ends_with(last)
+}
+
+/// Local times when a rule can wake up the computer. If the end is before the start, the window
+/// goes past midnight.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TimeWindow {
+    /// The days that the window starts on
+    pub days: Weekdays,
+    pub start_hour: u8,
+    pub start_minute: u8,
+    pub end_hour: u8,
+    pub end_minute: u8,
+}
+
+impl TimeWindow {
+    pub fn is_valid(&self) -> bool {
+        self.start_hour < 24 && self.start_minute < 60 && self.end_hour < 24 && self.end_minute < 60
+    }
+
+    pub fn contains(&self, time: LocalTime) -> bool {
+        let minute = |hour: u8, minute: u8| hour as u16 * 60 + minute as u16;
+        let start = minute(self.start_hour, self.start_minute);
+        let end = minute(self.end_hour, self.end_minute);
+        let now = minute(time.hour, time.minute);
+        if start <= end {
+            self.days.contains(time.day) && start <= now && now < end
+        } else {
+            let yesterday = (time.day + 6) % 7;
+            (self.days.contains(time.day) && now >= start)
+                || (self.days.contains(yesterday) && now < end)
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct WakeupRule {
+    pub matcher: DeviceMatcher,
+    /// Devices with a weaker signal than this are ignored, like -70 dBm
+    pub min_rssi: Option<i8>,
+    /// In the schedule's time zone. Rules with a window don't match until the time is synced.
+    pub time_window: Option<TimeWindow>,
+}
+
+impl WakeupRule {
+    /// A rule for one address, which is what the ESP used to save
+    pub fn address(address: [u8; 6]) -> Self {
+        Self {
+            matcher: DeviceMatcher::Address(address),
+            min_rssi: None,
+            time_window: None,
+        }
+    }
+
+    /// A rule for a device that was found by a discovery scan. Random addresses can change, so
+    /// those devices are matched by name if they have one.
+    pub fn for_device(advertisement: &BleAdvertisement) -> Self {
+        match (advertisement.address_type, &advertisement.name) {
+            (BleAddressType::Random, Some(name)) => Self {
+                matcher: DeviceMatcher::Name(name.clone()),
+                min_rssi: None,
+                time_window: None,
+            },
+            _ => Self::address(advertisement.address),
+        }
+    }
+
+    pub fn is_valid(&self) -> bool {
+        let is_matcher_valid = match &self.matcher {
+            DeviceMatcher::Name(pattern) => !pattern.is_empty(),
+            _ => true,
+        };
+        is_matcher_valid && self.time_window.is_none_or(|window| window.is_valid())
+    }
+
+    /// `now` is `None` if the time isn't synced yet
+    pub fn matches(&self, advertisement: &BleAdvertisement, now: Option<LocalTime>) -> bool {
+        self.matcher.matches(advertisement)
+            && self
+                .min_rssi
+                .is_none_or(|min_rssi| advertisement.rssi >= min_rssi)
+            && self
+                .time_window
+                .is_none_or(|window| now.is_some_and(|now| window.contains(now)))
+    }
+}
+
+/// The first rule that matches the device, if any
+pub fn matching_rule<'a>(
+    rules: &'a [WakeupRule],
+    advertisement: &BleAdvertisement,
+    now: Option<LocalTime>,
+) -> Option<&'a WakeupRule> {
+    rules.iter().find(|rule| rule.matches(advertisement, now))
+}
+
+/// The devices found by a discovery scan. Devices advertise many times a second, so this is used
+/// to only send a device again when something useful changed.
+#[derive(Debug, Clone, Default)]
+pub struct DiscoveredDevices(Vec<BleAdvertisement>);
+
+impl DiscoveredDevices {
+    /// Returns `true` if the device is new, or if its name showed up. Some devices only send
+    /// their name in a scan response, after the first advertisement.
+    pub fn add(&mut self, advertisement: BleAdvertisement) -> bool {
+        match self
+            .0
+            .iter_mut()
+            .find(|device| device.address == advertisement.address)
+        {
+            Some(device) => {
+                let is_name_new = device.name.is_none() && advertisement.name.is_some();
+                let name = advertisement.name.clone().or(device.name.take());
+                *device = BleAdvertisement {
+                    name,
+                    ..advertisement
+                };
+                is_name_new
+            }
+            None => {
+                self.0.push(advertisement);
+                true
+            }
+        }
+    }
+
+    /// Strongest signal first, which is usually the closest device
+    pub fn into_devices(mut self) -> Vec<BleAdvertisement> {
+        self.0.sort_by_key(|device| std::cmp::Reverse(device.rssi));
+        self.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ADDRESS: [u8; 6] = [0, 0x4D, 0x8D, 0x26, 0x3F, 0xC8];
+    /// The HID service, which game controllers and keyboards advertise
+    const HID: u16 = 0x1812;
+
+    fn controller() -> BleAdvertisement {
+        BleAdvertisement {
+            address: [1, 2, 3, 4, 5, 0x40],
+            address_type: BleAddressType::Random,
+            name: Some("Xbox Wireless Controller".into()),
+            service_uuids: vec![BleUuid::Uuid16(HID)],
+            manufacturer_data: Some(vec![0x06, 0x00, 0x03, 0x00, 0x80]),
+            rssi: -60,
+        }
+    }
+
+    fn rule(matcher: DeviceMatcher) -> WakeupRule {
+        WakeupRule {
+            matcher,
+            min_rssi: None,
+            time_window: None,
+        }
+    }
+
+    fn time(day: u8, hour: u8, minute: u8) -> Option<LocalTime> {
+        Some(LocalTime {
+            day,
+            hour,
+            minute,
+            second: 0,
+        })
+    }
+
+    #[test]
+    fn devices_are_matched() {
+        let device = controller();
+        assert!(rule(DeviceMatcher::Address(device.address)).matches(&device, None));
+        assert!(!WakeupRule::address(ADDRESS).matches(&device, None));
+        assert!(rule(DeviceMatcher::Name("xbox*controller".into())).matches(&device, None));
+        assert!(!rule(DeviceMatcher::Name("Xbox".into())).matches(&device, None));
+        assert!(rule(DeviceMatcher::ServiceUuid(BleUuid::Uuid16(HID))).matches(&device, None));
+        let mut hid_128 = [0; 16];
+        hid_128.copy_from_slice(&((HID as u128) << 96 | BASE_UUID).to_be_bytes());
+        assert!(rule(DeviceMatcher::ServiceUuid(BleUuid::Uuid128(hid_128))).matches(&device, None));
+        assert!(rule(DeviceMatcher::ManufacturerData {
+            company_id: 0x0006,
+            prefix: vec![0x03],
+        })
+        .matches(&device, None));
+        assert!(!rule(DeviceMatcher::ManufacturerData {
+            company_id: 0x004C,
+            prefix: vec![],
+        })
+        .matches(&device, None));
+        assert!(!rule(DeviceMatcher::Name("*".into())).matches(
+            &BleAdvertisement {
+                name: None,
+                ..device
+            },
+            None
+        ));
+    }
+
+    #[test]
+    fn names_are_matched_with_wildcards() {
+        assert!(name_matches("*", ""));
+        assert!(name_matches("Pixel*", "Pixel 8"));
+        assert!(name_matches("*controller", "DualSense Wireless Controller"));
+        assert!(name_matches("a*b*c", "abc"));
+        assert!(!name_matches("a*b*c", "acb"));
+        assert!(!name_matches("ab*ba", "aba"));
+        assert!(!name_matches("Pixel", "Pixel 8"));
+    }
+
+    #[test]
+    fn far_away_devices_are_ignored() {
+        let rule = WakeupRule {
+            min_rssi: Some(-70),
+            ..rule(DeviceMatcher::ServiceUuid(BleUuid::Uuid16(HID)))
+        };
+        assert!(rule.matches(&controller(), None));
+        let far_away = BleAdvertisement {
+            rssi: -85,
+            ..controller()
+        };
+        assert!(!rule.matches(&far_away, None));
+    }
+
+    #[test]
+    fn rules_only_match_in_their_time_window() {
+        let rule = WakeupRule {
+            time_window: Some(TimeWindow {
+                days: Weekdays::MONDAY_TO_FRIDAY,
+                start_hour: 18,
+                start_minute: 0,
+                end_hour: 1,
+                end_minute: 30,
+            }),
+            ..rule(DeviceMatcher::Name("*".into()))
+        };
+        let device = controller();
+        assert!(rule.matches(&device, time(0, 18, 0)));
+        // Friday night goes past midnight into Saturday
+        assert!(rule.matches(&device, time(5, 1, 29)));
+        assert!(!rule.matches(&device, time(5, 18, 0)));
+        assert!(!rule.matches(&device, time(0, 1, 0)));
+        assert!(!rule.matches(&device, time(2, 12, 0)));
+        // The time isn't synced
+        assert!(!rule.matches(&device, None));
+    }
+
+    #[test]
+    fn the_first_matching_rule_is_used() {
+        let rules = [
+            WakeupRule::address(ADDRESS),
+            rule(DeviceMatcher::Name("Xbox*".into())),
+            rule(DeviceMatcher::ServiceUuid(BleUuid::Uuid16(HID))),
+        ];
+        assert_eq!(matching_rule(&rules, &controller(), None), Some(&rules[1]));
+        assert_eq!(matching_rule(&rules[..1], &controller(), None), None);
+    }
+
+    #[test]
+    fn discovered_devices_are_only_sent_again_when_their_name_shows_up() {
+        let mut devices = DiscoveredDevices::default();
+        let without_name = BleAdvertisement {
+            name: None,
+            rssi: -90,
+            ..controller()
+        };
+        assert!(devices.add(without_name.clone()));
+        assert!(!devices.add(without_name.clone()));
+        assert!(devices.add(controller()));
+        // The name is kept when later advertisements don't have it
+        assert!(!devices.add(without_name));
+        let phone = BleAdvertisement {
+            address: ADDRESS,
+            address_type: BleAddressType::Public,
+            rssi: -50,
+            ..Default::default()
+        };
+        assert!(devices.add(phone.clone()));
+        assert_eq!(
+            devices.into_devices(),
+            [
+                phone,
+                BleAdvertisement {
+                    rssi: -90,
+                    ..controller()
+                }
+            ]
+        );
+    }
+
+    #[test]
+    fn rules_for_random_addresses_use_the_name() {
+        assert_eq!(
+            WakeupRule::for_device(&controller()).matcher,
+            DeviceMatcher::Name("Xbox Wireless Controller".into())
+        );
+        let public = BleAdvertisement {
+            address_type: BleAddressType::Public,
+            ..controller()
+        };
+        assert_eq!(
+            WakeupRule::for_device(&public),
+            WakeupRule::address(public.address)
+        );
+        let unnamed = BleAdvertisement {
+            name: None,
+            ..controller()
+        };
+        assert_eq!(
+            WakeupRule::for_device(&unnamed),
+            WakeupRule::address(unnamed.address)
+        );
+    }
+
+    #[test]
+    fn invalid_rules_are_rejected() {
+        assert!(WakeupRule::address(ADDRESS).is_valid());
+        assert!(!rule(DeviceMatcher::Name("".into())).is_valid());
+        let window = TimeWindow {
+            days: Weekdays::EVERY_DAY,
+            start_hour: 24,
+            start_minute: 0,
+            end_hour: 0,
+            end_minute: 0,
+        };
+        assert!(!WakeupRule {
+            time_window: Some(window),
+            ..WakeupRule::address(ADDRESS)
+        }
+        .is_valid());
+    }
+}
@@ -0,0 +1,24 @@
+//! How the ESP advertises itself on the local network with mDNS / DNS-SD, so that clients don't
+//! need its address.
+//!
+//! The ESP registers a [`SERVICE_TYPE`] service on port 80, with TXT records for the firmware
+//! version, the protocol version, and the device name.
+
+/// The DNS-SD service type, without the domain
+pub const SERVICE_TYPE: &str = "_smart-power-button._tcp";
+/// [`SERVICE_TYPE`] split into the service and protocol, like ESP-IDF's mDNS takes it
+pub const SERVICE_NAME: &str = "_smart-power-button";
+pub const SERVICE_PROTOCOL: &str = "_tcp";
+/// The name is longer than the 15 characters that RFC 6335 allows, so some mDNS libraries need
+/// their limit raised to this to advertise it
+pub const SERVICE_NAME_LEN: u8 = 18;
+
+/// TXT record with the firmware's version, like `0.1.0`
+pub const TXT_FIRMWARE_VERSION: &str = "version";
+/// TXT record with [`crate::protocol::PROTOCOL_VERSION`]
+pub const TXT_PROTOCOL_VERSION: &str = "protocol";
+/// TXT record with the device's name, which is its hostname
+pub const TXT_DEVICE_NAME: &str = "name";
+
+/// Used as the hostname and device name when no hostname is set
+pub const DEFAULT_DEVICE_NAME: &str = "smart-power-button";
@@ -0,0 +1,219 @@
+//! Summaries of how busy the HDD LED is, which are sent instead of every change of the LED.
+//!
+//! [`HddTracker`] adds up the LED's on time and bursts in 1 second buckets, and keeps the last
+//! minute of them, so it uses the same memory no matter how busy the disk is. Times are
+//! [`Duration`]s since any fixed point, so that this can be tested without waiting.
+use std::collections::VecDeque;
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+pub const BUCKET_DURATION: Duration = Duration::from_secs(1);
+/// How many buckets are kept, which is also the longest window
+pub const BUCKETS: usize = 60;
+/// If the LED turns on again within this long after turning off, it's the same burst
+pub const BURST_GAP: Duration = Duration::from_millis(200);
+
+/// The HDD LED during a window of time
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct HddStats {
+    /// How much of the window the LED was on, from 0 to 100
+    pub active_percent: u8,
+    /// How many times the LED started blinking after being off for at least [`BURST_GAP`]
+    pub bursts: u32,
+}
+
+/// Sent to the web page at most once a second, when it changes
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Default)]
+pub struct HddActivity {
+    pub last_second: HddStats,
+    pub last_10_seconds: HddStats,
+    pub last_minute: HddStats,
+    /// The active percent of each of the last [`BUCKETS`] seconds, oldest first, for graphs.
+    /// Shorter until the ESP has been running for a minute.
+    pub history: Vec<u8>,
+}
+
+/// Rounded up, so that any activity shows
+fn percent(active: Duration, total: Duration) -> u8 {
+    match total.as_millis() {
+        0 => 0,
+        total => (active.as_millis() * 100).div_ceil(total).min(100) as u8,
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+struct Bucket {
+    active: Duration,
+    bursts: u32,
+}
+
+#[derive(Debug, Clone)]
+pub struct HddTracker {
+    /// Finished buckets, oldest first
+    buckets: VecDeque<Bucket>,
+    current: Bucket,
+    current_start: Duration,
+    /// When the LED turned on, or when the current bucket started if that was later
+    on_since: Option<Duration>,
+    /// When the LED last turned off
+    off_since: Option<Duration>,
+}
+
+impl HddTracker {
+    pub fn new(is_on: bool, now: Duration) -> Self {
+        Self {
+            buckets: VecDeque::with_capacity(BUCKETS),
+            current: Bucket::default(),
+            current_start: now,
+            on_since: is_on.then_some(now),
+            off_since: None,
+        }
+    }
+
+    /// Call this when the LED changes
+    pub fn update(&mut self, is_on: bool, now: Duration) {
+        self.finish_buckets(now);
+        match (is_on, self.on_since) {
+            (true, None) => {
+                let is_new_burst = self
+                    .off_since
+                    .is_none_or(|off_since| now - off_since >= BURST_GAP);
+                if is_new_burst {
+                    self.current.bursts += 1;
+                }
+                self.on_since = Some(now);
+            }
+            (false, Some(on_since)) => {
+                self.current.active += now - on_since;
+                self.on_since = None;
+                self.off_since = Some(now);
+            }
+            _ => {}
+        }
+    }
+
+    /// When the current bucket ends. Call [`HddTracker::finish_buckets`] then.
+    pub fn next_bucket_end(&self) -> Duration {
+        self.current_start + BUCKET_DURATION
+    }
+
+    /// Finishes the buckets that ended before `now`
+    pub fn finish_buckets(&mut self, now: Duration) {
+        while now >= self.next_bucket_end() {
+            let end = self.next_bucket_end();
+            if let Some(on_since) = &mut self.on_since {
+                self.current.active += end - *on_since;
+                *on_since = end;
+            }
+            if self.buckets.len() == BUCKETS {
+                self.buckets.pop_front();
+            }
+            self.buckets.push_back(self.current);
+            self.current = Bucket::default();
+            self.current_start = end;
+        }
+    }
+
+    /// Stats for the last `buckets` finished buckets
+    fn stats(&self, buckets: usize) -> HddStats {
+        let buckets = self.buckets.iter().rev().take(buckets);
+        let count = buckets.len() as u32;
+        let (active, bursts) = buckets.fold((Duration::ZERO, 0), |(active, bursts), bucket| {
+            (active + bucket.active, bursts + bucket.bursts)
+        });
+        HddStats {
+            active_percent: percent(active, BUCKET_DURATION * count),
+            bursts,
+        }
+    }
+
+    /// Only counts finished buckets, so the numbers don't jump around during a second
+    pub fn activity(&self) -> HddActivity {
+        HddActivity {
+            last_second: self.stats(1),
+            last_10_seconds: self.stats(10),
+            last_minute: self.stats(BUCKETS),
+            history: self
+                .buckets
+                .iter()
+                .map(|bucket| percent(bucket.active, BUCKET_DURATION))
+                .collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ms(millis: u64) -> Duration {
+        Duration::from_millis(millis)
+    }
+
+    /// Feeds `(time in ms, is_on)` pairs and returns the activity at `end` ms
+    fn track(levels: &[(u64, bool)], end: u64) -> HddActivity {
+        let mut tracker = HddTracker::new(false, Duration::ZERO);
+        for &(at, is_on) in levels {
+            tracker.update(is_on, ms(at));
+        }
+        tracker.finish_buckets(ms(end));
+        tracker.activity()
+    }
+
+    #[test]
+    fn idle_disk_has_no_activity() {
+        let activity = track(&[], 5000);
+        assert_eq!(activity.last_second, HddStats::default());
+        assert_eq!(activity.last_minute, HddStats::default());
+        assert_eq!(activity.history, [0; 5]);
+    }
+
+    #[test]
+    fn on_time_is_split_into_buckets() {
+        // On from 0.5 s to 2.5 s
+        let activity = track(&[(500, true), (2500, false)], 4000);
+        assert_eq!(activity.history, [50, 100, 50, 0]);
+        assert_eq!(
+            activity.last_second,
+            HddStats {
+                active_percent: 0,
+                bursts: 0
+            }
+        );
+        assert_eq!(
+            activity.last_10_seconds,
+            HddStats {
+                active_percent: 50,
+                bursts: 1
+            }
+        );
+    }
+
+    #[test]
+    fn close_blinks_are_one_burst() {
+        // 5 blinks 30 ms apart, then the same again 280 ms later
+        let flicker = (0..5)
+            .flat_map(|i| [(i * 50, true), (i * 50 + 20, false)])
+            .collect::<Vec<_>>();
+        let mut levels = flicker.clone();
+        levels.extend(flicker.iter().map(|&(at, is_on)| (at + 500, is_on)));
+        let activity = track(&levels, 1000);
+        assert_eq!(activity.last_second.bursts, 2);
+        assert_eq!(activity.last_second.active_percent, 20);
+    }
+
+    #[test]
+    fn only_the_last_minute_is_kept() {
+        let mut tracker = HddTracker::new(true, Duration::ZERO);
+        tracker.finish_buckets(Duration::from_secs(90));
+        let activity = tracker.activity();
+        assert_eq!(activity.history.len(), BUCKETS);
+        assert_eq!(activity.last_minute.active_percent, 100);
+        tracker.update(false, Duration::from_secs(90));
+        tracker.finish_buckets(Duration::from_secs(120));
+        let activity = tracker.activity();
+        assert_eq!(activity.last_minute.active_percent, 50);
+        assert_eq!(activity.last_10_seconds.active_percent, 0);
+    }
+}
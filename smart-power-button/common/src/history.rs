@@ -0,0 +1,194 @@
+//! A log of what happened to the computer, so that you can tell when it last woke up and why.
+//!
+//! The ESP keeps the most recent events in NVS, so the history is kept across reboots.
+use std::net::IpAddr;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{MessageToEsp, Power, WakeupReason};
+
+/// When an event is added and there are already this many, the oldest one is forgotten
+pub const MAX_EVENTS: usize = 128;
+
+/// What pressed a button
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PressSource {
+    Web,
+    /// The address of the Bluetooth device that showed up
+    Bluetooth([u8; 6]),
+    Schedule,
+    /// A command from MQTT, like a button in Home Assistant
+    Mqtt,
+    /// The address that sent a Wake-on-LAN magic packet
+    WakeOnLan(IpAddr),
+}
+
+impl PressSource {
+    /// The wakeup reason to give the computer if this press turns it on
+    pub fn wakeup_reason(self, should_turn_on_tv: bool) -> WakeupReason {
+        match self {
+            PressSource::Web => WakeupReason::Web(should_turn_on_tv),
+            PressSource::Bluetooth(address) => WakeupReason::Bluetooth(address),
+            PressSource::Schedule => WakeupReason::Schedule(should_turn_on_tv),
+            PressSource::Mqtt => WakeupReason::Mqtt(should_turn_on_tv),
+            PressSource::WakeOnLan(address) => WakeupReason::WakeOnLan(address),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub enum HistoryEventKind {
+    /// The ESP started. The events before this one are from before it restarted.
+    Boot,
+    /// The computer turned on, suspended, or turned off
+    Power(Power),
+    /// How long the HDD LED was on during a period of time. Periods with no activity aren't
+    /// recorded.
+    HddActivity {
+        active_millis: u32,
+        period_secs: u32,
+    },
+    ButtonPress {
+        message: MessageToEsp,
+        source: PressSource,
+    },
+    /// A button press turned on the computer, and this is what the computer will be told
+    WakeupReason(WakeupReason),
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct HistoryEvent {
+    /// Seconds since the Unix epoch, or `None` if the ESP didn't know the time yet
+    pub time: Option<u64>,
+    pub kind: HistoryEventKind,
+}
+
+/// Adds an event, forgetting the oldest ones if there are too many
+pub fn add_event(events: &mut Vec<HistoryEvent>, event: HistoryEvent) {
+    events.push(event);
+    if events.len() > MAX_EVENTS {
+        events.drain(..events.len() - MAX_EVENTS);
+    }
+}
+
+/// The most recent time that the computer turned on, with the wakeup reason recorded for it if
+/// there is one
+pub fn last_wakeup(events: &[HistoryEvent]) -> Option<(&HistoryEvent, Option<WakeupReason>)> {
+    let index = events
+        .iter()
+        .rposition(|event| event.kind == HistoryEventKind::Power(Power::On))?;
+    // The wakeup reason is recorded when the button is pressed, which is before the power LED
+    // shows that the computer is on
+    let reason = events[..index]
+        .iter()
+        .rev()
+        .take_while(|event| !matches!(event.kind, HistoryEventKind::Power(_)))
+        .find_map(|event| match event.kind {
+            HistoryEventKind::WakeupReason(reason) => Some(reason),
+            _ => None,
+        });
+    Some((&events[index], reason))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn event(time: u64, kind: HistoryEventKind) -> HistoryEvent {
+        HistoryEvent {
+            time: Some(time),
+            kind,
+        }
+    }
+
+    #[test]
+    fn oldest_events_are_forgotten() {
+        let mut events = Vec::new();
+        for i in 0..MAX_EVENTS as u64 + 3 {
+            add_event(&mut events, event(i, HistoryEventKind::Boot));
+        }
+        assert_eq!(events.len(), MAX_EVENTS);
+        assert_eq!(events[0].time, Some(3));
+        assert_eq!(events[MAX_EVENTS - 1].time, Some(MAX_EVENTS as u64 + 2));
+    }
+
+    #[test]
+    fn press_source_gives_wakeup_reason() {
+        assert_eq!(
+            PressSource::Web.wakeup_reason(true),
+            WakeupReason::Web(true)
+        );
+        assert_eq!(
+            PressSource::Bluetooth([1; 6]).wakeup_reason(true),
+            WakeupReason::Bluetooth([1; 6])
+        );
+        assert_eq!(
+            PressSource::Schedule.wakeup_reason(false),
+            WakeupReason::Schedule(false)
+        );
+        assert_eq!(
+            PressSource::Mqtt.wakeup_reason(true),
+            WakeupReason::Mqtt(true)
+        );
+        let address = IpAddr::from([192, 168, 1, 2]);
+        assert_eq!(
+            PressSource::WakeOnLan(address).wakeup_reason(true),
+            WakeupReason::WakeOnLan(address)
+        );
+    }
+
+    #[test]
+    fn last_wakeup_has_its_reason() {
+        let events = vec![
+            event(1, HistoryEventKind::WakeupReason(WakeupReason::Web(false))),
+            event(2, HistoryEventKind::Power(Power::On)),
+            event(3, HistoryEventKind::Power(Power::Off)),
+            event(
+                4,
+                HistoryEventKind::ButtonPress {
+                    message: MessageToEsp::ShortPressPowerButton(true),
+                    source: PressSource::Schedule,
+                },
+            ),
+            event(
+                5,
+                HistoryEventKind::WakeupReason(WakeupReason::Schedule(true)),
+            ),
+            event(6, HistoryEventKind::Power(Power::On)),
+            event(
+                7,
+                HistoryEventKind::HddActivity {
+                    active_millis: 100,
+                    period_secs: 60,
+                },
+            ),
+        ];
+        let (wakeup, reason) = last_wakeup(&events).unwrap();
+        assert_eq!(wakeup.time, Some(6));
+        assert_eq!(reason, Some(WakeupReason::Schedule(true)));
+        // Turned on without the ESP, like by pressing the button on the computer itself
+        let (wakeup, reason) = last_wakeup(&events[..3]).unwrap();
+        assert_eq!(wakeup.time, Some(2));
+        assert_eq!(reason, Some(WakeupReason::Web(false)));
+        let (wakeup, reason) = last_wakeup(&events[1..3]).unwrap();
+        assert_eq!(wakeup.time, Some(2));
+        assert_eq!(reason, None);
+        assert_eq!(last_wakeup(&[]), None);
+    }
+
+    #[test]
+    fn events_round_trip() {
+        let events = vec![
+            event(1, HistoryEventKind::Boot),
+            HistoryEvent {
+                time: None,
+                kind: HistoryEventKind::Power(Power::Suspend),
+            },
+        ];
+        let bytes = postcard::to_allocvec(&events).unwrap();
+        assert_eq!(
+            postcard::from_bytes::<Vec<HistoryEvent>>(&bytes).unwrap(),
+            events
+        );
+    }
+}
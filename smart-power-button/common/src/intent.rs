@@ -0,0 +1,354 @@
+//! Commands that say what the computer should end up doing, instead of which button to press.
+//!
+//! [`IntentMachine`] decides which buttons to press from the power state, without doing any IO,
+//! so that it can be tested here. The ESP presses the buttons and feeds it the power state.
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{MessageToEsp, Power};
+
+/// How long to wait for the computer to turn on or wake up after pressing the power button
+pub const TURN_ON_TIMEOUT: Duration = Duration::from_secs(30);
+/// How long to wait for the computer to turn off after holding the power button
+pub const FORCE_OFF_TIMEOUT: Duration = Duration::from_secs(15);
+/// How long to wait for the ESP to figure out the power state if it doesn't know it yet
+pub const POWER_STATE_TIMEOUT: Duration = Duration::from_secs(10);
+/// How long the OS gets to shut down before the power button is held, if not given
+pub const DEFAULT_GRACEFUL_TIMEOUT: Duration = Duration::from_secs(120);
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Intent {
+    /// Turns on or wakes up the computer, and does nothing if it's already on. The `bool` is if
+    /// the TV should be turned on.
+    EnsureOn(bool),
+    /// Presses the power button so the OS shuts down, and holds it if the computer isn't off
+    /// after `graceful_timeout`. A suspended computer is woken up first. Does nothing if the
+    /// computer is already off.
+    EnsureOff { graceful_timeout: Duration },
+    /// Like [`Intent::EnsureOff`], and then [`Intent::EnsureOn`]
+    Restart { graceful_timeout: Duration },
+}
+
+/// What an intent is waiting for
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IntentPhase {
+    /// The ESP doesn't know if the computer is on yet
+    WaitingForPowerState,
+    /// The power button was pressed to turn on or wake up the computer
+    TurningOn,
+    /// The power button was pressed so the OS shuts down
+    ShuttingDown,
+    /// The OS didn't shut down in time, so the power button is being held
+    ForcingOff,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IntentOutcome {
+    /// The computer was already in the right state, so nothing was pressed
+    AlreadyDone,
+    Done,
+    /// The OS didn't shut down in time, so the computer was forced off
+    ForcedOff,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IntentError {
+    /// A different intent is still running
+    Conflict(Intent),
+    /// The ESP couldn't figure out if the computer is on
+    UnknownPowerState,
+    /// The computer didn't turn on after pressing the power button
+    DidntTurnOn,
+    /// The computer didn't turn off after holding the power button
+    DidntTurnOff,
+}
+
+/// Sent to the web page when it changes
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IntentStatus {
+    Running(Intent, IntentPhase),
+    Finished(Intent, Result<IntentOutcome, IntentError>),
+}
+
+/// What the ESP should do next
+#[derive(Debug, Clone, PartialEq)]
+pub enum Step {
+    /// Press the button, and then wait for the power state to change for up to `timeout`
+    Press {
+        message: MessageToEsp,
+        timeout: Duration,
+    },
+    /// Wait for the power state to change for up to `timeout` without pressing anything
+    Wait {
+        timeout: Duration,
+    },
+    /// Keep waiting until the same timeout as before
+    KeepWaiting,
+    Finish(Result<IntentOutcome, IntentError>),
+}
+
+#[derive(Debug, Clone)]
+pub struct IntentMachine {
+    intent: Intent,
+    phase: IntentPhase,
+    /// If a button was pressed
+    pressed: bool,
+    forced_off: bool,
+    /// For [`Intent::Restart`], if the computer was off at some point
+    was_off: bool,
+}
+
+impl IntentMachine {
+    /// Starts with the current power state
+    pub fn start(intent: Intent, power: Option<Power>) -> (Self, Step) {
+        let mut machine = Self {
+            intent,
+            phase: IntentPhase::WaitingForPowerState,
+            pressed: false,
+            forced_off: false,
+            was_off: false,
+        };
+        let step = machine.decide(power);
+        (machine, step)
+    }
+
+    pub fn intent(&self) -> Intent {
+        self.intent
+    }
+
+    pub fn phase(&self) -> IntentPhase {
+        self.phase
+    }
+
+    /// Call this when the power state changes
+    pub fn on_power(&mut self, power: Option<Power>) -> Step {
+        let is_done_waiting = match self.phase {
+            IntentPhase::WaitingForPowerState => power.is_some(),
+            IntentPhase::TurningOn => power == Some(Power::On),
+            IntentPhase::ShuttingDown | IntentPhase::ForcingOff => power == Some(Power::Off),
+        };
+        match is_done_waiting {
+            true => self.decide(power),
+            false => Step::KeepWaiting,
+        }
+    }
+
+    /// Call this when the timeout from the last [`Step::Press`] or [`Step::Wait`] is over
+    pub fn on_timeout(&mut self) -> Step {
+        match self.phase {
+            IntentPhase::WaitingForPowerState => Step::Finish(Err(IntentError::UnknownPowerState)),
+            IntentPhase::TurningOn => Step::Finish(Err(IntentError::DidntTurnOn)),
+            IntentPhase::ShuttingDown => {
+                self.forced_off = true;
+                self.press(
+                    IntentPhase::ForcingOff,
+                    MessageToEsp::LongPressPowerButton,
+                    FORCE_OFF_TIMEOUT,
+                )
+            }
+            IntentPhase::ForcingOff => Step::Finish(Err(IntentError::DidntTurnOff)),
+        }
+    }
+
+    fn press(&mut self, phase: IntentPhase, message: MessageToEsp, timeout: Duration) -> Step {
+        self.phase = phase;
+        self.pressed = true;
+        Step::Press { message, timeout }
+    }
+
+    fn finish(&self) -> Step {
+        Step::Finish(Ok(match (self.pressed, self.forced_off) {
+            (false, _) => IntentOutcome::AlreadyDone,
+            (true, false) => IntentOutcome::Done,
+            (true, true) => IntentOutcome::ForcedOff,
+        }))
+    }
+
+    /// What to do from `power`, when the machine isn't waiting for anything else
+    fn decide(&mut self, power: Option<Power>) -> Step {
+        let Some(power) = power else {
+            self.phase = IntentPhase::WaitingForPowerState;
+            return Step::Wait {
+                timeout: POWER_STATE_TIMEOUT,
+            };
+        };
+        if power == Power::Off {
+            self.was_off = true;
+        }
+        let (should_be_on, should_turn_on_tv, graceful_timeout) = match self.intent {
+            Intent::EnsureOn(should_turn_on_tv) => (true, should_turn_on_tv, Duration::ZERO),
+            Intent::EnsureOff { graceful_timeout } => (false, false, graceful_timeout),
+            Intent::Restart { graceful_timeout } => (self.was_off, false, graceful_timeout),
+        };
+        match (should_be_on, power) {
+            (true, Power::On) | (false, Power::Off) => self.finish(),
+            (true, Power::Off | Power::Suspend) => self.press(
+                IntentPhase::TurningOn,
+                MessageToEsp::ShortPressPowerButton(should_turn_on_tv),
+                TURN_ON_TIMEOUT,
+            ),
+            // The OS can't shut down while it's suspended
+            (false, Power::Suspend) => self.press(
+                IntentPhase::TurningOn,
+                MessageToEsp::ShortPressPowerButton(false),
+                TURN_ON_TIMEOUT,
+            ),
+            (false, Power::On) => self.press(
+                IntentPhase::ShuttingDown,
+                MessageToEsp::ShortPressPowerButton(false),
+                graceful_timeout,
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const GRACEFUL_TIMEOUT: Duration = Duration::from_secs(60);
+
+    fn short_press(timeout: Duration) -> Step {
+        Step::Press {
+            message: MessageToEsp::ShortPressPowerButton(false),
+            timeout,
+        }
+    }
+
+    #[test]
+    fn ensure_on_does_nothing_if_on() {
+        let (_, step) = IntentMachine::start(Intent::EnsureOn(false), Some(Power::On));
+        assert_eq!(step, Step::Finish(Ok(IntentOutcome::AlreadyDone)));
+    }
+
+    #[test]
+    fn ensure_on_presses_and_waits() {
+        let (mut machine, step) = IntentMachine::start(Intent::EnsureOn(true), Some(Power::Off));
+        assert_eq!(
+            step,
+            Step::Press {
+                message: MessageToEsp::ShortPressPowerButton(true),
+                timeout: TURN_ON_TIMEOUT
+            }
+        );
+        assert_eq!(machine.phase(), IntentPhase::TurningOn);
+        assert_eq!(machine.on_power(Some(Power::Off)), Step::KeepWaiting);
+        assert_eq!(
+            machine.on_power(Some(Power::On)),
+            Step::Finish(Ok(IntentOutcome::Done))
+        );
+
+        let (mut machine, _) = IntentMachine::start(Intent::EnsureOn(false), Some(Power::Suspend));
+        assert_eq!(
+            machine.on_timeout(),
+            Step::Finish(Err(IntentError::DidntTurnOn))
+        );
+    }
+
+    #[test]
+    fn waits_for_power_state() {
+        let (mut machine, step) = IntentMachine::start(Intent::EnsureOn(false), None);
+        assert_eq!(
+            step,
+            Step::Wait {
+                timeout: POWER_STATE_TIMEOUT
+            }
+        );
+        assert_eq!(machine.on_power(None), Step::KeepWaiting);
+        assert_eq!(
+            machine.on_power(Some(Power::On)),
+            Step::Finish(Ok(IntentOutcome::AlreadyDone))
+        );
+
+        let (mut machine, _) = IntentMachine::start(Intent::EnsureOn(false), None);
+        assert_eq!(
+            machine.on_timeout(),
+            Step::Finish(Err(IntentError::UnknownPowerState))
+        );
+    }
+
+    #[test]
+    fn ensure_off_shuts_down_gracefully() {
+        let intent = Intent::EnsureOff {
+            graceful_timeout: GRACEFUL_TIMEOUT,
+        };
+        let (_, step) = IntentMachine::start(intent, Some(Power::Off));
+        assert_eq!(step, Step::Finish(Ok(IntentOutcome::AlreadyDone)));
+
+        let (mut machine, step) = IntentMachine::start(intent, Some(Power::On));
+        assert_eq!(step, short_press(GRACEFUL_TIMEOUT));
+        assert_eq!(machine.phase(), IntentPhase::ShuttingDown);
+        // Suspending isn't shutting down
+        assert_eq!(machine.on_power(Some(Power::Suspend)), Step::KeepWaiting);
+        assert_eq!(
+            machine.on_power(Some(Power::Off)),
+            Step::Finish(Ok(IntentOutcome::Done))
+        );
+    }
+
+    #[test]
+    fn ensure_off_escalates_to_long_press() {
+        let intent = Intent::EnsureOff {
+            graceful_timeout: GRACEFUL_TIMEOUT,
+        };
+        let (mut machine, _) = IntentMachine::start(intent, Some(Power::On));
+        assert_eq!(
+            machine.on_timeout(),
+            Step::Press {
+                message: MessageToEsp::LongPressPowerButton,
+                timeout: FORCE_OFF_TIMEOUT
+            }
+        );
+        assert_eq!(machine.phase(), IntentPhase::ForcingOff);
+        assert_eq!(
+            machine.on_power(Some(Power::Off)),
+            Step::Finish(Ok(IntentOutcome::ForcedOff))
+        );
+
+        let (mut machine, _) = IntentMachine::start(intent, Some(Power::On));
+        machine.on_timeout();
+        assert_eq!(
+            machine.on_timeout(),
+            Step::Finish(Err(IntentError::DidntTurnOff))
+        );
+    }
+
+    #[test]
+    fn ensure_off_wakes_up_first() {
+        let intent = Intent::EnsureOff {
+            graceful_timeout: GRACEFUL_TIMEOUT,
+        };
+        let (mut machine, step) = IntentMachine::start(intent, Some(Power::Suspend));
+        assert_eq!(step, short_press(TURN_ON_TIMEOUT));
+        assert_eq!(machine.phase(), IntentPhase::TurningOn);
+        assert_eq!(
+            machine.on_power(Some(Power::On)),
+            short_press(GRACEFUL_TIMEOUT)
+        );
+        assert_eq!(machine.phase(), IntentPhase::ShuttingDown);
+    }
+
+    #[test]
+    fn restart_turns_off_and_on() {
+        let intent = Intent::Restart {
+            graceful_timeout: GRACEFUL_TIMEOUT,
+        };
+        let (mut machine, step) = IntentMachine::start(intent, Some(Power::On));
+        assert_eq!(step, short_press(GRACEFUL_TIMEOUT));
+        assert_eq!(machine.on_power(Some(Power::On)), Step::KeepWaiting);
+        assert_eq!(
+            machine.on_power(Some(Power::Off)),
+            short_press(TURN_ON_TIMEOUT)
+        );
+        assert_eq!(machine.phase(), IntentPhase::TurningOn);
+        assert_eq!(
+            machine.on_power(Some(Power::On)),
+            Step::Finish(Ok(IntentOutcome::Done))
+        );
+
+        // A computer that is off just gets turned on
+        let (_, step) = IntentMachine::start(intent, Some(Power::Off));
+        assert_eq!(step, short_press(TURN_ON_TIMEOUT));
+    }
+}
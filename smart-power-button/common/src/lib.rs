@@ -1,6 +1,27 @@
+use std::net::IpAddr;
+
 use serde::{Deserialize, Serialize};
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
+pub mod auth;
+pub mod bluetooth_wake;
+pub mod discovery;
+pub mod hdd;
+pub mod history;
+pub mod intent;
+pub mod machine;
+pub mod mqtt;
+pub mod ota;
+pub mod power_led;
+pub mod press;
+pub mod protocol;
+pub mod provisioning;
+pub mod rest;
+pub mod schedule;
+pub mod time_zone;
+pub mod wake_on_lan;
+pub mod wifi;
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub enum MessageToEsp {
     /// Used for things like powering on, triggering whatever the OS does when the power button is pressed, and waking up from suspend
     ShortPressPowerButton(bool),
@@ -8,22 +29,77 @@ pub enum MessageToEsp {
     LongPressPowerButton,
     /// Used to force restart the computer
     ShortPressResetButton,
+    /// Presses a button with any pattern, like a double press
+    Press {
+        button: press::ButtonKind,
+        pattern: press::PressPattern,
+    },
+}
+
+impl MessageToEsp {
+    /// Checks the pattern of [`MessageToEsp::Press`]. The other messages use the ESP's
+    /// [`press::PressConfig`], which was checked when it was saved.
+    pub fn validate(&self) -> Result<(), press::PressError> {
+        match self {
+            Self::Press { pattern, .. } => pattern.validate(),
+            _ => Ok(()),
+        }
+    }
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub enum MessageToWeb {
     /// If the power LED is on
     PowerLedStatus(bool),
     /// If the HDD led is on. (It's called the HDD led, but it also turns on when an SSD is in use).
+    /// Only sent to clients with [`protocol::Capability::RawHddLed`].
     HddLedStatus(bool),
+    /// How busy the HDD LED was. Sent at most once a second, when it changes.
+    HddActivity(hdd::HddActivity),
     /// If the power button is pressed
     PowerButtonStatus(bool),
     /// If the reset button is pressed
     ResetButtonStatus(bool),
+    /// Something new was added to the history
+    HistoryEvent(history::HistoryEvent),
+    /// What the ESP thinks the computer is doing. Sent when it changes.
+    PowerState(PowerState),
+    /// What the computer will be told when it asks why it woke up. Sent when it changes.
+    WakeupReason(Option<WakeupReason>),
+    /// How a firmware update is going. Sent when it changes.
+    OtaStatus(ota::OtaStatus),
+    /// The last intent that was started, if any. Sent when it changes.
+    IntentStatus(Option<intent::IntentStatus>),
+    /// Found by a [`protocol::FrameToEsp::BluetoothScan`] from this client
+    BluetoothDevice(bluetooth_wake::BleAdvertisement),
+}
+
+/// What the ESP thinks the computer is doing, based on the power LED
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+pub enum Power {
+    On,
+    Suspend,
+    Off,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+pub struct PowerState {
+    /// `None` until the ESP has watched the power LED for long enough to know
+    pub power: Option<Power>,
+    /// Seconds since the Unix epoch when `power` changed, or `None` if the ESP didn't know the
+    /// time yet
+    pub since: Option<u64>,
 }
 
-#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
 pub enum WakeupReason {
+    /// The `bool` is if the TV should be turned on
     Web(bool),
     Bluetooth([u8; 6]),
+    /// A rule in the schedule turned on the computer. The `bool` is if the TV should be turned on.
+    Schedule(bool),
+    /// A command from MQTT turned on the computer. The `bool` is if the TV should be turned on.
+    Mqtt(bool),
+    /// A Wake-on-LAN magic packet for the computer came from this address
+    WakeOnLan(IpAddr),
 }
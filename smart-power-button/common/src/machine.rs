@@ -0,0 +1,163 @@
+//! More than one computer on the same ESP, like two machines next to each other in a rack.
+//!
+//! The first machine is on the pins from the portal, and more machines can be put on spare GPIO
+//! pins with `PUT /machines`. Each machine has its own power state, wakeup reason, history,
+//! settings, and Bluetooth wakeup rules. The REST endpoints for a machine are under
+//! `/machines/<id>`, and the ones without that prefix are for the first machine, like before
+//! there were machines.
+use serde::{Deserialize, Serialize};
+
+use crate::provisioning::PinConfig;
+
+/// The machine's index in the list of machines. The first machine is 0.
+pub type MachineId = u8;
+
+/// The ESP32-C3 has enough spare pins for about 4 machines
+pub const MAX_MACHINES: usize = 4;
+pub const MAX_NAME_LEN: usize = 32;
+/// What the first machine is called if it doesn't have a name
+pub const DEFAULT_MACHINE_NAME: &str = "Computer";
+const PATH_PREFIX: &str = "/machines/";
+
+/// A computer on spare GPIO pins
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct MachineConfig {
+    pub name: String,
+    pub pins: PinConfig,
+}
+
+/// Saved in NVS and set with `PUT /machines`. The pins are set up when the ESP starts, so changes
+/// are used after it restarts.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Default)]
+pub struct MachinesConfig {
+    /// The name of the machine on the pins from the portal. Empty uses [`DEFAULT_MACHINE_NAME`].
+    pub first_name: String,
+    /// More machines, which get the ids after the first machine
+    pub others: Vec<MachineConfig>,
+}
+
+impl MachinesConfig {
+    /// The names of all the machines, in the order of their ids
+    pub fn names(&self) -> Vec<String> {
+        let first_name = match self.first_name.is_empty() {
+            true => DEFAULT_MACHINE_NAME.into(),
+            false => self.first_name.clone(),
+        };
+        std::iter::once(first_name)
+            .chain(self.others.iter().map(|machine| machine.name.clone()))
+            .collect()
+    }
+
+    /// The other machines can't share pins with each other. If they share pins with the first
+    /// machine, the ESP finds out when it starts and leaves them out.
+    pub fn is_valid(&self) -> bool {
+        let pins = self
+            .others
+            .iter()
+            .flat_map(|machine| {
+                let pins = machine.pins;
+                [
+                    pins.power_led,
+                    pins.hdd_led,
+                    pins.power_button,
+                    pins.reset_button,
+                ]
+            })
+            .collect::<Vec<_>>();
+        self.others.len() < MAX_MACHINES
+            && self.first_name.len() <= MAX_NAME_LEN
+            && self
+                .others
+                .iter()
+                .all(|machine| !machine.name.is_empty() && machine.name.len() <= MAX_NAME_LEN)
+            && pins
+                .iter()
+                .enumerate()
+                .all(|(i, pin)| !pins[i + 1..].contains(pin))
+    }
+}
+
+/// The path of a REST endpoint for `machine`, like `/machines/1/status`. The first machine uses
+/// the path without the prefix, so that older clients keep working.
+pub fn machine_path(machine: MachineId, path: &str) -> String {
+    match machine {
+        0 => path.into(),
+        machine => format!("{PATH_PREFIX}{machine}{path}"),
+    }
+}
+
+/// Splits a path from [`machine_path`] into the machine and the endpoint's path. Returns `None` if
+/// the prefix doesn't have a valid id or path after it.
+pub fn split_machine_path(path: &str) -> Option<(MachineId, &str)> {
+    let Some(rest) = path.strip_prefix(PATH_PREFIX) else {
+        return Some((0, path));
+    };
+    let slash = rest.find('/')?;
+    let (id, path) = rest.split_at(slash);
+    Some((id.parse().ok()?, path))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn other(name: &str, first_pin: u8) -> MachineConfig {
+        MachineConfig {
+            name: name.into(),
+            pins: PinConfig {
+                power_led: first_pin,
+                hdd_led: first_pin + 1,
+                power_button: first_pin + 2,
+                reset_button: first_pin + 3,
+            },
+        }
+    }
+
+    #[test]
+    fn paths_round_trip() {
+        assert_eq!(machine_path(0, "/status"), "/status");
+        assert_eq!(machine_path(2, "/power/short"), "/machines/2/power/short");
+        assert_eq!(split_machine_path("/status"), Some((0, "/status")));
+        assert_eq!(
+            split_machine_path("/machines/2/power/short"),
+            Some((2, "/power/short"))
+        );
+        assert_eq!(
+            split_machine_path("/machines/0/status"),
+            Some((0, "/status"))
+        );
+        // The endpoint for the machines config itself
+        assert_eq!(split_machine_path("/machines"), Some((0, "/machines")));
+        assert_eq!(split_machine_path("/machines/2"), None);
+        assert_eq!(split_machine_path("/machines/two/status"), None);
+        assert_eq!(split_machine_path("/machines/256/status"), None);
+    }
+
+    #[test]
+    fn first_machine_has_a_default_name() {
+        assert_eq!(MachinesConfig::default().names(), [DEFAULT_MACHINE_NAME]);
+        let config = MachinesConfig {
+            first_name: "Left".into(),
+            others: vec![other("Right", 1)],
+        };
+        assert_eq!(config.names(), ["Left", "Right"]);
+    }
+
+    #[test]
+    fn config_validation() {
+        assert!(MachinesConfig::default().is_valid());
+        let mut config = MachinesConfig {
+            first_name: "".into(),
+            others: vec![other("Right", 1), other("Top", 5)],
+        };
+        assert!(config.is_valid());
+        config.others.push(other("Bottom", 4));
+        assert!(!config.is_valid());
+        config.others[2] = other("", 10);
+        assert!(!config.is_valid());
+        config.others[2] = other("Bottom", 10);
+        assert!(config.is_valid());
+        config.others.push(other("Too many", 14));
+        assert!(!config.is_valid());
+    }
+}
@@ -0,0 +1,299 @@
+//! The ESP's optional MQTT client, which lets Home Assistant see the LEDs and press the buttons.
+//!
+//! All topics are under `<base_topic>/<node id>`, where the node id is the device name. States are
+//! published with retain, except for HDD activity which changes every second, and the ESP publishes
+//! Home Assistant discovery configs for all of them.
+use serde::{Deserialize, Serialize};
+
+use crate::{hdd::HddActivity, MessageToEsp, Power, WakeupReason};
+
+/// Sent on the command topics by Home Assistant's buttons
+pub const PRESS_PAYLOAD: &str = "PRESS";
+/// Sent on the short press topic to also turn on the TV if this turns on the computer
+pub const PRESS_WITH_TV_PAYLOAD: &str = "PRESS_TV";
+pub const ONLINE_PAYLOAD: &str = "online";
+/// Published by the broker when the ESP disconnects
+pub const OFFLINE_PAYLOAD: &str = "offline";
+
+/// Saved in NVS and set with `PUT /mqtt`
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct MqttConfig {
+    /// The broker's hostname or IP address. MQTT is off if this is empty.
+    pub host: String,
+    pub port: u16,
+    /// Empty to connect without logging in
+    pub username: String,
+    pub password: String,
+    pub base_topic: String,
+    /// Where Home Assistant looks for discovery configs
+    pub discovery_prefix: String,
+}
+
+impl Default for MqttConfig {
+    fn default() -> Self {
+        Self {
+            host: "".into(),
+            port: 1883,
+            username: "".into(),
+            password: "".into(),
+            base_topic: "smart-power-button".into(),
+            discovery_prefix: "homeassistant".into(),
+        }
+    }
+}
+
+/// Whether `topic` can be used in the middle of a topic name
+fn is_valid_topic_level(topic: &str) -> bool {
+    !topic.is_empty()
+        && !topic.starts_with('/')
+        && !topic.ends_with('/')
+        && !topic.contains(['+', '#', '\0'])
+}
+
+impl MqttConfig {
+    pub fn is_enabled(&self) -> bool {
+        !self.host.is_empty()
+    }
+
+    pub fn is_valid(&self) -> bool {
+        !self.is_enabled()
+            || (self.port != 0
+                && is_valid_topic_level(&self.base_topic)
+                && is_valid_topic_level(&self.discovery_prefix))
+    }
+}
+
+/// Home Assistant only allows letters, numbers, `_`, and `-` in node ids
+pub fn node_id(device_name: &str) -> String {
+    device_name
+        .chars()
+        .map(|c| match c.is_ascii_alphanumeric() || c == '-' {
+            true => c,
+            false => '_',
+        })
+        .collect()
+}
+
+/// The topics for one ESP
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MqttTopics {
+    node_id: String,
+    /// `<base_topic>/<node id>`
+    base: String,
+    discovery_prefix: String,
+}
+
+impl MqttTopics {
+    pub fn new(config: &MqttConfig, device_name: &str) -> Self {
+        let node_id = node_id(device_name);
+        Self {
+            base: format!("{}/{node_id}", config.base_topic),
+            node_id,
+            discovery_prefix: config.discovery_prefix.clone(),
+        }
+    }
+
+    pub fn node_id(&self) -> &str {
+        &self.node_id
+    }
+
+    /// [`ONLINE_PAYLOAD`] or [`OFFLINE_PAYLOAD`]
+    pub fn availability(&self) -> String {
+        format!("{}/availability", self.base)
+    }
+
+    /// [`led_payload`]
+    pub fn power_led(&self) -> String {
+        format!("{}/power_led", self.base)
+    }
+
+    /// [`hdd_activity_payload`], not retained
+    pub fn hdd_activity(&self) -> String {
+        format!("{}/hdd_activity", self.base)
+    }
+
+    /// Where older versions published the raw HDD LED, which is cleared on connect
+    pub fn old_hdd_led(&self) -> String {
+        format!("{}/hdd_led", self.base)
+    }
+
+    /// [`power_payload`]
+    pub fn power(&self) -> String {
+        format!("{}/power", self.base)
+    }
+
+    /// [`wakeup_reason_payload`]
+    pub fn wakeup_reason(&self) -> String {
+        format!("{}/wakeup_reason", self.base)
+    }
+
+    pub fn short_press(&self) -> String {
+        format!("{}/power_button/short_press", self.base)
+    }
+
+    pub fn long_press(&self) -> String {
+        format!("{}/power_button/long_press", self.base)
+    }
+
+    pub fn reset(&self) -> String {
+        format!("{}/reset_button/press", self.base)
+    }
+
+    /// The topics that [`MqttTopics::command`] understands
+    pub fn command_topics(&self) -> [String; 3] {
+        [self.short_press(), self.long_press(), self.reset()]
+    }
+
+    /// Where Home Assistant looks for the config of an entity
+    pub fn discovery(&self, component: &str, object_id: &str) -> String {
+        format!(
+            "{}/{component}/{}/{object_id}/config",
+            self.discovery_prefix, self.node_id
+        )
+    }
+
+    /// The button press for a message on a command topic
+    pub fn command(&self, topic: &str, payload: &[u8]) -> Option<MessageToEsp> {
+        if topic == self.short_press() {
+            match payload {
+                payload if payload == PRESS_WITH_TV_PAYLOAD.as_bytes() => {
+                    Some(MessageToEsp::ShortPressPowerButton(true))
+                }
+                _ => Some(MessageToEsp::ShortPressPowerButton(false)),
+            }
+        } else if topic == self.long_press() {
+            Some(MessageToEsp::LongPressPowerButton)
+        } else if topic == self.reset() {
+            Some(MessageToEsp::ShortPressResetButton)
+        } else {
+            None
+        }
+    }
+}
+
+pub fn led_payload(is_on: bool) -> &'static str {
+    match is_on {
+        true => "ON",
+        false => "OFF",
+    }
+}
+
+pub fn power_payload(power: Option<Power>) -> &'static str {
+    match power {
+        Some(Power::On) => "on",
+        Some(Power::Suspend) => "suspend",
+        Some(Power::Off) => "off",
+        None => "unknown",
+    }
+}
+
+/// How busy the HDD was in the last 10 seconds, in percent
+pub fn hdd_activity_payload(activity: &HddActivity) -> String {
+    activity.last_10_seconds.active_percent.to_string()
+}
+
+/// The values that [`power_payload`] sends, for Home Assistant's enum sensor
+pub const POWER_PAYLOADS: [&str; 4] = ["on", "suspend", "off", "unknown"];
+
+pub fn wakeup_reason_payload(reason: Option<WakeupReason>) -> String {
+    match reason {
+        None => "none".into(),
+        Some(WakeupReason::Web(_)) => "web".into(),
+        Some(WakeupReason::Bluetooth(address)) => format!(
+            "bluetooth {}",
+            address
+                .iter()
+                // The ESP keeps the bytes in the opposite order
+                .rev()
+                .map(|byte| format!("{byte:02X}"))
+                .collect::<Vec<_>>()
+                .join(":")
+        ),
+        Some(WakeupReason::Schedule(_)) => "schedule".into(),
+        Some(WakeupReason::Mqtt(_)) => "mqtt".into(),
+        Some(WakeupReason::WakeOnLan(address)) => format!("wake_on_lan {address}"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn topics() -> MqttTopics {
+        MqttTopics::new(&MqttConfig::default(), "gaming remote")
+    }
+
+    #[test]
+    fn topics_use_node_id() {
+        let topics = topics();
+        assert_eq!(topics.node_id(), "gaming_remote");
+        assert_eq!(topics.power(), "smart-power-button/gaming_remote/power");
+        assert_eq!(
+            topics.discovery("button", "reset"),
+            "homeassistant/button/gaming_remote/reset/config"
+        );
+    }
+
+    #[test]
+    fn commands_are_parsed() {
+        let topics = topics();
+        assert_eq!(
+            topics.command(&topics.short_press(), PRESS_PAYLOAD.as_bytes()),
+            Some(MessageToEsp::ShortPressPowerButton(false))
+        );
+        assert_eq!(
+            topics.command(&topics.short_press(), PRESS_WITH_TV_PAYLOAD.as_bytes()),
+            Some(MessageToEsp::ShortPressPowerButton(true))
+        );
+        assert_eq!(
+            topics.command(&topics.long_press(), b""),
+            Some(MessageToEsp::LongPressPowerButton)
+        );
+        assert_eq!(
+            topics.command(&topics.reset(), PRESS_PAYLOAD.as_bytes()),
+            Some(MessageToEsp::ShortPressResetButton)
+        );
+        assert_eq!(
+            topics.command(&topics.power(), PRESS_PAYLOAD.as_bytes()),
+            None
+        );
+    }
+
+    #[test]
+    fn payloads() {
+        assert_eq!(led_payload(true), "ON");
+        let mut activity = HddActivity::default();
+        activity.last_10_seconds.active_percent = 42;
+        assert_eq!(hdd_activity_payload(&activity), "42");
+        assert_eq!(power_payload(Some(Power::Suspend)), "suspend");
+        assert!(POWER_PAYLOADS.contains(&power_payload(None)));
+        assert_eq!(
+            wakeup_reason_payload(Some(WakeupReason::Bluetooth([
+                0, 0x4D, 0x8D, 0x26, 0x3F, 0xC8
+            ]))),
+            "bluetooth C8:3F:26:8D:4D:00"
+        );
+        assert_eq!(
+            wakeup_reason_payload(Some(WakeupReason::WakeOnLan([192, 168, 1, 2].into()))),
+            "wake_on_lan 192.168.1.2"
+        );
+    }
+
+    #[test]
+    fn config_validation() {
+        assert!(MqttConfig::default().is_valid());
+        let config = MqttConfig {
+            host: "broker".into(),
+            ..Default::default()
+        };
+        assert!(config.is_valid());
+        for base_topic in ["", "a/", "/a", "a/#", "+"] {
+            let config = MqttConfig {
+                base_topic: base_topic.into(),
+                ..config.clone()
+            };
+            assert!(!config.is_valid(), "{base_topic:?}");
+        }
+        assert!(!MqttConfig { port: 0, ..config }.is_valid());
+    }
+}
@@ -0,0 +1,111 @@
+//! Updating the ESP's firmware over Wi-Fi with `POST /ota`.
+//!
+//! The body is the firmware image made by `espflash save-image`. The request needs a
+//! `Content-Length` and the image's SHA-256 in [`SHA256_HEADER`]. The ESP writes the image to the
+//! OTA partition that isn't running, checks the size and hash, and restarts into it. Progress is
+//! sent over the WebSocket as [`crate::MessageToWeb::OtaStatus`]. If the new firmware doesn't
+//! connect to Wi-Fi and start its server after restarting, the ESP goes back to the old one.
+use std::fmt::{self, Display, Formatter};
+
+use serde::{Deserialize, Serialize};
+
+/// The image's SHA-256, as 64 hex digits
+pub const SHA256_HEADER: &str = "X-Firmware-Sha256";
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Default)]
+pub enum OtaStatus {
+    /// No update since the ESP started
+    #[default]
+    Idle,
+    Receiving {
+        received: u64,
+        total: u64,
+    },
+    /// The image was saved, and the ESP is about to restart into it
+    Restarting,
+    Failed(OtaError),
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub enum OtaError {
+    /// Another update is already being received
+    Busy,
+    MissingSize,
+    /// [`SHA256_HEADER`] is missing or isn't a SHA-256
+    MissingHash,
+    TooLarge {
+        size: u64,
+        max_size: u64,
+    },
+    /// The connection ended early or sent more than `Content-Length`
+    SizeMismatch {
+        expected: u64,
+        received: u64,
+    },
+    HashMismatch,
+    /// The OTA partition couldn't be written
+    Flash(String),
+}
+
+impl Display for OtaError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Busy => write!(f, "Another update is in progress"),
+            Self::MissingSize => write!(f, "The request needs a Content-Length"),
+            Self::MissingHash => write!(f, "The request needs an {SHA256_HEADER} header"),
+            Self::TooLarge { size, max_size } => write!(
+                f,
+                "The image is {size} bytes, but the OTA partition only fits {max_size}"
+            ),
+            Self::SizeMismatch { expected, received } => {
+                write!(f, "Expected {expected} bytes, but got {received}")
+            }
+            Self::HashMismatch => write!(f, "The image doesn't match its SHA-256"),
+            Self::Flash(e) => write!(f, "Error writing the OTA partition: {e}"),
+        }
+    }
+}
+
+impl std::error::Error for OtaError {}
+
+/// Parses the value of [`SHA256_HEADER`]
+pub fn parse_sha256(hex: &str) -> Option<[u8; 32]> {
+    let hex = hex.trim();
+    // `from_str_radix` would also take a `+`
+    if hex.len() != 64 || !hex.bytes().all(|digit| digit.is_ascii_hexdigit()) {
+        return None;
+    }
+    let mut hash = [0; 32];
+    for (i, byte) in hash.iter_mut().enumerate() {
+        *byte = u8::from_str_radix(&hex[i * 2..i * 2 + 2], 16).ok()?;
+    }
+    Some(hash)
+}
+
+/// Formats a hash for [`SHA256_HEADER`]
+pub fn format_sha256(hash: &[u8; 32]) -> String {
+    hash.iter().map(|byte| format!("{byte:02x}")).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sha256_round_trips() {
+        let hash = std::array::from_fn(|i| (i * 8) as u8);
+        let hex = format_sha256(&hash);
+        assert_eq!(hex.len(), 64);
+        assert_eq!(parse_sha256(&hex), Some(hash));
+        assert_eq!(parse_sha256(&hex.to_uppercase()), Some(hash));
+    }
+
+    #[test]
+    fn invalid_sha256_is_rejected() {
+        assert_eq!(parse_sha256(""), None);
+        assert_eq!(parse_sha256(&"0".repeat(63)), None);
+        assert_eq!(parse_sha256(&"g".repeat(64)), None);
+        assert_eq!(parse_sha256(&"+1".repeat(32)), None);
+        assert_eq!(parse_sha256(&"é".repeat(32)), None);
+    }
+}
@@ -0,0 +1,404 @@
+//! Telling if the computer is on, suspended, or off from its power LED.
+//!
+//! Most computers blink the power LED while suspended, but how fast depends on the motherboard,
+//! and some fade it in and out instead, which the optocoupler sees as a blink with noisy edges.
+//! [`PowerClassifier`] looks at how long the LED stays on or off between debounced edges, with
+//! the thresholds in [`BlinkConfig`]. [`calibrate`] suggests a config from edges recorded while
+//! the computer is suspended.
+//!
+//! Times are [`Duration`]s since any fixed point, so that this can be tested without waiting.
+use std::fmt::{self, Display, Formatter};
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+use crate::Power;
+
+/// The most time that calibration can record for
+pub const MAX_CALIBRATION_DURATION: Duration = Duration::from_secs(60);
+/// Calibration needs at least this many gaps between edges to find the blink
+pub const MIN_CALIBRATION_GAPS: usize = 4;
+/// The lowest debounce that calibration suggests
+const MIN_DEBOUNCE: Duration = Duration::from_millis(20);
+
+/// Saved in NVS and set with `PUT /power_led`
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BlinkConfig {
+    /// The LED has to stay at a new level for this long to count as an edge, so that noise and
+    /// the edges of a fading LED don't count
+    pub debounce: Duration,
+    /// The LED has to stay at a level for at least this long for the edge to count as a blink
+    pub min_half_period: Duration,
+    /// If the LED stays at a level for longer than this, the computer is on or off
+    pub max_half_period: Duration,
+    /// How many blinks in a row mean that the computer is suspended
+    pub blinks_for_suspend: u32,
+}
+
+impl Default for BlinkConfig {
+    fn default() -> Self {
+        Self {
+            debounce: Duration::from_millis(50),
+            min_half_period: Duration::from_millis(200),
+            max_half_period: Duration::from_secs(2),
+            blinks_for_suspend: 2,
+        }
+    }
+}
+
+impl BlinkConfig {
+    pub fn is_valid(&self) -> bool {
+        self.debounce < self.min_half_period
+            && self.min_half_period < self.max_half_period
+            && self.max_half_period <= Duration::from_secs(10)
+            && self.blinks_for_suspend > 0
+    }
+}
+
+/// Turns the power LED's levels into [`Power`]. Call [`PowerClassifier::update`] whenever the
+/// LED changes, and at [`PowerClassifier::next_deadline`].
+#[derive(Debug, Clone)]
+pub struct PowerClassifier {
+    config: BlinkConfig,
+    /// The debounced level
+    level: bool,
+    /// When `level` started
+    last_edge: Duration,
+    /// When the LED started being different from `level`, if it is
+    pending_since: Option<Duration>,
+    /// Blinks in a row, counted by the edges that ended them
+    blinks: u32,
+    power: Option<Power>,
+}
+
+impl PowerClassifier {
+    pub fn new(config: BlinkConfig, level: bool, now: Duration) -> Self {
+        Self {
+            config,
+            level,
+            last_edge: now,
+            pending_since: None,
+            blinks: 0,
+            power: None,
+        }
+    }
+
+    pub fn power(&self) -> Option<Power> {
+        self.power
+    }
+
+    /// Keeps the edges that were already seen
+    pub fn set_config(&mut self, config: BlinkConfig) {
+        self.config = config;
+    }
+
+    fn steady_power(&self) -> Power {
+        match self.level {
+            true => Power::On,
+            false => Power::Off,
+        }
+    }
+
+    /// When [`PowerClassifier::update`] should be called even if the LED doesn't change
+    pub fn next_deadline(&self) -> Option<Duration> {
+        match self.pending_since {
+            Some(since) => Some(since + self.config.debounce),
+            None if self.power != Some(self.steady_power()) || self.blinks > 0 => {
+                Some(self.last_edge + self.config.max_half_period)
+            }
+            None => None,
+        }
+    }
+
+    /// Returns the new power if it changed
+    pub fn update(&mut self, level: bool, now: Duration) -> Option<Power> {
+        let old_power = self.power;
+        match (level != self.level, self.pending_since) {
+            (true, None) => self.pending_since = Some(now),
+            // Shorter than the debounce, so it didn't happen
+            (false, Some(_)) => self.pending_since = None,
+            _ => {}
+        }
+        if let Some(since) = self.pending_since {
+            if now >= since + self.config.debounce {
+                self.edge(since);
+            }
+        }
+        if self.pending_since.is_none() && now >= self.last_edge + self.config.max_half_period {
+            self.blinks = 0;
+            self.power = Some(self.steady_power());
+        }
+        (self.power != old_power).then_some(self.power).flatten()
+    }
+
+    fn edge(&mut self, at: Duration) {
+        let half_period = at - self.last_edge;
+        self.level = !self.level;
+        self.last_edge = at;
+        self.pending_since = None;
+        if half_period >= self.config.min_half_period && half_period <= self.config.max_half_period
+        {
+            self.blinks += 1;
+        } else {
+            self.blinks = 0;
+        }
+        if self.blinks >= self.config.blinks_for_suspend {
+            self.power = Some(Power::Suspend);
+        }
+    }
+}
+
+/// What [`calibrate`] found
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Calibration {
+    /// How many edges there were after debouncing
+    pub edges: u32,
+    pub shortest_half_period: Duration,
+    pub longest_half_period: Duration,
+    pub suggested: BlinkConfig,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CalibrationError {
+    /// The LED didn't blink enough. The computer has to be suspended during calibration.
+    NotEnoughEdges,
+    /// The blink is too slow or too uneven to tell apart from on and off
+    Irregular,
+}
+
+impl Display for CalibrationError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NotEnoughEdges => write!(
+                f,
+                "The power LED didn't blink enough. Suspend the computer and try again."
+            ),
+            Self::Irregular => write!(f, "The power LED's blink is too slow or uneven"),
+        }
+    }
+}
+
+impl std::error::Error for CalibrationError {}
+
+/// Drops pairs of edges that are closer than `debounce`, like [`PowerClassifier`] does
+fn debounce_edges(edges: &[Duration], debounce: Duration) -> Vec<Duration> {
+    let mut debounced = Vec::<Duration>::new();
+    for &edge in edges {
+        match debounced.last() {
+            Some(&last) if edge - last < debounce => {
+                debounced.pop();
+            }
+            _ => debounced.push(edge),
+        }
+    }
+    debounced
+}
+
+fn gaps(edges: &[Duration]) -> Vec<Duration> {
+    edges.windows(2).map(|pair| pair[1] - pair[0]).collect()
+}
+
+/// Suggests a [`BlinkConfig`] from the times of the power LED's edges while the computer is
+/// suspended, in order
+pub fn calibrate(edges: &[Duration]) -> Result<Calibration, CalibrationError> {
+    let mut raw_gaps = gaps(edges);
+    if raw_gaps.len() < MIN_CALIBRATION_GAPS {
+        return Err(CalibrationError::NotEnoughEdges);
+    }
+    raw_gaps.sort();
+    let median = raw_gaps[raw_gaps.len() / 2];
+    // Anything much shorter than the blink is noise
+    let noise = raw_gaps
+        .iter()
+        .copied()
+        .filter(|&gap| gap < median / 4)
+        .max()
+        .unwrap_or_default();
+    let debounce = (noise * 2).clamp(MIN_DEBOUNCE, (median / 4).max(MIN_DEBOUNCE));
+
+    let edges = debounce_edges(edges, debounce);
+    let gaps = gaps(&edges);
+    if gaps.len() < MIN_CALIBRATION_GAPS {
+        return Err(CalibrationError::NotEnoughEdges);
+    }
+    let shortest = *gaps.iter().min().unwrap();
+    let longest = *gaps.iter().max().unwrap();
+    let suggested = BlinkConfig {
+        debounce,
+        min_half_period: (shortest / 2).max(debounce + Duration::from_millis(1)),
+        max_half_period: longest * 3 / 2,
+        blinks_for_suspend: BlinkConfig::default().blinks_for_suspend,
+    };
+    if !suggested.is_valid() {
+        return Err(CalibrationError::Irregular);
+    }
+    Ok(Calibration {
+        edges: edges.len() as u32,
+        shortest_half_period: shortest,
+        longest_half_period: longest,
+        suggested,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ms(millis: u64) -> Duration {
+        Duration::from_millis(millis)
+    }
+
+    /// Feeds `(time in ms, level)` pairs, calling the classifier at its deadlines in between
+    /// like the ESP does, and returns the power changes with their times in ms
+    fn classify(
+        config: BlinkConfig,
+        initial_level: bool,
+        levels: &[(u64, bool)],
+        end: u64,
+    ) -> Vec<(u64, Power)> {
+        let mut classifier = PowerClassifier::new(config, initial_level, Duration::ZERO);
+        let mut level = initial_level;
+        let mut changes = Vec::new();
+        let mut feed = |classifier: &mut PowerClassifier, level: bool, now: Duration| {
+            if let Some(power) = classifier.update(level, now) {
+                changes.push((now.as_millis() as u64, power));
+            }
+        };
+        for &(at, new_level) in levels.iter().chain([&(end, level)]) {
+            while let Some(deadline) = classifier.next_deadline().filter(|&d| d <= ms(at)) {
+                feed(&mut classifier, level, deadline);
+            }
+            level = new_level;
+            feed(&mut classifier, level, ms(at));
+        }
+        changes
+    }
+
+    /// The LED toggling every `half_period` ms from `start`, ending at `end`
+    fn blink(start: u64, end: u64, half_period: u64, first_level: bool) -> Vec<(u64, bool)> {
+        (start..end)
+            .step_by(half_period as usize)
+            .enumerate()
+            .map(|(i, at)| (at, first_level ^ (i % 2 == 1)))
+            .collect()
+    }
+
+    #[test]
+    fn steady_led_is_on_or_off() {
+        let config = BlinkConfig::default();
+        assert_eq!(classify(config, true, &[], 5000), [(2000, Power::On)]);
+        assert_eq!(classify(config, false, &[], 5000), [(2000, Power::Off)]);
+        assert_eq!(
+            classify(config, true, &[(3000, false)], 10_000),
+            [(2000, Power::On), (5000, Power::Off)]
+        );
+    }
+
+    #[test]
+    fn blinking_led_is_suspend() {
+        let config = BlinkConfig::default();
+        // On, then a 1 second blink, then off
+        let mut levels = blink(3000, 9000, 1000, false);
+        levels.push((9000, false));
+        assert_eq!(
+            classify(config, true, &levels, 15_000),
+            [
+                (2000, Power::On),
+                (5050, Power::Suspend),
+                (11_000, Power::Off)
+            ]
+        );
+    }
+
+    #[test]
+    fn fast_and_slow_blinks_need_their_own_config() {
+        let default = BlinkConfig::default();
+        let fast = blink(0, 5000, 100, true);
+        assert_eq!(classify(default, false, &fast, 5000), []);
+        let config = BlinkConfig {
+            min_half_period: ms(60),
+            ..default
+        };
+        assert_eq!(
+            classify(config, false, &fast, 5000),
+            [(250, Power::Suspend)]
+        );
+
+        let slow = blink(0, 20_000, 3000, true);
+        assert_eq!(
+            classify(default, false, &slow, 20_000),
+            [
+                (2000, Power::On),
+                (5000, Power::Off),
+                (8000, Power::On),
+                (11_000, Power::Off),
+                (14_000, Power::On),
+                (17_000, Power::Off),
+                (20_000, Power::On)
+            ]
+        );
+        let config = BlinkConfig {
+            max_half_period: ms(4000),
+            ..default
+        };
+        assert_eq!(
+            classify(config, false, &slow, 20_000),
+            [(6050, Power::Suspend)]
+        );
+    }
+
+    #[test]
+    fn noise_is_debounced() {
+        let config = BlinkConfig::default();
+        // A steady LED with short spikes isn't a blink
+        let spikes = (1..10)
+            .flat_map(|i| [(i * 500, false), (i * 500 + 10, true)])
+            .collect::<Vec<_>>();
+        assert_eq!(classify(config, true, &spikes, 10_000), [(2000, Power::On)]);
+
+        // A fading LED with noisy edges is still a blink
+        let noisy_blink = blink(0, 8000, 1000, true)
+            .into_iter()
+            .flat_map(|(at, level)| [(at, level), (at + 5, !level), (at + 15, level)])
+            .collect::<Vec<_>>();
+        assert_eq!(
+            classify(config, false, &noisy_blink, 8000),
+            [(2065, Power::Suspend)]
+        );
+    }
+
+    #[test]
+    fn calibration_suggests_a_config_that_works() {
+        let edges = blink(0, 10_000, 700, true)
+            .into_iter()
+            .flat_map(|(at, _)| [ms(at), ms(at + 8), ms(at + 12)])
+            .collect::<Vec<_>>();
+        let calibration = calibrate(&edges).unwrap();
+        assert_eq!(calibration.shortest_half_period, ms(700));
+        assert_eq!(calibration.longest_half_period, ms(700));
+        let config = calibration.suggested;
+        assert!(config.is_valid());
+        assert!(config.debounce >= ms(20) && config.debounce < ms(700));
+        assert!(config.max_half_period > ms(700));
+
+        let levels = blink(0, 10_000, 700, true);
+        assert_eq!(
+            classify(config, false, &levels, 10_000)
+                .into_iter()
+                .map(|(_, power)| power)
+                .collect::<Vec<_>>(),
+            [Power::Suspend]
+        );
+    }
+
+    #[test]
+    fn calibration_needs_a_blink() {
+        assert_eq!(calibrate(&[]), Err(CalibrationError::NotEnoughEdges));
+        assert_eq!(
+            calibrate(&[ms(0), ms(1000), ms(2000)]),
+            Err(CalibrationError::NotEnoughEdges)
+        );
+        let too_slow = [0, 8000, 16_000, 24_000, 32_000].map(ms);
+        assert_eq!(calibrate(&too_slow), Err(CalibrationError::Irregular));
+    }
+}
@@ -0,0 +1,201 @@
+//! How long the buttons are held.
+//!
+//! [`PressConfig`] is saved in NVS and set with `PUT /press_config`, for motherboards that need
+//! different timings. [`crate::MessageToEsp::Press`] presses a button with any [`PressPattern`],
+//! like a double press. Both are checked against the limits here, so that a bad config can't hold
+//! the power button forever.
+use std::fmt::{self, Display, Formatter};
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+/// The longest a button can be held at once
+pub const MAX_HOLD: Duration = Duration::from_secs(15);
+/// The longest a whole pattern can take, including the time between presses
+pub const MAX_PATTERN_DURATION: Duration = Duration::from_secs(30);
+pub const MAX_PATTERN_STEPS: usize = 16;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ButtonKind {
+    Power,
+    Reset,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PressStep {
+    /// Hold the button down for this long
+    Hold(Duration),
+    /// Let go of the button for this long
+    Release(Duration),
+}
+
+/// Steps that are done in order. The button is always let go at the end.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct PressPattern(pub Vec<PressStep>);
+
+impl PressPattern {
+    /// Holds the button once
+    pub fn hold(duration: Duration) -> Self {
+        Self(vec![PressStep::Hold(duration)])
+    }
+
+    /// Two presses of `hold`, with `gap` between them
+    pub fn double_press(hold: Duration, gap: Duration) -> Self {
+        Self(vec![
+            PressStep::Hold(hold),
+            PressStep::Release(gap),
+            PressStep::Hold(hold),
+        ])
+    }
+
+    pub fn duration(&self) -> Duration {
+        self.0
+            .iter()
+            .map(|step| match *step {
+                PressStep::Hold(duration) | PressStep::Release(duration) => duration,
+            })
+            .sum()
+    }
+
+    pub fn validate(&self) -> Result<(), PressError> {
+        if !self.0.iter().any(|step| matches!(step, PressStep::Hold(_))) {
+            return Err(PressError::NoHold);
+        }
+        if self.0.len() > MAX_PATTERN_STEPS {
+            return Err(PressError::TooManySteps);
+        }
+        if self.0.iter().any(|step| match *step {
+            PressStep::Hold(duration) => duration.is_zero() || duration > MAX_HOLD,
+            PressStep::Release(_) => false,
+        }) {
+            return Err(PressError::InvalidHold);
+        }
+        if self.duration() > MAX_PATTERN_DURATION {
+            return Err(PressError::TooLong);
+        }
+        Ok(())
+    }
+}
+
+/// How long [`crate::MessageToEsp::ShortPressPowerButton`],
+/// [`crate::MessageToEsp::LongPressPowerButton`], and
+/// [`crate::MessageToEsp::ShortPressResetButton`] hold the buttons
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PressConfig {
+    pub short_press: Duration,
+    /// Most computers turn off after the power button is held for 4 seconds
+    pub long_press: Duration,
+    pub reset_press: Duration,
+}
+
+impl Default for PressConfig {
+    fn default() -> Self {
+        Self {
+            short_press: Duration::from_millis(500),
+            long_press: Duration::from_secs(6),
+            reset_press: Duration::from_millis(500),
+        }
+    }
+}
+
+impl PressConfig {
+    pub fn validate(&self) -> Result<(), PressError> {
+        for duration in [self.short_press, self.long_press, self.reset_press] {
+            PressPattern::hold(duration).validate()?;
+        }
+        if self.short_press >= self.long_press {
+            return Err(PressError::ShortNotShorterThanLong);
+        }
+        Ok(())
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PressError {
+    /// The pattern never holds the button
+    NoHold,
+    TooManySteps,
+    /// A hold is 0 or longer than [`MAX_HOLD`]
+    InvalidHold,
+    /// The pattern takes longer than [`MAX_PATTERN_DURATION`]
+    TooLong,
+    ShortNotShorterThanLong,
+}
+
+impl Display for PressError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NoHold => write!(f, "The pattern never holds the button"),
+            Self::TooManySteps => write!(f, "Patterns can have at most {MAX_PATTERN_STEPS} steps"),
+            Self::InvalidHold => write!(
+                f,
+                "Buttons must be held for more than 0 ms and at most {} ms",
+                MAX_HOLD.as_millis()
+            ),
+            Self::TooLong => write!(
+                f,
+                "Patterns can take at most {} ms",
+                MAX_PATTERN_DURATION.as_millis()
+            ),
+            Self::ShortNotShorterThanLong => {
+                write!(f, "The short press must be shorter than the long press")
+            }
+        }
+    }
+}
+
+impl std::error::Error for PressError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn default_config_is_valid() {
+        assert_eq!(PressConfig::default().validate(), Ok(()));
+    }
+
+    #[test]
+    fn holds_are_limited() {
+        assert_eq!(
+            PressPattern::double_press(Duration::from_millis(200), Duration::from_millis(300))
+                .validate(),
+            Ok(())
+        );
+        assert_eq!(PressPattern::hold(MAX_HOLD).validate(), Ok(()));
+        assert_eq!(
+            PressPattern::hold(MAX_HOLD + Duration::from_millis(1)).validate(),
+            Err(PressError::InvalidHold)
+        );
+        assert_eq!(
+            PressPattern::hold(Duration::ZERO).validate(),
+            Err(PressError::InvalidHold)
+        );
+        assert_eq!(
+            PressPattern(vec![PressStep::Release(Duration::from_secs(1))]).validate(),
+            Err(PressError::NoHold)
+        );
+        let config = PressConfig {
+            long_press: Duration::from_secs(60),
+            ..Default::default()
+        };
+        assert_eq!(config.validate(), Err(PressError::InvalidHold));
+    }
+
+    #[test]
+    fn patterns_are_limited() {
+        let pattern = PressPattern(vec![PressStep::Hold(MAX_HOLD); 3]);
+        assert_eq!(pattern.duration(), MAX_HOLD * 3);
+        assert_eq!(pattern.validate(), Err(PressError::TooLong));
+        let pattern = PressPattern(vec![
+            PressStep::Hold(Duration::from_millis(10));
+            MAX_PATTERN_STEPS + 1
+        ]);
+        assert_eq!(pattern.validate(), Err(PressError::TooManySteps));
+        let config = PressConfig {
+            short_press: Duration::from_secs(6),
+            ..Default::default()
+        };
+        assert_eq!(config.validate(), Err(PressError::ShortNotShorterThanLong));
+    }
+}
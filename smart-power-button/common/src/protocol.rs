@@ -0,0 +1,449 @@
+//! The frames that are sent over the WebSocket between the web page and the ESP.
+//!
+//! The first frame each side sends is a [`Hello`]. After that the web page wraps every
+//! [`MessageToEsp`] in a [`FrameToEsp::Request`] and every [`Intent`] in a
+//! [`FrameToEsp::Intent`] with a request id, and the ESP replies to it with [`FrameToWeb::Ack`] or
+//! [`FrameToWeb::Error`] with the same id. Messages and intents say which
+//! [`crate::machine`] they're for.
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+use crate::intent::{Intent, IntentError};
+use crate::machine::MachineId;
+use crate::press::PressError;
+use crate::{MessageToEsp, MessageToWeb};
+
+/// Bump this whenever the frames or any of the messages inside them change
+pub const PROTOCOL_VERSION: u16 = 12;
+
+pub type RequestId = u32;
+
+/// Things that one side supports, so that the other side knows what it can use
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Capability {
+    PowerButton,
+    ResetButton,
+    /// Sends [`MessageToWeb::HistoryEvent`]
+    History,
+    /// Sends [`MessageToWeb::PowerState`] and [`MessageToWeb::WakeupReason`]
+    PowerState,
+    /// Takes firmware updates with `POST /ota` and sends [`MessageToWeb::OtaStatus`]
+    Ota,
+    /// Takes [`FrameToEsp::Intent`] and sends [`MessageToWeb::IntentStatus`]
+    Intents,
+    /// Takes [`MessageToEsp::Press`], and the press timings with `GET` and `PUT /press_config`
+    PressPatterns,
+    /// Takes the power LED config with `GET` and `PUT /power_led`, and calibrates it with
+    /// `POST /power_led/calibrate`
+    PowerLedCalibration,
+    /// Sends [`MessageToWeb::HddActivity`]
+    HddActivity,
+    /// Sent by the web page to get [`MessageToWeb::HddLedStatus`] every time the HDD LED changes
+    RawHddLed,
+    /// Takes [`FrameToEsp::BluetoothScan`] and `POST /bluetooth_scan`
+    BluetoothDiscovery,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Hello {
+    /// This must stay the first field so that [`peek_hello_version`] works for every version
+    pub protocol_version: u16,
+    pub capabilities: Vec<Capability>,
+    /// The names of the ESP's machines, in the order of their ids. Empty from the web page.
+    pub machines: Vec<String>,
+}
+
+impl Hello {
+    pub fn new(capabilities: Vec<Capability>) -> Self {
+        Self {
+            protocol_version: PROTOCOL_VERSION,
+            capabilities,
+            machines: Vec::new(),
+        }
+    }
+
+    pub fn has(&self, capability: Capability) -> bool {
+        self.capabilities.contains(&capability)
+    }
+
+    /// Checks a [`Hello`] received from the other side
+    pub fn check_version(&self) -> Result<(), ProtocolError> {
+        if self.protocol_version == PROTOCOL_VERSION {
+            Ok(())
+        } else {
+            Err(ProtocolError::VersionMismatch {
+                expected: PROTOCOL_VERSION,
+                actual: self.protocol_version,
+            })
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProtocolError {
+    /// The other side speaks a different protocol version. The connection will be closed.
+    VersionMismatch { expected: u16, actual: u16 },
+    /// A frame other than [`Hello`] was sent before the handshake was done
+    HandshakeRequired,
+    /// [`Hello`] was sent again after the handshake was done
+    UnexpectedHello,
+    /// The frame could not be decoded
+    Malformed,
+    /// The message needs a capability that the ESP doesn't have
+    Unsupported(Capability),
+    /// The intent was refused or didn't work
+    Intent(IntentError),
+    /// The press pattern is outside the limits in [`crate::press`]
+    Press(PressError),
+    /// The ESP's Bluetooth didn't scan
+    BluetoothScanFailed,
+    /// The ESP doesn't have a machine with this id
+    UnknownMachine(MachineId),
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub enum FrameToEsp {
+    /// Must stay the first variant so that [`peek_hello_version`] works for every version
+    Hello(Hello),
+    Request {
+        id: RequestId,
+        machine: MachineId,
+        message: MessageToEsp,
+    },
+    /// Acked when the computer is in the right state, which can take minutes
+    Intent {
+        id: RequestId,
+        machine: MachineId,
+        intent: Intent,
+    },
+    /// Scans for Bluetooth devices for `duration`, which is limited to
+    /// [`crate::bluetooth_wake::MAX_DISCOVERY_DURATION`]. The devices are sent to this client as
+    /// [`MessageToWeb::BluetoothDevice`], and then this is acked.
+    BluetoothScan { id: RequestId, duration: Duration },
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub enum FrameToWeb {
+    /// Must stay the first variant so that [`peek_hello_version`] works for every version
+    Hello(Hello),
+    /// The request with this id was done
+    Ack(RequestId),
+    /// `id` is `None` if the error isn't about a specific request
+    Error {
+        id: Option<RequestId>,
+        error: ProtocolError,
+    },
+    /// `machine` is `None` for messages about the whole ESP, like
+    /// [`MessageToWeb::OtaStatus`] and [`MessageToWeb::BluetoothDevice`]
+    Message {
+        machine: Option<MachineId>,
+        message: MessageToWeb,
+    },
+}
+
+/// Gets the protocol version out of an encoded [`FrameToEsp::Hello`] or [`FrameToWeb::Hello`],
+/// even if the rest of the frame is from a different version and can't be decoded
+pub fn peek_hello_version(bytes: &[u8]) -> Option<u16> {
+    let (variant, bytes) = postcard::take_from_bytes::<u32>(bytes).ok()?;
+    if variant != 0 {
+        return None;
+    }
+    let (version, _) = postcard::take_from_bytes::<u16>(bytes).ok()?;
+    Some(version)
+}
+
+fn decode<'a, T: Deserialize<'a>>(bytes: &'a [u8]) -> Result<T, ProtocolError> {
+    postcard::from_bytes(bytes).map_err(|_| match peek_hello_version(bytes) {
+        Some(actual) if actual != PROTOCOL_VERSION => ProtocolError::VersionMismatch {
+            expected: PROTOCOL_VERSION,
+            actual,
+        },
+        _ => ProtocolError::Malformed,
+    })
+}
+
+impl FrameToEsp {
+    pub fn encode(&self) -> Vec<u8> {
+        postcard::to_allocvec(self).expect("Frames can always be serialized")
+    }
+
+    pub fn decode(bytes: &[u8]) -> Result<Self, ProtocolError> {
+        decode(bytes)
+    }
+}
+
+impl FrameToWeb {
+    pub fn encode(&self) -> Vec<u8> {
+        postcard::to_allocvec(self).expect("Frames can always be serialized")
+    }
+
+    pub fn decode(bytes: &[u8]) -> Result<Self, ProtocolError> {
+        decode(bytes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bluetooth_wake::{BleAddressType, BleAdvertisement, BleUuid};
+    use crate::hdd::{HddActivity, HddStats};
+    use crate::history::{HistoryEvent, HistoryEventKind};
+    use crate::intent::{IntentOutcome, IntentPhase, IntentStatus};
+    use crate::ota::{OtaError, OtaStatus};
+    use crate::press::{ButtonKind, PressPattern};
+    use crate::{Power, PowerState, WakeupReason};
+
+    fn all_messages_to_esp() -> Vec<MessageToEsp> {
+        vec![
+            MessageToEsp::ShortPressPowerButton(false),
+            MessageToEsp::ShortPressPowerButton(true),
+            MessageToEsp::LongPressPowerButton,
+            MessageToEsp::ShortPressResetButton,
+            MessageToEsp::Press {
+                button: ButtonKind::Reset,
+                pattern: PressPattern::double_press(
+                    Duration::from_millis(200),
+                    Duration::from_millis(300),
+                ),
+            },
+        ]
+    }
+
+    fn all_messages_to_web() -> Vec<MessageToWeb> {
+        vec![
+            MessageToWeb::PowerLedStatus(true),
+            MessageToWeb::HddLedStatus(false),
+            MessageToWeb::HddActivity(HddActivity::default()),
+            MessageToWeb::HddActivity(HddActivity {
+                last_second: HddStats {
+                    active_percent: 100,
+                    bursts: 1,
+                },
+                last_10_seconds: HddStats {
+                    active_percent: 12,
+                    bursts: 3,
+                },
+                last_minute: HddStats {
+                    active_percent: 2,
+                    bursts: 3,
+                },
+                history: vec![0, 0, 5, 100],
+            }),
+            MessageToWeb::PowerButtonStatus(true),
+            MessageToWeb::ResetButtonStatus(false),
+            MessageToWeb::HistoryEvent(HistoryEvent {
+                time: Some(1_700_000_000),
+                kind: HistoryEventKind::Power(Power::On),
+            }),
+            MessageToWeb::PowerState(PowerState {
+                power: Some(Power::Suspend),
+                since: Some(1_700_000_000),
+            }),
+            MessageToWeb::PowerState(PowerState {
+                power: None,
+                since: None,
+            }),
+            MessageToWeb::WakeupReason(Some(WakeupReason::Bluetooth([1, 2, 3, 4, 5, 6]))),
+            MessageToWeb::WakeupReason(None),
+            MessageToWeb::OtaStatus(OtaStatus::Receiving {
+                received: 4096,
+                total: 1_000_000,
+            }),
+            MessageToWeb::OtaStatus(OtaStatus::Failed(OtaError::SizeMismatch {
+                expected: 1_000_000,
+                received: 4096,
+            })),
+            MessageToWeb::IntentStatus(None),
+            MessageToWeb::IntentStatus(Some(IntentStatus::Running(
+                Intent::EnsureOff {
+                    graceful_timeout: Duration::from_secs(60),
+                },
+                IntentPhase::ShuttingDown,
+            ))),
+            MessageToWeb::IntentStatus(Some(IntentStatus::Finished(
+                Intent::EnsureOn(true),
+                Ok(IntentOutcome::Done),
+            ))),
+            MessageToWeb::IntentStatus(Some(IntentStatus::Finished(
+                Intent::Restart {
+                    graceful_timeout: Duration::from_millis(1500),
+                },
+                Err(IntentError::DidntTurnOff),
+            ))),
+            MessageToWeb::BluetoothDevice(BleAdvertisement {
+                address: [1, 2, 3, 4, 5, 6],
+                address_type: BleAddressType::Random,
+                name: Some("Controller".into()),
+                service_uuids: vec![BleUuid::Uuid16(0x1812), BleUuid::Uuid128([7; 16])],
+                manufacturer_data: Some(vec![0x06, 0x00, 1]),
+                rssi: -70,
+            }),
+        ]
+    }
+
+    fn hello() -> Hello {
+        Hello::new(vec![Capability::PowerButton, Capability::ResetButton])
+    }
+
+    #[test]
+    fn frames_to_esp_round_trip() {
+        let frames = [FrameToEsp::Hello(hello())]
+            .into_iter()
+            .chain(
+                all_messages_to_esp()
+                    .into_iter()
+                    .enumerate()
+                    .map(|(id, message)| FrameToEsp::Request {
+                        id: id as RequestId,
+                        machine: id as MachineId,
+                        message,
+                    }),
+            )
+            .chain([
+                FrameToEsp::Intent {
+                    id: 9,
+                    machine: 1,
+                    intent: Intent::EnsureOn(false),
+                },
+                FrameToEsp::BluetoothScan {
+                    id: 10,
+                    duration: Duration::from_secs(10),
+                },
+            ]);
+        for frame in frames {
+            assert_eq!(FrameToEsp::decode(&frame.encode()), Ok(frame));
+        }
+    }
+
+    #[test]
+    fn frames_to_web_round_trip() {
+        let errors = [
+            ProtocolError::VersionMismatch {
+                expected: 1,
+                actual: 2,
+            },
+            ProtocolError::HandshakeRequired,
+            ProtocolError::UnexpectedHello,
+            ProtocolError::Malformed,
+            ProtocolError::Unsupported(Capability::ResetButton),
+            ProtocolError::Unsupported(Capability::Ota),
+            ProtocolError::Intent(IntentError::Conflict(Intent::EnsureOn(false))),
+            ProtocolError::Press(PressError::TooLong),
+            ProtocolError::BluetoothScanFailed,
+            ProtocolError::UnknownMachine(3),
+        ];
+        let frames = [
+            FrameToWeb::Hello(hello()),
+            FrameToWeb::Hello(Hello {
+                machines: vec!["Left".into(), "Right".into()],
+                ..hello()
+            }),
+            FrameToWeb::Ack(0),
+            FrameToWeb::Ack(RequestId::MAX),
+        ]
+        .into_iter()
+        .chain(
+            errors
+                .into_iter()
+                .map(|error| FrameToWeb::Error { id: Some(3), error }),
+        )
+        .chain([FrameToWeb::Error {
+            id: None,
+            error: ProtocolError::Malformed,
+        }])
+        .chain(
+            all_messages_to_web()
+                .into_iter()
+                .enumerate()
+                .map(|(i, message)| FrameToWeb::Message {
+                    machine: (i % 2 == 0).then_some(i as MachineId),
+                    message,
+                }),
+        );
+        for frame in frames {
+            assert_eq!(FrameToWeb::decode(&frame.encode()), Ok(frame));
+        }
+    }
+
+    #[test]
+    fn wakeup_reason_round_trips() {
+        for reason in [
+            None,
+            Some(WakeupReason::Web(true)),
+            Some(WakeupReason::Bluetooth([1, 2, 3, 4, 5, 6])),
+            Some(WakeupReason::Schedule(false)),
+            Some(WakeupReason::Mqtt(true)),
+            Some(WakeupReason::WakeOnLan([192, 168, 1, 2].into())),
+            Some(WakeupReason::WakeOnLan("fe80::1".parse().unwrap())),
+        ] {
+            let bytes = postcard::to_allocvec(&reason).unwrap();
+            let decoded = postcard::from_bytes::<Option<WakeupReason>>(&bytes).unwrap();
+            assert_eq!(decoded, reason);
+        }
+    }
+
+    #[test]
+    fn matching_version_is_accepted() {
+        assert_eq!(hello().check_version(), Ok(()));
+        assert_eq!(
+            peek_hello_version(&FrameToEsp::Hello(hello()).encode()),
+            Some(PROTOCOL_VERSION)
+        );
+    }
+
+    #[test]
+    fn different_version_is_rejected() {
+        let old = Hello {
+            protocol_version: PROTOCOL_VERSION + 1,
+            capabilities: vec![],
+            machines: vec![],
+        };
+        assert_eq!(
+            old.check_version(),
+            Err(ProtocolError::VersionMismatch {
+                expected: PROTOCOL_VERSION,
+                actual: PROTOCOL_VERSION + 1,
+            })
+        );
+    }
+
+    #[test]
+    fn undecodable_hello_from_other_version_is_a_version_mismatch() {
+        #[derive(Serialize)]
+        enum FutureFrame {
+            Hello {
+                protocol_version: u16,
+                new_field: u64,
+            },
+        }
+        let bytes = postcard::to_allocvec(&FutureFrame::Hello {
+            protocol_version: PROTOCOL_VERSION + 1,
+            // Not a valid `Vec<Capability>`
+            new_field: u64::MAX,
+        })
+        .unwrap();
+        let expected = ProtocolError::VersionMismatch {
+            expected: PROTOCOL_VERSION,
+            actual: PROTOCOL_VERSION + 1,
+        };
+        assert_eq!(FrameToEsp::decode(&bytes), Err(expected));
+        assert_eq!(FrameToWeb::decode(&bytes), Err(expected));
+    }
+
+    #[test]
+    fn garbage_is_malformed() {
+        assert_eq!(FrameToEsp::decode(&[]), Err(ProtocolError::Malformed));
+        assert_eq!(
+            FrameToWeb::decode(&[0xff; 3]),
+            Err(ProtocolError::Malformed)
+        );
+        // A request frame is not a hello
+        let request = FrameToEsp::Request {
+            id: 1,
+            machine: 0,
+            message: MessageToEsp::LongPressPowerButton,
+        };
+        assert_eq!(peek_hello_version(&request.encode()), None);
+    }
+}
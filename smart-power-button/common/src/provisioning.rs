@@ -0,0 +1,479 @@
+//! Wi-Fi and GPIO pin settings that are set at runtime instead of being compiled in.
+//!
+//! When the ESP has no Wi-Fi networks saved, or it can't connect to any of them, or the pins
+//! don't work, it starts its own access point with a captive portal. The portal page posts a
+//! [`ProvisioningForm`], which the ESP saves in NVS before restarting.
+use std::collections::HashMap;
+use std::net::Ipv4Addr;
+
+use serde::{Deserialize, Serialize};
+
+/// The longest SSID that Wi-Fi allows, in bytes
+pub const MAX_SSID_LEN: usize = 32;
+/// WPA passwords must be this long, in bytes
+pub const PASSWORD_LEN: std::ops::RangeInclusive<usize> = 8..=63;
+/// The longest hostname that the ESP allows
+pub const MAX_HOSTNAME_LEN: usize = 30;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum WifiSecurity {
+    /// Uses what the network says it uses when scanning. If it wasn't found, it's WPA2 if there is
+    /// a password and open if there isn't.
+    #[default]
+    Auto,
+    Open,
+    Wpa2,
+    Wpa3,
+}
+
+impl WifiSecurity {
+    fn parse(value: &str) -> Option<Self> {
+        match value {
+            "" | "auto" => Some(Self::Auto),
+            "open" => Some(Self::Open),
+            "wpa2" => Some(Self::Wpa2),
+            "wpa3" => Some(Self::Wpa3),
+            _ => None,
+        }
+    }
+}
+
+/// IPv4 settings to use instead of DHCP
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StaticIp {
+    pub ip: Ipv4Addr,
+    /// Like `24` for `255.255.255.0`
+    pub prefix_len: u8,
+    pub gateway: Ipv4Addr,
+    pub dns: Option<Ipv4Addr>,
+}
+
+impl StaticIp {
+    pub fn is_valid(&self) -> bool {
+        (1..=32).contains(&self.prefix_len)
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct WifiNetwork {
+    pub ssid: String,
+    /// Empty for open networks
+    pub password: String,
+    /// Networks with a higher priority are tried first. If more than one network with the same
+    /// priority is in range, the strongest one is tried first.
+    pub priority: u8,
+    pub security: WifiSecurity,
+    /// `None` uses DHCP
+    pub static_ip: Option<StaticIp>,
+}
+
+impl WifiNetwork {
+    pub fn is_valid(&self) -> bool {
+        !self.ssid.is_empty()
+            && self.ssid.len() <= MAX_SSID_LEN
+            && (self.password.is_empty() || PASSWORD_LEN.contains(&self.password.len()))
+            && !(self.security == WifiSecurity::Open && !self.password.is_empty())
+            && !(matches!(self.security, WifiSecurity::Wpa2 | WifiSecurity::Wpa3)
+                && self.password.is_empty())
+            && self.static_ip.as_ref().is_none_or(StaticIp::is_valid)
+    }
+
+    /// What to connect with. `scanned` is what the network looked like when scanning, if it was
+    /// found.
+    pub fn security_for(&self, scanned: Option<&ScannedNetwork>) -> WifiSecurity {
+        match (self.security, scanned.map(|scanned| scanned.security)) {
+            (WifiSecurity::Auto, Some(security)) if security != WifiSecurity::Auto => security,
+            (WifiSecurity::Auto, _) => match self.password.is_empty() {
+                true => WifiSecurity::Open,
+                false => WifiSecurity::Wpa2,
+            },
+            (security, _) => security,
+        }
+    }
+}
+
+pub fn is_valid_hostname(hostname: &str) -> bool {
+    !hostname.is_empty()
+        && hostname.len() <= MAX_HOSTNAME_LEN
+        && hostname
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-')
+        && !hostname.starts_with('-')
+        && !hostname.ends_with('-')
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Default)]
+pub struct WifiConfig {
+    pub networks: Vec<WifiNetwork>,
+    /// Empty uses the ESP's default hostname
+    pub hostname: String,
+}
+
+impl WifiConfig {
+    pub fn is_valid(&self) -> bool {
+        self.networks.iter().all(WifiNetwork::is_valid)
+            && (self.hostname.is_empty() || is_valid_hostname(&self.hostname))
+    }
+}
+
+/// GPIO pin numbers
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PinConfig {
+    pub power_led: u8,
+    pub hdd_led: u8,
+    pub power_button: u8,
+    pub reset_button: u8,
+}
+
+impl PinConfig {
+    pub fn is_valid(&self) -> bool {
+        let pins = [
+            self.power_led,
+            self.hdd_led,
+            self.power_button,
+            self.reset_button,
+        ];
+        pins.iter()
+            .enumerate()
+            .all(|(i, pin)| !pins[i + 1..].contains(pin))
+    }
+}
+
+impl Default for PinConfig {
+    /// The pins on the ESP32-C3 in the wiring diagram
+    fn default() -> Self {
+        Self {
+            power_led: 9,
+            hdd_led: 0,
+            power_button: 21,
+            reset_button: 8,
+        }
+    }
+}
+
+/// A network that the ESP found while scanning
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct ScannedNetwork {
+    pub ssid: String,
+    /// In dBm
+    pub signal_strength: i8,
+    /// [`WifiSecurity::Auto`] if it's something else, like WEP or WPA2 Enterprise
+    pub security: WifiSecurity,
+}
+
+/// What the portal page posts, as `application/x-www-form-urlencoded`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProvisioningForm {
+    pub wifi: WifiConfig,
+    pub pins: PinConfig,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ProvisioningFormError {
+    NoNetworks,
+    InvalidNetwork(String),
+    InvalidNetworkField { ssid: String, field: &'static str },
+    InvalidHostname,
+    MissingPin(&'static str),
+    InvalidPin(&'static str),
+    DuplicatePins,
+}
+
+impl std::fmt::Display for ProvisioningFormError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::NoNetworks => write!(f, "Enter at least one Wi-Fi network"),
+            Self::InvalidNetwork(ssid) => write!(
+                f,
+                "The network {ssid:?} is invalid. SSIDs can be at most {MAX_SSID_LEN} bytes. Passwords must be {} to {} bytes, or empty for open networks.",
+                PASSWORD_LEN.start(),
+                PASSWORD_LEN.end()
+            ),
+            Self::InvalidNetworkField { ssid, field } => {
+                write!(f, "The {field} for the network {ssid:?} is invalid")
+            }
+            Self::InvalidHostname => write!(
+                f,
+                "The hostname can be at most {MAX_HOSTNAME_LEN} letters, numbers, and dashes"
+            ),
+            Self::MissingPin(name) => write!(f, "Enter a pin number for {name}"),
+            Self::InvalidPin(name) => write!(f, "The pin number for {name} is invalid"),
+            Self::DuplicatePins => write!(f, "Each pin can only be used once"),
+        }
+    }
+}
+
+impl std::error::Error for ProvisioningFormError {}
+
+/// Parses an optional field. Empty values are `None`.
+fn parse_field<T: std::str::FromStr>(
+    fields: &HashMap<String, String>,
+    ssid: &str,
+    field: &'static str,
+) -> Result<Option<T>, ProvisioningFormError> {
+    match fields.get(field).map(|value| value.trim()) {
+        None | Some("") => Ok(None),
+        Some(value) => {
+            value
+                .parse()
+                .map(Some)
+                .map_err(|_| ProvisioningFormError::InvalidNetworkField {
+                    ssid: ssid.into(),
+                    field,
+                })
+        }
+    }
+}
+
+fn parse_network(
+    ssid: String,
+    fields: HashMap<String, String>,
+) -> Result<WifiNetwork, ProvisioningFormError> {
+    let invalid_field = |field| ProvisioningFormError::InvalidNetworkField {
+        ssid: ssid.clone(),
+        field,
+    };
+    let security = WifiSecurity::parse(fields.get("security").map_or("", String::as_str))
+        .ok_or(invalid_field("security"))?;
+    let static_ip = match parse_field(&fields, &ssid, "ip")? {
+        Some(ip) => Some(StaticIp {
+            ip,
+            prefix_len: parse_field(&fields, &ssid, "prefix_len")?
+                .ok_or(invalid_field("prefix_len"))?,
+            gateway: parse_field(&fields, &ssid, "gateway")?.ok_or(invalid_field("gateway"))?,
+            dns: parse_field(&fields, &ssid, "dns")?,
+        }),
+        None => None,
+    };
+    if static_ip.is_some_and(|static_ip| !static_ip.is_valid()) {
+        return Err(invalid_field("prefix_len"));
+    }
+    let network = WifiNetwork {
+        priority: parse_field(&fields, &ssid, "priority")?.unwrap_or_default(),
+        password: fields.get("password").cloned().unwrap_or_default(),
+        security,
+        static_ip,
+        ssid,
+    };
+    match network.is_valid() {
+        true => Ok(network),
+        false => Err(ProvisioningFormError::InvalidNetwork(network.ssid)),
+    }
+}
+
+impl ProvisioningForm {
+    /// Each network starts with an `ssid` field. The fields after it, like `password` and
+    /// `priority`, are for that network. Networks with an empty SSID are left out, so the page can
+    /// always show a few rows.
+    pub fn parse(body: &[u8]) -> Result<Self, ProvisioningFormError> {
+        let mut networks = Vec::<(String, HashMap<String, String>)>::new();
+        let mut hostname = String::new();
+        let mut pins: [(&'static str, Option<u8>); 4] = [
+            ("power_led", None),
+            ("hdd_led", None),
+            ("power_button", None),
+            ("reset_button", None),
+        ];
+        for (key, value) in form_urlencoded::parse(body) {
+            match key.as_ref() {
+                "ssid" => networks.push((value.into_owned(), HashMap::new())),
+                "hostname" => hostname = value.trim().to_owned(),
+                key => {
+                    if let Some((name, pin)) = pins.iter_mut().find(|(name, _)| *name == key) {
+                        *pin = Some(
+                            value
+                                .trim()
+                                .parse()
+                                .map_err(|_| ProvisioningFormError::InvalidPin(name))?,
+                        );
+                    } else if let Some((_, fields)) = networks.last_mut() {
+                        fields.insert(key.to_owned(), value.into_owned());
+                    }
+                }
+            }
+        }
+        let networks = networks
+            .into_iter()
+            .filter(|(ssid, _)| !ssid.is_empty())
+            .map(|(ssid, fields)| parse_network(ssid, fields))
+            .collect::<Result<Vec<_>, _>>()?;
+        if networks.is_empty() {
+            return Err(ProvisioningFormError::NoNetworks);
+        }
+        if !hostname.is_empty() && !is_valid_hostname(&hostname) {
+            return Err(ProvisioningFormError::InvalidHostname);
+        }
+        let [power_led, hdd_led, power_button, reset_button] =
+            pins.map(|(name, pin)| pin.ok_or(ProvisioningFormError::MissingPin(name)));
+        let pins = PinConfig {
+            power_led: power_led?,
+            hdd_led: hdd_led?,
+            power_button: power_button?,
+            reset_button: reset_button?,
+        };
+        if !pins.is_valid() {
+            return Err(ProvisioningFormError::DuplicatePins);
+        }
+        Ok(Self {
+            wifi: WifiConfig { networks, hostname },
+            pins,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PINS: &str = "power_led=9&hdd_led=0&power_button=21&reset_button=8";
+
+    fn network(ssid: &str, password: &str) -> WifiNetwork {
+        WifiNetwork {
+            ssid: ssid.into(),
+            password: password.into(),
+            priority: 0,
+            security: WifiSecurity::Auto,
+            static_ip: None,
+        }
+    }
+
+    #[test]
+    fn networks_are_parsed_in_order() {
+        let form = ProvisioningForm::parse(
+            format!(
+                "ssid=Home+Wi-Fi&password=hunter22&priority=2&security=wpa3\
+&ssid=&password=&ssid=Caf%C3%A9&password=&priority=&security=&hostname=gaming-pc&{PINS}"
+            )
+            .as_bytes(),
+        )
+        .unwrap();
+        assert_eq!(
+            form.wifi.networks,
+            vec![
+                WifiNetwork {
+                    priority: 2,
+                    security: WifiSecurity::Wpa3,
+                    ..network("Home Wi-Fi", "hunter22")
+                },
+                network("Café", ""),
+            ]
+        );
+        assert_eq!(form.wifi.hostname, "gaming-pc");
+        assert_eq!(form.pins, PinConfig::default());
+    }
+
+    #[test]
+    fn static_ip_is_parsed() {
+        let form = ProvisioningForm::parse(
+            format!("ssid=Lab&ip=10.0.0.5&prefix_len=8&gateway=10.0.0.1&dns=&{PINS}").as_bytes(),
+        )
+        .unwrap();
+        assert_eq!(
+            form.wifi.networks[0].static_ip,
+            Some(StaticIp {
+                ip: Ipv4Addr::new(10, 0, 0, 5),
+                prefix_len: 8,
+                gateway: Ipv4Addr::new(10, 0, 0, 1),
+                dns: None,
+            })
+        );
+        assert_eq!(
+            ProvisioningForm::parse(format!("ssid=Lab&ip=10.0.0.5&prefix_len=8&{PINS}").as_bytes()),
+            Err(ProvisioningFormError::InvalidNetworkField {
+                ssid: "Lab".into(),
+                field: "gateway"
+            })
+        );
+        assert_eq!(
+            ProvisioningForm::parse(
+                format!("ssid=Lab&ip=10.0.0.5&prefix_len=33&gateway=10.0.0.1&{PINS}").as_bytes()
+            ),
+            Err(ProvisioningFormError::InvalidNetworkField {
+                ssid: "Lab".into(),
+                field: "prefix_len"
+            })
+        );
+    }
+
+    #[test]
+    fn at_least_one_network_is_needed() {
+        assert_eq!(
+            ProvisioningForm::parse(format!("ssid=&password=&{PINS}").as_bytes()),
+            Err(ProvisioningFormError::NoNetworks)
+        );
+    }
+
+    #[test]
+    fn invalid_networks_are_rejected() {
+        assert_eq!(
+            ProvisioningForm::parse(format!("ssid=a&password=1234567&{PINS}").as_bytes()),
+            Err(ProvisioningFormError::InvalidNetwork("a".into()))
+        );
+        assert_eq!(
+            ProvisioningForm::parse(format!("ssid=a&security=wpa3&{PINS}").as_bytes()),
+            Err(ProvisioningFormError::InvalidNetwork("a".into()))
+        );
+        assert_eq!(
+            ProvisioningForm::parse(format!("ssid=a&security=wep&{PINS}").as_bytes()),
+            Err(ProvisioningFormError::InvalidNetworkField {
+                ssid: "a".into(),
+                field: "security"
+            })
+        );
+        assert!(!network(&"a".repeat(MAX_SSID_LEN + 1), "").is_valid());
+    }
+
+    #[test]
+    fn hostnames_are_checked() {
+        assert!(is_valid_hostname("gaming-pc-2"));
+        assert!(!is_valid_hostname("-gaming"));
+        assert!(!is_valid_hostname("gaming pc"));
+        assert!(!is_valid_hostname(&"a".repeat(MAX_HOSTNAME_LEN + 1)));
+        assert_eq!(
+            ProvisioningForm::parse(format!("ssid=a&hostname=a.b&{PINS}").as_bytes()),
+            Err(ProvisioningFormError::InvalidHostname)
+        );
+    }
+
+    #[test]
+    fn auto_security_uses_scan() {
+        let scanned = ScannedNetwork {
+            ssid: "a".into(),
+            signal_strength: -50,
+            security: WifiSecurity::Wpa3,
+        };
+        assert_eq!(
+            network("a", "hunter22").security_for(Some(&scanned)),
+            WifiSecurity::Wpa3
+        );
+        assert_eq!(
+            network("a", "hunter22").security_for(None),
+            WifiSecurity::Wpa2
+        );
+        assert_eq!(network("a", "").security_for(None), WifiSecurity::Open);
+        assert_eq!(
+            WifiNetwork {
+                security: WifiSecurity::Wpa2,
+                ..network("a", "hunter22")
+            }
+            .security_for(Some(&scanned)),
+            WifiSecurity::Wpa2
+        );
+    }
+
+    #[test]
+    fn pins_are_checked() {
+        assert_eq!(
+            ProvisioningForm::parse(b"ssid=a&power_led=1&hdd_led=2&power_button=3"),
+            Err(ProvisioningFormError::MissingPin("reset_button"))
+        );
+        assert_eq!(
+            ProvisioningForm::parse(b"ssid=a&power_led=1&hdd_led=x&power_button=3&reset_button=4"),
+            Err(ProvisioningFormError::InvalidPin("hdd_led"))
+        );
+        assert_eq!(
+            ProvisioningForm::parse(b"ssid=a&power_led=1&hdd_led=2&power_button=3&reset_button=1"),
+            Err(ProvisioningFormError::DuplicatePins)
+        );
+    }
+}
@@ -0,0 +1,40 @@
+//! The JSON bodies of the REST endpoints, for scripts and `curl`. The web page uses the WebSocket
+//! instead. `openapi.yaml` describes all the endpoints.
+use serde::{Deserialize, Serialize};
+
+use crate::PowerState;
+
+/// Sent by `GET /status`
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Status {
+    pub power_led: bool,
+    pub hdd_led: bool,
+    pub power_button_pressed: bool,
+    pub reset_button_pressed: bool,
+    pub power_state: PowerState,
+}
+
+/// The body of `POST /power/short`. The body can also be empty.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct ShortPressRequest {
+    /// If the TV should be turned on if this turns on the computer
+    #[serde(default)]
+    pub turn_on_tv: bool,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn short_press_fields_are_optional() {
+        assert_eq!(
+            serde_json::from_str::<ShortPressRequest>("{}").unwrap(),
+            ShortPressRequest { turn_on_tv: false }
+        );
+        assert_eq!(
+            serde_json::from_str::<ShortPressRequest>(r#"{"turn_on_tv":true}"#).unwrap(),
+            ShortPressRequest { turn_on_tv: true }
+        );
+    }
+}
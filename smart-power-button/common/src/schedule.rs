@@ -0,0 +1,195 @@
+//! Actions that the ESP does by itself at certain times, like turning on the computer at 07:00 on
+//! weekdays.
+use serde::{Deserialize, Serialize};
+
+use crate::time_zone::TimeZone;
+use crate::MessageToEsp;
+
+/// A set of days of the week. Bit 0 is Monday and bit 6 is Sunday.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Weekdays(pub u8);
+
+impl Weekdays {
+    pub const MONDAY_TO_FRIDAY: Self = Self(0b0011111);
+    pub const WEEKEND: Self = Self(0b1100000);
+    pub const EVERY_DAY: Self = Self(0b1111111);
+
+    /// `day` is the number of days since Monday
+    pub fn contains(self, day: u8) -> bool {
+        day < 7 && self.0 & (1 << day) != 0
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LocalTime {
+    /// The number of days since Monday
+    pub day: u8,
+    pub hour: u8,
+    pub minute: u8,
+    pub second: u8,
+}
+
+impl LocalTime {
+    /// The time in UTC
+    pub fn from_unix_time(seconds: u64) -> Self {
+        let days = seconds / (24 * 60 * 60);
+        let second_of_day = seconds % (24 * 60 * 60);
+        Self {
+            // 1970-01-01 was a Thursday
+            day: ((days + 3) % 7) as u8,
+            hour: (second_of_day / 3600) as u8,
+            minute: (second_of_day / 60 % 60) as u8,
+            second: (second_of_day % 60) as u8,
+        }
+    }
+}
+
+/// Lets rules only press the button when it would do what the rule is for. For example, a short
+/// press to wake up the computer would shut it down if it's already on.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum RunWhen {
+    #[default]
+    Always,
+    ComputerOn,
+    /// Off or suspended
+    ComputerOff,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ScheduleRule {
+    /// Disabled rules are kept, but they don't do anything
+    pub enabled: bool,
+    pub days: Weekdays,
+    /// In the schedule's time zone
+    pub hour: u8,
+    pub minute: u8,
+    pub run_when: RunWhen,
+    pub action: MessageToEsp,
+}
+
+impl ScheduleRule {
+    pub fn is_valid(&self) -> bool {
+        self.hour < 24 && self.minute < 60 && self.action.validate().is_ok()
+    }
+
+    /// `day` is the number of days since Monday
+    pub fn is_due(&self, day: u8, hour: u8, minute: u8) -> bool {
+        self.enabled && self.days.contains(day) && self.hour == hour && self.minute == minute
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+pub struct Schedule {
+    /// A POSIX `TZ` string, like `EST5EDT,M3.2.0,M11.1.0`. Empty means UTC. Also used for the
+    /// time windows of this machine's Bluetooth wakeup rules.
+    pub time_zone: String,
+    pub rules: Vec<ScheduleRule>,
+}
+
+impl Schedule {
+    pub fn is_valid(&self) -> bool {
+        TimeZone::parse(&self.time_zone).is_some() && self.rules.iter().all(ScheduleRule::is_valid)
+    }
+
+    /// UTC if [`Schedule::time_zone`] isn't valid
+    pub fn time_zone(&self) -> TimeZone {
+        TimeZone::parse(&self.time_zone).unwrap_or(TimeZone::UTC)
+    }
+
+    /// The rules that should run at this local time. `day` is the number of days since Monday.
+    pub fn due_rules(&self, day: u8, hour: u8, minute: u8) -> impl Iterator<Item = &ScheduleRule> {
+        self.rules
+            .iter()
+            .filter(move |rule| rule.is_due(day, hour, minute))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+    use crate::press::{ButtonKind, PressPattern};
+
+    fn rule(days: Weekdays, hour: u8, minute: u8) -> ScheduleRule {
+        ScheduleRule {
+            enabled: true,
+            days,
+            hour,
+            minute,
+            run_when: RunWhen::ComputerOff,
+            action: MessageToEsp::ShortPressPowerButton(false),
+        }
+    }
+
+    #[test]
+    fn weekdays() {
+        assert!(Weekdays::MONDAY_TO_FRIDAY.contains(0));
+        assert!(Weekdays::MONDAY_TO_FRIDAY.contains(4));
+        assert!(!Weekdays::MONDAY_TO_FRIDAY.contains(5));
+        assert!(Weekdays::WEEKEND.contains(6));
+        assert!(!Weekdays::EVERY_DAY.contains(7));
+        assert!(!Weekdays::default().contains(0));
+    }
+
+    #[test]
+    fn unix_time_to_utc() {
+        // 2024-01-01 was a Monday
+        assert_eq!(
+            LocalTime::from_unix_time(1_704_067_200 + 7 * 3600 + 30 * 60 + 5),
+            LocalTime {
+                day: 0,
+                hour: 7,
+                minute: 30,
+                second: 5
+            }
+        );
+        assert_eq!(LocalTime::from_unix_time(0).day, 3);
+    }
+
+    #[test]
+    fn rules_are_due_at_their_time() {
+        let schedule = Schedule {
+            time_zone: "".into(),
+            rules: vec![
+                rule(Weekdays::MONDAY_TO_FRIDAY, 7, 0),
+                ScheduleRule {
+                    enabled: false,
+                    ..rule(Weekdays::EVERY_DAY, 7, 0)
+                },
+                rule(Weekdays::EVERY_DAY, 2, 0),
+            ],
+        };
+        assert_eq!(schedule.due_rules(0, 7, 0).count(), 1);
+        assert_eq!(schedule.due_rules(5, 7, 0).count(), 0);
+        assert_eq!(schedule.due_rules(0, 7, 1).count(), 0);
+        assert_eq!(schedule.due_rules(6, 2, 0).count(), 1);
+    }
+
+    #[test]
+    fn invalid_times_are_rejected() {
+        assert!(rule(Weekdays::EVERY_DAY, 23, 59).is_valid());
+        assert!(!rule(Weekdays::EVERY_DAY, 24, 0).is_valid());
+        assert!(!rule(Weekdays::EVERY_DAY, 0, 60).is_valid());
+        assert!(!Schedule {
+            time_zone: "UTC\0".into(),
+            rules: vec![],
+        }
+        .is_valid());
+        assert!(!Schedule {
+            time_zone: "Europe/Berlin".into(),
+            rules: vec![],
+        }
+        .is_valid());
+    }
+
+    #[test]
+    fn invalid_press_patterns_are_rejected() {
+        let mut rule = rule(Weekdays::EVERY_DAY, 7, 0);
+        rule.action = MessageToEsp::Press {
+            button: ButtonKind::Power,
+            pattern: PressPattern::hold(Duration::from_secs(60)),
+        };
+        assert!(!rule.is_valid());
+    }
+}
@@ -0,0 +1,391 @@
+//! POSIX `TZ` strings, like `EST5EDT,M3.2.0,M11.1.0`. They're worked out here instead of with the
+//! `TZ` environment variable, so that each machine's schedule can have its own time zone.
+use crate::schedule::LocalTime;
+
+const DAY: i64 = 24 * 60 * 60;
+const HOUR: i64 = 60 * 60;
+
+/// The day that daylight saving time starts or ends on
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Date {
+    /// `Jn`: day 1 to 365, not counting February 29
+    Julian(u16),
+    /// `n`: day 0 to 365, counting February 29
+    DayOfYear(u16),
+    /// `Mm.w.d`: weekday `d` (0 is Sunday) of week `w` (5 is the last one) of month `m`
+    MonthWeekDay { month: u8, week: u8, weekday: u8 },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Transition {
+    date: Date,
+    /// Seconds after midnight in the local time before the transition
+    time: i64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct DaylightSaving {
+    /// Seconds east of UTC
+    offset: i64,
+    start: Transition,
+    end: Transition,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TimeZone {
+    /// Seconds east of UTC, which is the opposite sign of the offset in the `TZ` string
+    offset: i64,
+    daylight_saving: Option<DaylightSaving>,
+}
+
+impl TimeZone {
+    pub const UTC: Self = Self {
+        offset: 0,
+        daylight_saving: None,
+    };
+
+    /// `None` if `tz` isn't a POSIX `TZ` string. Empty means UTC.
+    pub fn parse(tz: &str) -> Option<Self> {
+        if tz.is_empty() {
+            return Some(Self::UTC);
+        }
+        let mut parser = Parser(tz);
+        parser.name()?;
+        let offset = -parser.time(24)?;
+        if parser.is_empty() {
+            return Some(Self {
+                offset,
+                daylight_saving: None,
+            });
+        }
+        parser.name()?;
+        let daylight_offset = match parser.0.starts_with(',') || parser.is_empty() {
+            true => offset + HOUR,
+            false => -parser.time(24)?,
+        };
+        let (start, end) = if parser.is_empty() {
+            // The US rules, which glibc also uses when there are none
+            (
+                Transition {
+                    date: Date::MonthWeekDay {
+                        month: 3,
+                        week: 2,
+                        weekday: 0,
+                    },
+                    time: 2 * HOUR,
+                },
+                Transition {
+                    date: Date::MonthWeekDay {
+                        month: 11,
+                        week: 1,
+                        weekday: 0,
+                    },
+                    time: 2 * HOUR,
+                },
+            )
+        } else {
+            parser.expect(',')?;
+            let start = parser.transition()?;
+            parser.expect(',')?;
+            (start, parser.transition()?)
+        };
+        parser.is_empty().then_some(Self {
+            offset,
+            daylight_saving: Some(DaylightSaving {
+                offset: daylight_offset,
+                start,
+                end,
+            }),
+        })
+    }
+
+    /// The local time at `seconds` since the Unix epoch
+    pub fn local_time(&self, seconds: u64) -> LocalTime {
+        let utc = seconds as i64;
+        let offset = match self.daylight_saving {
+            Some(daylight_saving) if daylight_saving.is_active(utc, self.offset) => {
+                daylight_saving.offset
+            }
+            _ => self.offset,
+        };
+        LocalTime::from_unix_time((utc + offset).max(0) as u64)
+    }
+}
+
+impl DaylightSaving {
+    fn is_active(&self, utc: i64, standard_offset: i64) -> bool {
+        let year = year_of_day((utc + standard_offset).div_euclid(DAY));
+        let start = self.start.unix_time(year) - standard_offset;
+        let end = self.end.unix_time(year) - self.offset;
+        if start < end {
+            start <= utc && utc < end
+        } else {
+            // In the southern hemisphere it's on over new year
+            utc < end || start <= utc
+        }
+    }
+}
+
+impl Transition {
+    /// In local time
+    fn unix_time(&self, year: i64) -> i64 {
+        self.date.day(year) * DAY + self.time
+    }
+}
+
+impl Date {
+    /// Days since the Unix epoch
+    fn day(&self, year: i64) -> i64 {
+        let january_1 = days_from_civil(year, 1, 1);
+        match *self {
+            Date::Julian(day) => january_1 + day as i64 - 1 + (is_leap(year) && day >= 60) as i64,
+            Date::DayOfYear(day) => january_1 + day as i64,
+            Date::MonthWeekDay {
+                month,
+                week,
+                weekday,
+            } => {
+                let first = days_from_civil(year, month as i64, 1);
+                let next_month = match month {
+                    12 => days_from_civil(year + 1, 1, 1),
+                    month => days_from_civil(year, month as i64 + 1, 1),
+                };
+                // 1970-01-01 was a Thursday
+                let first_weekday = (first + 4).rem_euclid(7);
+                let mut day =
+                    first + (weekday as i64 - first_weekday).rem_euclid(7) + (week as i64 - 1) * 7;
+                // Week 5 means the last one, which can be the fourth
+                while day >= next_month {
+                    day -= 7;
+                }
+                day
+            }
+        }
+    }
+}
+
+fn is_leap(year: i64) -> bool {
+    year % 4 == 0 && (year % 100 != 0 || year % 400 == 0)
+}
+
+/// Days since the Unix epoch, from <https://howardhinnant.github.io/date_algorithms.html>
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146_097 + day_of_era - 719_468
+}
+
+/// The year of a day since the Unix epoch, from the same place as [`days_from_civil`]
+fn year_of_day(days: i64) -> i64 {
+    let days = days + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days - era * 146_097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    // Starts from March
+    let month = (5 * day_of_year + 2) / 153;
+    year_of_era + era * 400 + (month >= 10) as i64
+}
+
+/// What's left of a `TZ` string
+struct Parser<'a>(&'a str);
+
+impl Parser<'_> {
+    fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    fn expect(&mut self, c: char) -> Option<()> {
+        self.0 = self.0.strip_prefix(c)?;
+        Some(())
+    }
+
+    /// A name like `EST`, or `<+03>` for names that aren't letters
+    fn name(&mut self) -> Option<()> {
+        let len = match self.0.strip_prefix('<') {
+            Some(rest) => {
+                let end = rest.find('>')?;
+                if !rest[..end]
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || c == '+' || c == '-')
+                {
+                    return None;
+                }
+                self.0 = &rest[end + 1..];
+                end
+            }
+            None => {
+                let end = self
+                    .0
+                    .find(|c: char| !c.is_ascii_alphabetic())
+                    .unwrap_or(self.0.len());
+                self.0 = &self.0[end..];
+                end
+            }
+        };
+        (len >= 3).then_some(())
+    }
+
+    fn number(&mut self, max: i64) -> Option<i64> {
+        let end = self
+            .0
+            .find(|c: char| !c.is_ascii_digit())
+            .unwrap_or(self.0.len());
+        if end == 0 || end > 3 {
+            return None;
+        }
+        let number = self.0[..end].parse().ok()?;
+        self.0 = &self.0[end..];
+        (number <= max).then_some(number)
+    }
+
+    /// `[+-]hh[:mm[:ss]]` in seconds
+    fn time(&mut self, max_hours: i64) -> Option<i64> {
+        let sign = match self.0.chars().next() {
+            Some('-') => -1,
+            _ => 1,
+        };
+        self.0 = self.0.strip_prefix(['+', '-']).unwrap_or(self.0);
+        let mut seconds = self.number(max_hours)? * HOUR;
+        if self.expect(':').is_some() {
+            seconds += self.number(59)? * 60;
+            if self.expect(':').is_some() {
+                seconds += self.number(59)?;
+            }
+        }
+        Some(sign * seconds)
+    }
+
+    /// A date with an optional time, which is 02:00 if it's left out
+    fn transition(&mut self) -> Option<Transition> {
+        let date = if self.expect('J').is_some() {
+            Date::Julian(self.number(365).filter(|&day| day >= 1)? as u16)
+        } else if self.expect('M').is_some() {
+            let month = self.number(12).filter(|&month| month >= 1)? as u8;
+            self.expect('.')?;
+            let week = self.number(5).filter(|&week| week >= 1)? as u8;
+            self.expect('.')?;
+            let weekday = self.number(6)? as u8;
+            Date::MonthWeekDay {
+                month,
+                week,
+                weekday,
+            }
+        } else {
+            Date::DayOfYear(self.number(365)? as u16)
+        };
+        let time = match self.expect('/') {
+            // Extended past a day, like in RFC 8536
+            Some(()) => self.time(167)?,
+            None => 2 * HOUR,
+        };
+        Some(Transition { date, time })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Seconds since the Unix epoch for a UTC time
+    fn utc(year: i64, month: i64, day: i64, hour: i64, minute: i64) -> u64 {
+        (days_from_civil(year, month, day) * DAY + hour * HOUR + minute * 60) as u64
+    }
+
+    fn hour_and_minute(tz: &str, seconds: u64) -> (u8, u8) {
+        let time = TimeZone::parse(tz).unwrap().local_time(seconds);
+        (time.hour, time.minute)
+    }
+
+    #[test]
+    fn dates() {
+        assert_eq!(days_from_civil(1970, 1, 1), 0);
+        assert_eq!(days_from_civil(2024, 1, 1), 19_723);
+        assert_eq!(year_of_day(19_723), 2024);
+        assert_eq!(year_of_day(19_722), 2023);
+        assert_eq!(year_of_day(days_from_civil(2024, 12, 31)), 2024);
+        assert_eq!(year_of_day(-1), 1969);
+    }
+
+    #[test]
+    fn fixed_offsets() {
+        assert_eq!(TimeZone::parse(""), Some(TimeZone::UTC));
+        assert_eq!(TimeZone::parse("UTC0"), Some(TimeZone::UTC));
+        let noon = utc(2024, 6, 1, 12, 0);
+        assert_eq!(hour_and_minute("JST-9", noon), (21, 0));
+        assert_eq!(hour_and_minute("<+0530>-5:30", noon), (17, 30));
+        assert_eq!(hour_and_minute("<-03>3", noon), (9, 0));
+        // Going back a day also goes back a weekday
+        let time = TimeZone::parse("HST10")
+            .unwrap()
+            .local_time(utc(2024, 1, 1, 5, 0));
+        assert_eq!((time.day, time.hour), (6, 19));
+    }
+
+    #[test]
+    fn northern_daylight_saving() {
+        let new_york = "EST5EDT,M3.2.0,M11.1.0";
+        assert_eq!(hour_and_minute(new_york, utc(2024, 1, 15, 12, 0)), (7, 0));
+        assert_eq!(hour_and_minute(new_york, utc(2024, 7, 15, 12, 0)), (8, 0));
+        // 2024-03-10 02:00 EST is 07:00 UTC
+        assert_eq!(hour_and_minute(new_york, utc(2024, 3, 10, 6, 59)), (1, 59));
+        assert_eq!(hour_and_minute(new_york, utc(2024, 3, 10, 7, 0)), (3, 0));
+        // 2024-11-03 02:00 EDT is 06:00 UTC
+        assert_eq!(hour_and_minute(new_york, utc(2024, 11, 3, 5, 59)), (1, 59));
+        assert_eq!(hour_and_minute(new_york, utc(2024, 11, 3, 6, 0)), (1, 0));
+        // The rules and the daylight saving offset can be left out
+        assert_eq!(
+            TimeZone::parse("EST5EDT"),
+            TimeZone::parse("EST5EDT4,M3.2.0/2,M11.1.0/02:00:00")
+        );
+        // The last Sunday of March and October, at 01:00 UTC
+        let berlin = "CET-1CEST,M3.5.0,M10.5.0/3";
+        assert_eq!(hour_and_minute(berlin, utc(2024, 3, 31, 0, 59)), (1, 59));
+        assert_eq!(hour_and_minute(berlin, utc(2024, 3, 31, 1, 0)), (3, 0));
+        assert_eq!(hour_and_minute(berlin, utc(2024, 10, 27, 0, 59)), (2, 59));
+        assert_eq!(hour_and_minute(berlin, utc(2024, 10, 27, 1, 0)), (2, 0));
+    }
+
+    #[test]
+    fn southern_daylight_saving() {
+        let sydney = "AEST-10AEDT,M10.1.0,M4.1.0/3";
+        assert_eq!(hour_and_minute(sydney, utc(2024, 1, 15, 0, 0)), (11, 0));
+        assert_eq!(hour_and_minute(sydney, utc(2024, 7, 15, 0, 0)), (10, 0));
+        assert_eq!(hour_and_minute(sydney, utc(2024, 12, 31, 14, 0)), (1, 0));
+    }
+
+    #[test]
+    fn julian_days() {
+        // Day 60 is always March 1 with `J`, and February 29 in leap years without it
+        let j = TimeZone::parse("AAA0BBB,J60/0,J300").unwrap();
+        let n = TimeZone::parse("AAA0BBB,59/0,300").unwrap();
+        let february_29 = utc(2024, 2, 29, 12, 0);
+        assert_eq!(j.local_time(february_29).hour, 12);
+        assert_eq!(n.local_time(february_29).hour, 13);
+        assert_eq!(j.local_time(utc(2024, 3, 1, 12, 0)).hour, 13);
+    }
+
+    #[test]
+    fn invalid_strings_are_rejected() {
+        for tz in [
+            "UTC",
+            "U0",
+            "UTC\0",
+            "EST5EDT,",
+            "EST5EDT,M3.2.0",
+            "EST5EDT,M13.1.0,M11.1.0",
+            "EST5EDT,M3.0.0,M11.1.0",
+            "EST5EDT,J0,J100",
+            "EST25",
+            "<+03",
+            "<+0 3>-3",
+            "EST5EDT,M3.2.0,M11.1.0x",
+        ] {
+            assert_eq!(TimeZone::parse(tz), None, "{tz:?}");
+        }
+    }
+}
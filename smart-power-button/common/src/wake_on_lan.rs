@@ -0,0 +1,101 @@
+//! Wake-on-LAN magic packets, which the ESP turns into power button presses.
+//!
+//! A magic packet has 6 `0xFF` bytes and then the MAC address 16 times. It's usually sent to UDP
+//! port 7 or 9, and it can be anywhere in the payload. Some tools add a SecureOn password after
+//! it, which is ignored.
+use serde::{Deserialize, Serialize};
+
+/// The UDP ports that Wake-on-LAN tools send to
+pub const PORTS: [u16; 2] = [7, 9];
+const SYNC: [u8; 6] = [0xFF; 6];
+const MAGIC_PACKET_LEN: usize = 6 + 16 * 6;
+
+/// Saved in NVS and set with `PUT /wake_on_lan`
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct WakeOnLanConfig {
+    /// The computer's MAC address, in the order that it's written. Wake-on-LAN is off if this is
+    /// `None`.
+    pub mac: Option<[u8; 6]>,
+}
+
+impl WakeOnLanConfig {
+    /// Network cards never have an all zero or multicast address
+    pub fn is_valid(&self) -> bool {
+        self.mac.is_none_or(|mac| mac != [0; 6] && mac[0] & 1 == 0)
+    }
+
+    /// Whether `payload` has a magic packet for the computer
+    pub fn is_for_computer(&self, payload: &[u8]) -> bool {
+        self.mac.is_some() && parse_magic_packet(payload) == self.mac
+    }
+}
+
+/// Finds the first magic packet in `payload` and returns its MAC address
+pub fn parse_magic_packet(payload: &[u8]) -> Option<[u8; 6]> {
+    payload.windows(MAGIC_PACKET_LEN).find_map(|window| {
+        let (sync, macs) = window.split_at(SYNC.len());
+        let mac: [u8; 6] = macs[..6].try_into().unwrap();
+        (sync == SYNC && macs.chunks_exact(6).all(|chunk| chunk == mac)).then_some(mac)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MAC: [u8; 6] = [0x00, 0x1A, 0x2B, 0x3C, 0x4D, 0x5E];
+
+    fn magic_packet(mac: [u8; 6]) -> Vec<u8> {
+        let mut packet = SYNC.to_vec();
+        for _ in 0..16 {
+            packet.extend_from_slice(&mac);
+        }
+        packet
+    }
+
+    #[test]
+    fn magic_packets_are_parsed() {
+        assert_eq!(parse_magic_packet(&magic_packet(MAC)), Some(MAC));
+        // With a header before it and a SecureOn password after it
+        let mut payload = b"header".to_vec();
+        payload.extend(magic_packet(MAC));
+        payload.extend_from_slice(&[1, 2, 3, 4, 5, 6]);
+        assert_eq!(parse_magic_packet(&payload), Some(MAC));
+        // Extra sync bytes before the MAC
+        let mut payload = vec![0xFF; 3];
+        payload.extend(magic_packet(MAC));
+        assert_eq!(parse_magic_packet(&payload), Some(MAC));
+    }
+
+    #[test]
+    fn broken_packets_are_ignored() {
+        let packet = magic_packet(MAC);
+        assert_eq!(parse_magic_packet(&packet[..packet.len() - 1]), None);
+        assert_eq!(parse_magic_packet(&[]), None);
+        let mut packet = magic_packet(MAC);
+        packet[50] ^= 1;
+        assert_eq!(parse_magic_packet(&packet), None);
+        let mut packet = magic_packet(MAC);
+        packet[0] = 0;
+        assert_eq!(parse_magic_packet(&packet), None);
+    }
+
+    #[test]
+    fn only_the_computers_mac_is_accepted() {
+        let config = WakeOnLanConfig { mac: Some(MAC) };
+        assert!(config.is_for_computer(&magic_packet(MAC)));
+        assert!(!config.is_for_computer(&magic_packet([1, 2, 3, 4, 5, 6])));
+        assert!(!WakeOnLanConfig::default().is_for_computer(&magic_packet(MAC)));
+    }
+
+    #[test]
+    fn config_validation() {
+        assert!(WakeOnLanConfig::default().is_valid());
+        assert!(WakeOnLanConfig { mac: Some(MAC) }.is_valid());
+        assert!(!WakeOnLanConfig { mac: Some([0; 6]) }.is_valid());
+        assert!(!WakeOnLanConfig {
+            mac: Some([0xFF; 6])
+        }
+        .is_valid());
+    }
+}
@@ -0,0 +1,138 @@
+//! How the ESP picks a saved Wi-Fi network to connect to, and what it reports about the
+//! connection in `GET /wifi_status`.
+use std::net::Ipv4Addr;
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+use crate::provisioning::{ScannedNetwork, WifiNetwork};
+
+/// The longest time between reconnect attempts
+pub const MAX_RECONNECT_DELAY: Duration = Duration::from_secs(5 * 60);
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Default)]
+pub enum WifiState {
+    #[default]
+    Connecting,
+    Connected {
+        ssid: String,
+        ip: Ipv4Addr,
+    },
+    /// None of the networks worked. The ESP tries again after `backoff_secs`.
+    WaitingToRetry,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Default)]
+pub struct WifiStatus {
+    pub state: WifiState,
+    /// Attempts to connect to any of the networks that failed in a row
+    pub failed_attempts: u32,
+    /// How long the ESP waits after a failed attempt before trying again
+    pub backoff_secs: u64,
+}
+
+/// Doubles with each failed attempt, starting at 1 second
+pub fn reconnect_delay(failed_attempts: u32) -> Duration {
+    match failed_attempts {
+        0 => Duration::ZERO,
+        n => Duration::from_secs(1u64 << (n - 1).min(16)).min(MAX_RECONNECT_DELAY),
+    }
+}
+
+/// The order to try the saved networks in. Networks that were found while scanning come first,
+/// by priority and then by signal strength. The rest come after, by priority, because hidden
+/// networks don't show up when scanning.
+pub fn connect_order<'a, 'b>(
+    networks: &'a [WifiNetwork],
+    scanned: &'b [ScannedNetwork],
+) -> Vec<(&'a WifiNetwork, Option<&'b ScannedNetwork>)> {
+    let mut order = networks
+        .iter()
+        .map(|network| {
+            let strongest = scanned
+                .iter()
+                .filter(|scanned| scanned.ssid == network.ssid)
+                .max_by_key(|scanned| scanned.signal_strength);
+            (network, strongest)
+        })
+        .collect::<Vec<_>>();
+    // Stable, so networks that are the same otherwise stay in the order they were saved in
+    order.sort_by_key(|(network, scanned)| {
+        (
+            scanned.is_none(),
+            u8::MAX - network.priority,
+            scanned.map_or(0, |scanned| -(scanned.signal_strength as i16)),
+        )
+    });
+    order
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::provisioning::WifiSecurity;
+
+    fn network(ssid: &str, priority: u8) -> WifiNetwork {
+        WifiNetwork {
+            ssid: ssid.into(),
+            password: "".into(),
+            priority,
+            security: WifiSecurity::Auto,
+            static_ip: None,
+        }
+    }
+
+    fn scanned(ssid: &str, signal_strength: i8) -> ScannedNetwork {
+        ScannedNetwork {
+            ssid: ssid.into(),
+            signal_strength,
+            security: WifiSecurity::Open,
+        }
+    }
+
+    fn ssids(order: Vec<(&WifiNetwork, Option<&ScannedNetwork>)>) -> Vec<String> {
+        order
+            .into_iter()
+            .map(|(network, _)| network.ssid.clone())
+            .collect()
+    }
+
+    #[test]
+    fn found_networks_come_first() {
+        let networks = [network("hidden", 9), network("home", 0)];
+        let scanned = [scanned("home", -80)];
+        let order = connect_order(&networks, &scanned);
+        assert_eq!(ssids(order), ["home", "hidden"]);
+    }
+
+    #[test]
+    fn higher_priority_comes_first() {
+        let networks = [network("home", 0), network("lab", 1)];
+        let scanned = [scanned("home", -40), scanned("lab", -80)];
+        let order = connect_order(&networks, &scanned);
+        assert_eq!(ssids(order), ["lab", "home"]);
+    }
+
+    #[test]
+    fn strongest_comes_first_with_same_priority() {
+        let networks = [network("home", 0), network("lab", 0), network("cafe", 0)];
+        let found = [
+            scanned("home", -80),
+            scanned("lab", -90),
+            scanned("lab", -40),
+            scanned("cafe", -60),
+        ];
+        let order = connect_order(&networks, &found);
+        assert_eq!(order[0].1, Some(&scanned("lab", -40)));
+        assert_eq!(ssids(order), ["lab", "cafe", "home"]);
+    }
+
+    #[test]
+    fn reconnect_delay_backs_off() {
+        assert_eq!(reconnect_delay(0), Duration::ZERO);
+        assert_eq!(reconnect_delay(1), Duration::from_secs(1));
+        assert_eq!(reconnect_delay(4), Duration::from_secs(8));
+        assert_eq!(reconnect_delay(20), MAX_RECONNECT_DELAY);
+        assert_eq!(reconnect_delay(u32::MAX), MAX_RECONNECT_DELAY);
+    }
+}
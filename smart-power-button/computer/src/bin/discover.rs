@@ -0,0 +1,29 @@
+use std::time::Duration;
+
+use smart_power_button_computer::discovery::discover;
+
+/// Lists the smart power buttons on the local network. Takes how many seconds to look for.
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    let seconds = match std::env::args().nth(1) {
+        Some(seconds) => seconds.parse()?,
+        None => 3,
+    };
+    let devices = discover(Duration::from_secs(seconds)).await?;
+    if devices.is_empty() {
+        println!("No smart power buttons found");
+    }
+    for device in devices {
+        println!(
+            "{} at http://{} (hostname: {}, firmware: {}, protocol: {})",
+            device.name.as_deref().unwrap_or(&device.fullname),
+            device.address(),
+            device.hostname,
+            device.firmware_version.as_deref().unwrap_or("unknown"),
+            device
+                .protocol_version
+                .map_or("unknown".into(), |version| version.to_string()),
+        );
+    }
+    Ok(())
+}
@@ -0,0 +1,14 @@
+use std::path::PathBuf;
+
+use anyhow::Context;
+use smart_power_button_computer::ota::push_firmware;
+
+/// Updates the remote's firmware. Takes the image made by `espflash save-image`.
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    let image = std::env::args()
+        .nth(1)
+        .map(PathBuf::from)
+        .context("Usage: ota <firmware image>")?;
+    push_firmware(&image).await
+}
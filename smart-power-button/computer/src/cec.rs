@@ -0,0 +1,88 @@
+use std::process::Stdio;
+
+use anyhow::{ensure, Context};
+use tokio::{io::AsyncWriteExt, process::Command};
+
+use crate::display::{AudioOutput, DisplayBackend};
+
+/// CEC limits names to 14 characters
+const MAX_NAME_LENGTH: usize = 14;
+
+/// Any display with HDMI-CEC, controlled by running `cec-client` from libcec. The computer
+/// needs a CEC adapter, like a Pulse-Eight USB adapter or a GPU that supports CEC.
+#[derive(Debug)]
+pub struct Cec {
+    /// Shown by the TV for the computer's input
+    pub device_name: String,
+}
+
+impl Cec {
+    pub fn new(device_name: &str) -> Self {
+        Self {
+            device_name: device_name.into(),
+        }
+    }
+
+    /// Runs one `cec-client` command, like `on 0`
+    async fn send(&self, command: &str) -> anyhow::Result<()> {
+        let name = self
+            .device_name
+            .chars()
+            .take(MAX_NAME_LENGTH)
+            .collect::<String>();
+        // `-t p` registers the computer as a playback device, which usually gets logical address 4
+        let mut child = Command::new("cec-client")
+            .args(["-s", "-d", "1", "-t", "p", "-o", &name])
+            .stdin(Stdio::piped())
+            .stdout(Stdio::null())
+            .spawn()
+            .context("Error running cec-client")?;
+        // `-s` runs the command and exits when stdin is closed
+        let mut stdin = child.stdin.take().unwrap();
+        stdin.write_all(format!("{command}\n").as_bytes()).await?;
+        drop(stdin);
+        let status = child.wait().await?;
+        ensure!(
+            status.success(),
+            "cec-client {command:?} failed with {status}"
+        );
+        Ok(())
+    }
+}
+
+impl DisplayBackend for Cec {
+    async fn power_on(&mut self) -> anyhow::Result<()> {
+        self.send("on 0").await
+    }
+
+    async fn power_off(&mut self) -> anyhow::Result<()> {
+        self.send("standby 0").await
+    }
+
+    /// Makes the computer the active source, so the TV switches to it
+    async fn set_input(&mut self, device_name: &str) -> anyhow::Result<()> {
+        self.device_name = device_name.into();
+        self.send("as").await
+    }
+
+    async fn set_game_mode(&mut self, _enabled: bool) -> anyhow::Result<()> {
+        println!("CEC can't change game mode, skipping it");
+        Ok(())
+    }
+
+    async fn launch_app(&mut self, _app_id: &str) -> anyhow::Result<()> {
+        println!("CEC can't open apps, skipping it");
+        Ok(())
+    }
+
+    /// Turns System Audio Mode on or off, which plays the TV's sound on the sound system
+    /// (logical address 5)
+    async fn set_audio_output(&mut self, output: AudioOutput) -> anyhow::Result<()> {
+        match output {
+            // System Audio Mode Request with the TV's physical address
+            AudioOutput::SoundSystem => self.send("tx 45:70:00:00").await,
+            // System Audio Mode Request without an address turns it off
+            AudioOutput::Display => self.send("tx 45:70").await,
+        }
+    }
+}
@@ -1,13 +1,29 @@
-use crate::apps::NETFLIX;
+use crate::{
+    apps::NETFLIX,
+    display::{DisplayKind, DisplaySettings},
+};
+use smart_power_button_common::machine::MachineId;
 
 pub const SHOULD_CONTROL_SOUND_SYSTEM: bool = true;
-pub const SHOULD_CONTROL_TV: bool = true;
+/// How to control the TV. `DisplayKind::None` leaves it alone.
+pub const DISPLAY_BACKEND: DisplayKind = DisplayKind::Samsung;
 /// The device display name for HDMI1
 pub const DEVICE_NAME: &str = "Gaming Computer";
 pub const SHOULD_SWITCH_SOUND_OUTPUT: bool = true;
 pub const IGNORE_TV_POWER_STATE: bool = false;
 pub const TV_MAC_ADDRESS: &str = "e4:7d:bd:b6:54:3f";
 pub const TV_IP_ADDRESS: &str = "samsung";
+/// Opened on the TV before turning it off, if the display can open apps
 pub const APP_TO_OPEN: &str = NETFLIX;
-pub const REMOTE_ADDRESS: &str = "gaming-computer-remote";
+pub const DISPLAY_SETTINGS: DisplaySettings = DisplaySettings {
+    device_name: DEVICE_NAME,
+    switch_audio_output: SHOULD_SWITCH_SOUND_OUTPUT,
+    app_on_power_off: Some(APP_TO_OPEN),
+};
+/// Like `gaming-computer-remote` or `192.168.1.5:80`. `None` finds the remote with mDNS.
+pub const REMOTE_ADDRESS: Option<&str> = None;
+/// Token from logging in to the remote with `POST /login`. The remote rejects requests without one.
+pub const REMOTE_TOKEN: Option<&str> = None;
+/// Which of the remote's machines this computer is. The first machine is 0.
+pub const REMOTE_MACHINE: MachineId = 0;
 pub const TV_DATA_FILE: &str = "/var/lib/tv_state";
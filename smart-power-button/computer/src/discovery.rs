@@ -0,0 +1,112 @@
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::time::Duration;
+
+use anyhow::anyhow;
+use mdns_sd::{ServiceDaemon, ServiceEvent, ServiceInfo};
+use smart_power_button_common::discovery::{
+    SERVICE_TYPE, TXT_DEVICE_NAME, TXT_FIRMWARE_VERSION, TXT_PROTOCOL_VERSION,
+};
+use tokio::time::{timeout_at, Instant};
+
+use crate::config::REMOTE_ADDRESS;
+
+/// How long to look for the remote when [`REMOTE_ADDRESS`] isn't set
+pub const DISCOVERY_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// A smart power button that advertised itself with mDNS
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DiscoveredDevice {
+    /// The mDNS instance name, like `gaming-remote._smart-power-button._tcp.local.`
+    pub fullname: String,
+    pub name: Option<String>,
+    pub firmware_version: Option<String>,
+    pub protocol_version: Option<u16>,
+    /// Like `gaming-remote.local.`
+    pub hostname: String,
+    pub addresses: Vec<IpAddr>,
+    pub port: u16,
+}
+
+impl DiscoveredDevice {
+    fn from_service_info(info: &ServiceInfo) -> Self {
+        let mut addresses = info.get_addresses().iter().copied().collect::<Vec<_>>();
+        // IPv4 first, because link-local IPv6 addresses need a scope to be used
+        addresses.sort_by_key(|address| (address.is_ipv6(), *address));
+        Self {
+            fullname: info.get_fullname().into(),
+            name: info.get_property_val_str(TXT_DEVICE_NAME).map(Into::into),
+            firmware_version: info
+                .get_property_val_str(TXT_FIRMWARE_VERSION)
+                .map(Into::into),
+            protocol_version: info
+                .get_property_val_str(TXT_PROTOCOL_VERSION)
+                .and_then(|version| version.parse().ok()),
+            hostname: info.get_hostname().into(),
+            addresses,
+            port: info.get_port(),
+        }
+    }
+
+    /// `host:port` to use in URLs
+    pub fn address(&self) -> String {
+        match self.addresses.first() {
+            Some(IpAddr::V4(ip)) => format!("{ip}:{}", self.port),
+            Some(IpAddr::V6(ip)) => format!("[{ip}]:{}", self.port),
+            None => format!("{}:{}", self.hostname.trim_end_matches('.'), self.port),
+        }
+    }
+}
+
+/// Browses until `stop` returns true for the devices found so far, or until `duration` is over
+async fn browse(
+    duration: Duration,
+    mut stop: impl FnMut(&[DiscoveredDevice]) -> bool,
+) -> anyhow::Result<Vec<DiscoveredDevice>> {
+    let mdns = ServiceDaemon::new()?;
+    let receiver = mdns.browse(&format!("{SERVICE_TYPE}.local."))?;
+    let deadline = Instant::now() + duration;
+    // By fullname, so devices that are resolved again aren't listed twice
+    let mut devices = HashMap::new();
+    while let Ok(event) = timeout_at(deadline, receiver.recv_async()).await {
+        match event? {
+            ServiceEvent::ServiceResolved(info) => {
+                let device = DiscoveredDevice::from_service_info(&info);
+                devices.insert(device.fullname.clone(), device);
+            }
+            ServiceEvent::ServiceRemoved(_, fullname) => {
+                devices.remove(&fullname);
+            }
+            _ => {}
+        }
+        if stop(&devices.values().cloned().collect::<Vec<_>>()) {
+            break;
+        }
+    }
+    let _ = mdns.shutdown();
+    let mut devices = devices.into_values().collect::<Vec<_>>();
+    devices.sort_by(|a, b| a.fullname.cmp(&b.fullname));
+    Ok(devices)
+}
+
+/// Lists the devices that answer within `duration`
+pub async fn discover(duration: Duration) -> anyhow::Result<Vec<DiscoveredDevice>> {
+    browse(duration, |_| false).await
+}
+
+/// Returns the first device that answers
+pub async fn find_device(timeout: Duration) -> anyhow::Result<DiscoveredDevice> {
+    browse(timeout, |devices| !devices.is_empty())
+        .await?
+        .into_iter()
+        .next()
+        .ok_or(anyhow!("No smart power button found with mDNS"))
+}
+
+/// [`REMOTE_ADDRESS`] if it's set, or else the address of the first device found with mDNS
+pub async fn remote_address() -> anyhow::Result<String> {
+    match REMOTE_ADDRESS {
+        Some(address) => Ok(address.into()),
+        None => Ok(find_device(DISCOVERY_TIMEOUT).await?.address()),
+    }
+}
@@ -0,0 +1,326 @@
+use anyhow::Context;
+
+use crate::{
+    cec::Cec,
+    config::{DEVICE_NAME, TV_MAC_ADDRESS},
+    samsung_tv::SamsungTv,
+};
+
+/// Where the TV plays sound
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AudioOutput {
+    /// The TV's own speakers
+    Display,
+    SoundSystem,
+}
+
+/// A TV or monitor that gets turned on and off with the computer
+// Only implemented and used in this crate, so the futures don't need to be `Send`
+#[allow(async_fn_in_trait)]
+pub trait DisplayBackend {
+    async fn power_on(&mut self) -> anyhow::Result<()>;
+    async fn power_off(&mut self) -> anyhow::Result<()>;
+    /// Switches to the computer's input, which is named `device_name` if the display can name it
+    async fn set_input(&mut self, device_name: &str) -> anyhow::Result<()>;
+    async fn set_game_mode(&mut self, enabled: bool) -> anyhow::Result<()>;
+    /// Opens an app on the TV, like [`crate::apps::NETFLIX`]
+    async fn launch_app(&mut self, app_id: &str) -> anyhow::Result<()>;
+    async fn set_audio_output(&mut self, output: AudioOutput) -> anyhow::Result<()>;
+}
+
+/// Which [`DisplayBackend`] to use, set in [`crate::config::DISPLAY_BACKEND`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DisplayKind {
+    /// A Samsung smart TV, controlled over the network
+    Samsung,
+    /// Any display that supports HDMI-CEC, controlled with `cec-client` from libcec
+    Cec,
+    /// Don't control the display
+    None,
+}
+
+/// For displays that can't be controlled
+#[derive(Debug, Default)]
+pub struct NoDisplay;
+
+impl DisplayBackend for NoDisplay {
+    async fn power_on(&mut self) -> anyhow::Result<()> {
+        Ok(())
+    }
+    async fn power_off(&mut self) -> anyhow::Result<()> {
+        Ok(())
+    }
+    async fn set_input(&mut self, _device_name: &str) -> anyhow::Result<()> {
+        Ok(())
+    }
+    async fn set_game_mode(&mut self, _enabled: bool) -> anyhow::Result<()> {
+        Ok(())
+    }
+    async fn launch_app(&mut self, _app_id: &str) -> anyhow::Result<()> {
+        Ok(())
+    }
+    async fn set_audio_output(&mut self, _output: AudioOutput) -> anyhow::Result<()> {
+        Ok(())
+    }
+}
+
+/// The display from the config
+pub enum Display {
+    Samsung(SamsungTv),
+    Cec(Cec),
+    None(NoDisplay),
+}
+
+impl Display {
+    /// `token` is the Samsung TV's token from the last time
+    pub fn new(kind: DisplayKind, token: Option<String>) -> Self {
+        match kind {
+            DisplayKind::Samsung => Self::Samsung(SamsungTv::new(TV_MAC_ADDRESS, token)),
+            DisplayKind::Cec => Self::Cec(Cec::new(DEVICE_NAME)),
+            DisplayKind::None => Self::None(NoDisplay),
+        }
+    }
+
+    /// The Samsung TV's token, which should be saved for the next time
+    pub fn samsung_token(self) -> Option<String> {
+        match self {
+            Self::Samsung(tv) => tv.remote.token,
+            _ => None,
+        }
+    }
+}
+
+impl DisplayBackend for Display {
+    async fn power_on(&mut self) -> anyhow::Result<()> {
+        match self {
+            Self::Samsung(display) => display.power_on().await,
+            Self::Cec(display) => display.power_on().await,
+            Self::None(display) => display.power_on().await,
+        }
+    }
+    async fn power_off(&mut self) -> anyhow::Result<()> {
+        match self {
+            Self::Samsung(display) => display.power_off().await,
+            Self::Cec(display) => display.power_off().await,
+            Self::None(display) => display.power_off().await,
+        }
+    }
+    async fn set_input(&mut self, device_name: &str) -> anyhow::Result<()> {
+        match self {
+            Self::Samsung(display) => display.set_input(device_name).await,
+            Self::Cec(display) => display.set_input(device_name).await,
+            Self::None(display) => display.set_input(device_name).await,
+        }
+    }
+    async fn set_game_mode(&mut self, enabled: bool) -> anyhow::Result<()> {
+        match self {
+            Self::Samsung(display) => display.set_game_mode(enabled).await,
+            Self::Cec(display) => display.set_game_mode(enabled).await,
+            Self::None(display) => display.set_game_mode(enabled).await,
+        }
+    }
+    async fn launch_app(&mut self, app_id: &str) -> anyhow::Result<()> {
+        match self {
+            Self::Samsung(display) => display.launch_app(app_id).await,
+            Self::Cec(display) => display.launch_app(app_id).await,
+            Self::None(display) => display.launch_app(app_id).await,
+        }
+    }
+    async fn set_audio_output(&mut self, output: AudioOutput) -> anyhow::Result<()> {
+        match self {
+            Self::Samsung(display) => display.set_audio_output(output).await,
+            Self::Cec(display) => display.set_audio_output(output).await,
+            Self::None(display) => display.set_audio_output(output).await,
+        }
+    }
+}
+
+/// What to do with the display when the computer turns on and off
+#[derive(Debug, Clone, Copy)]
+pub struct DisplaySettings<'a> {
+    /// The name for the computer's input
+    pub device_name: &'a str,
+    /// Play sound on the sound system while the computer is on
+    pub switch_audio_output: bool,
+    /// Opened before turning off, so it's open the next time the TV is used without the computer
+    pub app_on_power_off: Option<&'a str>,
+}
+
+/// Turns on the display and switches it to the computer
+pub async fn turn_on_display(
+    display: &mut impl DisplayBackend,
+    settings: &DisplaySettings<'_>,
+) -> anyhow::Result<()> {
+    println!("Turning display on");
+    display
+        .power_on()
+        .await
+        .context("Error turning on display")?;
+    if settings.switch_audio_output {
+        display
+            .set_audio_output(AudioOutput::SoundSystem)
+            .await
+            .context("Error switching sound output to sound system")?;
+    }
+    display
+        .set_input(settings.device_name)
+        .await
+        .context("Error switching input")?;
+    display
+        .set_game_mode(true)
+        .await
+        .context("Error turning on game mode")?;
+    Ok(())
+}
+
+/// Undoes [`turn_on_display`] and turns off the display
+pub async fn turn_off_display(
+    display: &mut impl DisplayBackend,
+    settings: &DisplaySettings<'_>,
+) -> anyhow::Result<()> {
+    println!("Turning display off");
+    display
+        .set_game_mode(false)
+        .await
+        .context("Error turning off game mode")?;
+    if settings.switch_audio_output {
+        display
+            .set_audio_output(AudioOutput::Display)
+            .await
+            .context("Error switching sound output to display")?;
+    }
+    if let Some(app_id) = settings.app_on_power_off {
+        println!("Opening app");
+        display
+            .launch_app(app_id)
+            .await
+            .context("Error opening app")?;
+    }
+    display
+        .power_off()
+        .await
+        .context("Error turning off display")?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::bail;
+
+    use super::*;
+
+    /// Records the calls, and fails the one named `fail_on`
+    #[derive(Default)]
+    struct MockDisplay {
+        calls: Vec<String>,
+        fail_on: Option<&'static str>,
+    }
+
+    impl MockDisplay {
+        fn call(&mut self, call: String) -> anyhow::Result<()> {
+            let name = call.split('(').next().unwrap().to_owned();
+            self.calls.push(call);
+            if self.fail_on == Some(name.as_str()) {
+                bail!("{name} failed");
+            }
+            Ok(())
+        }
+    }
+
+    impl DisplayBackend for MockDisplay {
+        async fn power_on(&mut self) -> anyhow::Result<()> {
+            self.call("power_on".into())
+        }
+        async fn power_off(&mut self) -> anyhow::Result<()> {
+            self.call("power_off".into())
+        }
+        async fn set_input(&mut self, device_name: &str) -> anyhow::Result<()> {
+            self.call(format!("set_input({device_name})"))
+        }
+        async fn set_game_mode(&mut self, enabled: bool) -> anyhow::Result<()> {
+            self.call(format!("set_game_mode({enabled})"))
+        }
+        async fn launch_app(&mut self, app_id: &str) -> anyhow::Result<()> {
+            self.call(format!("launch_app({app_id})"))
+        }
+        async fn set_audio_output(&mut self, output: AudioOutput) -> anyhow::Result<()> {
+            self.call(format!("set_audio_output({output:?})"))
+        }
+    }
+
+    const SETTINGS: DisplaySettings = DisplaySettings {
+        device_name: "Gaming Computer",
+        switch_audio_output: true,
+        app_on_power_off: Some("11101200001"),
+    };
+
+    #[tokio::test]
+    async fn turning_on_switches_to_the_computer() {
+        let mut display = MockDisplay::default();
+        turn_on_display(&mut display, &SETTINGS).await.unwrap();
+        assert_eq!(
+            display.calls,
+            [
+                "power_on",
+                "set_audio_output(SoundSystem)",
+                "set_input(Gaming Computer)",
+                "set_game_mode(true)",
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn turning_off_opens_the_app_before_powering_off() {
+        let mut display = MockDisplay::default();
+        turn_off_display(&mut display, &SETTINGS).await.unwrap();
+        assert_eq!(
+            display.calls,
+            [
+                "set_game_mode(false)",
+                "set_audio_output(Display)",
+                "launch_app(11101200001)",
+                "power_off",
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn optional_steps_are_skipped() {
+        let settings = DisplaySettings {
+            switch_audio_output: false,
+            app_on_power_off: None,
+            ..SETTINGS
+        };
+        let mut display = MockDisplay::default();
+        turn_on_display(&mut display, &settings).await.unwrap();
+        turn_off_display(&mut display, &settings).await.unwrap();
+        assert_eq!(
+            display.calls,
+            [
+                "power_on",
+                "set_input(Gaming Computer)",
+                "set_game_mode(true)",
+                "set_game_mode(false)",
+                "power_off",
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn stops_at_the_first_error() {
+        let mut display = MockDisplay {
+            fail_on: Some("set_input"),
+            ..Default::default()
+        };
+        let error = turn_on_display(&mut display, &SETTINGS).await.unwrap_err();
+        assert_eq!(error.to_string(), "Error switching input");
+        assert_eq!(
+            display.calls,
+            [
+                "power_on",
+                "set_audio_output(SoundSystem)",
+                "set_input(Gaming Computer)",
+            ]
+        );
+    }
+}
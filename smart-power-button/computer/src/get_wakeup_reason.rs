@@ -1,16 +1,20 @@
-use crate::config::REMOTE_ADDRESS;
+use crate::config::{REMOTE_MACHINE, REMOTE_TOKEN};
+use crate::discovery::remote_address;
 use postcard::from_bytes;
 use reqwest::Client;
+use smart_power_button_common::machine::machine_path;
 use smart_power_button_common::WakeupReason;
 
 pub async fn get_wakeup_reason() -> anyhow::Result<Option<WakeupReason>> {
+    let address = remote_address().await?;
+    let mut request = Client::new().delete(format!(
+        "http://{address}{}",
+        machine_path(REMOTE_MACHINE, "/wakeup_reason")
+    ));
+    if let Some(token) = REMOTE_TOKEN {
+        request = request.bearer_auth(token);
+    }
     Ok(from_bytes(
-        &Client::new()
-            .delete(format!("http://{REMOTE_ADDRESS}/wakeup_reason"))
-            .send()
-            .await?
-            .error_for_status()?
-            .bytes()
-            .await?,
+        &request.send().await?.error_for_status()?.bytes().await?,
     )?)
 }
@@ -1,10 +1,15 @@
 pub mod apps;
+pub mod cec;
 pub mod config;
+pub mod discovery;
+pub mod display;
 pub mod get_wakeup_reason;
+pub mod ota;
 pub mod power_down;
 pub mod power_up;
 pub mod retry_strategy;
 pub mod samsung;
+pub mod samsung_tv;
 pub mod sound_system;
 pub mod systemd_integration;
 pub mod toggle_game_mode;
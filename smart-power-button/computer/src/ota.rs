@@ -0,0 +1,34 @@
+use std::path::Path;
+
+use anyhow::bail;
+use reqwest::Client;
+use sha2::{Digest, Sha256};
+use smart_power_button_common::ota::{format_sha256, SHA256_HEADER};
+
+use crate::config::REMOTE_TOKEN;
+use crate::discovery::remote_address;
+
+/// Sends a firmware image made by `espflash save-image` to the remote, which restarts into it
+pub async fn push_firmware(image: &Path) -> anyhow::Result<()> {
+    let image = tokio::fs::read(image).await?;
+    let sha256 = format_sha256(&Sha256::digest(&image).into());
+    let address = remote_address().await?;
+    println!(
+        "Sending {} bytes (SHA-256 {sha256}) to {address}...",
+        image.len()
+    );
+    let mut request = Client::new()
+        .post(format!("http://{address}/ota"))
+        .header(SHA256_HEADER, sha256)
+        .body(image);
+    if let Some(token) = REMOTE_TOKEN {
+        request = request.bearer_auth(token);
+    }
+    let response = request.send().await?;
+    let status = response.status();
+    if !status.is_success() {
+        bail!("Update failed ({status}): {}", response.text().await?);
+    }
+    println!("Update saved. The remote is restarting.");
+    Ok(())
+}
@@ -0,0 +1,136 @@
+use std::time::Duration;
+
+use anyhow::Context;
+use tokio::time::sleep;
+use wakey::WolPacket;
+
+use crate::{
+    config::TV_IP_ADDRESS,
+    display::{AudioOutput, DisplayBackend},
+    samsung::Samsung,
+    toggle_game_mode::toggle_game_mode,
+};
+
+/// A Samsung TV, controlled by pressing keys on its menus. The key sequences are for one menu
+/// layout, so other models might need changes.
+pub struct SamsungTv {
+    pub remote: Samsung,
+    /// For turning it on with Wake-on-LAN
+    pub mac_address: String,
+}
+
+impl SamsungTv {
+    /// `token` is from the last time the TV accepted this computer
+    pub fn new(mac_address: &str, token: Option<String>) -> Self {
+        Self {
+            remote: Samsung {
+                ip: TV_IP_ADDRESS.into(),
+                app_name: "Gaming Computer".into(),
+                token,
+            },
+            mac_address: mac_address.into(),
+        }
+    }
+
+    async fn press(&mut self, key: &str, wait: f64) -> anyhow::Result<()> {
+        self.remote.send_key(key).await?;
+        sleep(Duration::from_secs_f64(wait)).await;
+        Ok(())
+    }
+
+    /// Goes to the home menu and all the way left, where the other key sequences start
+    async fn go_home(&mut self) -> anyhow::Result<()> {
+        self.press("KEY_HOME", 1.0)
+            .await
+            .context("Error sending HOME key to TV")?;
+        for _ in 0..11 {
+            self.press("KEY_LEFT", 0.3).await?;
+        }
+        Ok(())
+    }
+}
+
+impl DisplayBackend for SamsungTv {
+    async fn power_on(&mut self) -> anyhow::Result<()> {
+        WolPacket::from_string(&self.mac_address, ':')?
+            .send_magic()
+            .context("Error sending WOL packet to TV")?;
+        // Wait for it to fully turn on
+        sleep(Duration::from_secs_f64(7.0)).await;
+        Ok(())
+    }
+
+    async fn power_off(&mut self) -> anyhow::Result<()> {
+        self.remote.send_key("KEY_POWER").await?;
+        Ok(())
+    }
+
+    /// Switches to HDMI1, and changes it to be the "Game Console" type named `device_name`
+    async fn set_input(&mut self, device_name: &str) -> anyhow::Result<()> {
+        self.go_home().await?;
+        // Go to source settings
+        self.press("KEY_RIGHT", 0.3).await?;
+        self.press("KEY_UP", 0.3).await?;
+        // Move all the way to the left
+        for _ in 0..5 {
+            self.press("KEY_LEFT", 0.3).await?;
+        }
+        // Select HDMI1
+        self.press("KEY_RIGHT", 0.3).await?;
+        // Go up to "Choose type"
+        self.press("KEY_UP", 0.3).await?;
+        self.press("KEY_UP", 0.3).await?;
+        self.press("KEY_ENTER", 1.5).await?;
+        // Go all the way up in case it's already set to "Game Console"
+        for _ in 0..4 {
+            self.press("KEY_UP", 0.9).await?;
+        }
+        // Go down to "Game Console"
+        for _ in 0..2 {
+            self.press("KEY_DOWN", 0.5).await?;
+        }
+        // Select "Game Console"
+        self.press("KEY_ENTER", 0.2).await?;
+        // Go right to edit the name
+        self.remote.send_key("KEY_RIGHT").await?;
+        // Click on the name to edit it
+        self.press("KEY_ENTER", 2.9).await?;
+        self.remote.send_text(device_name).await?;
+        sleep(Duration::from_secs_f64(2.9)).await;
+        // Exit the typing
+        self.press("KEY_RETURN", 1.9).await?;
+        // Go down to the "OK" button
+        self.press("KEY_DOWN", 0.5).await?;
+        // Press the "OK" button
+        self.press("KEY_ENTER", 2.0).await?;
+        // Switch to HDMI1. The TV will show the "Detecting device" spinner, so cancel it.
+        self.press("KEY_ENTER", 3.0).await?;
+        self.press("KEY_RETURN", 3.0).await?;
+        Ok(())
+    }
+
+    /// The TV only has a toggle, so this assumes game mode isn't `enabled` yet
+    async fn set_game_mode(&mut self, _enabled: bool) -> anyhow::Result<()> {
+        toggle_game_mode(&mut self.remote).await
+    }
+
+    async fn launch_app(&mut self, app_id: &str) -> anyhow::Result<()> {
+        self.remote.open_app(app_id).await?;
+        // 5.5s wasn't enough when Netflix was opened as a "cold start"
+        sleep(Duration::from_secs_f64(8.5)).await;
+        Ok(())
+    }
+
+    /// The TV only has a toggle between its speakers and the sound system, so this assumes it's
+    /// on the other one
+    async fn set_audio_output(&mut self, _output: AudioOutput) -> anyhow::Result<()> {
+        self.go_home().await?;
+        self.press("KEY_UP", 0.3).await?;
+        self.press("KEY_RIGHT", 0.3).await?;
+        self.press("KEY_RIGHT", 0.3).await?;
+        self.press("KEY_ENTER", 0.3).await?;
+        // Go back to home settings
+        self.press("KEY_DOWN", 0.3).await?;
+        Ok(())
+    }
+}
@@ -13,6 +13,8 @@ pub enum OffReason {
     Exit,
 }
 
+// Only implemented and used in this crate, so the futures don't need to be `Send`
+#[allow(async_fn_in_trait)]
 pub trait ExternalDeviceManager {
     async fn turn_on(&mut self) -> anyhow::Result<()>;
     async fn turn_off(&mut self, reason: OffReason) -> anyhow::Result<()>;
@@ -0,0 +1,122 @@
+//! With the `static-files` feature, compresses the web page built by Trunk in `../web/dist` with
+//! gzip and Brotli, and lists the files for `static_assets::WEB_PAGE`
+use std::env;
+use std::fmt::Write as _;
+use std::fs;
+use std::io::{self, Write as _};
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use sha2::{Digest, Sha256};
+
+fn main() -> io::Result<()> {
+    println!("cargo:rerun-if-changed=build.rs");
+    let out_dir = PathBuf::from(env::var_os("OUT_DIR").unwrap());
+    let mut assets = String::from("&[\n");
+    if env::var_os("CARGO_FEATURE_STATIC_FILES").is_some() {
+        let dist = Path::new(&env::var_os("CARGO_MANIFEST_DIR").unwrap()).join("../web/dist");
+        println!("cargo:rerun-if-changed={}", dist.display());
+        let compressed_dir = out_dir.join("web_page");
+        let mut files = Vec::new();
+        list_files(&dist, &mut files)?;
+        files.sort();
+        for file in files {
+            let relative = file
+                .strip_prefix(&dist)
+                .unwrap()
+                .to_str()
+                .expect("File names in web/dist must be UTF-8")
+                .replace('\\', "/");
+            let contents = fs::read(&file)?;
+            let modified = fs::metadata(&file)?
+                .modified()?
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |since| since.as_secs());
+            let hash =
+                Sha256::digest(&contents)[..16]
+                    .iter()
+                    .fold(String::new(), |mut hash, byte| {
+                        write!(hash, "{byte:02x}").unwrap();
+                        hash
+                    });
+            let gzip = save_if_smaller(
+                &compressed_dir,
+                &relative,
+                "gz",
+                &contents,
+                gzip(&contents)?,
+            )?;
+            let brotli = save_if_smaller(
+                &compressed_dir,
+                &relative,
+                "br",
+                &contents,
+                brotli(&contents)?,
+            )?;
+            writeln!(
+                assets,
+                "    StaticAsset {{ path: {relative:?}, contents: include_bytes!({:?}), gzip: {}, brotli: {}, hash: {hash:?}, modified: {modified} }},",
+                file.display().to_string(),
+                include_option(gzip),
+                include_option(brotli),
+            )
+            .unwrap();
+        }
+    }
+    assets.push(']');
+    fs::write(out_dir.join("web_page.rs"), assets)
+}
+
+fn list_files(dir: &Path, files: &mut Vec<PathBuf>) -> io::Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            list_files(&path, files)?;
+        } else {
+            files.push(path);
+        }
+    }
+    Ok(())
+}
+
+fn gzip(contents: &[u8]) -> io::Result<Vec<u8>> {
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::best());
+    encoder.write_all(contents)?;
+    encoder.finish()
+}
+
+fn brotli(contents: &[u8]) -> io::Result<Vec<u8>> {
+    let mut compressed = Vec::new();
+    {
+        let mut writer = brotli::CompressorWriter::new(&mut compressed, 4096, 11, 22);
+        writer.write_all(contents)?;
+    }
+    Ok(compressed)
+}
+
+/// Images and other files that are already compressed don't get smaller, so they're only sent
+/// as they are
+fn save_if_smaller(
+    dir: &Path,
+    relative: &str,
+    extension: &str,
+    contents: &[u8],
+    compressed: Vec<u8>,
+) -> io::Result<Option<PathBuf>> {
+    if compressed.len() >= contents.len() {
+        return Ok(None);
+    }
+    let path = dir.join(format!("{relative}.{extension}"));
+    fs::create_dir_all(path.parent().unwrap())?;
+    fs::write(&path, compressed)?;
+    Ok(Some(path))
+}
+
+fn include_option(path: Option<PathBuf>) -> String {
+    match path {
+        Some(path) => format!("Some(include_bytes!({:?}))", path.display().to_string()),
+        None => "None".into(),
+    }
+}
@@ -0,0 +1,264 @@
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::anyhow;
+use futures::future::select_all;
+use log::info;
+use postcard::{from_bytes, to_allocvec};
+use smart_power_button_common::bluetooth_wake::{
+    matching_rule, BleAdvertisement, DiscoveredDevices, WakeupRule, MAX_DISCOVERY_DURATION,
+};
+use smart_power_button_common::history::PressSource;
+use smart_power_button_common::{MessageToEsp, Power};
+use tokio::select;
+use tokio::sync::{mpsc, oneshot};
+use tokio::time::{sleep, sleep_until, Instant};
+
+use crate::clock::unix_time;
+use crate::machine::Machine;
+use crate::nvs_value::Storage;
+
+/// How long each scan for wakeup devices is. Discovery scans wait for the current one to end.
+const WAKE_SCAN_DURATION: Duration = Duration::from_secs(5);
+/// How long to wait after waking up the computer before scanning again
+const WAKE_COOLDOWN: Duration = Duration::from_secs(10);
+
+/// Finds Bluetooth devices. On the ESP this uses NimBLE.
+pub trait BleScanner {
+    /// Scans for up to `duration` until a device that `is_wakeup_device` accepts shows up, and
+    /// returns what it advertised. Returns `None` if the scan timed out.
+    fn find_device(
+        &mut self,
+        duration: Duration,
+        is_wakeup_device: impl Fn(&BleAdvertisement) -> bool,
+    ) -> impl Future<Output = anyhow::Result<Option<BleAdvertisement>>>;
+
+    /// Scans for `duration` and calls `on_device` for everything that's advertised
+    fn scan(
+        &mut self,
+        duration: Duration,
+        on_device: impl FnMut(BleAdvertisement),
+    ) -> impl Future<Output = anyhow::Result<()>>;
+}
+
+/// Sent to [`bluetooth_wake`] to run a discovery scan
+pub struct DiscoveryRequest {
+    duration: Duration,
+    devices_tx: mpsc::UnboundedSender<BleAdvertisement>,
+    result_tx: oneshot::Sender<anyhow::Result<()>>,
+}
+
+/// Starts discovery scans, so that users can pick wakeup devices from a list
+#[derive(Clone)]
+pub struct BleDiscovery {
+    requests_tx: mpsc::Sender<DiscoveryRequest>,
+}
+
+impl BleDiscovery {
+    /// Pass the receiver to [`bluetooth_wake`]
+    pub fn new() -> (Self, mpsc::Receiver<DiscoveryRequest>) {
+        let (requests_tx, requests_rx) = mpsc::channel(4);
+        (Self { requests_tx }, requests_rx)
+    }
+
+    /// Scans for `duration`, which is limited to [`MAX_DISCOVERY_DURATION`]. The scan starts after
+    /// the current wake scan or discovery scan.
+    pub async fn discover(&self, duration: Duration) -> anyhow::Result<Discovery> {
+        let (devices_tx, devices_rx) = mpsc::unbounded_channel();
+        let (result_tx, result_rx) = oneshot::channel();
+        self.requests_tx
+            .send(DiscoveryRequest {
+                duration: duration.min(MAX_DISCOVERY_DURATION),
+                devices_tx,
+                result_tx,
+            })
+            .await
+            .map_err(|_| anyhow!("The Bluetooth scanner isn't running"))?;
+        Ok(Discovery {
+            devices_rx,
+            result_rx,
+        })
+    }
+}
+
+/// A discovery scan that was started
+pub struct Discovery {
+    devices_rx: mpsc::UnboundedReceiver<BleAdvertisement>,
+    result_rx: oneshot::Receiver<anyhow::Result<()>>,
+}
+
+impl Discovery {
+    /// The next device that is new, or that has a name now. Returns `None` when the scan is done.
+    pub async fn next_device(&mut self) -> Option<BleAdvertisement> {
+        self.devices_rx.recv().await
+    }
+
+    /// Waits for the scan to be done, and returns every device that was found
+    pub async fn finish(mut self) -> anyhow::Result<Vec<BleAdvertisement>> {
+        let mut devices = DiscoveredDevices::default();
+        while let Some(device) = self.next_device().await {
+            devices.add(device);
+        }
+        self.result_rx
+            .await
+            .map_err(|_| anyhow!("The Bluetooth scanner stopped"))??;
+        Ok(devices.into_devices())
+    }
+}
+
+/// The ESP used to only save addresses, as a postcard `Vec<[u8; 6]>` at `old_key`. If there are
+/// no rules at `new_key` yet, this saves a rule for each of those addresses there.
+pub fn migrate_wakeup_devices(
+    storage: &mut impl Storage,
+    old_key: &str,
+    new_key: &str,
+) -> anyhow::Result<()> {
+    if storage.get_blob(new_key)?.is_some() {
+        return Ok(());
+    }
+    if let Some(bytes) = storage.get_blob(old_key)? {
+        let addresses: Vec<[u8; 6]> = from_bytes(&bytes)?;
+        info!(
+            "Moving {} Bluetooth wakeup devices to rules",
+            addresses.len()
+        );
+        let rules = addresses
+            .into_iter()
+            .map(WakeupRule::address)
+            .collect::<Vec<_>>();
+        storage.set_blob(new_key, &to_allocvec(&rules)?)?;
+    }
+    Ok(())
+}
+
+/// Runs a discovery scan and sends the devices that are new or changed
+async fn run_discovery(scanner: &mut impl BleScanner, request: DiscoveryRequest) {
+    info!("Discovering Bluetooth devices for {:?}", request.duration);
+    let mut devices = DiscoveredDevices::default();
+    let result = scanner
+        .scan(request.duration, |advertisement| {
+            if devices.add(advertisement.clone()) {
+                let _ = request.devices_tx.send(advertisement);
+            }
+        })
+        .await;
+    if let Err(e) = &result {
+        log::error!("Error discovering Bluetooth devices: {e:#?}");
+    }
+    let _ = request.result_tx.send(result);
+}
+
+/// Scans for Bluetooth devices that match the machines' rules and turns on the power button of
+/// the machine whose rules match when they show up. The rules' time windows are in the time zone
+/// of the machine's schedule.
+///
+/// This is the only thing that uses the scanner, so discovery scans from [`BleDiscovery`] are run
+/// here too, between wake scans.
+pub async fn bluetooth_wake(
+    machines: Arc<[Machine]>,
+    mut scanner: impl BleScanner,
+    mut discovery_requests: mpsc::Receiver<DiscoveryRequest>,
+) {
+    let mut receivers = machines
+        .iter()
+        .map(|machine| {
+            (
+                machine.power_io.power_rx.clone(),
+                machine.bluetooth_wakeup_rules_rx.clone(),
+                machine.schedule_rx.clone(),
+            )
+        })
+        .collect::<Vec<_>>();
+    // After waking up a computer, it takes some time for it to actually turn on
+    let mut cooldowns_until = vec![None; machines.len()];
+    loop {
+        if let Ok(request) = discovery_requests.try_recv() {
+            run_discovery(&mut scanner, request).await;
+            continue;
+        }
+        let now = Instant::now();
+        // The rules of the machines that can be woken up now
+        let wakeable = receivers
+            .iter()
+            .zip(&cooldowns_until)
+            .enumerate()
+            .filter_map(
+                |(id, ((power_rx, rules_rx, schedule_rx), cooldown_until))| {
+                    let rules = rules_rx.get();
+                    let is_off = matches!(power_rx.get(), Some(Power::Off) | Some(Power::Suspend));
+                    let is_cooling_down = cooldown_until.is_some_and(|until| now < until);
+                    let time_zone = schedule_rx.get().time_zone();
+                    (is_off && !rules.is_empty() && !is_cooling_down)
+                        .then_some((id, rules, time_zone))
+                },
+            )
+            .collect::<Vec<_>>();
+        if !wakeable.is_empty() {
+            log::debug!("Scanning for Bluetooth devices that match these rules: {wakeable:?}");
+            let matching_machine = |advertisement: &BleAdvertisement| {
+                let now = unix_time();
+                wakeable
+                    .iter()
+                    .find(|(_, rules, time_zone)| {
+                        let local_time = now.map(|now| time_zone.local_time(now));
+                        matching_rule(rules, advertisement, local_time).is_some()
+                    })
+                    .map(|&(id, ..)| id)
+            };
+            match scanner
+                .find_device(WAKE_SCAN_DURATION, |advertisement| {
+                    matching_machine(advertisement).is_some()
+                })
+                .await
+            {
+                Ok(Some(advertisement)) => {
+                    // The time could have left a rule's window since the scan matched it
+                    let Some(id) = matching_machine(&advertisement) else {
+                        continue;
+                    };
+                    let machine = &machines[id];
+                    info!(
+                        "Detected wake device for {}: {advertisement:02X?}. Waking...",
+                        machine.name
+                    );
+                    machine
+                        .power_io
+                        .run(
+                            MessageToEsp::ShortPressPowerButton(false),
+                            PressSource::Bluetooth(advertisement.address),
+                        )
+                        .await;
+                    info!("Computer was woken up. Cooldown until BLE scanning will start again.");
+                    cooldowns_until[id] = Some(Instant::now() + WAKE_COOLDOWN);
+                }
+                Ok(None) => {}
+                Err(e) => {
+                    log::error!("Error finding a wake device: {e:#?}");
+                    sleep(Duration::from_secs(1)).await;
+                }
+            }
+        } else {
+            let changes = select_all(receivers.iter_mut().flat_map(|(power_rx, rules_rx, _)| {
+                [
+                    Box::pin(power_rx.until_change()) as Pin<Box<dyn Future<Output = ()> + Send>>,
+                    Box::pin(rules_rx.until_change()),
+                ]
+            }));
+            let next_cooldown_end = cooldowns_until
+                .iter()
+                .flatten()
+                .filter(|&&until| now < until)
+                .min()
+                .copied();
+            select! {
+                Some(request) = discovery_requests.recv() => {
+                    run_discovery(&mut scanner, request).await;
+                }
+                _ = changes => {}
+                _ = sleep_until(next_cooldown_end.unwrap_or(now)), if next_cooldown_end.is_some() => {}
+            }
+        }
+    }
+}
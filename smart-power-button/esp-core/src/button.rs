@@ -0,0 +1,108 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use log::error;
+use smart_power_button_common::press::{PressPattern, PressStep};
+use tokio::sync::broadcast::{channel, Sender};
+use tokio::sync::{Mutex, MutexGuard};
+use tokio::time::sleep;
+
+/// What presses a button. On the ESP this is a GPIO pin connected to the button's wires.
+pub trait ButtonPin: Send + 'static {
+    fn set_pressed(&mut self, is_pressed: bool) -> anyhow::Result<()>;
+}
+
+#[derive(Clone)]
+pub struct Button {
+    pin: Arc<Mutex<Box<dyn ButtonPin>>>,
+    sender: Sender<()>,
+    is_pressed: Arc<AtomicBool>,
+}
+
+impl Button {
+    pub fn new(mut pin: impl ButtonPin) -> anyhow::Result<Self> {
+        pin.set_pressed(false)?;
+        Ok(Self {
+            pin: Arc::new(Mutex::new(Box::new(pin))),
+            sender: channel(16).0,
+            is_pressed: Arc::new(AtomicBool::new(false)),
+        })
+    }
+
+    pub async fn is_pressed(&self) -> bool {
+        self.is_pressed.load(Ordering::Relaxed)
+    }
+
+    pub async fn press(&self, duration: Duration) {
+        self.press_pattern(&PressPattern::hold(duration)).await
+    }
+
+    /// Doesn't check the pattern, so check it with [`PressPattern::validate`] first. The button is
+    /// released at the end, even if this future is dropped halfway through.
+    pub async fn press_pattern(&self, pattern: &PressPattern) {
+        let mut press = PressGuard {
+            button: self,
+            pin: self.pin.lock().await,
+        };
+        for step in &pattern.0 {
+            let (is_pressed, duration) = match *step {
+                PressStep::Hold(duration) => (true, duration),
+                PressStep::Release(duration) => (false, duration),
+            };
+            press.set_pressed(is_pressed);
+            sleep(duration).await;
+        }
+    }
+
+    pub async fn until_change(&self) {
+        self.sender.subscribe().recv().await.unwrap();
+    }
+
+    // pub fn try_lock(&self) -> Result<ButtonLock<T>, TryLockError> {
+    //     let pin = self.pin.try_lock()?;
+    //     Ok(ButtonLock {
+    //         pin,
+    //         sender: &self.sender
+    //     })
+    // }
+}
+
+/// Holds the pin during a pattern, and releases the button when dropped
+struct PressGuard<'a> {
+    button: &'a Button,
+    pin: MutexGuard<'a, Box<dyn ButtonPin>>,
+}
+
+impl PressGuard<'_> {
+    fn set_pressed(&mut self, is_pressed: bool) {
+        if let Err(e) = self.pin.set_pressed(is_pressed) {
+            error!("Error setting button pin to pressed = {is_pressed}: {e:?}");
+        }
+        self.button.is_pressed.store(is_pressed, Ordering::Relaxed);
+        let _ = self.button.sender.send(());
+    }
+}
+
+impl Drop for PressGuard<'_> {
+    fn drop(&mut self) {
+        self.set_pressed(false);
+    }
+}
+
+// pub struct ButtonLock<'a, T: InputPin + OutputPin> {
+//     pin: MutexGuard<'a, PinDriver<'static, T, InputOutput>>,
+//     sender: &'a Sender<()>,
+// }
+//
+// impl<'a, T: InputPin + OutputPin> ButtonLock<'a, T> {
+//     async fn press(&mut self, duration: Duration) {
+//         self.set_and_broadcast(self.pin.deref_mut(), Level::Low);
+//         sleep(duration).await;
+//         self.set_and_broadcast(self.pin.deref_mut(), Level::High);
+//     }
+//
+//     pub async fn long_press(&mut self) {
+//         self.press(Duration::from_secs(6)).await
+//     }
+// }
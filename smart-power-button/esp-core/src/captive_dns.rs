@@ -0,0 +1,77 @@
+//! Answers every DNS query with the ESP's own address, so that phones and laptops that join the
+//! ESP's access point open the portal page.
+use std::net::Ipv4Addr;
+
+use log::error;
+use tokio::net::UdpSocket;
+
+const HEADER_LEN: usize = 12;
+const TYPE_A: u16 = 1;
+const TYPE_ANY: u16 = 255;
+const CLASS_IN: u16 = 1;
+const TTL_SECS: u32 = 60;
+
+pub async fn serve_captive_dns(socket: UdpSocket, ip: Ipv4Addr) -> anyhow::Result<()> {
+    let mut buffer = [0; 512];
+    loop {
+        let (len, from) = socket.recv_from(&mut buffer).await?;
+        if let Some(response) = answer(&buffer[..len], ip) {
+            if let Err(e) = socket.send_to(&response, from).await {
+                error!("Error sending DNS response to {from}: {e}");
+            }
+        }
+    }
+}
+
+fn read_u16(bytes: &[u8], index: usize) -> Option<u16> {
+    Some(u16::from_be_bytes([
+        *bytes.get(index)?,
+        *bytes.get(index + 1)?,
+    ]))
+}
+
+/// Makes a response to the first question in `query`. `A` questions are answered with `ip`, and
+/// other types get an empty answer. Returns `None` for anything that isn't a normal query.
+fn answer(query: &[u8], ip: Ipv4Addr) -> Option<Vec<u8>> {
+    let flags = read_u16(query, 2)?;
+    let is_response = flags & 0x8000 != 0;
+    let opcode = (flags >> 11) & 0xf;
+    if is_response || opcode != 0 || read_u16(query, 4)? == 0 {
+        return None;
+    }
+    // Skip the labels of the name. Queries don't use compression.
+    let mut name_end = HEADER_LEN;
+    loop {
+        let label_len = *query.get(name_end)? as usize;
+        name_end += 1;
+        if label_len == 0 {
+            break;
+        }
+        if label_len & 0xc0 != 0 {
+            return None;
+        }
+        name_end += label_len;
+    }
+    let query_type = read_u16(query, name_end)?;
+    let question = query.get(HEADER_LEN..name_end + 4)?;
+    let has_answer = matches!(query_type, TYPE_A | TYPE_ANY);
+
+    let mut response = Vec::with_capacity(HEADER_LEN + question.len() + 16);
+    response.extend_from_slice(&query[..2]);
+    // Response, authoritative, and "recursion desired" copied from the query
+    response.extend_from_slice(&(0x8400 | (flags & 0x0100)).to_be_bytes());
+    response.extend_from_slice(&1u16.to_be_bytes());
+    response.extend_from_slice(&(has_answer as u16).to_be_bytes());
+    response.extend_from_slice(&[0; 4]);
+    response.extend_from_slice(question);
+    if has_answer {
+        // A pointer to the name in the question
+        response.extend_from_slice(&[0xc0, HEADER_LEN as u8]);
+        response.extend_from_slice(&TYPE_A.to_be_bytes());
+        response.extend_from_slice(&CLASS_IN.to_be_bytes());
+        response.extend_from_slice(&TTL_SECS.to_be_bytes());
+        response.extend_from_slice(&4u16.to_be_bytes());
+        response.extend_from_slice(&ip.octets());
+    }
+    Some(response)
+}
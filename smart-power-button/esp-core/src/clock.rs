@@ -0,0 +1,8 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Seconds since the Unix epoch, or `None` if the time hasn't been synced yet
+pub fn unix_time() -> Option<u64> {
+    let seconds = SystemTime::now().duration_since(UNIX_EPOCH).ok()?.as_secs();
+    // The clock starts at 1970 when the ESP boots. This is 2024-01-01.
+    (seconds >= 1_704_067_200).then_some(seconds)
+}
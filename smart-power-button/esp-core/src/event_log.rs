@@ -0,0 +1,198 @@
+use std::collections::{HashMap, VecDeque};
+use std::future::Future;
+use std::mem::{discriminant, Discriminant};
+use std::sync::Arc;
+
+use log::warn;
+use parking_lot::Mutex;
+use smart_power_button_common::intent::IntentStatus;
+use smart_power_button_common::ota::OtaStatus;
+use smart_power_button_common::MessageToWeb;
+use tokio::join;
+use tokio::sync::broadcast;
+use tokio::sync::broadcast::error::RecvError;
+
+use crate::button::Button;
+use crate::power_io::PowerIo;
+use crate::value_channel::ValueReceiver;
+
+/// How many messages are kept for clients that reconnect
+const RECENT_MESSAGES: usize = 64;
+
+/// A message with its place in the log. Sequence numbers start at 1 when the ESP starts.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SequencedMessage {
+    /// Random for each start of the ESP, so sequence numbers from before a restart aren't
+    /// mistaken for current ones
+    pub boot_id: u32,
+    pub seq: u64,
+    pub message: MessageToWeb,
+}
+
+impl SequencedMessage {
+    /// `<boot id>-<seq>`, which clients send back in `Last-Event-ID`
+    pub fn event_id(&self) -> String {
+        format!("{:08x}-{}", self.boot_id, self.seq)
+    }
+}
+
+/// The sequence number in `event_id` if it's from this start of the ESP
+fn parse_event_id(event_id: &str, boot_id: u32) -> Option<u64> {
+    let (boot, seq) = event_id.trim().split_once('-')?;
+    match u32::from_str_radix(boot, 16).ok()? == boot_id {
+        true => seq.parse().ok(),
+        false => None,
+    }
+}
+
+struct Log {
+    next_seq: u64,
+    recent: VecDeque<SequencedMessage>,
+    /// The last message of each kind, except history events, so new clients can get the state
+    latest: HashMap<Discriminant<MessageToWeb>, SequencedMessage>,
+}
+
+/// Numbers every [`MessageToWeb`] that the WebSocket would send, so that `/events` clients can
+/// resume where they left off. Clones share the same log.
+#[derive(Clone)]
+pub struct EventLog {
+    boot_id: u32,
+    log: Arc<Mutex<Log>>,
+    sender: broadcast::Sender<SequencedMessage>,
+}
+
+impl EventLog {
+    /// The future watches the receivers and adds their changes to the log. `random_bytes` picks
+    /// the boot id.
+    pub fn new(
+        power_io: PowerIo,
+        ota_status_rx: ValueReceiver<OtaStatus>,
+        intent_status_rx: ValueReceiver<Option<IntentStatus>>,
+        random_bytes: fn(&mut [u8]),
+    ) -> (impl Future<Output = ()>, Self) {
+        let mut boot_id = [0; 4];
+        random_bytes(&mut boot_id);
+        let event_log = Self {
+            boot_id: u32::from_le_bytes(boot_id),
+            log: Arc::new(Mutex::new(Log {
+                next_seq: 1,
+                recent: VecDeque::new(),
+                latest: HashMap::new(),
+            })),
+            sender: broadcast::channel(RECENT_MESSAGES).0,
+        };
+        (
+            event_log
+                .clone()
+                .watch(power_io, ota_status_rx, intent_status_rx),
+            event_log,
+        )
+    }
+
+    fn record(&self, message: MessageToWeb) {
+        let mut log = self.log.lock();
+        let kind = discriminant(&message);
+        let is_history = matches!(message, MessageToWeb::HistoryEvent(_));
+        if !is_history
+            && log
+                .latest
+                .get(&kind)
+                .is_some_and(|latest| latest.message == message)
+        {
+            return;
+        }
+        let message = SequencedMessage {
+            boot_id: self.boot_id,
+            seq: log.next_seq,
+            message,
+        };
+        log.next_seq += 1;
+        if log.recent.len() == RECENT_MESSAGES {
+            log.recent.pop_front();
+        }
+        log.recent.push_back(message.clone());
+        if !is_history {
+            log.latest.insert(kind, message.clone());
+        }
+        // Sent while the log is locked, so subscribers don't miss or repeat anything
+        let _ = self.sender.send(message);
+    }
+
+    /// The messages after `last_event_id`, followed by the receiver for new ones. If some of
+    /// those messages were forgotten, or `last_event_id` is `None` or from before the ESP
+    /// restarted, this returns the latest message of each kind instead.
+    pub fn subscribe(
+        &self,
+        last_event_id: Option<&str>,
+    ) -> (Vec<SequencedMessage>, broadcast::Receiver<SequencedMessage>) {
+        let last_seq = last_event_id.and_then(|id| parse_event_id(id, self.boot_id));
+        let log = self.log.lock();
+        let oldest_seq = log
+            .recent
+            .front()
+            .map_or(log.next_seq, |message| message.seq);
+        let messages = match last_seq {
+            Some(last_seq) if last_seq < log.next_seq && last_seq + 1 >= oldest_seq => log
+                .recent
+                .iter()
+                .filter(|message| message.seq > last_seq)
+                .cloned()
+                .collect(),
+            _ => {
+                let mut latest = log.latest.values().cloned().collect::<Vec<_>>();
+                latest.sort_by_key(|message| message.seq);
+                latest
+            }
+        };
+        (messages, self.sender.subscribe())
+    }
+
+    async fn watch_value<T: Clone>(
+        &self,
+        mut rx: ValueReceiver<T>,
+        message: impl Fn(T) -> MessageToWeb,
+    ) {
+        loop {
+            self.record(message(rx.get()));
+            rx.until_change().await;
+        }
+    }
+
+    async fn watch_button(&self, button: Button, message: impl Fn(bool) -> MessageToWeb) {
+        loop {
+            self.record(message(button.is_pressed().await));
+            button.until_change().await;
+        }
+    }
+
+    async fn watch(
+        self,
+        power_io: PowerIo,
+        ota_status_rx: ValueReceiver<OtaStatus>,
+        intent_status_rx: ValueReceiver<Option<IntentStatus>>,
+    ) {
+        let mut history_rx = power_io.history.subscribe();
+        let history_future = async {
+            loop {
+                match history_rx.recv().await {
+                    Ok(event) => self.record(MessageToWeb::HistoryEvent(event)),
+                    Err(RecvError::Lagged(skipped)) => {
+                        warn!("Skipped {skipped} history events for the event log");
+                    }
+                    Err(RecvError::Closed) => break,
+                }
+            }
+        };
+        join!(
+            self.watch_value(power_io.power_led_rx, MessageToWeb::PowerLedStatus),
+            self.watch_value(power_io.hdd_activity_rx, MessageToWeb::HddActivity),
+            self.watch_button(power_io.power_button, MessageToWeb::PowerButtonStatus),
+            self.watch_button(power_io.reset_button, MessageToWeb::ResetButtonStatus),
+            self.watch_value(power_io.power_state_rx, MessageToWeb::PowerState),
+            self.watch_value(power_io.wakeup_reason_rx, MessageToWeb::WakeupReason),
+            self.watch_value(ota_status_rx, MessageToWeb::OtaStatus),
+            self.watch_value(intent_status_rx, MessageToWeb::IntentStatus),
+            history_future,
+        );
+    }
+}
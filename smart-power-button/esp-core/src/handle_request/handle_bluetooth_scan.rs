@@ -0,0 +1,48 @@
+use std::time::Duration;
+
+use http_body_util::combinators::BoxBody;
+use hyper::body::Bytes;
+use hyper::{Method, Request, Response, StatusCode};
+
+use crate::bluetooth_wake::BleDiscovery;
+use crate::hyper_util::{accepts_json, empty, full, serialize_response};
+use crate::Error;
+
+/// How long `/bluetooth_scan` scans for if the request doesn't say
+const DEFAULT_SCAN_DURATION: Duration = Duration::from_secs(10);
+
+/// Scans for Bluetooth devices for `?secs=N` seconds and responds with the devices that were
+/// found, strongest signal first. WebSocket clients can use
+/// [`smart_power_button_common::protocol::FrameToEsp::BluetoothScan`] to get them as they're found.
+pub async fn handle_bluetooth_scan(
+    req: Request<hyper::body::Incoming>,
+    ble_discovery: &BleDiscovery,
+) -> Result<Response<BoxBody<Bytes, hyper::Error>>, Error> {
+    if req.method() != Method::POST {
+        let mut response = Response::new(empty());
+        *response.status_mut() = StatusCode::METHOD_NOT_ALLOWED;
+        return Ok(response);
+    }
+    let duration = req
+        .uri()
+        .query()
+        .and_then(|query| {
+            query
+                .split('&')
+                .find_map(|pair| pair.strip_prefix("secs=")?.parse().ok())
+        })
+        .map_or(DEFAULT_SCAN_DURATION, Duration::from_secs);
+    let result = match ble_discovery.discover(duration).await {
+        Ok(discovery) => discovery.finish().await,
+        Err(e) => Err(e),
+    };
+    match result {
+        Ok(devices) => serialize_response(&devices, accepts_json(&req)),
+        Err(e) => {
+            log::error!("Error scanning for Bluetooth devices: {e:#?}");
+            let mut response = Response::new(full(e.to_string()));
+            *response.status_mut() = StatusCode::INTERNAL_SERVER_ERROR;
+            Ok(response)
+        }
+    }
+}
@@ -0,0 +1,107 @@
+use std::time::Duration;
+
+use http_body_util::combinators::BoxBody;
+use http_body_util::{BodyExt, StreamBody};
+use hyper::body::{Bytes, Frame};
+use hyper::header::{HeaderValue, CACHE_CONTROL, CONTENT_TYPE};
+use hyper::{Method, Request, Response, StatusCode};
+use log::warn;
+use tokio::select;
+use tokio::sync::broadcast::error::RecvError;
+use tokio::sync::mpsc;
+use tokio::time::{interval, MissedTickBehavior};
+
+use crate::event_log::{EventLog, SequencedMessage};
+use crate::hyper_util::empty;
+use crate::Error;
+
+/// Sent as a comment when nothing else was sent for this long, so proxies and clients don't
+/// close the connection, and the ESP notices when the client is gone
+pub const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(15);
+/// How long clients should wait before reconnecting
+const RETRY_MILLIS: u32 = 3000;
+
+fn sse_message(message: &SequencedMessage) -> Result<Bytes, serde_json::Error> {
+    Ok(format!(
+        "id: {}\ndata: {}\n\n",
+        message.event_id(),
+        serde_json::to_string(&message.message)?
+    )
+    .into())
+}
+
+/// Streams every [`smart_power_button_common::MessageToWeb`] as Server-Sent Events with JSON
+/// data. Clients that send `Last-Event-ID` get the messages they missed, or the current state if
+/// the ESP forgot them or restarted since.
+pub async fn handle_events(
+    req: Request<hyper::body::Incoming>,
+    event_log: &EventLog,
+) -> Result<Response<BoxBody<Bytes, hyper::Error>>, Error> {
+    if req.method() != Method::GET {
+        let mut response = Response::new(empty());
+        *response.status_mut() = StatusCode::METHOD_NOT_ALLOWED;
+        return Ok(response);
+    }
+    let last_event_id = req
+        .headers()
+        .get("Last-Event-ID")
+        .and_then(|id| id.to_str().ok());
+    let (messages, mut receiver) = event_log.subscribe(last_event_id);
+
+    let (sender, mut body_receiver) = mpsc::channel::<Bytes>(16);
+    // Stops when sending fails, which is when hyper drops the body because the client left
+    tokio::spawn(async move {
+        let send = |bytes: Bytes| sender.send(bytes);
+        if send(format!("retry: {RETRY_MILLIS}\n\n").into())
+            .await
+            .is_err()
+        {
+            return;
+        }
+        for message in &messages {
+            match sse_message(message) {
+                Ok(bytes) => {
+                    if send(bytes).await.is_err() {
+                        return;
+                    }
+                }
+                Err(e) => warn!("Error serializing event {}: {e:?}", message.event_id()),
+            }
+        }
+        let mut heartbeat = interval(HEARTBEAT_INTERVAL);
+        heartbeat.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        heartbeat.reset();
+        loop {
+            let bytes = select! {
+                message = receiver.recv() => match message {
+                    Ok(message) => match sse_message(&message) {
+                        Ok(bytes) => bytes,
+                        Err(e) => {
+                            warn!("Error serializing event {}: {e:?}", message.event_id());
+                            continue;
+                        }
+                    },
+                    // The client reconnects with the last ID it got, and gets the current state
+                    // if the ESP doesn't have the missed messages anymore
+                    Err(RecvError::Lagged(_)) | Err(RecvError::Closed) => return,
+                },
+                _ = heartbeat.tick() => Bytes::from_static(b": heartbeat\n\n"),
+            };
+            if send(bytes).await.is_err() {
+                return;
+            }
+            heartbeat.reset();
+        }
+    });
+
+    let body = StreamBody::new(futures::stream::poll_fn(move |cx| {
+        body_receiver
+            .poll_recv(cx)
+            .map(|bytes| bytes.map(|bytes| Ok(Frame::data(bytes))))
+    }));
+    let mut response = Response::new(body.boxed());
+    let headers = response.headers_mut();
+    headers.insert(CONTENT_TYPE, HeaderValue::from_static("text/event-stream"));
+    headers.insert(CACHE_CONTROL, HeaderValue::from_static("no-cache"));
+    Ok(response)
+}
@@ -0,0 +1,29 @@
+use std::time::Duration;
+
+use http_body_util::combinators::BoxBody;
+use hyper::body::Bytes;
+use hyper::{Method, Request, Response, StatusCode};
+
+use crate::hyper_util::empty;
+use crate::Error;
+
+pub async fn handle_factory_reset(
+    req: Request<hyper::body::Incoming>,
+    factory_reset: fn(),
+) -> Result<Response<BoxBody<Bytes, hyper::Error>>, Error> {
+    match *req.method() {
+        Method::POST => {
+            tokio::spawn(async move {
+                // Give the response time to get to the client
+                tokio::time::sleep(Duration::from_secs(1)).await;
+                factory_reset();
+            });
+            Ok(Response::new(empty()))
+        }
+        _ => {
+            let mut response = Response::new(empty());
+            *response.status_mut() = StatusCode::METHOD_NOT_ALLOWED;
+            Ok(response)
+        }
+    }
+}
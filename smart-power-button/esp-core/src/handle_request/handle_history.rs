@@ -0,0 +1,22 @@
+use http_body_util::combinators::BoxBody;
+use hyper::body::Bytes;
+use hyper::{Method, Request, Response, StatusCode};
+
+use crate::history::History;
+use crate::hyper_util::{accepts_json, empty, serialize_response};
+use crate::Error;
+
+/// Sends JSON if the request accepts it, and postcard otherwise
+pub async fn handle_history(
+    req: Request<hyper::body::Incoming>,
+    history: &History,
+) -> Result<Response<BoxBody<Bytes, hyper::Error>>, Error> {
+    match *req.method() {
+        Method::GET => serialize_response(&history.events(), accepts_json(&req)),
+        _ => {
+            let mut response = Response::new(empty());
+            *response.status_mut() = StatusCode::METHOD_NOT_ALLOWED;
+            Ok(response)
+        }
+    }
+}
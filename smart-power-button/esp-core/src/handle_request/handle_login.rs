@@ -0,0 +1,114 @@
+use std::time::Duration;
+
+use http_body_util::combinators::BoxBody;
+use http_body_util::BodyExt;
+use hyper::body::Bytes;
+use hyper::header::AUTHORIZATION;
+use hyper::{Method, Request, Response, StatusCode};
+use log::{info, warn};
+use smart_power_button_common::auth::{
+    add_token, find_token, is_authorized, is_correct_password, request_token, token_from_bytes,
+    AuthToken, LoginRequest, LoginResponse, TOKEN_BYTES,
+};
+use tokio::time::sleep;
+
+use crate::hyper_util::{
+    accepts_json, bad_request, deserialize_body, empty, sends_json, serialize_response,
+};
+use crate::server_state::ServerState;
+use crate::Error;
+
+fn authorization<B>(req: &Request<B>) -> Option<&str> {
+    req.headers()
+        .get(AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+}
+
+pub fn is_request_authorized<B>(req: &Request<B>, tokens: &[AuthToken]) -> bool {
+    is_authorized(tokens, authorization(req), req.uri().query())
+}
+
+fn random_token(server_state: &ServerState) -> String {
+    let mut bytes = [0; TOKEN_BYTES];
+    (server_state.random_bytes)(&mut bytes);
+    token_from_bytes(bytes)
+}
+
+fn status(status: StatusCode) -> Response<BoxBody<Bytes, hyper::Error>> {
+    let mut response = Response::new(empty());
+    *response.status_mut() = status;
+    response
+}
+
+pub async fn handle_login(
+    req: Request<hyper::body::Incoming>,
+    server_state: &ServerState,
+) -> Result<Response<BoxBody<Bytes, hyper::Error>>, Error> {
+    match *req.method() {
+        // Lets clients check if their token still works
+        Method::GET => Ok(status(
+            match is_request_authorized(&req, &server_state.auth_tokens_rx.get()) {
+                true => StatusCode::NO_CONTENT,
+                false => StatusCode::UNAUTHORIZED,
+            },
+        )),
+        Method::POST => {
+            let is_json = sends_json(&req);
+            let replies_json = accepts_json(&req);
+            let body = req.collect().await?.to_bytes();
+            let LoginRequest {
+                password,
+                client_name,
+            } = match deserialize_body(&body, is_json) {
+                Ok(request) => request,
+                Err(e) => return Ok(bad_request(e)),
+            };
+            if !is_correct_password(&server_state.pairing_password, &password) {
+                warn!("Wrong pairing password from {client_name:?}");
+                // Makes guessing the password slow
+                sleep(Duration::from_secs(1)).await;
+                return Ok(status(StatusCode::UNAUTHORIZED));
+            }
+            let token = random_token(server_state);
+            let mut auth_tokens_tx = server_state.auth_tokens_tx.lock().await;
+            let mut tokens = server_state.auth_tokens_rx.get();
+            info!("Logging in {client_name:?}");
+            add_token(
+                &mut tokens,
+                AuthToken {
+                    client_name,
+                    token: token.clone(),
+                },
+            );
+            match auth_tokens_tx.set(tokens).await {
+                Ok(()) => serialize_response(&LoginResponse { token }, replies_json),
+                Err(e) => {
+                    log::error!("Error saving auth tokens: {e:#?}");
+                    Ok(status(StatusCode::INTERNAL_SERVER_ERROR))
+                }
+            }
+        }
+        // Logs out by forgetting the token that this request was made with
+        Method::DELETE => {
+            let mut auth_tokens_tx = server_state.auth_tokens_tx.lock().await;
+            let mut tokens = server_state.auth_tokens_rx.get();
+            match request_token(authorization(&req), req.uri().query())
+                .and_then(|token| find_token(&tokens, token))
+            {
+                Some(index) => {
+                    let removed = tokens.remove(index);
+                    info!("Logging out {:?}", removed.client_name);
+                    match auth_tokens_tx.set(tokens).await {
+                        Ok(()) => Ok(status(StatusCode::NO_CONTENT)),
+                        Err(e) => {
+                            log::error!("Error saving auth tokens: {e:#?}");
+                            Ok(status(StatusCode::INTERNAL_SERVER_ERROR))
+                        }
+                    }
+                }
+                None => Ok(status(StatusCode::UNAUTHORIZED)),
+            }
+        }
+        _ => Ok(status(StatusCode::METHOD_NOT_ALLOWED)),
+    }
+}
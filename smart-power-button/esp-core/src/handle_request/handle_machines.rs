@@ -0,0 +1,55 @@
+use http_body_util::combinators::BoxBody;
+use http_body_util::BodyExt;
+use hyper::body::Bytes;
+use hyper::{Method, Request, Response, StatusCode};
+use smart_power_button_common::machine::MachinesConfig;
+use tokio::sync::Mutex;
+
+use crate::hyper_util::{
+    accepts_json, bad_request, deserialize_body, empty, sends_json, serialize_response,
+};
+use crate::nvs_value::NvsValue;
+use crate::value_channel::ValueReceiver;
+use crate::Error;
+
+/// Uses JSON if the request has JSON headers, and postcard otherwise. Changes are used after the
+/// ESP restarts, because that is when the pins are set up.
+pub async fn handle_machines(
+    req: Request<hyper::body::Incoming>,
+    machines_config_tx: &Mutex<NvsValue<MachinesConfig>>,
+    machines_config_rx: ValueReceiver<MachinesConfig>,
+) -> Result<Response<BoxBody<Bytes, hyper::Error>>, Error> {
+    match *req.method() {
+        Method::GET => serialize_response(&machines_config_rx.get(), accepts_json(&req)),
+        Method::PUT => {
+            let is_json = sends_json(&req);
+            let body = req.collect().await?.to_bytes();
+            let config: MachinesConfig = match deserialize_body(&body, is_json) {
+                Ok(config) => config,
+                Err(e) => return Ok(bad_request(e)),
+            };
+            if !config.is_valid() {
+                let mut response = Response::new(empty());
+                *response.status_mut() = StatusCode::BAD_REQUEST;
+                return Ok(response);
+            }
+            match machines_config_tx.lock().await.set(config).await {
+                Ok(()) => {
+                    let response = Response::new(empty());
+                    Ok(response)
+                }
+                Err(e) => {
+                    log::error!("Error saving machines config: {e:#?}");
+                    let mut response = Response::new(empty());
+                    *response.status_mut() = StatusCode::INTERNAL_SERVER_ERROR;
+                    Ok(response)
+                }
+            }
+        }
+        _ => {
+            let mut response = Response::new(empty());
+            *response.status_mut() = StatusCode::METHOD_NOT_ALLOWED;
+            Ok(response)
+        }
+    }
+}
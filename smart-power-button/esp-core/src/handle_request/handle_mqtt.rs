@@ -0,0 +1,54 @@
+use http_body_util::combinators::BoxBody;
+use http_body_util::BodyExt;
+use hyper::body::Bytes;
+use hyper::{Method, Request, Response, StatusCode};
+use postcard::to_allocvec;
+use smart_power_button_common::mqtt::MqttConfig;
+use tokio::sync::Mutex;
+
+use crate::hyper_util::{bad_request, deserialize_body, empty, full};
+use crate::nvs_value::NvsValue;
+use crate::value_channel::ValueReceiver;
+use crate::Error;
+
+pub async fn handle_mqtt(
+    req: Request<hyper::body::Incoming>,
+    mqtt_config_tx: &Mutex<NvsValue<MqttConfig>>,
+    mqtt_config_rx: ValueReceiver<MqttConfig>,
+) -> Result<Response<BoxBody<Bytes, hyper::Error>>, Error> {
+    match *req.method() {
+        Method::GET => {
+            let response = Response::new(full(to_allocvec(&mqtt_config_rx.get()).unwrap()));
+            Ok(response)
+        }
+        Method::PUT => {
+            let body = req.collect().await?.to_bytes();
+            let config = match deserialize_body::<MqttConfig>(&body, false) {
+                Ok(config) => config,
+                Err(e) => return Ok(bad_request(e)),
+            };
+            if !config.is_valid() {
+                let mut response = Response::new(empty());
+                *response.status_mut() = StatusCode::BAD_REQUEST;
+                return Ok(response);
+            }
+            match mqtt_config_tx.lock().await.set(config).await {
+                Ok(()) => {
+                    let response = Response::new(empty());
+                    Ok(response)
+                }
+                Err(e) => {
+                    log::error!("Error saving MQTT config: {e:#?}");
+                    let mut response = Response::new(empty());
+                    *response.status_mut() = StatusCode::INTERNAL_SERVER_ERROR;
+                    Ok(response)
+                }
+            }
+        }
+        _ => {
+            let mut response = Response::new(empty());
+            *response.status_mut() = StatusCode::METHOD_NOT_ALLOWED;
+            Ok(response)
+        }
+    }
+}
@@ -0,0 +1,57 @@
+use http_body_util::combinators::BoxBody;
+use http_body_util::BodyExt;
+use hyper::body::Bytes;
+use hyper::header::CONTENT_LENGTH;
+use hyper::{Method, Request, Response, StatusCode};
+use smart_power_button_common::ota::{parse_sha256, OtaError, SHA256_HEADER};
+
+use crate::hyper_util::{empty, full};
+use crate::ota::Ota;
+use crate::Error;
+
+pub async fn handle_ota(
+    req: Request<hyper::body::Incoming>,
+    ota: &Ota,
+) -> Result<Response<BoxBody<Bytes, hyper::Error>>, Error> {
+    match *req.method() {
+        Method::POST => {
+            let size = req
+                .headers()
+                .get(CONTENT_LENGTH)
+                .and_then(|size| size.to_str().ok()?.parse::<u64>().ok());
+            let sha256 = req
+                .headers()
+                .get(SHA256_HEADER)
+                .and_then(|hash| parse_sha256(hash.to_str().ok()?));
+            let result = match (size, sha256) {
+                (None, _) => Err(OtaError::MissingSize),
+                (_, None) => Err(OtaError::MissingHash),
+                (Some(size), Some(sha256)) => {
+                    ota.update(size, sha256, req.into_body().into_data_stream())
+                        .await
+                }
+            };
+            match result {
+                Ok(()) => Ok(Response::new(empty())),
+                Err(e) => {
+                    let mut response = Response::new(full(e.to_string()));
+                    *response.status_mut() = match e {
+                        OtaError::Busy => StatusCode::CONFLICT,
+                        OtaError::MissingSize => StatusCode::LENGTH_REQUIRED,
+                        OtaError::TooLarge { .. } => StatusCode::PAYLOAD_TOO_LARGE,
+                        OtaError::MissingHash
+                        | OtaError::SizeMismatch { .. }
+                        | OtaError::HashMismatch => StatusCode::BAD_REQUEST,
+                        OtaError::Flash(_) => StatusCode::INTERNAL_SERVER_ERROR,
+                    };
+                    Ok(response)
+                }
+            }
+        }
+        _ => {
+            let mut response = Response::new(empty());
+            *response.status_mut() = StatusCode::METHOD_NOT_ALLOWED;
+            Ok(response)
+        }
+    }
+}
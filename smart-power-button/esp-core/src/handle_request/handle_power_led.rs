@@ -0,0 +1,92 @@
+use std::time::Duration;
+
+use http_body_util::combinators::BoxBody;
+use http_body_util::BodyExt;
+use hyper::body::Bytes;
+use hyper::{Method, Request, Response, StatusCode};
+use smart_power_button_common::power_led::BlinkConfig;
+use tokio::sync::Mutex;
+
+use crate::hyper_util::{
+    accepts_json, bad_request, deserialize_body, empty, full, sends_json, serialize_response,
+};
+use crate::nvs_value::NvsValue;
+use crate::value_channel::ValueReceiver;
+use crate::watch_power::calibrate_power_led;
+use crate::Error;
+
+/// How long `/power_led/calibrate` records for if the request doesn't say
+const DEFAULT_CALIBRATION_DURATION: Duration = Duration::from_secs(10);
+
+/// Gets and sets the config for telling suspend apart from on and off. Uses JSON if the request
+/// has JSON headers, and postcard otherwise.
+pub async fn handle_power_led(
+    req: Request<hyper::body::Incoming>,
+    blink_config_tx: &Mutex<NvsValue<BlinkConfig>>,
+    blink_config_rx: ValueReceiver<BlinkConfig>,
+) -> Result<Response<BoxBody<Bytes, hyper::Error>>, Error> {
+    match *req.method() {
+        Method::GET => serialize_response(&blink_config_rx.get(), accepts_json(&req)),
+        Method::PUT => {
+            let is_json = sends_json(&req);
+            let body = req.collect().await?.to_bytes();
+            let config: BlinkConfig = match deserialize_body(&body, is_json) {
+                Ok(config) => config,
+                Err(e) => return Ok(bad_request(e)),
+            };
+            if !config.is_valid() {
+                let mut response = Response::new(empty());
+                *response.status_mut() = StatusCode::BAD_REQUEST;
+                return Ok(response);
+            }
+            match blink_config_tx.lock().await.set(config).await {
+                Ok(()) => {
+                    let response = Response::new(empty());
+                    Ok(response)
+                }
+                Err(e) => {
+                    log::error!("Error saving power LED config: {e:#?}");
+                    let mut response = Response::new(empty());
+                    *response.status_mut() = StatusCode::INTERNAL_SERVER_ERROR;
+                    Ok(response)
+                }
+            }
+        }
+        _ => {
+            let mut response = Response::new(empty());
+            *response.status_mut() = StatusCode::METHOD_NOT_ALLOWED;
+            Ok(response)
+        }
+    }
+}
+
+/// Records the power LED for `?secs=N` seconds and responds with the suggested config, without
+/// saving it. If the LED didn't blink in a way that works, the response is 422 with the reason
+/// as text.
+pub async fn handle_power_led_calibrate(
+    req: Request<hyper::body::Incoming>,
+    power_led_rx: ValueReceiver<bool>,
+) -> Result<Response<BoxBody<Bytes, hyper::Error>>, Error> {
+    if req.method() != Method::POST {
+        let mut response = Response::new(empty());
+        *response.status_mut() = StatusCode::METHOD_NOT_ALLOWED;
+        return Ok(response);
+    }
+    let duration = req
+        .uri()
+        .query()
+        .and_then(|query| {
+            query
+                .split('&')
+                .find_map(|pair| pair.strip_prefix("secs=")?.parse().ok())
+        })
+        .map_or(DEFAULT_CALIBRATION_DURATION, Duration::from_secs);
+    match calibrate_power_led(power_led_rx, duration).await {
+        Ok(calibration) => serialize_response(&calibration, accepts_json(&req)),
+        Err(e) => {
+            let mut response = Response::new(full(e.to_string()));
+            *response.status_mut() = StatusCode::UNPROCESSABLE_ENTITY;
+            Ok(response)
+        }
+    }
+}
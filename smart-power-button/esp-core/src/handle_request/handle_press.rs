@@ -0,0 +1,51 @@
+use http_body_util::combinators::BoxBody;
+use http_body_util::BodyExt;
+use hyper::body::Bytes;
+use hyper::{Method, Request, Response, StatusCode};
+use smart_power_button_common::history::PressSource;
+use smart_power_button_common::rest::ShortPressRequest;
+use smart_power_button_common::MessageToEsp;
+
+use crate::hyper_util::empty;
+use crate::power_io::PowerIo;
+use crate::Error;
+
+/// Handles `POST /power/short`, `POST /power/long`, and `POST /reset`, which is `path` without the
+/// machine. Responds after the button is released, which is 6 seconds for a long press. The press
+/// finishes even if the client disconnects. Presses count as coming from the web page.
+pub async fn handle_press(
+    req: Request<hyper::body::Incoming>,
+    path: &str,
+    power_io: PowerIo,
+) -> Result<Response<BoxBody<Bytes, hyper::Error>>, Error> {
+    if req.method() != Method::POST {
+        let mut response = Response::new(empty());
+        *response.status_mut() = StatusCode::METHOD_NOT_ALLOWED;
+        return Ok(response);
+    }
+    let message = match path {
+        "/power/short" => {
+            let body = req.collect().await?.to_bytes();
+            let request = match body.is_empty() {
+                true => Ok(ShortPressRequest::default()),
+                false => serde_json::from_slice::<ShortPressRequest>(&body),
+            };
+            match request {
+                Ok(request) => MessageToEsp::ShortPressPowerButton(request.turn_on_tv),
+                Err(e) => {
+                    let mut response = Response::new(empty());
+                    *response.status_mut() = StatusCode::BAD_REQUEST;
+                    log::warn!("Invalid short press request: {e}");
+                    return Ok(response);
+                }
+            }
+        }
+        "/power/long" => MessageToEsp::LongPressPowerButton,
+        "/reset" => MessageToEsp::ShortPressResetButton,
+        path => unreachable!("{path} isn't routed here"),
+    };
+    // hyper drops this future when the client disconnects, and the button has to be released even
+    // then, so the press runs in its own task
+    tokio::spawn(async move { power_io.run(message, PressSource::Web).await }).await?;
+    Ok(Response::new(empty()))
+}
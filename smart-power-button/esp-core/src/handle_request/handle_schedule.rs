@@ -2,33 +2,43 @@ use http_body_util::combinators::BoxBody;
 use http_body_util::BodyExt;
 use hyper::body::Bytes;
 use hyper::{Method, Request, Response, StatusCode};
-use postcard::{from_bytes, to_allocvec};
+use postcard::to_allocvec;
+use smart_power_button_common::schedule::Schedule;
 use tokio::sync::Mutex;
 
-use crate::bluetooth_wakeup_devices::BluetoothWakeupDevices;
-use crate::hyper_util::{empty, full};
+use crate::hyper_util::{bad_request, deserialize_body, empty, full};
+use crate::nvs_value::NvsValue;
 use crate::value_channel::ValueReceiver;
 use crate::Error;
 
-pub async fn handle_bluetooth_wakeup_devices(
+pub async fn handle_schedule(
     req: Request<hyper::body::Incoming>,
-    wakeup_devices_tx: &Mutex<BluetoothWakeupDevices>,
-    wakeup_devices_rx: ValueReceiver<Vec<[u8; 6]>>,
+    schedule_tx: &Mutex<NvsValue<Schedule>>,
+    schedule_rx: ValueReceiver<Schedule>,
 ) -> Result<Response<BoxBody<Bytes, hyper::Error>>, Error> {
     match *req.method() {
         Method::GET => {
-            let response = Response::new(full(to_allocvec(&wakeup_devices_rx.get()).unwrap()));
+            let response = Response::new(full(to_allocvec(&schedule_rx.get()).unwrap()));
             Ok(response)
         }
         Method::PUT => {
-            let devices = from_bytes(&req.collect().await?.to_bytes())?;
-            match wakeup_devices_tx.lock().await.set(devices).await {
+            let body = req.collect().await?.to_bytes();
+            let schedule = match deserialize_body::<Schedule>(&body, false) {
+                Ok(schedule) => schedule,
+                Err(e) => return Ok(bad_request(e)),
+            };
+            if !schedule.is_valid() {
+                let mut response = Response::new(empty());
+                *response.status_mut() = StatusCode::BAD_REQUEST;
+                return Ok(response);
+            }
+            match schedule_tx.lock().await.set(schedule).await {
                 Ok(()) => {
                     let response = Response::new(empty());
                     Ok(response)
                 }
                 Err(e) => {
-                    log::error!("Error saving wakeup devices: {e:#?}");
+                    log::error!("Error saving schedule: {e:#?}");
                     let mut response = Response::new(empty());
                     *response.status_mut() = StatusCode::INTERNAL_SERVER_ERROR;
                     Ok(response)
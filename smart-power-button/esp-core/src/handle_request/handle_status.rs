@@ -0,0 +1,32 @@
+use http_body_util::combinators::BoxBody;
+use hyper::body::Bytes;
+use hyper::{Method, Request, Response, StatusCode};
+use smart_power_button_common::rest::Status;
+
+use crate::hyper_util::{empty, serialize_response};
+use crate::power_io::PowerIo;
+use crate::Error;
+
+/// Always sends JSON, since this is for scripts. The web page gets these over the WebSocket.
+pub async fn handle_status(
+    req: Request<hyper::body::Incoming>,
+    power_io: PowerIo,
+) -> Result<Response<BoxBody<Bytes, hyper::Error>>, Error> {
+    match *req.method() {
+        Method::GET => serialize_response(
+            &Status {
+                power_led: power_io.power_led_rx.get(),
+                hdd_led: power_io.hdd_led_rx.get(),
+                power_button_pressed: power_io.power_button.is_pressed().await,
+                reset_button_pressed: power_io.reset_button.is_pressed().await,
+                power_state: power_io.power_state_rx.get(),
+            },
+            true,
+        ),
+        _ => {
+            let mut response = Response::new(empty());
+            *response.status_mut() = StatusCode::METHOD_NOT_ALLOWED;
+            Ok(response)
+        }
+    }
+}
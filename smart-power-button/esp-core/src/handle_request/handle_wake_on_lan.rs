@@ -0,0 +1,54 @@
+use http_body_util::combinators::BoxBody;
+use http_body_util::BodyExt;
+use hyper::body::Bytes;
+use hyper::{Method, Request, Response, StatusCode};
+use smart_power_button_common::wake_on_lan::WakeOnLanConfig;
+use tokio::sync::Mutex;
+
+use crate::hyper_util::{
+    accepts_json, bad_request, deserialize_body, empty, sends_json, serialize_response,
+};
+use crate::nvs_value::NvsValue;
+use crate::value_channel::ValueReceiver;
+use crate::Error;
+
+/// Uses JSON if the request has JSON headers, and postcard otherwise
+pub async fn handle_wake_on_lan(
+    req: Request<hyper::body::Incoming>,
+    wake_on_lan_config_tx: &Mutex<NvsValue<WakeOnLanConfig>>,
+    wake_on_lan_config_rx: ValueReceiver<WakeOnLanConfig>,
+) -> Result<Response<BoxBody<Bytes, hyper::Error>>, Error> {
+    match *req.method() {
+        Method::GET => serialize_response(&wake_on_lan_config_rx.get(), accepts_json(&req)),
+        Method::PUT => {
+            let is_json = sends_json(&req);
+            let body = req.collect().await?.to_bytes();
+            let config: WakeOnLanConfig = match deserialize_body(&body, is_json) {
+                Ok(config) => config,
+                Err(e) => return Ok(bad_request(e)),
+            };
+            if !config.is_valid() {
+                let mut response = Response::new(empty());
+                *response.status_mut() = StatusCode::BAD_REQUEST;
+                return Ok(response);
+            }
+            match wake_on_lan_config_tx.lock().await.set(config).await {
+                Ok(()) => {
+                    let response = Response::new(empty());
+                    Ok(response)
+                }
+                Err(e) => {
+                    log::error!("Error saving Wake-on-LAN config: {e:#?}");
+                    let mut response = Response::new(empty());
+                    *response.status_mut() = StatusCode::INTERNAL_SERVER_ERROR;
+                    Ok(response)
+                }
+            }
+        }
+        _ => {
+            let mut response = Response::new(empty());
+            *response.status_mut() = StatusCode::METHOD_NOT_ALLOWED;
+            Ok(response)
+        }
+    }
+}
@@ -0,0 +1,23 @@
+use http_body_util::combinators::BoxBody;
+use hyper::body::Bytes;
+use hyper::{Method, Request, Response, StatusCode};
+use smart_power_button_common::wifi::WifiStatus;
+
+use crate::hyper_util::{accepts_json, empty, serialize_response};
+use crate::value_channel::ValueReceiver;
+use crate::Error;
+
+/// Sends JSON if the request accepts it, and postcard otherwise
+pub async fn handle_wifi_status(
+    req: Request<hyper::body::Incoming>,
+    wifi_status_rx: ValueReceiver<WifiStatus>,
+) -> Result<Response<BoxBody<Bytes, hyper::Error>>, Error> {
+    match *req.method() {
+        Method::GET => serialize_response(&wifi_status_rx.get(), accepts_json(&req)),
+        _ => {
+            let mut response = Response::new(empty());
+            *response.status_mut() = StatusCode::METHOD_NOT_ALLOWED;
+            Ok(response)
+        }
+    }
+}
@@ -0,0 +1,239 @@
+use crate::serve_websocket::serve_websocket;
+use crate::server_state::ServerState;
+use crate::websocket_upgrade::{is_upgrade_request, upgrade};
+use crate::Error;
+use handle_bluetooth_scan::handle_bluetooth_scan;
+use handle_bluetooth_wakeup_devices::handle_bluetooth_wakeup_devices;
+use handle_events::handle_events;
+use handle_factory_reset::handle_factory_reset;
+use handle_history::handle_history;
+use handle_login::{handle_login, is_request_authorized};
+use handle_machines::handle_machines;
+use handle_mqtt::handle_mqtt;
+use handle_ota::handle_ota;
+use handle_power_led::{handle_power_led, handle_power_led_calibrate};
+use handle_press::handle_press;
+use handle_press_config::handle_press_config;
+use handle_schedule::handle_schedule;
+use handle_status::handle_status;
+use handle_wake_on_lan::handle_wake_on_lan;
+use handle_wakeup_reason::handle_wakeup_reason;
+use handle_wifi_status::handle_wifi_status;
+use http_body_util::combinators::BoxBody;
+use hyper::body::Bytes;
+use hyper::header::{
+    HeaderValue, ACCESS_CONTROL_ALLOW_HEADERS, ACCESS_CONTROL_ALLOW_METHODS,
+    ACCESS_CONTROL_ALLOW_ORIGIN, WWW_AUTHENTICATE,
+};
+use hyper::{Method, Request, Response, StatusCode};
+use log::error;
+use serve_static::serve_static;
+use smart_power_button_common::machine::split_machine_path;
+
+use crate::hyper_util::empty;
+
+mod handle_bluetooth_scan;
+mod handle_bluetooth_wakeup_devices;
+mod handle_events;
+mod handle_factory_reset;
+mod handle_history;
+mod handle_login;
+mod handle_machines;
+mod handle_mqtt;
+mod handle_ota;
+mod handle_power_led;
+mod handle_press;
+mod handle_press_config;
+mod handle_schedule;
+mod handle_status;
+mod handle_wake_on_lan;
+mod handle_wakeup_reason;
+mod handle_wifi_status;
+mod serve_static;
+
+/// The web page itself and logging in don't need a token. Everything else does, including paths
+/// for machines that don't exist.
+fn requires_auth<B>(req: &Request<B>) -> bool {
+    is_upgrade_request(req)
+        || split_machine_path(req.uri().path()).is_none_or(|(_, path)| {
+            matches!(
+                path,
+                "/wakeup_reason"
+                    | "/bluetooth_wakeup_devices"
+                    | "/bluetooth_scan"
+                    | "/schedule"
+                    | "/history"
+                    | "/factory_reset"
+                    | "/wifi_status"
+                    | "/ota"
+                    | "/mqtt"
+                    | "/status"
+                    | "/power/short"
+                    | "/power/long"
+                    | "/reset"
+                    | "/events"
+                    | "/press_config"
+                    | "/power_led"
+                    | "/power_led/calibrate"
+                    | "/wake_on_lan"
+                    | "/machines"
+            )
+        })
+}
+
+pub async fn handle_request(
+    req: Request<hyper::body::Incoming>,
+    server_state: ServerState,
+) -> Result<Response<BoxBody<Bytes, hyper::Error>>, Error> {
+    // Tokens are sent in headers and not cookies, so it's safe to let pages from anywhere use the
+    // API. This is needed when developing the web page with `trunk serve`.
+    let mut response = match *req.method() {
+        Method::OPTIONS => {
+            let mut response = Response::new(empty());
+            *response.status_mut() = StatusCode::NO_CONTENT;
+            let headers = response.headers_mut();
+            headers.insert(
+                ACCESS_CONTROL_ALLOW_HEADERS,
+                HeaderValue::from_static(
+                    "Authorization, Content-Type, Last-Event-ID, X-Firmware-Sha256",
+                ),
+            );
+            headers.insert(
+                ACCESS_CONTROL_ALLOW_METHODS,
+                HeaderValue::from_static("GET, POST, PUT, DELETE"),
+            );
+            response
+        }
+        _ => route(req, server_state).await?,
+    };
+    response
+        .headers_mut()
+        .insert(ACCESS_CONTROL_ALLOW_ORIGIN, HeaderValue::from_static("*"));
+    Ok(response)
+}
+
+async fn route(
+    mut req: Request<hyper::body::Incoming>,
+    server_state: ServerState,
+) -> Result<Response<BoxBody<Bytes, hyper::Error>>, Error> {
+    if requires_auth(&req) && !is_request_authorized(&req, &server_state.auth_tokens_rx.get()) {
+        let mut response = Response::new(empty());
+        *response.status_mut() = StatusCode::UNAUTHORIZED;
+        response
+            .headers_mut()
+            .insert(WWW_AUTHENTICATE, HeaderValue::from_static("Bearer"));
+        return Ok(response);
+    }
+
+    // Check if the request is a websocket upgrade request.
+    if is_upgrade_request(&req) {
+        let (response, websocket) = upgrade(&mut req)?;
+
+        // Spawn a task to handle the websocket connection.
+        tokio::spawn(async move {
+            if let Err(e) = serve_websocket(
+                websocket,
+                server_state.machines,
+                server_state.ota.status_rx,
+                server_state.ble_discovery,
+            )
+            .await
+            {
+                error!("Error in websocket connection: {e}");
+            }
+        });
+
+        // Return the response so the spawned future can continue.
+        Ok(response)
+    } else {
+        let full_path = req.uri().path().to_owned();
+        match full_path.as_str() {
+            // For the whole ESP, so these don't have a machine
+            "/login" => handle_login(req, &server_state).await,
+            "/bluetooth_scan" => handle_bluetooth_scan(req, &server_state.ble_discovery).await,
+            "/wifi_status" => handle_wifi_status(req, server_state.wifi_status_rx).await,
+            "/factory_reset" => handle_factory_reset(req, server_state.factory_reset).await,
+            "/ota" => handle_ota(req, &server_state.ota).await,
+            "/mqtt" => {
+                handle_mqtt(
+                    req,
+                    &server_state.mqtt_config_tx,
+                    server_state.mqtt_config_rx,
+                )
+                .await
+            }
+            "/machines" => {
+                handle_machines(
+                    req,
+                    &server_state.machines_config_tx,
+                    server_state.machines_config_rx,
+                )
+                .await
+            }
+            full_path => route_machine(req, full_path, &server_state).await,
+        }
+    }
+}
+
+/// Routes the endpoints that are for one machine
+async fn route_machine(
+    req: Request<hyper::body::Incoming>,
+    full_path: &str,
+    server_state: &ServerState,
+) -> Result<Response<BoxBody<Bytes, hyper::Error>>, Error> {
+    let Some((machine, path)) = split_machine_path(full_path)
+        .and_then(|(id, path)| Some((server_state.machines.get(id as usize)?, path)))
+    else {
+        let mut response = Response::new(empty());
+        *response.status_mut() = StatusCode::NOT_FOUND;
+        return Ok(response);
+    };
+    match path {
+        "/wakeup_reason" => handle_wakeup_reason(req, machine.power_io.clone()).await,
+        "/bluetooth_wakeup_devices" => {
+            handle_bluetooth_wakeup_devices(
+                req,
+                &machine.bluetooth_wakeup_rules_tx,
+                machine.bluetooth_wakeup_rules_rx.clone(),
+            )
+            .await
+        }
+        "/schedule" => {
+            handle_schedule(req, &machine.schedule_tx, machine.schedule_rx.clone()).await
+        }
+        "/history" => handle_history(req, &machine.power_io.history).await,
+        "/status" => handle_status(req, machine.power_io.clone()).await,
+        "/power/short" | "/power/long" | "/reset" => {
+            handle_press(req, path, machine.power_io.clone()).await
+        }
+        "/events" => handle_events(req, &machine.event_log).await,
+        "/press_config" => {
+            handle_press_config(
+                req,
+                &machine.press_config_tx,
+                machine.power_io.press_config_rx.clone(),
+            )
+            .await
+        }
+        "/power_led" => {
+            handle_power_led(
+                req,
+                &machine.blink_config_tx,
+                machine.power_io.blink_config_rx.clone(),
+            )
+            .await
+        }
+        "/power_led/calibrate" => {
+            handle_power_led_calibrate(req, machine.power_io.power_led_rx.clone()).await
+        }
+        "/wake_on_lan" => {
+            handle_wake_on_lan(
+                req,
+                &machine.wake_on_lan_config_tx,
+                machine.wake_on_lan_config_rx.clone(),
+            )
+            .await
+        }
+        _ => serve_static(req, server_state.static_assets).await,
+    }
+}
@@ -0,0 +1,257 @@
+use std::ops::RangeInclusive;
+
+use chrono::{DateTime, NaiveDateTime, Utc};
+use http_body_util::combinators::BoxBody;
+use hyper::body::Bytes;
+use hyper::header::{
+    HeaderName, HeaderValue, ACCEPT_ENCODING, ACCEPT_RANGES, CACHE_CONTROL, CONTENT_ENCODING,
+    CONTENT_RANGE, CONTENT_TYPE, ETAG, IF_MODIFIED_SINCE, IF_NONE_MATCH, IF_RANGE, LAST_MODIFIED,
+    RANGE, VARY,
+};
+use hyper::{Method, Request, Response, StatusCode};
+
+use crate::http_content_type::content_type;
+use crate::hyper_util::{empty, full};
+use crate::static_assets::StaticAsset;
+use crate::Error;
+
+/// A header that isn't visible ASCII, which is answered with 400 Bad Request. Other headers that
+/// can't be understood are ignored, like RFC 9110 says.
+struct BadHeader;
+
+/// The formats of an HTTP-date: IMF-fixdate, and the obsolete RFC 850 and asctime formats
+const HTTP_DATE_FORMATS: [&str; 3] = [
+    "%a, %d %b %Y %H:%M:%S GMT",
+    "%A, %d-%b-%y %H:%M:%S GMT",
+    "%a %b %e %H:%M:%S %Y",
+];
+
+/// What a `Range` header asks for
+enum ByteRange {
+    /// The whole file, also used for several ranges, which aren't supported, and for ranges that
+    /// can't be understood
+    Whole,
+    Part(RangeInclusive<usize>),
+    /// Starts past the end of the file
+    Unsatisfiable,
+}
+
+/// One way of sending an asset
+struct Representation {
+    contents: &'static [u8],
+    /// `None` for the asset as it is
+    encoding: Option<&'static str>,
+    etag: String,
+}
+
+pub async fn serve_static(
+    req: Request<hyper::body::Incoming>,
+    assets: &[StaticAsset],
+) -> Result<Response<BoxBody<Bytes, hyper::Error>>, Error> {
+    let path = match &req.uri().path()[1..] {
+        "" => "index.html",
+        path => path,
+    };
+    let Some(asset) = assets.iter().find(|asset| asset.path == path) else {
+        let mut not_found = Response::new(empty());
+        *not_found.status_mut() = StatusCode::NOT_FOUND;
+        return Ok(not_found);
+    };
+    if req.method() != Method::GET {
+        let mut method_not_allowed = Response::new(empty());
+        *method_not_allowed.status_mut() = StatusCode::METHOD_NOT_ALLOWED;
+        return Ok(method_not_allowed);
+    }
+    match respond(&req, asset) {
+        Ok(response) => Ok(response),
+        Err(BadHeader) => {
+            let mut bad_request = Response::new(empty());
+            *bad_request.status_mut() = StatusCode::BAD_REQUEST;
+            Ok(bad_request)
+        }
+    }
+}
+
+fn respond<B>(
+    req: &Request<B>,
+    asset: &StaticAsset,
+) -> Result<Response<BoxBody<Bytes, hyper::Error>>, BadHeader> {
+    let representation = choose_representation(asset, header(req, ACCEPT_ENCODING)?);
+    let last_modified = DateTime::<Utc>::from_timestamp(asset.modified as i64, 0)
+        .unwrap_or_default()
+        .format("%a, %d %b %Y %H:%M:%S GMT")
+        .to_string();
+    let mut response = Response::new(empty());
+    let headers = response.headers_mut();
+    headers.insert(ETAG, header_value(&representation.etag));
+    headers.insert(LAST_MODIFIED, header_value(&last_modified));
+    // The file names don't change between builds, so the browser has to check for a new version
+    // every time. That's cheap with the ETag.
+    headers.insert(CACHE_CONTROL, HeaderValue::from_static("no-cache"));
+    headers.insert(VARY, HeaderValue::from_static("Accept-Encoding"));
+
+    // If-None-Match is used instead of If-Modified-Since when there are both
+    let not_modified = match header(req, IF_NONE_MATCH)? {
+        Some(if_none_match) => etag_matches(if_none_match, &representation.etag),
+        None => header(req, IF_MODIFIED_SINCE)?
+            .and_then(parse_http_date)
+            .is_some_and(|if_modified_since| if_modified_since >= asset.modified as i64),
+    };
+    if not_modified {
+        *response.status_mut() = StatusCode::NOT_MODIFIED;
+        return Ok(response);
+    }
+
+    let headers = response.headers_mut();
+    headers.insert(
+        CONTENT_TYPE,
+        HeaderValue::from_static(content_type(asset.path)),
+    );
+    headers.insert(ACCEPT_RANGES, HeaderValue::from_static("bytes"));
+    if let Some(encoding) = representation.encoding {
+        headers.insert(CONTENT_ENCODING, HeaderValue::from_static(encoding));
+    }
+    let contents = representation.contents;
+    // A range for an old version of the file would be mixed up with the new one
+    let is_same_version = header(req, IF_RANGE)?.is_none_or(|tag| tag == representation.etag);
+    let range = match header(req, RANGE)? {
+        Some(range) if is_same_version => parse_range(range, contents.len()),
+        _ => ByteRange::Whole,
+    };
+    match range {
+        ByteRange::Whole => *response.body_mut() = full(contents),
+        ByteRange::Part(range) => {
+            *response.status_mut() = StatusCode::PARTIAL_CONTENT;
+            response.headers_mut().insert(
+                CONTENT_RANGE,
+                header_value(&format!(
+                    "bytes {}-{}/{}",
+                    range.start(),
+                    range.end(),
+                    contents.len()
+                )),
+            );
+            *response.body_mut() = full(&contents[range]);
+        }
+        ByteRange::Unsatisfiable => {
+            *response.status_mut() = StatusCode::RANGE_NOT_SATISFIABLE;
+            response.headers_mut().insert(
+                CONTENT_RANGE,
+                header_value(&format!("bytes */{}", contents.len())),
+            );
+        }
+    }
+    Ok(response)
+}
+
+/// A header as a string, or [`BadHeader`] if it isn't visible ASCII
+fn header<B>(req: &Request<B>, name: HeaderName) -> Result<Option<&str>, BadHeader> {
+    req.headers()
+        .get(name)
+        .map(|value| value.to_str().map_err(|_| BadHeader))
+        .transpose()
+}
+
+/// For values that were made from valid header values and numbers
+fn header_value(value: &str) -> HeaderValue {
+    HeaderValue::from_str(value).expect("Invalid header value")
+}
+
+/// The smallest version that the client accepts
+fn choose_representation(asset: &StaticAsset, accept_encoding: Option<&str>) -> Representation {
+    let accept_encoding = accept_encoding.unwrap_or("");
+    let options = [("br", asset.brotli), ("gzip", asset.gzip)];
+    for (encoding, contents) in options {
+        if let Some(contents) = contents.filter(|_| accepts_encoding(accept_encoding, encoding)) {
+            return Representation {
+                contents,
+                encoding: Some(encoding),
+                // Each encoding needs its own strong ETag
+                etag: format!("\"{}-{encoding}\"", asset.hash),
+            };
+        }
+    }
+    Representation {
+        contents: asset.contents,
+        encoding: None,
+        etag: format!("\"{}\"", asset.hash),
+    }
+}
+
+/// If `Accept-Encoding` lists `encoding` (or `*`) without `q=0`
+fn accepts_encoding(accept_encoding: &str, encoding: &str) -> bool {
+    let mut wildcard = false;
+    for item in accept_encoding.split(',') {
+        let mut parts = item.split(';').map(str::trim);
+        let name = parts.next().unwrap_or("");
+        let is_rejected = parts.any(|parameter| {
+            parameter
+                .strip_prefix("q=")
+                .and_then(|q| q.parse::<f32>().ok())
+                .is_some_and(|q| q <= 0.0)
+        });
+        if name.eq_ignore_ascii_case(encoding) {
+            return !is_rejected;
+        }
+        if name == "*" {
+            wildcard = !is_rejected;
+        }
+    }
+    wildcard
+}
+
+/// If `If-None-Match` has `etag`, comparing weakly like RFC 9110 says. Tags that aren't quoted
+/// don't match anything.
+fn etag_matches(if_none_match: &str, etag: &str) -> bool {
+    if if_none_match.trim() == "*" {
+        return true;
+    }
+    if_none_match
+        .split(',')
+        .map(str::trim)
+        .any(|tag| tag.strip_prefix("W/").unwrap_or(tag) == etag)
+}
+
+/// Seconds since the Unix epoch, or `None` if `date` isn't an HTTP-date
+fn parse_http_date(date: &str) -> Option<i64> {
+    HTTP_DATE_FORMATS.iter().find_map(|format| {
+        NaiveDateTime::parse_from_str(date.trim(), format)
+            .ok()
+            .map(|date| date.and_utc().timestamp())
+    })
+}
+
+/// Parses `Range: bytes=...` for a file with `len` bytes. Other units and ranges that can't be
+/// understood get the whole file.
+fn parse_range(range: &str, len: usize) -> ByteRange {
+    let Some(range) = range.trim().strip_prefix("bytes=").map(str::trim) else {
+        return ByteRange::Whole;
+    };
+    if range.contains(',') {
+        return ByteRange::Whole;
+    }
+    let Some((start, end)) = range.split_once('-') else {
+        return ByteRange::Whole;
+    };
+    let parse = |number: &str| number.trim().parse::<usize>().ok();
+    let range = match (start.trim(), end.trim()) {
+        // The last `suffix` bytes
+        ("", suffix) => match parse(suffix) {
+            Some(0) => return ByteRange::Unsatisfiable,
+            Some(suffix) => len.saturating_sub(suffix)..=len.saturating_sub(1),
+            None => return ByteRange::Whole,
+        },
+        (start, "") => match parse(start) {
+            Some(start) => start..=len.saturating_sub(1),
+            None => return ByteRange::Whole,
+        },
+        (start, end) => match (parse(start), parse(end)) {
+            (Some(start), Some(end)) if start <= end => start..=end.min(len.saturating_sub(1)),
+            _ => return ByteRange::Whole,
+        },
+    };
+    if len == 0 || *range.start() >= len {
+        return ByteRange::Unsatisfiable;
+    }
+    ByteRange::Part(range)
+}
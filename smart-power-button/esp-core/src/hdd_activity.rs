@@ -0,0 +1,34 @@
+use std::time::Duration;
+
+use futures::Future;
+use smart_power_button_common::hdd::{HddActivity, HddTracker};
+use tokio::select;
+use tokio::time::{sleep_until, Instant};
+
+use crate::value_channel::{value_channel, ValueReceiver};
+
+/// Summarizes the HDD LED with [`HddTracker`]. The summary changes at most once a second, so it
+/// can be sent to clients that don't want every change of the LED.
+pub fn watch_hdd_activity(
+    mut hdd_led_rx: ValueReceiver<bool>,
+) -> (impl Future<Output = ()>, ValueReceiver<HddActivity>) {
+    let (tx, rx) = value_channel(HddActivity::default());
+    (
+        async move {
+            let start = Instant::now();
+            let mut tracker = HddTracker::new(hdd_led_rx.get(), Duration::ZERO);
+            loop {
+                select! {
+                    _ = hdd_led_rx.until_change() => {
+                        tracker.update(hdd_led_rx.get(), start.elapsed());
+                    }
+                    _ = sleep_until(start + tracker.next_bucket_end()) => {
+                        tracker.finish_buckets(start.elapsed());
+                        tx.update_if_changed(tracker.activity()).await;
+                    }
+                }
+            }
+        },
+        rx,
+    )
+}
@@ -0,0 +1,180 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::Context;
+use log::{error, warn};
+use parking_lot::Mutex;
+use postcard::{from_bytes, to_allocvec};
+use serde::{Deserialize, Serialize};
+use smart_power_button_common::history::{add_event, HistoryEvent, HistoryEventKind, MAX_EVENTS};
+use smart_power_button_common::Power;
+use tokio::join;
+use tokio::sync::broadcast;
+use tokio::time::{timeout_at, Instant};
+
+use crate::clock::unix_time;
+use crate::nvs_value::Storage;
+use crate::value_channel::ValueReceiver;
+
+/// How often the HDD LED activity is summarized
+const HDD_SUMMARY_PERIOD: Duration = Duration::from_secs(10 * 60);
+/// How many events are saved together in one NVS key. Recording an event only rewrites the newest
+/// slot instead of the whole history, so it wears the flash less and needs less free space.
+const SLOT_EVENTS: usize = 16;
+/// When all slots are used, the oldest one is reused. There is one more than needed for
+/// [`MAX_EVENTS`], so that many are still kept when the newest slot was just started.
+const SLOTS: usize = MAX_EVENTS.div_ceil(SLOT_EVENTS) + 1;
+
+/// The events saved in one NVS key
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+struct Slot {
+    /// One more than the slot before it, so the newest slot can be found after a restart
+    number: u32,
+    events: Vec<HistoryEvent>,
+}
+
+/// On the ESP, keys can be at most 15 characters long, and the machine's id is added to them
+fn slot_key(index: usize) -> String {
+    format!("events{index}")
+}
+
+struct Log {
+    storage: Box<dyn Storage>,
+    /// Oldest first
+    events: Vec<HistoryEvent>,
+    /// Where [`Log::newest`] is saved
+    newest_index: usize,
+    newest: Slot,
+}
+
+/// Keeps the history in NVS and sends new events to WebSocket clients. Clones share the same
+/// history.
+#[derive(Clone)]
+pub struct History {
+    log: Arc<Mutex<Log>>,
+    sender: broadcast::Sender<HistoryEvent>,
+}
+
+impl History {
+    /// Loads the events that were saved in `storage`. Slots that can't be read are skipped.
+    pub fn new(storage: impl Storage) -> anyhow::Result<Self> {
+        let mut slots = Vec::new();
+        for index in 0..SLOTS {
+            let Some(bytes) = storage.get_blob(&slot_key(index))? else {
+                continue;
+            };
+            match from_bytes::<Slot>(&bytes) {
+                Ok(slot) => slots.push((index, slot)),
+                Err(e) => warn!("Skipping history slot {index}, which can't be read: {e}"),
+            }
+        }
+        slots.sort_by_key(|(_, slot)| slot.number);
+        let mut events = Vec::new();
+        for (_, slot) in &slots {
+            for event in &slot.events {
+                add_event(&mut events, event.clone());
+            }
+        }
+        let (newest_index, newest) = slots.pop().unwrap_or_default();
+        Ok(Self {
+            log: Arc::new(Mutex::new(Log {
+                storage: Box::new(storage),
+                events,
+                newest_index,
+                newest,
+            })),
+            sender: broadcast::channel(16).0,
+        })
+    }
+
+    /// Saves the event and sends it to subscribers. If saving fails, the event is still sent and
+    /// kept in memory until the ESP restarts.
+    pub async fn record(&self, kind: HistoryEventKind) -> anyhow::Result<()> {
+        let event = HistoryEvent {
+            time: unix_time(),
+            kind,
+        };
+        let result = {
+            let mut log = self.log.lock();
+            add_event(&mut log.events, event.clone());
+            if log.newest.events.len() == SLOT_EVENTS {
+                log.newest_index = (log.newest_index + 1) % SLOTS;
+                log.newest = Slot {
+                    number: log.newest.number.wrapping_add(1),
+                    events: Vec::new(),
+                };
+            }
+            log.newest.events.push(event.clone());
+            let key = slot_key(log.newest_index);
+            to_allocvec(&log.newest)
+                .map_err(anyhow::Error::from)
+                .and_then(|bytes| log.storage.set_blob(&key, &bytes))
+        };
+        let _ = self.sender.send(event);
+        result.context("Error saving history")
+    }
+
+    /// Oldest first
+    pub fn events(&self) -> Vec<HistoryEvent> {
+        self.log.lock().events.clone()
+    }
+
+    /// Receives events as they are recorded
+    pub fn subscribe(&self) -> broadcast::Receiver<HistoryEvent> {
+        self.sender.subscribe()
+    }
+}
+
+/// Records power changes and summaries of HDD LED activity
+pub async fn watch_history(
+    mut power_rx: ValueReceiver<Option<Power>>,
+    mut hdd_led_rx: ValueReceiver<bool>,
+    history: History,
+) {
+    let power_future = async {
+        loop {
+            power_rx.until_change().await;
+            if let Some(power) = power_rx.get() {
+                if let Err(e) = history.record(HistoryEventKind::Power(power)).await {
+                    error!("{e:#}");
+                }
+            }
+        }
+    };
+    let hdd_future = async {
+        loop {
+            let period_end = Instant::now() + HDD_SUMMARY_PERIOD;
+            let mut active = Duration::ZERO;
+            let mut on_since = hdd_led_rx.get().then(Instant::now);
+            while timeout_at(period_end, hdd_led_rx.until_change())
+                .await
+                .is_ok()
+            {
+                let now = Instant::now();
+                match (hdd_led_rx.get(), on_since) {
+                    (true, None) => on_since = Some(now),
+                    (false, Some(since)) => {
+                        active += now - since;
+                        on_since = None;
+                    }
+                    _ => {}
+                }
+            }
+            if let Some(since) = on_since {
+                active += period_end.saturating_duration_since(since);
+            }
+            if !active.is_zero() {
+                let result = history
+                    .record(HistoryEventKind::HddActivity {
+                        active_millis: active.as_millis() as u32,
+                        period_secs: HDD_SUMMARY_PERIOD.as_secs() as u32,
+                    })
+                    .await;
+                if let Err(e) = result {
+                    error!("{e:#}");
+                }
+            }
+        }
+    };
+    join!(power_future, hdd_future);
+}
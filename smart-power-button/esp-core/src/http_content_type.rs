@@ -0,0 +1,38 @@
+use std::collections::HashMap;
+use std::sync::LazyLock;
+
+pub const HTML: &str = "text/html";
+pub const JS: &str = "application/javascript";
+pub const WASM: &str = "application/wasm";
+pub const CSS: &str = "text/css";
+pub const JSON: &str = "application/json";
+pub const WEB_MANIFEST: &str = "application/manifest+json";
+pub const SVG: &str = "image/svg+xml";
+pub const PNG: &str = "image/png";
+pub const ICO: &str = "image/x-icon";
+pub const TEXT: &str = "text/plain";
+/// For files with an extension that isn't in [`EXTENSION_MAP`]
+pub const UNKNOWN: &str = "application/octet-stream";
+
+pub static EXTENSION_MAP: LazyLock<HashMap<&'static str, &'static str>> = LazyLock::new(|| {
+    let mut m = HashMap::new();
+    m.insert("html", HTML);
+    m.insert("js", JS);
+    m.insert("wasm", WASM);
+    m.insert("css", CSS);
+    m.insert("json", JSON);
+    m.insert("webmanifest", WEB_MANIFEST);
+    m.insert("svg", SVG);
+    m.insert("png", PNG);
+    m.insert("ico", ICO);
+    m.insert("txt", TEXT);
+    m
+});
+
+/// The `Content-Type` for a file at `path`
+pub fn content_type(path: &str) -> &'static str {
+    path.rsplit_once('.')
+        .and_then(|(_, extension)| EXTENSION_MAP.get(extension.to_ascii_lowercase().as_str()))
+        .copied()
+        .unwrap_or(UNKNOWN)
+}
@@ -0,0 +1,74 @@
+use http_body_util::{combinators::BoxBody, BodyExt, Empty, Full};
+use hyper::body::Bytes;
+use hyper::header::{HeaderValue, ACCEPT, CONTENT_TYPE};
+use hyper::{Request, Response, StatusCode};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+use crate::Error;
+
+// We create some utility functions to make Empty and Full bodies
+// fit our broadened Response body type.
+pub fn empty() -> BoxBody<Bytes, hyper::Error> {
+    Empty::<Bytes>::new()
+        .map_err(|never| match never {})
+        .boxed()
+}
+
+pub fn full<T: Into<Bytes>>(chunk: T) -> BoxBody<Bytes, hyper::Error> {
+    Full::new(chunk.into())
+        .map_err(|never| match never {})
+        .boxed()
+}
+
+fn has_json_header<B>(req: &Request<B>, header: hyper::header::HeaderName) -> bool {
+    req.headers()
+        .get(header)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.contains("application/json"))
+}
+
+/// If the request has `Accept: application/json`
+pub fn accepts_json<B>(req: &Request<B>) -> bool {
+    has_json_header(req, ACCEPT)
+}
+
+/// If the request has `Content-Type: application/json`
+pub fn sends_json<B>(req: &Request<B>) -> bool {
+    has_json_header(req, CONTENT_TYPE)
+}
+
+/// A response with `value` as JSON if `json` is `true`, and postcard otherwise
+pub fn serialize_response(
+    value: &impl Serialize,
+    json: bool,
+) -> Result<Response<BoxBody<Bytes, hyper::Error>>, Error> {
+    let response = if json {
+        let mut response = Response::new(full(serde_json::to_vec(value)?));
+        response
+            .headers_mut()
+            .insert(CONTENT_TYPE, HeaderValue::from_static("application/json"));
+        response
+    } else {
+        Response::new(full(postcard::to_allocvec(value)?))
+    };
+    Ok(response)
+}
+
+/// A `400 Bad Request` response that says what was wrong
+pub fn bad_request(error: impl std::fmt::Display) -> Response<BoxBody<Bytes, hyper::Error>> {
+    let mut response = Response::new(full(error.to_string()));
+    *response.status_mut() = StatusCode::BAD_REQUEST;
+    response
+}
+
+/// Reads `body` as JSON if `json` is `true`, and postcard otherwise. The error should be sent
+/// back with [`bad_request`], instead of dropping the connection.
+pub fn deserialize_body<T: DeserializeOwned>(body: &[u8], json: bool) -> Result<T, String> {
+    let result = if json {
+        serde_json::from_slice(body).map_err(|e| e.to_string())
+    } else {
+        postcard::from_bytes(body).map_err(|e| e.to_string())
+    };
+    result.inspect_err(|e| log::warn!("Invalid request body: {e}"))
+}
@@ -0,0 +1,115 @@
+use std::sync::Arc;
+
+use log::info;
+use parking_lot::Mutex;
+use smart_power_button_common::history::PressSource;
+use smart_power_button_common::intent::{
+    Intent, IntentError, IntentMachine, IntentOutcome, IntentStatus, Step,
+};
+use tokio::select;
+use tokio::sync::broadcast;
+use tokio::time::{sleep_until, Instant};
+
+use crate::power_io::PowerIo;
+use crate::value_channel::{value_channel, ValueReceiver, ValueSender};
+
+type IntentResult = Result<IntentOutcome, IntentError>;
+/// The running intent, and where its result will be sent
+type Running = Option<(Intent, broadcast::Sender<IntentResult>)>;
+
+/// Runs one [`Intent`] at a time. Clones share the same intent.
+#[derive(Clone)]
+pub struct IntentRunner {
+    power_io: PowerIo,
+    running: Arc<Mutex<Running>>,
+    status_tx: Arc<ValueSender<Option<IntentStatus>>>,
+    pub status_rx: ValueReceiver<Option<IntentStatus>>,
+}
+
+impl IntentRunner {
+    pub fn new(power_io: PowerIo) -> Self {
+        let (status_tx, status_rx) = value_channel(None);
+        Self {
+            power_io,
+            running: Default::default(),
+            status_tx: Arc::new(status_tx),
+            status_rx,
+        }
+    }
+
+    /// Presses the buttons until the computer is in the state that `intent` wants. If the same
+    /// intent is already running, this waits for it instead. A different one is refused.
+    pub async fn run(&self, intent: Intent, source: PressSource) -> IntentResult {
+        let mut result_rx = {
+            let mut running = self.running.lock();
+            match &*running {
+                Some((running, _)) if *running != intent => {
+                    return Err(IntentError::Conflict(*running));
+                }
+                Some((_, result_tx)) => Some(result_tx.subscribe()),
+                None => {
+                    *running = Some((intent, broadcast::channel(1).0));
+                    None
+                }
+            }
+        };
+        if let Some(result_rx) = &mut result_rx {
+            return result_rx
+                .recv()
+                .await
+                .expect("The result is always sent before the sender is dropped");
+        }
+
+        info!("Running intent {intent:?}");
+        let result = self.run_machine(intent, source).await;
+        info!("Intent {intent:?} finished: {result:?}");
+        if let Some((_, result_tx)) = self.running.lock().take() {
+            let _ = result_tx.send(result);
+        }
+        self.status_tx
+            .update(Some(IntentStatus::Finished(intent, result)))
+            .await;
+        result
+    }
+
+    async fn run_machine(&self, intent: Intent, source: PressSource) -> IntentResult {
+        let mut power_rx = self.power_io.power_rx.clone();
+        let (mut machine, mut step) = IntentMachine::start(intent, power_rx.get());
+        let mut deadline = Instant::now();
+        loop {
+            match step {
+                Step::Press { message, timeout } => {
+                    self.report(&machine).await;
+                    self.power_io.run(message, source).await;
+                    deadline = Instant::now() + timeout;
+                    // The power state can change while the button is pressed
+                    step = machine.on_power(power_rx.get());
+                    continue;
+                }
+                Step::Wait { timeout } => {
+                    self.report(&machine).await;
+                    deadline = Instant::now() + timeout;
+                }
+                Step::KeepWaiting => {}
+                Step::Finish(result) => return result,
+            }
+            step = select! {
+                _ = power_rx.until_change() => machine.on_power(power_rx.get()),
+                _ = sleep_until(deadline) => match machine.on_power(power_rx.get()) {
+                    // Checked again so that a missed change can't cause a long press
+                    Step::KeepWaiting => machine.on_timeout(),
+                    step => step,
+                },
+            };
+        }
+    }
+
+    async fn report(&self, machine: &IntentMachine) {
+        self.status_tx
+            .update(Some(IntentStatus::Running(
+                machine.intent(),
+                machine.phase(),
+            )))
+            .await;
+    }
+}
@@ -0,0 +1,29 @@
+//! The parts of the ESP code that don't need an ESP, so that they can be run and tested on a
+//! normal computer. The `esp` crate connects these to the GPIO pins, NVS, and Bluetooth.
+pub mod bluetooth_wake;
+pub mod button;
+pub mod captive_dns;
+pub mod clock;
+pub mod event_log;
+pub mod handle_request;
+pub mod hdd_activity;
+pub mod history;
+mod http_content_type;
+pub mod hyper_util;
+pub mod intents;
+pub mod machine;
+pub mod mqtt;
+pub mod nvs_value;
+pub mod ota;
+pub mod portal;
+pub mod power_io;
+pub mod serve;
+pub mod serve_websocket;
+pub mod server_state;
+pub mod static_assets;
+pub mod value_channel;
+pub mod wake_on_lan;
+pub mod watch_power;
+mod websocket_upgrade;
+
+pub type Error = Box<dyn std::error::Error + Send + Sync + 'static>;
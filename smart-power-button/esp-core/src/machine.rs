@@ -0,0 +1,47 @@
+use std::sync::Arc;
+
+use smart_power_button_common::bluetooth_wake::WakeupRule;
+use smart_power_button_common::machine::MachineId;
+use smart_power_button_common::power_led::BlinkConfig;
+use smart_power_button_common::press::PressConfig;
+use smart_power_button_common::schedule::Schedule;
+use smart_power_button_common::wake_on_lan::WakeOnLanConfig;
+use tokio::sync::Mutex;
+
+use crate::event_log::EventLog;
+use crate::intents::IntentRunner;
+use crate::nvs_value::NvsValue;
+use crate::power_io::PowerIo;
+use crate::value_channel::ValueReceiver;
+
+/// One computer and everything that the ESP keeps for it. Its id is its index in
+/// [`crate::server_state::ServerState::machines`].
+#[derive(Clone)]
+pub struct Machine {
+    pub name: Arc<str>,
+    pub power_io: PowerIo,
+    pub intents: IntentRunner,
+    /// For `/events`
+    pub event_log: EventLog,
+    pub bluetooth_wakeup_rules_tx: Arc<Mutex<NvsValue<Vec<WakeupRule>>>>,
+    pub bluetooth_wakeup_rules_rx: ValueReceiver<Vec<WakeupRule>>,
+    pub schedule_tx: Arc<Mutex<NvsValue<Schedule>>>,
+    pub schedule_rx: ValueReceiver<Schedule>,
+    /// The receiver is in `power_io`
+    pub press_config_tx: Arc<Mutex<NvsValue<PressConfig>>>,
+    /// The receiver is in `power_io`
+    pub blink_config_tx: Arc<Mutex<NvsValue<BlinkConfig>>>,
+    pub wake_on_lan_config_tx: Arc<Mutex<NvsValue<WakeOnLanConfig>>>,
+    pub wake_on_lan_config_rx: ValueReceiver<WakeOnLanConfig>,
+}
+
+impl Machine {
+    /// The name that Home Assistant shows for the machine. The first machine uses the ESP's name,
+    /// like before there were machines.
+    pub fn mqtt_name(&self, id: MachineId, device_name: &str) -> String {
+        match id {
+            0 => device_name.into(),
+            _ => format!("{device_name} {}", self.name),
+        }
+    }
+}
@@ -0,0 +1,286 @@
+use std::collections::HashMap;
+use std::future::Future;
+
+use log::{info, warn};
+use serde_json::{json, Value};
+use smart_power_button_common::history::PressSource;
+use smart_power_button_common::mqtt::{
+    hdd_activity_payload, led_payload, power_payload, wakeup_reason_payload, MqttConfig,
+    MqttTopics, ONLINE_PAYLOAD, POWER_PAYLOADS, PRESS_PAYLOAD, PRESS_WITH_TV_PAYLOAD,
+};
+use smart_power_button_common::wifi::reconnect_delay;
+use tokio::select;
+
+use crate::power_io::PowerIo;
+use crate::value_channel::ValueReceiver;
+
+/// A message on a subscribed topic
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MqttMessage {
+    pub topic: String,
+    pub payload: Vec<u8>,
+}
+
+/// Connects to MQTT brokers. On the ESP this uses ESP-IDF's MQTT client.
+pub trait MqttConnector {
+    type Client: MqttClient;
+    type Messages: MqttMessages;
+
+    /// Connects with `availability_topic` as the last will, so the broker publishes
+    /// [`smart_power_button_common::mqtt::OFFLINE_PAYLOAD`] to it when the ESP disconnects.
+    /// Returns after the broker accepted the connection.
+    fn connect(
+        &mut self,
+        config: &MqttConfig,
+        client_id: &str,
+        availability_topic: &str,
+    ) -> impl Future<Output = anyhow::Result<(Self::Client, Self::Messages)>>;
+}
+
+pub trait MqttClient {
+    fn publish(
+        &mut self,
+        topic: &str,
+        payload: &[u8],
+        retain: bool,
+    ) -> impl Future<Output = anyhow::Result<()>>;
+    fn subscribe(&mut self, topic: &str) -> impl Future<Output = anyhow::Result<()>>;
+}
+
+pub trait MqttMessages {
+    /// Waits for a message on a subscribed topic. Returns an error when the connection is lost.
+    fn next(&mut self) -> impl Future<Output = anyhow::Result<MqttMessage>>;
+}
+
+/// What Home Assistant shows about the ESP
+#[derive(Debug, Clone)]
+pub struct MqttDevice {
+    pub name: String,
+    pub firmware_version: String,
+}
+
+/// The Home Assistant discovery configs, as `(topic, payload)`
+pub fn discovery_configs(topics: &MqttTopics, device: &MqttDevice) -> Vec<(String, String)> {
+    let device_config = json!({
+        "identifiers": [topics.node_id()],
+        "name": device.name,
+        "manufacturer": "smart-power-button",
+        "sw_version": device.firmware_version,
+    });
+    let entity = |component: &str, object_id: &str, name: &str, extra: Value| {
+        let mut config = json!({
+            "name": name,
+            "unique_id": format!("{}_{object_id}", topics.node_id()),
+            "availability_topic": topics.availability(),
+            "device": device_config,
+        });
+        if let (Some(config), Value::Object(extra)) = (config.as_object_mut(), extra) {
+            config.extend(extra);
+        }
+        (topics.discovery(component, object_id), config.to_string())
+    };
+    let button = |object_id: &str, name: &str, command_topic: String, payload: &str| {
+        entity(
+            "button",
+            object_id,
+            name,
+            json!({ "command_topic": command_topic, "payload_press": payload }),
+        )
+    };
+    vec![
+        entity(
+            "binary_sensor",
+            "power_led",
+            "Power LED",
+            json!({
+                "state_topic": topics.power_led(),
+                "payload_on": led_payload(true),
+                "payload_off": led_payload(false),
+                "device_class": "power",
+            }),
+        ),
+        entity(
+            "sensor",
+            "hdd_activity",
+            "HDD activity",
+            json!({
+                "state_topic": topics.hdd_activity(),
+                "unit_of_measurement": "%",
+                "state_class": "measurement",
+                "icon": "mdi:harddisk",
+            }),
+        ),
+        entity(
+            "sensor",
+            "power",
+            "Computer",
+            json!({
+                "state_topic": topics.power(),
+                "device_class": "enum",
+                "options": POWER_PAYLOADS,
+            }),
+        ),
+        entity(
+            "sensor",
+            "wakeup_reason",
+            "Wakeup reason",
+            json!({ "state_topic": topics.wakeup_reason() }),
+        ),
+        button(
+            "short_press",
+            "Power button",
+            topics.short_press(),
+            PRESS_PAYLOAD,
+        ),
+        button(
+            "short_press_tv",
+            "Power button and TV",
+            topics.short_press(),
+            PRESS_WITH_TV_PAYLOAD,
+        ),
+        button(
+            "long_press",
+            "Force off",
+            topics.long_press(),
+            PRESS_PAYLOAD,
+        ),
+        button("reset", "Reset", topics.reset(), PRESS_PAYLOAD),
+    ]
+}
+
+/// Publishes the power LED, HDD activity, power state, and wakeup reason, and presses the buttons for commands,
+/// whenever MQTT is configured. Reconnects when the config changes or the connection is lost.
+pub async fn run_mqtt(
+    mut connector: impl MqttConnector,
+    mut config_rx: ValueReceiver<MqttConfig>,
+    power_io: PowerIo,
+    device: MqttDevice,
+) {
+    let mut failed_attempts = 0;
+    loop {
+        let config = config_rx.get();
+        if !config.is_enabled() {
+            info!("MQTT is off");
+            config_rx.until_change().await;
+            continue;
+        }
+        let session = async {
+            let result = run_session(
+                &mut connector,
+                &config,
+                &power_io,
+                &device,
+                &mut failed_attempts,
+            )
+            .await;
+            failed_attempts = failed_attempts.saturating_add(1);
+            let delay = reconnect_delay(failed_attempts);
+            if let Err(e) = result {
+                warn!("MQTT error: {e:?}. Reconnecting in {delay:?}");
+            }
+            tokio::time::sleep(delay).await;
+        };
+        select! {
+            _ = session => {}
+            _ = config_rx.until_change() => {
+                info!("MQTT config changed. Reconnecting.");
+                failed_attempts = 0;
+            }
+        }
+    }
+}
+
+/// Runs until the connection is lost
+async fn run_session(
+    connector: &mut impl MqttConnector,
+    config: &MqttConfig,
+    power_io: &PowerIo,
+    device: &MqttDevice,
+    failed_attempts: &mut u32,
+) -> anyhow::Result<()> {
+    let topics = MqttTopics::new(config, &device.name);
+    info!("Connecting to MQTT broker {}:{}", config.host, config.port);
+    let (mut client, mut messages) = connector
+        .connect(config, topics.node_id(), &topics.availability())
+        .await?;
+    info!("Connected to MQTT broker");
+    *failed_attempts = 0;
+    for topic in topics.command_topics() {
+        client.subscribe(&topic).await?;
+    }
+    for (topic, payload) in discovery_configs(&topics, device) {
+        client.publish(&topic, payload.as_bytes(), true).await?;
+    }
+    // Older versions had a retained HDD LED sensor, and an empty retained message deletes it
+    for topic in [
+        topics.discovery("binary_sensor", "hdd_led"),
+        topics.old_hdd_led(),
+    ] {
+        client.publish(&topic, b"", true).await?;
+    }
+    client
+        .publish(&topics.availability(), ONLINE_PAYLOAD.as_bytes(), true)
+        .await?;
+
+    let PowerIo {
+        mut power_led_rx,
+        mut hdd_activity_rx,
+        mut power_state_rx,
+        mut wakeup_reason_rx,
+        ..
+    } = power_io.clone();
+    // What was last published to each state topic, so only changes are published
+    let mut published = HashMap::new();
+    loop {
+        // `(topic, payload, retain)`. HDD activity changes up to every second, so it isn't
+        // retained.
+        let states = [
+            (
+                topics.power_led(),
+                led_payload(power_led_rx.get()).to_owned(),
+                true,
+            ),
+            (
+                topics.hdd_activity(),
+                hdd_activity_payload(&hdd_activity_rx.get()),
+                false,
+            ),
+            (
+                topics.power(),
+                power_payload(power_state_rx.get().power).to_owned(),
+                true,
+            ),
+            (
+                topics.wakeup_reason(),
+                wakeup_reason_payload(wakeup_reason_rx.get()),
+                true,
+            ),
+        ];
+        for (topic, payload, retain) in states {
+            if published.get(&topic) != Some(&payload) {
+                client.publish(&topic, payload.as_bytes(), retain).await?;
+                published.insert(topic, payload);
+            }
+        }
+        select! {
+            _ = power_led_rx.until_change() => {}
+            _ = hdd_activity_rx.until_change() => {}
+            _ = power_state_rx.until_change() => {}
+            _ = wakeup_reason_rx.until_change() => {}
+            message = messages.next() => {
+                let message = message?;
+                match topics.command(&message.topic, &message.payload) {
+                    Some(command) => {
+                        info!("MQTT command: {command:?}");
+                        let power_io = power_io.clone();
+                        // Pressing takes a while, and the states should still be published
+                        tokio::spawn(async move {
+                            power_io.run(command, PressSource::Mqtt).await;
+                        });
+                    }
+                    None => warn!("Unexpected MQTT message on {}", message.topic),
+                }
+            }
+        }
+    }
+}
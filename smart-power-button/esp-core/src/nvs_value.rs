@@ -0,0 +1,122 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use log::warn;
+use parking_lot::Mutex;
+use postcard::{from_bytes, to_allocvec};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use smart_power_button_common::machine::MachineId;
+
+use crate::value_channel::{value_channel, ValueReceiver, ValueSender};
+
+/// Where [`NvsValue`]s are saved. On the ESP this is a namespace in NVS.
+pub trait Storage: Send + 'static {
+    fn get_blob(&self, key: &str) -> anyhow::Result<Option<Vec<u8>>>;
+    fn set_blob(&mut self, key: &str, value: &[u8]) -> anyhow::Result<()>;
+    /// Does nothing if there is no blob for `key`
+    fn remove(&mut self, key: &str) -> anyhow::Result<()>;
+}
+
+/// Keeps blobs in memory, so they are gone after restarting. Clones share the same blobs.
+#[derive(Default, Clone)]
+pub struct MemoryStorage(Arc<Mutex<HashMap<String, Vec<u8>>>>);
+
+impl Storage for MemoryStorage {
+    fn get_blob(&self, key: &str) -> anyhow::Result<Option<Vec<u8>>> {
+        Ok(self.0.lock().get(key).cloned())
+    }
+
+    fn set_blob(&mut self, key: &str, value: &[u8]) -> anyhow::Result<()> {
+        self.0.lock().insert(key.to_owned(), value.to_vec());
+        Ok(())
+    }
+
+    fn remove(&mut self, key: &str) -> anyhow::Result<()> {
+        self.0.lock().remove(key);
+        Ok(())
+    }
+}
+
+/// Keeps the values of each [`crate::machine::Machine`] apart by adding the machine's id to the
+/// keys. The first machine uses the keys from before there were machines, so nothing has to be
+/// moved.
+#[derive(Clone)]
+pub struct MachineStorage<S> {
+    storage: S,
+    machine: MachineId,
+}
+
+impl<S: Storage> MachineStorage<S> {
+    pub fn new(storage: S, machine: MachineId) -> Self {
+        Self { storage, machine }
+    }
+
+    /// Keys are still short enough for the ESP if they're at most 14 characters long
+    fn key(&self, key: &str) -> String {
+        match self.machine {
+            0 => key.into(),
+            machine => format!("{key}{machine}"),
+        }
+    }
+}
+
+impl<S: Storage> Storage for MachineStorage<S> {
+    fn get_blob(&self, key: &str) -> anyhow::Result<Option<Vec<u8>>> {
+        self.storage.get_blob(&self.key(key))
+    }
+
+    fn set_blob(&mut self, key: &str, value: &[u8]) -> anyhow::Result<()> {
+        let key = self.key(key);
+        self.storage.set_blob(&key, value)
+    }
+
+    fn remove(&mut self, key: &str) -> anyhow::Result<()> {
+        let key = self.key(key);
+        self.storage.remove(&key)
+    }
+}
+
+/// A value that is saved in NVS with postcard, and can be watched for changes
+pub struct NvsValue<T> {
+    storage: Box<dyn Storage>,
+    key: &'static str,
+    value_sender: ValueSender<T>,
+}
+
+impl<T: Serialize + DeserializeOwned + Default> NvsValue<T> {
+    /// On the ESP, `key` can be at most 15 characters long. A saved value that can't be read, like
+    /// one from a firmware where the type was different, is erased and replaced with the default,
+    /// so the ESP still starts.
+    pub fn new(
+        mut storage: impl Storage,
+        key: &'static str,
+    ) -> anyhow::Result<(Self, ValueReceiver<T>)> {
+        let value = match storage.get_blob(key)? {
+            Some(bytes) => match from_bytes(&bytes) {
+                Ok(value) => value,
+                Err(e) => {
+                    warn!("Erasing {key}, which can't be read: {e}");
+                    storage.remove(key)?;
+                    Default::default()
+                }
+            },
+            None => Default::default(),
+        };
+        let (value_sender, value_receiver) = value_channel(value);
+        Ok((
+            Self {
+                storage: Box::new(storage),
+                key,
+                value_sender,
+            },
+            value_receiver,
+        ))
+    }
+
+    pub async fn set(&mut self, value: T) -> anyhow::Result<()> {
+        self.storage.set_blob(self.key, &to_allocvec(&value)?)?;
+        self.value_sender.update(value).await;
+        Ok(())
+    }
+}
@@ -0,0 +1,156 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use futures::{Stream, StreamExt};
+use hyper::body::Bytes;
+use log::{error, info, warn};
+use sha2::{Digest, Sha256};
+use smart_power_button_common::ota::{OtaError, OtaStatus};
+use tokio::sync::Mutex;
+
+use crate::value_channel::{value_channel, ValueReceiver, ValueSender};
+
+/// The OTA partition that isn't running. On the ESP this uses `esp_ota_*`.
+pub trait OtaPartition: Send + Sync + 'static {
+    /// The largest image that fits
+    fn max_size(&self) -> u64;
+    /// Erases the partition for an image of `size` bytes. This can block for seconds, so
+    /// [`Ota`] calls it on a blocking thread.
+    fn begin(&self, size: u64) -> anyhow::Result<Box<dyn OtaWriter>>;
+}
+
+/// An image being written. Dropping it without [`OtaWriter::finish`] throws the image away. Its
+/// methods are called on a blocking thread too.
+pub trait OtaWriter: Send {
+    fn write(&mut self, bytes: &[u8]) -> anyhow::Result<()>;
+    /// Checks the image and boots it after the next restart
+    fn finish(self: Box<Self>) -> anyhow::Result<()>;
+}
+
+/// Receives firmware updates. Clones share the same partition and status.
+#[derive(Clone)]
+pub struct Ota {
+    partition: Arc<dyn OtaPartition>,
+    /// Held while an update is being received, so there is only one at a time
+    busy: Arc<Mutex<()>>,
+    status_tx: Arc<ValueSender<OtaStatus>>,
+    pub status_rx: ValueReceiver<OtaStatus>,
+    restart: fn(),
+}
+
+impl Ota {
+    /// `restart` is called after an update is saved
+    pub fn new(partition: impl OtaPartition, restart: fn()) -> Self {
+        let (status_tx, status_rx) = value_channel(OtaStatus::Idle);
+        Self {
+            partition: Arc::new(partition),
+            busy: Default::default(),
+            status_tx: Arc::new(status_tx),
+            status_rx,
+            restart,
+        }
+    }
+
+    /// Writes an image of `size` bytes from `body`, checks it against `sha256`, and restarts
+    /// into it
+    pub async fn update<E: std::fmt::Display>(
+        &self,
+        size: u64,
+        sha256: [u8; 32],
+        body: impl Stream<Item = Result<Bytes, E>>,
+    ) -> Result<(), OtaError> {
+        let Ok(_busy) = self.busy.try_lock() else {
+            return Err(OtaError::Busy);
+        };
+        let result = self.receive(size, sha256, body).await;
+        match &result {
+            Ok(()) => {
+                info!("Firmware update saved. Restarting.");
+                self.status_tx.update(OtaStatus::Restarting).await;
+                let restart = self.restart;
+                tokio::spawn(async move {
+                    // Give the response time to get to the client
+                    tokio::time::sleep(Duration::from_secs(1)).await;
+                    restart();
+                });
+            }
+            Err(e) => {
+                error!("Firmware update failed: {e}");
+                self.status_tx.update(OtaStatus::Failed(e.clone())).await;
+            }
+        }
+        result
+    }
+
+    async fn receive<E: std::fmt::Display>(
+        &self,
+        size: u64,
+        sha256: [u8; 32],
+        body: impl Stream<Item = Result<Bytes, E>>,
+    ) -> Result<(), OtaError> {
+        if size == 0 {
+            return Err(OtaError::MissingSize);
+        }
+        let max_size = self.partition.max_size();
+        if size > max_size {
+            return Err(OtaError::TooLarge { size, max_size });
+        }
+        info!("Receiving a {size} byte firmware update");
+        let flash_error = |e: anyhow::Error| OtaError::Flash(format!("{e:#}"));
+        let partition = self.partition.clone();
+        let mut writer = blocking(move || partition.begin(size))
+            .await
+            .map_err(flash_error)?;
+        let mut hasher = Sha256::new();
+        let mut received = 0;
+        self.set_progress(received, size).await;
+        let mut body = std::pin::pin!(body);
+        while let Some(chunk) = body.next().await {
+            let chunk = match chunk {
+                Ok(chunk) => chunk,
+                // This is reported as not getting enough bytes
+                Err(e) => {
+                    warn!("Error receiving firmware update: {e}");
+                    break;
+                }
+            };
+            let chunk_len = chunk.len() as u64;
+            received += chunk_len;
+            if received > size {
+                break;
+            }
+            hasher.update(&chunk);
+            writer = blocking(move || writer.write(&chunk).map(|()| writer))
+                .await
+                .map_err(flash_error)?;
+            // Only when the percent changes, so the WebSocket isn't flooded
+            if (received - chunk_len) * 100 / size != received * 100 / size {
+                self.set_progress(received, size).await;
+            }
+        }
+        if received != size {
+            return Err(OtaError::SizeMismatch {
+                expected: size,
+                received,
+            });
+        }
+        if hasher.finalize().as_slice() != sha256 {
+            return Err(OtaError::HashMismatch);
+        }
+        blocking(move || writer.finish()).await.map_err(flash_error)
+    }
+
+    async fn set_progress(&self, received: u64, total: u64) {
+        self.status_tx
+            .update(OtaStatus::Receiving { received, total })
+            .await;
+    }
+}
+
+/// Runs a flash operation on a blocking thread, so erasing and writing don't stall the other tasks,
+/// like pressing buttons and answering the WebSocket
+async fn blocking<T: Send + 'static>(
+    f: impl FnOnce() -> anyhow::Result<T> + Send + 'static,
+) -> anyhow::Result<T> {
+    tokio::task::spawn_blocking(f).await?
+}
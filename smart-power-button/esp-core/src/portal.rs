@@ -0,0 +1,276 @@
+//! The page that the ESP serves on its own access point to set up Wi-Fi and the GPIO pins. It is
+//! plain HTML so that it works in the small browsers that phones open for captive portals.
+use std::sync::Arc;
+use std::time::Duration;
+
+use http_body_util::combinators::BoxBody;
+use http_body_util::BodyExt;
+use hyper::body::Bytes;
+use hyper::header::{HeaderValue, CONTENT_TYPE, LOCATION};
+use hyper::service::service_fn;
+use hyper::{Method, Request, Response, StatusCode};
+use hyper_util::rt::TokioIo;
+use log::{error, info};
+use smart_power_button_common::provisioning::{
+    PinConfig, ProvisioningForm, ScannedNetwork, WifiConfig, WifiNetwork, WifiSecurity,
+};
+use tokio::net::TcpListener;
+use tokio::sync::{Mutex, Notify};
+
+use crate::http_content_type::HTML;
+use crate::hyper_util::full;
+use crate::nvs_value::NvsValue;
+use crate::value_channel::ValueReceiver;
+use crate::Error;
+
+/// The page always has at least this many rows for networks
+const MIN_NETWORK_ROWS: usize = 3;
+
+#[derive(Clone)]
+pub struct PortalState {
+    /// What the ESP found while scanning, strongest first
+    pub networks: Arc<[ScannedNetwork]>,
+    pub wifi_config_tx: Arc<Mutex<NvsValue<WifiConfig>>>,
+    pub wifi_config_rx: ValueReceiver<WifiConfig>,
+    pub pin_config_tx: Arc<Mutex<NvsValue<PinConfig>>>,
+    pub pin_config_rx: ValueReceiver<PinConfig>,
+    /// Notified after new settings are saved. The ESP restarts to use them.
+    pub saved: Arc<Notify>,
+    /// Erases everything that the ESP saved and restarts
+    pub factory_reset: fn(),
+    /// Every other page redirects here, like `http://192.168.71.1/`
+    pub portal_url: Arc<str>,
+}
+
+/// Serves the portal page on `listener`
+pub async fn serve_portal(listener: TcpListener, state: PortalState) -> anyhow::Result<()> {
+    loop {
+        let (stream, _) = listener.accept().await?;
+        let state = state.clone();
+        tokio::spawn(async move {
+            if let Err(err) = hyper::server::conn::http1::Builder::new()
+                .serve_connection(
+                    TokioIo::new(stream),
+                    service_fn(move |req| handle_portal_request(req, state.clone())),
+                )
+                .await
+            {
+                error!("Error serving portal connection: {:?}", err);
+            }
+        });
+    }
+}
+
+fn html_response(status: StatusCode, body: String) -> Response<BoxBody<Bytes, hyper::Error>> {
+    let mut response = Response::new(full(body));
+    *response.status_mut() = status;
+    response
+        .headers_mut()
+        .insert(CONTENT_TYPE, HeaderValue::from_static(HTML));
+    response
+}
+
+async fn handle_portal_request(
+    req: Request<hyper::body::Incoming>,
+    state: PortalState,
+) -> Result<Response<BoxBody<Bytes, hyper::Error>>, Error> {
+    match (req.method(), req.uri().path()) {
+        (&Method::GET, "/") => Ok(html_response(StatusCode::OK, render_page(&state, None))),
+        (&Method::POST, "/") => {
+            let body = req.collect().await?.to_bytes();
+            match ProvisioningForm::parse(&body) {
+                Ok(form) => {
+                    info!(
+                        "Saving {} Wi-Fi networks and pins {:?}",
+                        form.wifi.networks.len(),
+                        form.pins
+                    );
+                    let result = async {
+                        state.wifi_config_tx.lock().await.set(form.wifi).await?;
+                        state.pin_config_tx.lock().await.set(form.pins).await
+                    };
+                    match result.await {
+                        Ok(()) => {
+                            state.saved.notify_one();
+                            Ok(html_response(
+                                StatusCode::OK,
+                                render_message(
+                                    "Saved. The ESP is restarting and will join your Wi-Fi.",
+                                ),
+                            ))
+                        }
+                        Err(e) => {
+                            error!("Error saving settings: {e:#?}");
+                            Ok(html_response(
+                                StatusCode::INTERNAL_SERVER_ERROR,
+                                render_message("Error saving settings"),
+                            ))
+                        }
+                    }
+                }
+                Err(e) => Ok(html_response(
+                    StatusCode::BAD_REQUEST,
+                    render_page(&state, Some(&e.to_string())),
+                )),
+            }
+        }
+        (&Method::POST, "/factory_reset") => {
+            let factory_reset = state.factory_reset;
+            tokio::spawn(async move {
+                // Give the response time to get to the browser
+                tokio::time::sleep(Duration::from_secs(1)).await;
+                factory_reset();
+            });
+            Ok(html_response(
+                StatusCode::OK,
+                render_message("Erasing everything and restarting..."),
+            ))
+        }
+        // Phones check for captive portals by loading a page and seeing if they get what they
+        // expect, so send them to the portal page instead
+        _ => {
+            let mut response = html_response(StatusCode::FOUND, String::new());
+            response
+                .headers_mut()
+                .insert(LOCATION, HeaderValue::from_str(&state.portal_url)?);
+            Ok(response)
+        }
+    }
+}
+
+/// Escapes text for use in HTML text and quoted attributes
+fn escape(text: &str) -> String {
+    text.chars()
+        .map(|c| match c {
+            '&' => "&amp;".into(),
+            '<' => "&lt;".into(),
+            '>' => "&gt;".into(),
+            '"' => "&quot;".into(),
+            '\'' => "&#39;".into(),
+            c => c.to_string(),
+        })
+        .collect()
+}
+
+fn render_document(body: &str) -> String {
+    format!(
+        "<!DOCTYPE html>\
+<html><head><meta charset=\"utf-8\"><meta name=\"viewport\" content=\"width=device-width, initial-scale=1\">\
+<title>Smart Power Button Setup</title></head><body><h1>Smart Power Button Setup</h1>{body}</body></html>"
+    )
+}
+
+fn render_message(message: &str) -> String {
+    render_document(&format!("<p>{}</p>", escape(message)))
+}
+
+fn describe_security(security: WifiSecurity) -> &'static str {
+    match security {
+        WifiSecurity::Auto => "other",
+        WifiSecurity::Open => "open",
+        WifiSecurity::Wpa2 => "WPA2",
+        WifiSecurity::Wpa3 => "WPA3",
+    }
+}
+
+fn render_network_row(index: usize, network: Option<&WifiNetwork>) -> String {
+    let ssid = network
+        .map(|network| escape(&network.ssid))
+        .unwrap_or_default();
+    let priority = network.map_or(0, |network| network.priority);
+    let security = network.map_or(WifiSecurity::Auto, |network| network.security);
+    let security_options = [
+        ("auto", "Automatic", WifiSecurity::Auto),
+        ("open", "Open", WifiSecurity::Open),
+        ("wpa2", "WPA2", WifiSecurity::Wpa2),
+        ("wpa3", "WPA3", WifiSecurity::Wpa3),
+    ]
+    .map(|(value, label, option)| {
+        let selected = if option == security { " selected" } else { "" };
+        format!("<option value=\"{value}\"{selected}>{label}</option>")
+    })
+    .concat();
+    let static_ip = network.and_then(|network| network.static_ip);
+    let ip_field = |name: &str, label: &str, value: Option<String>| {
+        format!(
+            "<label>{label} <input name=\"{name}\" value=\"{}\"></label> ",
+            value.unwrap_or_default()
+        )
+    };
+    // The fields must stay in this order, because they belong to the `ssid` before them
+    format!(
+        "<fieldset><legend>Network {}</legend>\
+<p><label>SSID <input name=\"ssid\" list=\"networks\" maxlength=\"32\" value=\"{ssid}\"></label> \
+<label>Password <input name=\"password\" type=\"password\" maxlength=\"63\"></label></p>\
+<p><label>Priority <input name=\"priority\" type=\"number\" min=\"0\" max=\"255\" value=\"{priority}\"></label> \
+<label>Security <select name=\"security\">{security_options}</select></label></p>\
+<p>Static IP (leave empty for DHCP): {}{}{}{}</p></fieldset>",
+        index + 1,
+        ip_field("ip", "IP", static_ip.map(|static_ip| static_ip.ip.to_string())),
+        ip_field(
+            "prefix_len",
+            "Prefix length",
+            static_ip.map(|static_ip| static_ip.prefix_len.to_string())
+        ),
+        ip_field(
+            "gateway",
+            "Gateway",
+            static_ip.map(|static_ip| static_ip.gateway.to_string())
+        ),
+        ip_field(
+            "dns",
+            "DNS",
+            static_ip
+                .and_then(|static_ip| static_ip.dns)
+                .map(|dns| dns.to_string())
+        ),
+    )
+}
+
+fn render_page(state: &PortalState, error: Option<&str>) -> String {
+    let error = error
+        .map(|error| format!("<p><strong>{}</strong></p>", escape(error)))
+        .unwrap_or_default();
+    let scanned_networks = state
+        .networks
+        .iter()
+        .map(|network| {
+            format!(
+                "<option value=\"{}\">{} dBm, {}</option>",
+                escape(&network.ssid),
+                network.signal_strength,
+                describe_security(network.security)
+            )
+        })
+        .collect::<String>();
+    // Passwords aren't sent back, so they have to be entered again
+    let wifi_config = state.wifi_config_rx.get();
+    let network_rows = (0..(wifi_config.networks.len() + 1).max(MIN_NETWORK_ROWS))
+        .map(|i| render_network_row(i, wifi_config.networks.get(i)))
+        .collect::<String>();
+    let hostname = escape(&wifi_config.hostname);
+    let pins = state.pin_config_rx.get();
+    let pin_rows = [
+        ("power_led", "Power LED", pins.power_led),
+        ("hdd_led", "HDD LED", pins.hdd_led),
+        ("power_button", "Power button", pins.power_button),
+        ("reset_button", "Reset button", pins.reset_button),
+    ]
+    .map(|(name, label, pin)| {
+        format!(
+            "<p><label>{label} <input name=\"{name}\" type=\"number\" min=\"0\" max=\"255\" value=\"{pin}\" required></label></p>"
+        )
+    })
+    .concat();
+    render_document(&format!(
+        "{error}<form method=\"post\" action=\"/\">\
+<h2>Wi-Fi</h2><p>Networks with a higher priority are tried first. If networks have the same priority, the strongest one is tried first. Leave the password empty for open networks.</p>\
+<datalist id=\"networks\">{scanned_networks}</datalist>{network_rows}\
+<p><label>Hostname (leave empty for the default) <input name=\"hostname\" maxlength=\"30\" value=\"{hostname}\"></label></p>\
+<h2>GPIO pins</h2>{pin_rows}\
+<button type=\"submit\">Save and restart</button></form>\
+<h2>Factory reset</h2><p>Erases the Wi-Fi networks, pins, logins, schedule, history, and everything else.</p>\
+<form method=\"post\" action=\"/factory_reset\" onsubmit=\"return confirm('Erase everything?')\">\
+<button type=\"submit\">Factory reset</button></form>"
+    ))
+}
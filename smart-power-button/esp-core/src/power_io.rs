@@ -0,0 +1,146 @@
+use crate::button::Button;
+use crate::hdd_activity::watch_hdd_activity;
+use crate::history::{watch_history, History};
+use crate::value_channel::{value_channel, ValueReceiver, ValueSender};
+use crate::watch_power::watch_power;
+use log::{error, warn};
+use smart_power_button_common::hdd::HddActivity;
+use smart_power_button_common::history::{HistoryEventKind, PressSource};
+use smart_power_button_common::power_led::BlinkConfig;
+use smart_power_button_common::press::{ButtonKind, PressConfig};
+use smart_power_button_common::{MessageToEsp, Power, PowerState, WakeupReason};
+use std::{future::Future, sync::Arc};
+use tokio::join;
+
+pub struct PowerIo {
+    pub power_led_rx: ValueReceiver<bool>,
+    pub hdd_led_rx: ValueReceiver<bool>,
+    /// Sent instead of [`PowerIo::hdd_led_rx`] to clients that didn't ask for every change
+    pub hdd_activity_rx: ValueReceiver<HddActivity>,
+    pub power_button: Button,
+    pub reset_button: Button,
+    pub wakeup_reason_tx: Arc<ValueSender<Option<WakeupReason>>>,
+    pub wakeup_reason_rx: ValueReceiver<Option<WakeupReason>>,
+    pub power_rx: ValueReceiver<Option<Power>>,
+    pub power_state_rx: ValueReceiver<PowerState>,
+    pub history: History,
+    pub press_config_rx: ValueReceiver<PressConfig>,
+    pub blink_config_rx: ValueReceiver<BlinkConfig>,
+}
+
+impl Clone for PowerIo {
+    fn clone(&self) -> Self {
+        Self {
+            power_led_rx: self.power_led_rx.clone(),
+            hdd_led_rx: self.hdd_led_rx.clone(),
+            hdd_activity_rx: self.hdd_activity_rx.clone(),
+            power_button: self.power_button.clone(),
+            reset_button: self.reset_button.clone(),
+            wakeup_reason_tx: self.wakeup_reason_tx.clone(),
+            wakeup_reason_rx: self.wakeup_reason_rx.clone(),
+            power_rx: self.power_rx.clone(),
+            power_state_rx: self.power_state_rx.clone(),
+            history: self.history.clone(),
+            press_config_rx: self.press_config_rx.clone(),
+            blink_config_rx: self.blink_config_rx.clone(),
+        }
+    }
+}
+
+impl PowerIo {
+    /// The LEDs are `true` when they are on
+    pub fn new(
+        power_led_rx: ValueReceiver<bool>,
+        hdd_led_rx: ValueReceiver<bool>,
+        power_button: Button,
+        reset_button: Button,
+        history: History,
+        press_config_rx: ValueReceiver<PressConfig>,
+        blink_config_rx: ValueReceiver<BlinkConfig>,
+    ) -> (impl Future<Output = ()> + Sized, Self) {
+        let (power_future, power_rx, power_state_rx) =
+            watch_power(power_led_rx.clone(), blink_config_rx.clone());
+        let (wakeup_reason_tx, wakeup_reason_rx) = value_channel(None);
+        let (hdd_activity_future, hdd_activity_rx) = watch_hdd_activity(hdd_led_rx.clone());
+        let history_future = watch_history(power_rx.clone(), hdd_led_rx.clone(), history.clone());
+        let power_io = Self {
+            power_led_rx,
+            hdd_led_rx,
+            hdd_activity_rx,
+            power_button,
+            reset_button,
+            wakeup_reason_tx: Arc::new(wakeup_reason_tx),
+            wakeup_reason_rx,
+            power_rx,
+            power_state_rx,
+            history,
+            press_config_rx,
+            blink_config_rx,
+        };
+        (
+            async {
+                // TODO: Error handling
+                let _ = join!(power_future, hdd_activity_future, history_future);
+            },
+            power_io,
+        )
+    }
+
+    /// Presses the buttons for `message` and records it in the history. If this turns on the
+    /// computer, the wakeup reason is set from `source`.
+    pub async fn run(&self, message: MessageToEsp, source: PressSource) {
+        if let Err(e) = message.validate() {
+            warn!("Not running {message:?} from {source:?}: {e}");
+            return;
+        }
+        let press_config = self.press_config_rx.get();
+        // The button is still pressed if the history can't be saved
+        let result = self
+            .history
+            .record(HistoryEventKind::ButtonPress {
+                message: message.clone(),
+                source,
+            })
+            .await;
+        if let Err(e) = result {
+            error!("{e:#}");
+        }
+        match message {
+            MessageToEsp::ShortPressPowerButton(should_turn_on_tv) => {
+                self.set_wakeup_reason(source, should_turn_on_tv).await;
+                self.power_button.press(press_config.short_press).await
+            }
+            MessageToEsp::LongPressPowerButton => {
+                self.power_button.press(press_config.long_press).await
+            }
+            MessageToEsp::ShortPressResetButton => {
+                self.reset_button.press(press_config.reset_press).await
+            }
+            MessageToEsp::Press { button, pattern } => {
+                let button = match button {
+                    ButtonKind::Power => {
+                        self.set_wakeup_reason(source, false).await;
+                        &self.power_button
+                    }
+                    ButtonKind::Reset => &self.reset_button,
+                };
+                button.press_pattern(&pattern).await
+            }
+        }
+    }
+
+    /// Sets the wakeup reason from `source` if the power button is about to turn on the computer
+    async fn set_wakeup_reason(&self, source: PressSource, should_turn_on_tv: bool) {
+        if let Some(Power::Off) | Some(Power::Suspend) = self.power_rx.get() {
+            let wakeup_reason = source.wakeup_reason(should_turn_on_tv);
+            self.wakeup_reason_tx.update(Some(wakeup_reason)).await;
+            let result = self
+                .history
+                .record(HistoryEventKind::WakeupReason(wakeup_reason))
+                .await;
+            if let Err(e) = result {
+                error!("{e:#}");
+            }
+        }
+    }
+}
@@ -0,0 +1,40 @@
+use hyper::service::service_fn;
+use hyper::Request;
+use hyper_util::rt::TokioIo;
+use log::{error, info};
+use tokio::net::TcpListener;
+
+use crate::handle_request::handle_request;
+use crate::server_state::ServerState;
+
+/// Serves the web page, API, and WebSocket on `listener`
+pub async fn serve(listener: TcpListener, server_state: ServerState) -> anyhow::Result<()> {
+    loop {
+        info!("Waiting for new connection on socket: {listener:?}");
+        let (stream, _) = listener.accept().await?;
+
+        let io = TokioIo::new(stream);
+        let server_state = server_state.clone();
+        tokio::spawn({
+            async move {
+                info!("Spawned handler!");
+                if let Err(err) = hyper::server::conn::http1::Builder::new()
+                    .keep_alive(true)
+                    // `service_fn` converts our function in a `Service`
+                    .serve_connection(
+                        io,
+                        service_fn({
+                            move |req: Request<hyper::body::Incoming>| {
+                                handle_request(req, server_state.clone())
+                            }
+                        }),
+                    )
+                    .with_upgrades()
+                    .await
+                {
+                    error!("Error serving connection: {:?}", err);
+                }
+            }
+        });
+    }
+}
@@ -0,0 +1,366 @@
+use crate::bluetooth_wake::BleDiscovery;
+use crate::button::Button;
+use crate::machine::Machine;
+use crate::power_io::PowerIo;
+use crate::value_channel::ValueReceiver;
+use crate::websocket_upgrade::WebSocket;
+use crate::Error;
+use futures::stream::{FuturesUnordered, SplitSink};
+use futures::{SinkExt, StreamExt};
+use log::warn;
+use smart_power_button_common::history::PressSource;
+use smart_power_button_common::machine::MachineId;
+use smart_power_button_common::ota::OtaStatus;
+use smart_power_button_common::protocol::{
+    Capability, FrameToEsp, FrameToWeb, Hello, ProtocolError,
+};
+use smart_power_button_common::MessageToWeb;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use tokio::sync::broadcast::error::RecvError;
+use tokio::sync::Mutex;
+use tokio_tungstenite::tungstenite::Message;
+
+type Writer = Arc<Mutex<SplitSink<WebSocket, Message>>>;
+type Task = Pin<Box<dyn Future<Output = anyhow::Result<()>> + Send>>;
+
+/// What this ESP can do. Sent to the web page in the handshake.
+fn capabilities() -> Vec<Capability> {
+    vec![
+        Capability::PowerButton,
+        Capability::ResetButton,
+        Capability::History,
+        Capability::PowerState,
+        Capability::Ota,
+        Capability::Intents,
+        Capability::PressPatterns,
+        Capability::PowerLedCalibration,
+        Capability::HddActivity,
+        Capability::BluetoothDiscovery,
+    ]
+}
+
+async fn send_message(
+    w: &Writer,
+    machine: Option<MachineId>,
+    message: MessageToWeb,
+) -> anyhow::Result<()> {
+    w.lock()
+        .await
+        .send(Message::Binary(
+            FrameToWeb::Message { machine, message }.encode(),
+        ))
+        .await?;
+    Ok(())
+}
+
+/// Sends the value now and every time it changes
+fn watch_value<T: Clone + Send + Sync + 'static>(
+    w: Writer,
+    machine: Option<MachineId>,
+    mut rx: ValueReceiver<T>,
+    message: fn(T) -> MessageToWeb,
+) -> Task {
+    Box::pin(async move {
+        loop {
+            send_message(&w, machine, message(rx.get())).await?;
+            rx.until_change().await;
+        }
+    })
+}
+
+fn watch_button(
+    w: Writer,
+    machine: MachineId,
+    button: Button,
+    message: fn(bool) -> MessageToWeb,
+) -> Task {
+    Box::pin(async move {
+        loop {
+            send_message(&w, Some(machine), message(button.is_pressed().await)).await?;
+            button.until_change().await;
+        }
+    })
+}
+
+/// Sends everything about one machine
+fn watch_machine(w: &Writer, id: MachineId, machine: &Machine, client_hello: &Hello) -> Vec<Task> {
+    let PowerIo {
+        power_led_rx,
+        hdd_led_rx,
+        hdd_activity_rx,
+        power_button,
+        reset_button,
+        history,
+        power_state_rx,
+        wakeup_reason_rx,
+        ..
+    } = machine.power_io.clone();
+    let machine_id = Some(id);
+    let mut tasks = vec![
+        watch_value(
+            w.clone(),
+            machine_id,
+            power_led_rx,
+            MessageToWeb::PowerLedStatus,
+        ),
+        watch_value(
+            w.clone(),
+            machine_id,
+            hdd_activity_rx,
+            MessageToWeb::HddActivity,
+        ),
+        watch_button(w.clone(), id, power_button, MessageToWeb::PowerButtonStatus),
+        watch_button(w.clone(), id, reset_button, MessageToWeb::ResetButtonStatus),
+        watch_value(
+            w.clone(),
+            machine_id,
+            power_state_rx,
+            MessageToWeb::PowerState,
+        ),
+        watch_value(
+            w.clone(),
+            machine_id,
+            wakeup_reason_rx,
+            MessageToWeb::WakeupReason,
+        ),
+        watch_value(
+            w.clone(),
+            machine_id,
+            machine.intents.status_rx.clone(),
+            MessageToWeb::IntentStatus,
+        ),
+        Box::pin({
+            let w = w.clone();
+            let mut history_rx = history.subscribe();
+            async move {
+                loop {
+                    match history_rx.recv().await {
+                        Ok(event) => {
+                            send_message(&w, machine_id, MessageToWeb::HistoryEvent(event)).await?
+                        }
+                        Err(RecvError::Lagged(skipped)) => {
+                            warn!("Skipped {skipped} history events for a WebSocket client");
+                        }
+                        Err(RecvError::Closed) => break Ok(()),
+                    }
+                }
+            }
+        }),
+    ];
+    // The LED can change many times a second, so only clients that ask get every change
+    if client_hello.has(Capability::RawHddLed) {
+        tasks.push(watch_value(
+            w.clone(),
+            machine_id,
+            hdd_led_rx,
+            MessageToWeb::HddLedStatus,
+        ));
+    }
+    tasks
+}
+
+/// Handle a websocket connection.
+pub async fn serve_websocket(
+    websocket: impl Future<Output = Result<WebSocket, hyper::Error>>,
+    machines: Arc<[Machine]>,
+    ota_status_rx: ValueReceiver<OtaStatus>,
+    ble_discovery: BleDiscovery,
+) -> Result<(), Error> {
+    let websocket = websocket.await?;
+    let (w, mut r) = websocket.split();
+    let w = Arc::new(Mutex::new(w));
+
+    // The web page has to say hello before anything else, so that we can reject old versions
+    let handshake = loop {
+        match r.next().await {
+            Some(message) => {
+                if let Message::Binary(msg) = message? {
+                    break match FrameToEsp::decode(&msg) {
+                        Ok(FrameToEsp::Hello(hello)) => hello.check_version().map(|()| hello),
+                        Ok(
+                            FrameToEsp::Request { .. }
+                            | FrameToEsp::Intent { .. }
+                            | FrameToEsp::BluetoothScan { .. },
+                        ) => Err(ProtocolError::HandshakeRequired),
+                        Err(e) => Err(e),
+                    };
+                }
+            }
+            None => return Ok(()),
+        }
+    };
+    let client_hello = match handshake {
+        Ok(hello) => hello,
+        Err(error) => {
+            warn!("WebSocket handshake failed: {error:?}");
+            let mut w = w.lock().await;
+            w.send(Message::Binary(
+                FrameToWeb::Error { id: None, error }.encode(),
+            ))
+            .await?;
+            w.close().await?;
+            return Ok(());
+        }
+    };
+    w.lock()
+        .await
+        .send(Message::Binary(
+            FrameToWeb::Hello(Hello {
+                machines: machines
+                    .iter()
+                    .map(|machine| machine.name.to_string())
+                    .collect(),
+                ..Hello::new(capabilities())
+            })
+            .encode(),
+        ))
+        .await?;
+
+    let mut futures = machines
+        .iter()
+        .zip(0..)
+        .flat_map(|(machine, id)| watch_machine(&w, id, machine, &client_hello))
+        .collect::<Vec<_>>();
+    futures.push(watch_value(
+        w.clone(),
+        None,
+        ota_status_rx,
+        MessageToWeb::OtaStatus,
+    ));
+    futures.push(Box::pin({
+        let w = w.clone();
+        async move {
+            while let Some(message) = r.next().await {
+                if let Message::Binary(msg) = message? {
+                    match FrameToEsp::decode(&msg) {
+                        Ok(FrameToEsp::Request {
+                            id,
+                            machine,
+                            message,
+                        }) => {
+                            let power_io = machines
+                                .get(machine as usize)
+                                .map(|machine| machine.power_io.clone());
+                            let w = w.clone();
+                            tokio::spawn(async move {
+                                let frame = match (power_io, message.validate()) {
+                                    (None, _) => FrameToWeb::Error {
+                                        id: Some(id),
+                                        error: ProtocolError::UnknownMachine(machine),
+                                    },
+                                    (Some(power_io), Ok(())) => {
+                                        power_io.run(message, PressSource::Web).await;
+                                        // Only ack after the press is done, so the web page knows it actually happened
+                                        FrameToWeb::Ack(id)
+                                    }
+                                    (Some(_), Err(error)) => FrameToWeb::Error {
+                                        id: Some(id),
+                                        error: ProtocolError::Press(error),
+                                    },
+                                };
+                                if let Err(e) =
+                                    w.lock().await.send(Message::Binary(frame.encode())).await
+                                {
+                                    warn!("Error sending result of request {id}: {e:?}");
+                                }
+                            });
+                        }
+                        Ok(FrameToEsp::Intent {
+                            id,
+                            machine,
+                            intent,
+                        }) => {
+                            let intents = machines
+                                .get(machine as usize)
+                                .map(|machine| machine.intents.clone());
+                            let w = w.clone();
+                            tokio::spawn(async move {
+                                let result = match intents {
+                                    Some(intents) => intents
+                                        .run(intent, PressSource::Web)
+                                        .await
+                                        .map_err(ProtocolError::Intent),
+                                    None => Err(ProtocolError::UnknownMachine(machine)),
+                                };
+                                let frame = match result {
+                                    Ok(_) => FrameToWeb::Ack(id),
+                                    Err(error) => FrameToWeb::Error {
+                                        id: Some(id),
+                                        error,
+                                    },
+                                };
+                                if let Err(e) =
+                                    w.lock().await.send(Message::Binary(frame.encode())).await
+                                {
+                                    warn!("Error sending result of request {id}: {e:?}");
+                                }
+                            });
+                        }
+                        Ok(FrameToEsp::BluetoothScan { id, duration }) => {
+                            let ble_discovery = ble_discovery.clone();
+                            let w = w.clone();
+                            tokio::spawn(async move {
+                                let result = async {
+                                    let mut discovery = ble_discovery.discover(duration).await?;
+                                    while let Some(device) = discovery.next_device().await {
+                                        send_message(
+                                            &w,
+                                            None,
+                                            MessageToWeb::BluetoothDevice(device),
+                                        )
+                                        .await?;
+                                    }
+                                    discovery.finish().await
+                                };
+                                let frame = match result.await {
+                                    Ok(_) => FrameToWeb::Ack(id),
+                                    Err(e) => {
+                                        warn!("Bluetooth scan {id} failed: {e:?}");
+                                        FrameToWeb::Error {
+                                            id: Some(id),
+                                            error: ProtocolError::BluetoothScanFailed,
+                                        }
+                                    }
+                                };
+                                if let Err(e) =
+                                    w.lock().await.send(Message::Binary(frame.encode())).await
+                                {
+                                    warn!("Error sending result of request {id}: {e:?}");
+                                }
+                            });
+                        }
+                        Ok(FrameToEsp::Hello(_)) => {
+                            w.lock()
+                                .await
+                                .send(Message::Binary(
+                                    FrameToWeb::Error {
+                                        id: None,
+                                        error: ProtocolError::UnexpectedHello,
+                                    }
+                                    .encode(),
+                                ))
+                                .await?;
+                        }
+                        Err(error) => {
+                            warn!("Error parsing message: {error:?}");
+                            w.lock()
+                                .await
+                                .send(Message::Binary(
+                                    FrameToWeb::Error { id: None, error }.encode(),
+                                ))
+                                .await?;
+                        }
+                    }
+                }
+            }
+            Ok(())
+        }
+    }));
+    let mut iter = futures.into_iter().collect::<FuturesUnordered<_>>();
+    while let Some(result) = iter.next().await {
+        result?
+    }
+    Ok(())
+}
@@ -0,0 +1,39 @@
+use std::sync::Arc;
+
+use smart_power_button_common::auth::AuthToken;
+use smart_power_button_common::machine::MachinesConfig;
+use smart_power_button_common::mqtt::MqttConfig;
+use smart_power_button_common::wifi::WifiStatus;
+use tokio::sync::Mutex;
+
+use crate::bluetooth_wake::BleDiscovery;
+use crate::machine::Machine;
+use crate::nvs_value::NvsValue;
+use crate::ota::Ota;
+use crate::static_assets::StaticAsset;
+use crate::value_channel::ValueReceiver;
+
+/// Everything that the HTTP and WebSocket handlers need
+#[derive(Clone)]
+pub struct ServerState {
+    /// Never empty. The first machine is the one on the pins from the portal.
+    pub machines: Arc<[Machine]>,
+    /// Used when the ESP starts, so it can be different from `machines`
+    pub machines_config_tx: Arc<Mutex<NvsValue<MachinesConfig>>>,
+    pub machines_config_rx: ValueReceiver<MachinesConfig>,
+    pub ble_discovery: BleDiscovery,
+    pub auth_tokens_tx: Arc<Mutex<NvsValue<Vec<AuthToken>>>>,
+    pub auth_tokens_rx: ValueReceiver<Vec<AuthToken>>,
+    pub wifi_status_rx: ValueReceiver<WifiStatus>,
+    pub mqtt_config_tx: Arc<Mutex<NvsValue<MqttConfig>>>,
+    pub mqtt_config_rx: ValueReceiver<MqttConfig>,
+    pub ota: Ota,
+    /// Clients log in with this to get a token
+    pub pairing_password: Arc<str>,
+    /// Fills the buffer with random bytes that are good enough for tokens
+    pub random_bytes: fn(&mut [u8]),
+    /// Erases everything that the ESP saved, including the Wi-Fi networks, and restarts
+    pub factory_reset: fn(),
+    /// Served for paths that aren't endpoints, usually [`crate::static_assets::WEB_PAGE`]
+    pub static_assets: &'static [StaticAsset],
+}
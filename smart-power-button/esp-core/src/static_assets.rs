@@ -0,0 +1,20 @@
+//! Files that are served as they are, like the web page built by Trunk
+
+/// A file, with compressed copies that were made when building
+#[derive(Debug)]
+pub struct StaticAsset {
+    /// Without the leading slash, like `index.html`
+    pub path: &'static str,
+    pub contents: &'static [u8],
+    /// `None` if gzip didn't make it smaller
+    pub gzip: Option<&'static [u8]>,
+    /// `None` if Brotli didn't make it smaller
+    pub brotli: Option<&'static [u8]>,
+    /// Hex of the start of the SHA-256 of `contents`, for the ETags
+    pub hash: &'static str,
+    /// Seconds since the Unix epoch, for `Last-Modified`
+    pub modified: u64,
+}
+
+/// The web page from `../web/dist`. Empty without the `static-files` feature.
+pub const WEB_PAGE: &[StaticAsset] = include!(concat!(env!("OUT_DIR"), "/web_page.rs"));
@@ -10,7 +10,7 @@ pub struct ValueSender<T> {
 }
 
 impl<T> ValueSender<T> {
-    pub fn get(&self) -> RwLockReadGuard<T> {
+    pub fn get(&self) -> RwLockReadGuard<'_, T> {
         self.value.read()
     }
 
@@ -18,6 +18,13 @@ impl<T> ValueSender<T> {
         *self.value.write() = value;
         let _ = self.sender.send(());
     }
+
+    /// Sets the value and returns the old one
+    pub async fn replace(&self, value: T) -> T {
+        let old_value = std::mem::replace(&mut *self.value.write(), value);
+        let _ = self.sender.send(());
+        old_value
+    }
 }
 
 impl<T: PartialEq> ValueSender<T> {
@@ -50,11 +57,14 @@ impl<T: Clone> ValueReceiver<T> {
 pub fn value_channel<T>(initial_value: T) -> (ValueSender<T>, ValueReceiver<T>) {
     let value = Arc::new(RwLock::new(initial_value));
     let (sender, _) = broadcast::channel(16);
-    (ValueSender {
-        value: value.clone(),
-        sender: sender.clone(),
-    }, ValueReceiver {
-        value: value.clone(),
-        sender: sender.clone(),
-    })
-}
\ No newline at end of file
+    (
+        ValueSender {
+            value: value.clone(),
+            sender: sender.clone(),
+        },
+        ValueReceiver {
+            value: value.clone(),
+            sender: sender.clone(),
+        },
+    )
+}
@@ -0,0 +1,103 @@
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::sync::Arc;
+use std::time::Duration;
+
+use futures::future::join_all;
+use log::{error, info};
+use smart_power_button_common::history::PressSource;
+use smart_power_button_common::wake_on_lan::WakeOnLanConfig;
+use smart_power_button_common::{MessageToEsp, Power};
+use tokio::join;
+use tokio::net::UdpSocket;
+use tokio::sync::mpsc;
+use tokio::time::{sleep, Instant};
+
+use crate::machine::Machine;
+use crate::value_channel::ValueReceiver;
+
+/// Wake-on-LAN tools often send a few packets, and it takes a while to see that the computer is
+/// on, so packets are ignored for this long after pressing the power button
+const COOLDOWN: Duration = Duration::from_secs(10);
+
+/// Binds a socket on every interface for each port. Ports that can't be bound are logged and
+/// skipped.
+pub async fn bind_wake_on_lan_sockets(ports: &[u16]) -> Vec<UdpSocket> {
+    let mut sockets = Vec::with_capacity(ports.len());
+    for &port in ports {
+        match UdpSocket::bind(SocketAddr::from((Ipv4Addr::UNSPECIFIED, port))).await {
+            Ok(socket) => sockets.push(socket),
+            Err(e) => error!("Error listening for Wake-on-LAN on port {port}: {e}"),
+        }
+    }
+    sockets
+}
+
+/// Sends the machine and the address of everything that sends a magic packet for one of the
+/// machines
+async fn receive_magic_packets(
+    socket: UdpSocket,
+    config_rxs: Vec<ValueReceiver<WakeOnLanConfig>>,
+    senders_tx: mpsc::Sender<(usize, IpAddr)>,
+) {
+    let mut buffer = [0; 512];
+    loop {
+        match socket.recv_from(&mut buffer).await {
+            Ok((len, from)) => {
+                let machine = config_rxs
+                    .iter()
+                    .position(|config_rx| config_rx.get().is_for_computer(&buffer[..len]));
+                if let Some(machine) = machine {
+                    // If the last packet is still being handled, this one isn't needed
+                    let _ = senders_tx.try_send((machine, from.ip()));
+                }
+            }
+            Err(e) => {
+                error!("Error receiving Wake-on-LAN packets: {e}");
+                sleep(Duration::from_secs(1)).await;
+            }
+        }
+    }
+}
+
+/// Short presses the power button of a machine when a magic packet for it comes to any of the
+/// `sockets` while it is off or suspended
+pub async fn wake_on_lan(sockets: Vec<UdpSocket>, machines: Arc<[Machine]>) {
+    let config_rxs = machines
+        .iter()
+        .map(|machine| machine.wake_on_lan_config_rx.clone())
+        .collect::<Vec<_>>();
+    let (senders_tx, mut senders_rx) = mpsc::channel(machines.len());
+    let receive_future = join_all(
+        sockets
+            .into_iter()
+            .map(|socket| receive_magic_packets(socket, config_rxs.clone(), senders_tx.clone())),
+    );
+    let press_future = async {
+        let mut cooldowns_until = vec![None; machines.len()];
+        while let Some((id, sender)) = senders_rx.recv().await {
+            let cooldown_until: &mut Option<Instant> = &mut cooldowns_until[id];
+            if cooldown_until.is_some_and(|until| Instant::now() < until) {
+                continue;
+            }
+            let machine = &machines[id];
+            let power = machine.power_io.power_rx.get();
+            if !matches!(power, Some(Power::Off) | Some(Power::Suspend)) {
+                info!(
+                    "Ignoring Wake-on-LAN for {} from {sender} because the power is {power:?}",
+                    machine.name
+                );
+                continue;
+            }
+            info!("Wake-on-LAN for {} from {sender}. Waking...", machine.name);
+            machine
+                .power_io
+                .run(
+                    MessageToEsp::ShortPressPowerButton(false),
+                    PressSource::WakeOnLan(sender),
+                )
+                .await;
+            *cooldown_until = Some(Instant::now() + COOLDOWN);
+        }
+    };
+    join!(receive_future, press_future);
+}
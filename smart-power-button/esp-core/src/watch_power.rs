@@ -0,0 +1,100 @@
+use std::time::Duration;
+
+use futures::Future;
+use smart_power_button_common::power_led::{
+    calibrate, BlinkConfig, Calibration, CalibrationError, PowerClassifier,
+    MAX_CALIBRATION_DURATION,
+};
+use smart_power_button_common::{Power, PowerState};
+use tokio::select;
+use tokio::time::{sleep, sleep_until, Instant};
+
+use crate::clock::unix_time;
+use crate::value_channel::{value_channel, ValueReceiver, ValueSender};
+
+async fn set_power(
+    tx: &ValueSender<Option<Power>>,
+    state_tx: &ValueSender<PowerState>,
+    power: Power,
+) {
+    let is_changed = *tx.get() != Some(power);
+    if is_changed {
+        tx.update(Some(power)).await;
+        state_tx
+            .update(PowerState {
+                power: Some(power),
+                since: unix_time(),
+            })
+            .await;
+    }
+    log::info!("Detected Power: {power:?}");
+}
+
+/// Figures out the power from the power LED with [`PowerClassifier`]. The config can change
+/// while this runs.
+pub fn watch_power(
+    mut power_led_rx: ValueReceiver<bool>,
+    mut blink_config_rx: ValueReceiver<BlinkConfig>,
+) -> (
+    impl Future<Output = anyhow::Result<()>>,
+    ValueReceiver<Option<Power>>,
+    ValueReceiver<PowerState>,
+) {
+    let (tx, rx) = value_channel(None);
+    let (state_tx, state_rx) = value_channel(PowerState {
+        power: None,
+        since: None,
+    });
+    (
+        async move {
+            let start = Instant::now();
+            let mut classifier =
+                PowerClassifier::new(blink_config_rx.get(), power_led_rx.get(), Duration::ZERO);
+            loop {
+                let deadline = classifier.next_deadline().map(|deadline| start + deadline);
+                select! {
+                    _ = power_led_rx.until_change() => {}
+                    _ = blink_config_rx.until_change() => {
+                        classifier.set_config(blink_config_rx.get());
+                    }
+                    _ = sleep_until(deadline.unwrap_or(start)), if deadline.is_some() => {}
+                }
+                // The LED is read again every time, so a change that happened while this wasn't
+                // waiting isn't missed
+                if let Some(power) = classifier.update(power_led_rx.get(), start.elapsed()) {
+                    set_power(&tx, &state_tx, power).await;
+                }
+            }
+        },
+        rx,
+        state_rx,
+    )
+}
+
+/// Records the power LED's edges for `duration`, which is limited to
+/// [`MAX_CALIBRATION_DURATION`], and suggests a [`BlinkConfig`] from them. The computer should be
+/// suspended while this runs.
+pub async fn calibrate_power_led(
+    mut power_led_rx: ValueReceiver<bool>,
+    duration: Duration,
+) -> Result<Calibration, CalibrationError> {
+    let start = Instant::now();
+    let end = sleep(duration.min(MAX_CALIBRATION_DURATION));
+    tokio::pin!(end);
+    let mut level = power_led_rx.get();
+    let mut edges = Vec::new();
+    loop {
+        select! {
+            _ = power_led_rx.until_change() => {
+                let new_level = power_led_rx.get();
+                if new_level != level {
+                    level = new_level;
+                    edges.push(start.elapsed());
+                }
+            }
+            _ = &mut end => break,
+        }
+    }
+    log::info!("Recorded {} power LED edges for calibration", edges.len());
+    calibrate(&edges)
+}
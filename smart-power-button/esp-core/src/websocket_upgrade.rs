@@ -0,0 +1,64 @@
+use std::future::Future;
+
+use http_body_util::combinators::BoxBody;
+use hyper::body::{Bytes, Incoming};
+use hyper::header::{
+    HeaderName, HeaderValue, CONNECTION, SEC_WEBSOCKET_ACCEPT, SEC_WEBSOCKET_KEY, UPGRADE,
+};
+use hyper::upgrade::Upgraded;
+use hyper::{Request, Response, StatusCode};
+use hyper_util::rt::TokioIo;
+use tokio_tungstenite::tungstenite::handshake::derive_accept_key;
+use tokio_tungstenite::tungstenite::protocol::Role;
+use tokio_tungstenite::WebSocketStream;
+
+use crate::hyper_util::empty;
+use crate::Error;
+
+pub type WebSocket = WebSocketStream<TokioIo<Upgraded>>;
+type UpgradeResponse = Response<BoxBody<Bytes, hyper::Error>>;
+
+/// If any of the comma separated values in the header is `value`
+fn header_contains<B>(req: &Request<B>, name: HeaderName, value: &str) -> bool {
+    req.headers().get_all(name).iter().any(|header| {
+        header.to_str().is_ok_and(|header| {
+            header
+                .split(',')
+                .any(|part| part.trim().eq_ignore_ascii_case(value))
+        })
+    })
+}
+
+pub fn is_upgrade_request<B>(req: &Request<B>) -> bool {
+    header_contains(req, CONNECTION, "upgrade") && header_contains(req, UPGRADE, "websocket")
+}
+
+/// Returns the response that accepts the upgrade, and a future that gives the WebSocket after the
+/// response is sent
+pub fn upgrade(
+    req: &mut Request<Incoming>,
+) -> Result<
+    (
+        UpgradeResponse,
+        impl Future<Output = Result<WebSocket, hyper::Error>>,
+    ),
+    Error,
+> {
+    let accept = derive_accept_key(
+        req.headers()
+            .get(SEC_WEBSOCKET_KEY)
+            .ok_or("Missing Sec-WebSocket-Key header")?
+            .as_bytes(),
+    );
+    let on_upgrade = hyper::upgrade::on(req);
+    let mut response = Response::new(empty());
+    *response.status_mut() = StatusCode::SWITCHING_PROTOCOLS;
+    let headers = response.headers_mut();
+    headers.insert(CONNECTION, HeaderValue::from_static("upgrade"));
+    headers.insert(UPGRADE, HeaderValue::from_static("websocket"));
+    headers.insert(SEC_WEBSOCKET_ACCEPT, HeaderValue::from_str(&accept)?);
+    Ok((response, async move {
+        let upgraded = on_upgrade.await?;
+        Ok(WebSocketStream::from_raw_socket(TokioIo::new(upgraded), Role::Server, None).await)
+    }))
+}
@@ -0,0 +1,8 @@
+use esp_idf_svc::sntp::EspSntp;
+use esp_idf_svc::sys::EspError;
+
+/// Starts syncing the time over the internet. The time stays synced as long as the returned
+/// value is kept.
+pub fn start_sntp() -> Result<EspSntp<'static>, EspError> {
+    EspSntp::new_default()
+}
@@ -1,14 +0,0 @@
-use std::collections::HashMap;
-use std::sync::LazyLock;
-
-pub const HTML: &str = "text/html";
-pub const JS: &str = "application/javascript";
-pub const WASM: &str = "application/wasm";
-
-pub static EXTENSION_MAP: LazyLock<HashMap<&'static str, &'static str>> = LazyLock::new(|| {
-    let mut m = HashMap::new();
-    m.insert("html", HTML);
-    m.insert("js", JS);
-    m.insert("wasm", WASM);
-    m
-});
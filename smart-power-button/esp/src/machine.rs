@@ -0,0 +1,120 @@
+use std::future::Future;
+use std::sync::Arc;
+
+use esp_idf_svc::hal::gpio::AnyIOPin;
+use esp_idf_svc::nvs::EspDefaultNvsPartition;
+use log::error;
+use smart_power_button_common::history::HistoryEventKind;
+use smart_power_button_common::machine::MachineId;
+use smart_power_button_common::ota::OtaStatus;
+use smart_power_button_common::power_led::BlinkConfig;
+use smart_power_button_common::press::PressConfig;
+use smart_power_button_common::provisioning::PinConfig;
+use smart_power_button_esp_core::event_log::EventLog;
+use smart_power_button_esp_core::history::History;
+use smart_power_button_esp_core::intents::IntentRunner;
+use smart_power_button_esp_core::machine::Machine;
+use smart_power_button_esp_core::nvs_value::{MachineStorage, NvsValue};
+use smart_power_button_esp_core::power_io::PowerIo;
+use smart_power_button_esp_core::value_channel::ValueReceiver;
+use tokio::join;
+use tokio::sync::Mutex;
+
+use crate::fill_random;
+use crate::nvs_storage::NvsStorage;
+use crate::power_io::new_power_io;
+
+/// A namespace in NVS, with the machine's id added to the keys
+fn machine_storage(
+    nvs: &EspDefaultNvsPartition,
+    namespace: &str,
+    id: MachineId,
+) -> anyhow::Result<MachineStorage<NvsStorage>> {
+    Ok(MachineStorage::new(
+        NvsStorage::new(nvs.clone(), namespace)?,
+        id,
+    ))
+}
+
+/// A machine's pins, before the rest of the machine is set up
+pub struct MachineIo<F> {
+    future: F,
+    power_io: PowerIo,
+    press_config_tx: NvsValue<PressConfig>,
+    blink_config_tx: NvsValue<BlinkConfig>,
+}
+
+/// Takes the machine's pins and connects them to [`PowerIo`]
+pub fn new_machine_io(
+    nvs: &EspDefaultNvsPartition,
+    id: MachineId,
+    pins: &mut [Option<AnyIOPin>],
+    pin_config: PinConfig,
+) -> anyhow::Result<MachineIo<impl Future<Output = ()>>> {
+    let (press_config_tx, press_config_rx) =
+        NvsValue::new(machine_storage(nvs, "press", id)?, "config")?;
+    let (blink_config_tx, blink_config_rx) =
+        NvsValue::new(machine_storage(nvs, "power_led", id)?, "config")?;
+    let (future, power_io) = new_power_io(
+        pins,
+        pin_config,
+        History::new(machine_storage(nvs, "history", id)?)?,
+        press_config_rx,
+        blink_config_rx,
+    )?;
+    Ok(MachineIo {
+        future,
+        power_io,
+        press_config_tx,
+        blink_config_tx,
+    })
+}
+
+/// Sets up everything else for the machine, and records that the ESP started in its history
+pub async fn new_machine(
+    nvs: &EspDefaultNvsPartition,
+    id: MachineId,
+    name: String,
+    io: MachineIo<impl Future<Output = ()>>,
+    ota_status_rx: ValueReceiver<OtaStatus>,
+) -> anyhow::Result<(Machine, impl Future<Output = ()>)> {
+    let MachineIo {
+        future: power_io_future,
+        power_io,
+        press_config_tx,
+        blink_config_tx,
+    } = io;
+    let (bluetooth_wakeup_rules_tx, bluetooth_wakeup_rules_rx) =
+        NvsValue::new(machine_storage(nvs, "wakeup_devices", id)?, "rules")?;
+    let (schedule_tx, schedule_rx) =
+        NvsValue::new(machine_storage(nvs, "schedule", id)?, "schedule")?;
+    let (wake_on_lan_config_tx, wake_on_lan_config_rx) =
+        NvsValue::new(machine_storage(nvs, "wake_on_lan", id)?, "config")?;
+    if let Err(e) = power_io.history.record(HistoryEventKind::Boot).await {
+        error!("{e:#}");
+    }
+    let intents = IntentRunner::new(power_io.clone());
+    let (event_log_future, event_log) = EventLog::new(
+        power_io.clone(),
+        ota_status_rx,
+        intents.status_rx.clone(),
+        fill_random,
+    );
+    let machine = Machine {
+        name: name.into(),
+        power_io,
+        intents,
+        event_log,
+        bluetooth_wakeup_rules_tx: Arc::new(Mutex::new(bluetooth_wakeup_rules_tx)),
+        bluetooth_wakeup_rules_rx,
+        schedule_tx: Arc::new(Mutex::new(schedule_tx)),
+        schedule_rx,
+        press_config_tx: Arc::new(Mutex::new(press_config_tx)),
+        blink_config_tx: Arc::new(Mutex::new(blink_config_tx)),
+        wake_on_lan_config_tx: Arc::new(Mutex::new(wake_on_lan_config_tx)),
+        wake_on_lan_config_rx,
+    };
+    Ok((machine, async move {
+        join!(power_io_future, event_log_future);
+    }))
+}
@@ -1,36 +1,85 @@
-#![feature(async_closure)]
 #![feature(iter_intersperse)]
 
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::clock::start_sntp;
 use crate::gpio_pins_vec::GpioPinsVecExt;
-use crate::power_io::PowerIo;
+use crate::machine::{new_machine, new_machine_io};
+use crate::mdns::advertise;
+use crate::mqtt::EspMqttConnector;
+use crate::nimble_scanner::NimbleScanner;
+use crate::nvs_storage::NvsStorage;
+use crate::ota::{confirm_firmware, roll_back_unless_confirmed, EspOtaPartition};
+use crate::portal::{run_portal, PortalConfig};
 use crate::run_server::run_server;
+use crate::scheduler::run_schedule;
 use crate::wifi_loop::WifiLoop;
-use bluetooth_wake::bluetooth_wake;
-use bluetooth_wakeup_devices::BluetoothWakeupDevices;
+use dotenvy_macro::dotenv;
 use esp_idf_svc::eventloop::EspSystemEventLoop;
 use esp_idf_svc::hal::peripherals::Peripherals;
+use esp_idf_svc::hal::reset::restart;
 use esp_idf_svc::nvs::EspDefaultNvsPartition;
-use esp_idf_svc::sys::esp;
+use esp_idf_svc::sys::{esp, esp_fill_random, nvs_flash_erase};
 use esp_idf_svc::timer::EspTaskTimerService;
 use esp_idf_svc::wifi::{AsyncWifi, EspWifi};
-use log::info;
+use futures::future::join_all;
+use log::{error, info};
+use smart_power_button_common::discovery::DEFAULT_DEVICE_NAME;
+use smart_power_button_common::wake_on_lan::PORTS as WAKE_ON_LAN_PORTS;
+use smart_power_button_esp_core::bluetooth_wake::{
+    bluetooth_wake, migrate_wakeup_devices, BleDiscovery,
+};
+use smart_power_button_esp_core::machine::Machine;
+use smart_power_button_esp_core::mqtt::{run_mqtt, MqttDevice};
+use smart_power_button_esp_core::nvs_value::NvsValue;
+use smart_power_button_esp_core::ota::Ota;
+use smart_power_button_esp_core::server_state::ServerState;
+use smart_power_button_esp_core::static_assets::WEB_PAGE;
+use smart_power_button_esp_core::wake_on_lan::{bind_wake_on_lan_sockets, wake_on_lan};
 use tokio::join;
+use tokio::sync::Mutex;
 
-mod bluetooth_wake;
-mod bluetooth_wakeup_devices;
-mod button;
+mod clock;
 mod gpio_pins_vec;
-mod handle_request;
-mod http_content_type;
-mod hyper_util;
+mod machine;
+mod mdns;
+mod mqtt;
+mod nimble_scanner;
+mod nvs_storage;
+mod ota;
+mod portal;
 mod power_io;
 mod run_server;
-mod serve_websocket;
-mod value_channel;
+mod scheduler;
 mod watch_input;
-mod watch_power;
 mod wifi_loop;
 
+/// How long updated firmware has to connect to Wi-Fi before the ESP goes back to the old firmware
+const OTA_ROLLBACK_TIMEOUT: Duration = Duration::from_secs(5 * 60);
+/// For the thread that writes firmware updates. `esp_ota_end` checks the image, which needs more
+/// than the default pthread stack.
+const OTA_THREAD_STACK_SIZE: usize = 16 * 1024;
+
+const FIRMWARE_VERSION: &str = env!("CARGO_PKG_VERSION");
+
+/// Clients log in with this to get a token. It's also the setup portal's Wi-Fi password.
+const PAIRING_PASSWORD: &str = dotenv!("PAIRING_PASSWORD");
+
+fn fill_random(bytes: &mut [u8]) {
+    // This is a true random number generator as long as Wi-Fi or Bluetooth is on
+    unsafe { esp_fill_random(bytes.as_mut_ptr().cast(), bytes.len()) };
+}
+
+/// Erases all of NVS, including the Wi-Fi networks and pins, and restarts
+fn factory_reset() {
+    info!("Factory reset");
+    if let Err(e) = esp!(unsafe { nvs_flash_erase() }) {
+        error!("Error erasing NVS: {e:?}");
+    }
+    restart();
+}
+
 fn main() -> anyhow::Result<()> {
     // It is necessary to call this function once. Otherwise, some patches to the runtime
     // implemented by esp-idf-sys might not link properly. See https://github.com/esp-rs/esp-idf-template/issues/71
@@ -60,46 +109,161 @@ fn main() -> anyhow::Result<()> {
         .into_iter()
         .map(|pin| pin.into())
         .collect::<Vec<_>>();
-    let (power_io_future, power_io) = PowerIo::new(&mut pins)?;
-    let (bluetooth_wakeup_devices_tx, bluetooth_wakeup_devices_rx) =
-        BluetoothWakeupDevices::new(nvs.clone())?;
+    let (wifi_config_tx, wifi_config_rx) =
+        NvsValue::new(NvsStorage::new(nvs.clone(), "wifi")?, "config")?;
+    let (pin_config_tx, pin_config_rx) =
+        NvsValue::new(NvsStorage::new(nvs.clone(), "pins")?, "config")?;
+    let (machines_config_tx, machines_config_rx) =
+        NvsValue::new(NvsStorage::new(nvs.clone(), "machines")?, "config")?;
+    migrate_wakeup_devices(
+        &mut NvsStorage::new(nvs.clone(), "wakeup_devices")?,
+        "devices",
+        "rules",
+    )?;
+    let first_machine_io = new_machine_io(&nvs, 0, &mut pins, pin_config_rx.get());
+    // The ids are the machines' places in the list, so the machines after one that can't be set up
+    // are left out too
+    let mut other_machine_ios = Vec::new();
+    for (machine, id) in machines_config_rx.get().others.into_iter().zip(1..) {
+        match new_machine_io(&nvs, id, &mut pins, machine.pins) {
+            Ok(io) => other_machine_ios.push((machine.name, io)),
+            Err(e) => {
+                error!("Error setting up the GPIO pins for {}: {e:?}", machine.name);
+                break;
+            }
+        }
+    }
+    let (auth_tokens_tx, auth_tokens_rx) =
+        NvsValue::new(NvsStorage::new(nvs.clone(), "auth")?, "tokens")?;
+    let (mqtt_config_tx, mqtt_config_rx) =
+        NvsValue::new(NvsStorage::new(nvs.clone(), "mqtt")?, "config")?;
     info!("Starting async run loop");
     tokio::runtime::Builder::new_current_thread()
         .enable_all()
+        // Blocking threads are only used for writing firmware updates to flash, so one with a
+        // small stack is enough. Tokio's default of 2 MiB doesn't fit in the ESP's RAM.
+        .max_blocking_threads(1)
+        .thread_stack_size(OTA_THREAD_STACK_SIZE)
         .build()?
         .block_on(async move {
-            let mut wifi_loop = WifiLoop::new(wifi);
+            tokio::spawn(roll_back_unless_confirmed(OTA_ROLLBACK_TIMEOUT));
+            let (mut wifi_loop, wifi_status_rx) = WifiLoop::new(wifi, wifi_config_rx.clone());
+            let connect_result = wifi_loop.initial_connect().await;
+            let first_machine_io = match (first_machine_io, connect_result) {
+                (Ok(io), Ok(())) => io,
+                (io, connect_result) => {
+                    if let Err(e) = io {
+                        error!("Error setting up the GPIO pins: {e:?}");
+                    }
+                    if let Err(e) = connect_result {
+                        error!("Error connecting to Wi-Fi: {e:?}");
+                    }
+                    run_portal(
+                        wifi_loop.into_wifi(),
+                        PortalConfig {
+                            wifi_config_tx,
+                            wifi_config_rx,
+                            pin_config_tx,
+                            pin_config_rx,
+                            password: PAIRING_PASSWORD,
+                        },
+                    )
+                    .await?;
+                    restart();
+                }
+            };
+            // Wi-Fi and the pins work, so this firmware is good enough to take another update
+            confirm_firmware();
 
             info!("Preparing to launch server...");
+            let device_name = match wifi_config_rx.get().hostname {
+                hostname if hostname.is_empty() => DEFAULT_DEVICE_NAME.to_owned(),
+                hostname => hostname,
+            };
+            let ota = Ota::new(EspOtaPartition::new()?, || restart());
+            let first_name = machines_config_rx.get().names().swap_remove(0);
+            let mut machines = Vec::new();
+            let mut machine_futures = Vec::new();
+            for ((name, io), id) in std::iter::once((first_name, first_machine_io))
+                .chain(other_machine_ios)
+                .zip(0..)
+            {
+                let (machine, future) =
+                    new_machine(&nvs, id, name, io, ota.status_rx.clone()).await?;
+                machines.push(machine);
+                machine_futures.push(future);
+            }
+            let machines: Arc<[Machine]> = machines.into();
+            let (ble_discovery, discovery_requests) = BleDiscovery::new();
             let server_future = {
-                let power_io = power_io.clone();
-                let bluetooth_wakeup_devices_rx = bluetooth_wakeup_devices_rx.clone();
+                let server_state = ServerState {
+                    machines: machines.clone(),
+                    machines_config_tx: Arc::new(Mutex::new(machines_config_tx)),
+                    machines_config_rx,
+                    ble_discovery,
+                    auth_tokens_tx: Arc::new(Mutex::new(auth_tokens_tx)),
+                    auth_tokens_rx,
+                    wifi_status_rx,
+                    mqtt_config_tx: Arc::new(Mutex::new(mqtt_config_tx)),
+                    mqtt_config_rx: mqtt_config_rx.clone(),
+                    ota,
+                    pairing_password: PAIRING_PASSWORD.into(),
+                    random_bytes: fill_random,
+                    factory_reset,
+                    static_assets: WEB_PAGE,
+                };
+                let device_name = device_name.clone();
                 async move {
-                    wifi_loop.configure().await.unwrap();
-                    wifi_loop.initial_connect().await.unwrap();
                     let (ip_info, hostname) = wifi_loop.get_ip_info();
+                    let _mdns = advertise(&device_name)
+                        .inspect_err(|e| error!("Error advertising with mDNS: {e:?}"));
+                    // Scheduled actions don't run until the time is synced
+                    let _sntp = start_sntp().unwrap();
                     let _ = join!(
-                        run_server(
-                            ip_info,
-                            &hostname,
-                            power_io,
-                            bluetooth_wakeup_devices_tx,
-                            bluetooth_wakeup_devices_rx
-                        ),
+                        run_server(ip_info, &hostname, server_state),
                         wifi_loop.stay_connected()
                     );
                 }
             };
 
             let bluetooth_wake_future =
-                bluetooth_wake(power_io.clone(), bluetooth_wakeup_devices_rx.clone());
+                bluetooth_wake(machines.clone(), NimbleScanner::take(), discovery_requests);
+
+            let wake_on_lan_future = {
+                let machines = machines.clone();
+                async move {
+                    let sockets = bind_wake_on_lan_sockets(&WAKE_ON_LAN_PORTS).await;
+                    wake_on_lan(sockets, machines).await
+                }
+            };
+
+            let schedule_future = join_all(machines.iter().map(|machine| {
+                run_schedule(machine.power_io.clone(), machine.schedule_rx.clone())
+            }));
+
+            let mqtt_future = join_all(machines.iter().zip(0..).map(|(machine, id)| {
+                run_mqtt(
+                    EspMqttConnector,
+                    mqtt_config_rx.clone(),
+                    machine.power_io.clone(),
+                    MqttDevice {
+                        name: machine.mqtt_name(id, &device_name),
+                        firmware_version: FIRMWARE_VERSION.into(),
+                    },
+                )
+            }));
 
             info!("Entering main Wi-Fi run loop...");
-            let _ = join!(power_io_future, server_future, bluetooth_wake_future);
+            let _ = join!(
+                join_all(machine_futures),
+                server_future,
+                bluetooth_wake_future,
+                wake_on_lan_future,
+                schedule_future,
+                mqtt_future
+            );
             Ok::<(), anyhow::Error>(())
         })?;
 
     Ok(())
 }
-
-type Error = Box<dyn std::error::Error + Send + Sync + 'static>;
@@ -0,0 +1,30 @@
+use esp_idf_svc::mdns::EspMdns;
+use log::info;
+use smart_power_button_common::discovery::{
+    SERVICE_NAME, SERVICE_PROTOCOL, TXT_DEVICE_NAME, TXT_FIRMWARE_VERSION, TXT_PROTOCOL_VERSION,
+};
+use smart_power_button_common::protocol::PROTOCOL_VERSION;
+
+use crate::FIRMWARE_VERSION;
+
+/// Advertises the server as `<name>.local` and as a `_smart-power-button._tcp` service, so the
+/// computer can find it without an address. Stops advertising when dropped.
+pub fn advertise(name: &str) -> anyhow::Result<EspMdns> {
+    let mut mdns = EspMdns::take()?;
+    mdns.set_hostname(name)?;
+    mdns.set_instance_name(name)?;
+    let protocol_version = PROTOCOL_VERSION.to_string();
+    mdns.add_service(
+        Some(name),
+        SERVICE_NAME,
+        SERVICE_PROTOCOL,
+        80,
+        &[
+            (TXT_FIRMWARE_VERSION, FIRMWARE_VERSION),
+            (TXT_PROTOCOL_VERSION, &protocol_version),
+            (TXT_DEVICE_NAME, name),
+        ],
+    )?;
+    info!("Advertising {name}.local with mDNS");
+    Ok(mdns)
+}
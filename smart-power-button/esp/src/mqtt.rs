@@ -0,0 +1,107 @@
+use anyhow::anyhow;
+use esp_idf_svc::mqtt::client::{
+    Details, EspMqttClient, EventPayload, LwtConfiguration, MqttClientConfiguration, QoS,
+};
+use smart_power_button_common::mqtt::{MqttConfig, OFFLINE_PAYLOAD};
+use smart_power_button_esp_core::mqtt::{MqttClient, MqttConnector, MqttMessage, MqttMessages};
+use tokio::sync::mpsc;
+
+/// What ESP-IDF's MQTT task reports, sent from its callback
+enum MqttEvent {
+    Connected,
+    Message(MqttMessage),
+    Disconnected(String),
+}
+
+/// Connects with ESP-IDF's MQTT client, which runs in its own task and reconnects by itself.
+/// Reconnecting is left to [`smart_power_button_esp_core::mqtt::run_mqtt`], so the client is
+/// dropped as soon as it disconnects.
+pub struct EspMqttConnector;
+
+impl MqttConnector for EspMqttConnector {
+    type Client = EspMqttClientWrapper;
+    type Messages = EspMqttMessages;
+
+    async fn connect(
+        &mut self,
+        config: &MqttConfig,
+        client_id: &str,
+        availability_topic: &str,
+    ) -> anyhow::Result<(EspMqttClientWrapper, EspMqttMessages)> {
+        let url = format!("mqtt://{}:{}", config.host, config.port);
+        let (event_tx, mut event_rx) = mpsc::unbounded_channel();
+        let client = EspMqttClient::new_cb(
+            &url,
+            &MqttClientConfiguration {
+                client_id: Some(client_id),
+                username: Some(&config.username).filter(|username| !username.is_empty()),
+                password: Some(&config.password).filter(|_| !config.username.is_empty()),
+                lwt: Some(LwtConfiguration {
+                    topic: availability_topic,
+                    payload: OFFLINE_PAYLOAD.as_bytes(),
+                    qos: QoS::AtLeastOnce,
+                    retain: true,
+                }),
+                disable_auto_reconnect: true,
+                ..Default::default()
+            },
+            move |event| {
+                let event = match event.payload() {
+                    EventPayload::Connected(_) => MqttEvent::Connected,
+                    EventPayload::Received {
+                        topic: Some(topic),
+                        data,
+                        details: Details::Complete,
+                        ..
+                    } => MqttEvent::Message(MqttMessage {
+                        topic: topic.into(),
+                        payload: data.into(),
+                    }),
+                    EventPayload::Disconnected => MqttEvent::Disconnected("Disconnected".into()),
+                    EventPayload::Error(e) => MqttEvent::Disconnected(e.to_string()),
+                    _ => return,
+                };
+                let _ = event_tx.send(event);
+            },
+        )?;
+        loop {
+            match event_rx.recv().await {
+                Some(MqttEvent::Connected) => break,
+                Some(MqttEvent::Message(_)) => {}
+                Some(MqttEvent::Disconnected(e)) => return Err(anyhow!(e)),
+                None => return Err(anyhow!("MQTT client stopped")),
+            }
+        }
+        Ok((EspMqttClientWrapper(client), EspMqttMessages(event_rx)))
+    }
+}
+
+pub struct EspMqttClientWrapper(EspMqttClient<'static>);
+
+impl MqttClient for EspMqttClientWrapper {
+    async fn publish(&mut self, topic: &str, payload: &[u8], retain: bool) -> anyhow::Result<()> {
+        // Queued in the outbox instead of waiting for the broker, so this doesn't block
+        self.0.enqueue(topic, QoS::AtLeastOnce, retain, payload)?;
+        Ok(())
+    }
+
+    async fn subscribe(&mut self, topic: &str) -> anyhow::Result<()> {
+        self.0.subscribe(topic, QoS::AtLeastOnce)?;
+        Ok(())
+    }
+}
+
+pub struct EspMqttMessages(mpsc::UnboundedReceiver<MqttEvent>);
+
+impl MqttMessages for EspMqttMessages {
+    async fn next(&mut self) -> anyhow::Result<MqttMessage> {
+        loop {
+            match self.0.recv().await {
+                Some(MqttEvent::Message(message)) => return Ok(message),
+                Some(MqttEvent::Connected) => {}
+                Some(MqttEvent::Disconnected(e)) => return Err(anyhow!(e)),
+                None => return Err(anyhow!("MQTT client stopped")),
+            }
+        }
+    }
+}
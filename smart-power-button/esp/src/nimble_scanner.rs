@@ -0,0 +1,74 @@
+use std::time::Duration;
+
+use anyhow::anyhow;
+use esp32_nimble::{
+    BLEAddressType, BLEAdvertisedDevice, BLEDevice, BLEScan, BleUuid as NimbleUuid,
+};
+use smart_power_button_common::bluetooth_wake::{BleAddressType, BleAdvertisement, BleUuid};
+use smart_power_button_esp_core::bluetooth_wake::BleScanner;
+
+/// Scans with the ESP's Bluetooth
+pub struct NimbleScanner(&'static mut BLEScan);
+
+impl NimbleScanner {
+    pub fn take() -> Self {
+        Self(BLEDevice::take().get_scan())
+    }
+}
+
+fn to_advertisement(device: &BLEAdvertisedDevice) -> BleAdvertisement {
+    BleAdvertisement {
+        address: device.addr().val(),
+        address_type: match device.addr().addr_type() {
+            BLEAddressType::Public | BLEAddressType::PublicID => BleAddressType::Public,
+            BLEAddressType::Random | BLEAddressType::RandomID => BleAddressType::Random,
+        },
+        name: Some(device.name().to_string()).filter(|name| !name.is_empty()),
+        service_uuids: device
+            .get_service_uuids()
+            .map(|uuid| match uuid {
+                NimbleUuid::Uuid16(uuid) => BleUuid::Uuid16(uuid),
+                NimbleUuid::Uuid32(uuid) => BleUuid::Uuid32(uuid),
+                // NimBLE keeps the bytes in the opposite order
+                NimbleUuid::Uuid128(mut bytes) => {
+                    bytes.reverse();
+                    BleUuid::Uuid128(bytes)
+                }
+            })
+            .collect(),
+        manufacturer_data: device.get_manufacture_data().map(|data| data.to_vec()),
+        rssi: device.rssi().clamp(i8::MIN as i32, i8::MAX as i32) as i8,
+    }
+}
+
+impl BleScanner for NimbleScanner {
+    async fn find_device(
+        &mut self,
+        duration: Duration,
+        is_wakeup_device: impl Fn(&BleAdvertisement) -> bool,
+    ) -> anyhow::Result<Option<BleAdvertisement>> {
+        self.0
+            .find_device(duration.as_millis() as i32, |device| {
+                is_wakeup_device(&to_advertisement(device))
+            })
+            .await
+            .map(|device| device.as_ref().map(to_advertisement))
+            .map_err(|e| anyhow!("{e:?}"))
+    }
+
+    async fn scan(
+        &mut self,
+        duration: Duration,
+        mut on_device: impl FnMut(BleAdvertisement),
+    ) -> anyhow::Result<()> {
+        // Never accepting a device makes this scan for the whole duration
+        self.0
+            .find_device(duration.as_millis() as i32, |device| {
+                on_device(to_advertisement(device));
+                false
+            })
+            .await
+            .map(|_| ())
+            .map_err(|e| anyhow!("{e:?}"))
+    }
+}
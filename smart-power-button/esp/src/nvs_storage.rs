@@ -0,0 +1,39 @@
+use anyhow::anyhow;
+use esp_idf_svc::nvs::{EspDefaultNvs, EspDefaultNvsPartition, EspNvs};
+use smart_power_button_esp_core::nvs_value::Storage;
+
+/// A namespace in NVS
+pub struct NvsStorage(EspDefaultNvs);
+
+impl NvsStorage {
+    /// `namespace` can be at most 15 characters long
+    pub fn new(nvs: EspDefaultNvsPartition, namespace: &str) -> anyhow::Result<Self> {
+        Ok(Self(EspNvs::new(nvs, namespace, true)?))
+    }
+}
+
+impl Storage for NvsStorage {
+    fn get_blob(&self, key: &str) -> anyhow::Result<Option<Vec<u8>>> {
+        match self.0.blob_len(key)? {
+            Some(len) => {
+                let mut buffer = vec![0; len];
+                let bytes = self
+                    .0
+                    .get_blob(key, &mut buffer)?
+                    .ok_or(anyhow!("None blob"))?;
+                Ok(Some(bytes.to_vec()))
+            }
+            None => Ok(None),
+        }
+    }
+
+    fn set_blob(&mut self, key: &str, value: &[u8]) -> anyhow::Result<()> {
+        self.0.set_blob(key, value)?;
+        Ok(())
+    }
+
+    fn remove(&mut self, key: &str) -> anyhow::Result<()> {
+        self.0.remove(key)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,115 @@
+use std::time::Duration;
+
+use anyhow::anyhow;
+use esp_idf_svc::sys::{
+    esp, esp_ota_abort, esp_ota_begin, esp_ota_end, esp_ota_get_next_update_partition,
+    esp_ota_get_running_partition, esp_ota_get_state_partition, esp_ota_handle_t,
+    esp_ota_img_states_t, esp_ota_img_states_t_ESP_OTA_IMG_PENDING_VERIFY,
+    esp_ota_mark_app_invalid_rollback_and_reboot, esp_ota_mark_app_valid_cancel_rollback,
+    esp_ota_set_boot_partition, esp_ota_write, esp_partition_t,
+};
+use log::{error, info, warn};
+use smart_power_button_esp_core::ota::{OtaPartition, OtaWriter};
+
+/// The OTA partition that isn't running
+pub struct EspOtaPartition(&'static esp_partition_t);
+
+// The partition table is read once and never changes
+unsafe impl Send for EspOtaPartition {}
+unsafe impl Sync for EspOtaPartition {}
+
+impl EspOtaPartition {
+    pub fn new() -> anyhow::Result<Self> {
+        unsafe { esp_ota_get_next_update_partition(std::ptr::null()).as_ref() }
+            .map(Self)
+            .ok_or(anyhow!("There is no OTA partition. Check partitions.csv."))
+    }
+}
+
+impl OtaPartition for EspOtaPartition {
+    fn max_size(&self) -> u64 {
+        self.0.size.into()
+    }
+
+    fn begin(&self, size: u64) -> anyhow::Result<Box<dyn OtaWriter>> {
+        let mut handle = 0;
+        // This erases the partition, which blocks for a few seconds. `Ota` calls this on a blocking
+        // thread.
+        esp!(unsafe { esp_ota_begin(self.0, size.try_into()?, &mut handle) })?;
+        Ok(Box::new(EspOtaWriter {
+            partition: self.0,
+            handle: Some(handle),
+        }))
+    }
+}
+
+struct EspOtaWriter {
+    partition: &'static esp_partition_t,
+    /// `None` after `esp_ota_end`
+    handle: Option<esp_ota_handle_t>,
+}
+
+unsafe impl Send for EspOtaWriter {}
+
+impl OtaWriter for EspOtaWriter {
+    fn write(&mut self, bytes: &[u8]) -> anyhow::Result<()> {
+        let handle = self.handle.ok_or(anyhow!("The update is already done"))?;
+        esp!(unsafe { esp_ota_write(handle, bytes.as_ptr().cast(), bytes.len()) })?;
+        Ok(())
+    }
+
+    fn finish(mut self: Box<Self>) -> anyhow::Result<()> {
+        let handle = self
+            .handle
+            .take()
+            .ok_or(anyhow!("The update is already done"))?;
+        // This checks that the image is valid for this chip
+        esp!(unsafe { esp_ota_end(handle) })?;
+        esp!(unsafe { esp_ota_set_boot_partition(self.partition) })?;
+        Ok(())
+    }
+}
+
+impl Drop for EspOtaWriter {
+    fn drop(&mut self) {
+        if let Some(handle) = self.handle.take() {
+            if let Err(e) = esp!(unsafe { esp_ota_abort(handle) }) {
+                error!("Error aborting firmware update: {e:?}");
+            }
+        }
+    }
+}
+
+/// If this is the first boot after an update that hasn't been confirmed yet
+pub fn is_pending_verify() -> bool {
+    let mut state: esp_ota_img_states_t = 0;
+    esp!(unsafe { esp_ota_get_state_partition(esp_ota_get_running_partition(), &mut state) })
+        .is_ok()
+        && state == esp_ota_img_states_t_ESP_OTA_IMG_PENDING_VERIFY
+}
+
+/// Keeps this firmware. Until this is called after an update, restarting goes back to the old
+/// firmware.
+pub fn confirm_firmware() {
+    if is_pending_verify() {
+        match esp!(unsafe { esp_ota_mark_app_valid_cancel_rollback() }) {
+            Ok(()) => info!("Confirmed the updated firmware"),
+            Err(e) => error!("Error confirming the updated firmware: {e:?}"),
+        }
+    }
+}
+
+/// Goes back to the old firmware if this one wasn't confirmed within `timeout`
+pub async fn roll_back_unless_confirmed(timeout: Duration) {
+    if !is_pending_verify() {
+        return;
+    }
+    info!("This firmware is new. It has {timeout:?} to connect and start the server.");
+    tokio::time::sleep(timeout).await;
+    if is_pending_verify() {
+        warn!("The updated firmware didn't start in time. Going back to the old firmware.");
+        if let Err(e) = esp!(unsafe { esp_ota_mark_app_invalid_rollback_and_reboot() }) {
+            error!("Error rolling back: {e:?}");
+        }
+    }
+}
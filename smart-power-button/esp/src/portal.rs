@@ -0,0 +1,110 @@
+use std::collections::HashSet;
+use std::net::Ipv4Addr;
+use std::sync::Arc;
+use std::time::Duration;
+
+use esp_idf_svc::wifi::{
+    AccessPointConfiguration, AsyncWifi, AuthMethod, ClientConfiguration, Configuration, EspWifi,
+};
+use log::{info, warn};
+use smart_power_button_common::provisioning::{PinConfig, WifiConfig, PASSWORD_LEN};
+use smart_power_button_esp_core::captive_dns::serve_captive_dns;
+use smart_power_button_esp_core::nvs_value::NvsValue;
+use smart_power_button_esp_core::portal::{serve_portal, PortalState};
+use smart_power_button_esp_core::value_channel::ValueReceiver;
+use tokio::net::{TcpListener, UdpSocket};
+use tokio::sync::{Mutex, Notify};
+
+use crate::factory_reset;
+use crate::wifi_loop::scan_networks;
+
+const ACCESS_POINT_SSID: &str = "Smart Power Button Setup";
+/// If there are saved networks that just didn't work, try them again after this long
+const RETRY_SAVED_NETWORKS_AFTER: Duration = Duration::from_secs(10 * 60);
+
+pub struct PortalConfig {
+    pub wifi_config_tx: NvsValue<WifiConfig>,
+    pub wifi_config_rx: ValueReceiver<WifiConfig>,
+    pub pin_config_tx: NvsValue<PinConfig>,
+    pub pin_config_rx: ValueReceiver<PinConfig>,
+    /// The access point's password. If it's too short or long for WPA2, the access point is open.
+    pub password: &'static str,
+}
+
+/// Starts an access point with the setup portal. Returns when the ESP should restart, which is
+/// after new settings are saved or when it's time to try the saved networks again.
+pub async fn run_portal(
+    mut wifi: AsyncWifi<EspWifi<'_>>,
+    config: PortalConfig,
+) -> anyhow::Result<()> {
+    info!("Starting the setup portal...");
+    if wifi.is_started()? {
+        wifi.stop().await?;
+    }
+    let auth_method = match PASSWORD_LEN.contains(&config.password.len()) {
+        true => AuthMethod::WPA2Personal,
+        false => {
+            warn!(
+                "The pairing password can't be a Wi-Fi password, so the setup portal's Wi-Fi is open"
+            );
+            AuthMethod::None
+        }
+    };
+    // Mixed mode lets the ESP scan for networks while it is an access point
+    wifi.set_configuration(&Configuration::Mixed(
+        ClientConfiguration::default(),
+        AccessPointConfiguration {
+            ssid: ACCESS_POINT_SSID.try_into().unwrap(),
+            password: match auth_method {
+                AuthMethod::None => Default::default(),
+                _ => config.password.try_into().unwrap(),
+            },
+            auth_method,
+            ..Default::default()
+        },
+    ))?;
+    wifi.start().await?;
+
+    info!("Scanning for Wi-Fi networks...");
+    let mut networks = scan_networks(&mut wifi).await?;
+    // Each access point of a network shows up, but the page only needs the strongest one
+    let mut seen = HashSet::new();
+    networks.retain(|network| seen.insert(network.ssid.clone()));
+    info!("Found {} networks", networks.len());
+
+    let ip = wifi.wifi().ap_netif().get_ip_info()?.ip;
+    let portal_url = format!("http://{ip}/");
+    info!("Connect to {ACCESS_POINT_SSID:?} and open {portal_url}");
+    let has_saved_networks = !config.wifi_config_rx.get().networks.is_empty();
+    let saved = Arc::new(Notify::new());
+    let portal_state = PortalState {
+        networks: networks.into(),
+        wifi_config_tx: Arc::new(Mutex::new(config.wifi_config_tx)),
+        wifi_config_rx: config.wifi_config_rx,
+        pin_config_tx: Arc::new(Mutex::new(config.pin_config_tx)),
+        pin_config_rx: config.pin_config_rx,
+        saved: saved.clone(),
+        factory_reset,
+        portal_url: portal_url.into(),
+    };
+    let listener = TcpListener::bind("0.0.0.0:80").await?;
+    let dns_socket = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 53)).await?;
+
+    let retry_future = async {
+        match has_saved_networks {
+            true => tokio::time::sleep(RETRY_SAVED_NETWORKS_AFTER).await,
+            false => std::future::pending().await,
+        }
+    };
+    tokio::select! {
+        result = serve_portal(listener, portal_state) => result?,
+        result = serve_captive_dns(dns_socket, ip) => result?,
+        _ = saved.notified() => {
+            info!("Settings saved");
+            // Give the response time to get to the browser
+            tokio::time::sleep(Duration::from_secs(1)).await;
+        },
+        _ = retry_future => info!("Nothing was saved. Trying the saved networks again."),
+    }
+    Ok(())
+}
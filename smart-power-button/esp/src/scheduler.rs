@@ -0,0 +1,51 @@
+use std::time::Duration;
+
+use log::info;
+use smart_power_button_common::history::PressSource;
+use smart_power_button_common::schedule::{RunWhen, Schedule};
+use smart_power_button_common::Power;
+use smart_power_button_esp_core::clock::unix_time;
+use smart_power_button_esp_core::power_io::PowerIo;
+use smart_power_button_esp_core::value_channel::ValueReceiver;
+use tokio::select;
+use tokio::time::sleep;
+
+fn should_run(run_when: RunWhen, power: Option<Power>) -> bool {
+    match run_when {
+        RunWhen::Always => true,
+        RunWhen::ComputerOn => power == Some(Power::On),
+        RunWhen::ComputerOff => matches!(power, Some(Power::Off) | Some(Power::Suspend)),
+    }
+}
+
+/// Runs the actions in the schedule at their times. Nothing runs until the time is synced.
+pub async fn run_schedule(power_io: PowerIo, mut schedule: ValueReceiver<Schedule>) {
+    // So that a rule doesn't run twice if checking takes less than a minute
+    let mut last_checked = None;
+    loop {
+        let schedule_now = schedule.get();
+        let time_zone = schedule_now.time_zone();
+        let wait = match unix_time().map(|now| time_zone.local_time(now)) {
+            Some(now) => {
+                let minute = (now.day, now.hour, now.minute);
+                if last_checked != Some(minute) {
+                    last_checked = Some(minute);
+                    for rule in schedule_now.due_rules(now.day, now.hour, now.minute) {
+                        if should_run(rule.run_when, power_io.power_rx.get()) {
+                            info!("Running scheduled action: {rule:?}");
+                            power_io
+                                .run(rule.action.clone(), PressSource::Schedule)
+                                .await;
+                        }
+                    }
+                }
+                Duration::from_secs(60 - now.second.min(59) as u64)
+            }
+            None => Duration::from_secs(10),
+        };
+        select! {
+            _ = sleep(wait) => {}
+            _ = schedule.until_change() => {}
+        }
+    }
+}
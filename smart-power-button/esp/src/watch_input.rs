@@ -1,6 +1,6 @@
-use crate::value_channel::{value_channel, ValueReceiver};
 use esp_idf_svc::hal::gpio::{InputPin, InterruptType, OutputPin, PinDriver, Pull};
 use log::info;
+use smart_power_button_esp_core::value_channel::{value_channel, ValueReceiver};
 use std::future::Future;
 
 pub fn watch_input<T: InputPin + OutputPin>(
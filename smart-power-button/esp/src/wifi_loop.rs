@@ -1,38 +1,138 @@
-use dotenvy_macro::dotenv;
-use esp_idf_svc::ipv4::IpInfo;
-use esp_idf_svc::sys::EspError;
+use anyhow::anyhow;
+use esp_idf_svc::ipv4::{
+    self, ClientSettings, Configuration as IpConfiguration, IpInfo, Mask, Subnet,
+};
+use esp_idf_svc::netif::{EspNetif, NetifConfiguration};
 use esp_idf_svc::wifi::{AsyncWifi, AuthMethod, ClientConfiguration, Configuration, EspWifi};
-use log::info;
+use log::{info, warn};
+use smart_power_button_common::provisioning::{
+    ScannedNetwork, WifiConfig, WifiNetwork, WifiSecurity,
+};
+use smart_power_button_common::wifi::{connect_order, reconnect_delay, WifiState, WifiStatus};
+use smart_power_button_esp_core::value_channel::{value_channel, ValueReceiver, ValueSender};
 
-const WIFI_SSID: &str = dotenv!("WIFI_SSID");
-const WIFI_PASSWORD: &str = dotenv!("WIFI_PASS");
+/// Scans for networks, strongest first
+pub async fn scan_networks(
+    wifi: &mut AsyncWifi<EspWifi<'_>>,
+) -> anyhow::Result<Vec<ScannedNetwork>> {
+    let mut networks = wifi
+        .scan()
+        .await?
+        .into_iter()
+        .filter(|network| !network.ssid.is_empty())
+        .map(|network| ScannedNetwork {
+            ssid: network.ssid.as_str().into(),
+            signal_strength: network.signal_strength,
+            security: match network.auth_method {
+                Some(AuthMethod::None) => WifiSecurity::Open,
+                Some(
+                    AuthMethod::WPA2Personal
+                    | AuthMethod::WPAWPA2Personal
+                    | AuthMethod::WPA2WPA3Personal,
+                ) => WifiSecurity::Wpa2,
+                Some(AuthMethod::WPA3Personal) => WifiSecurity::Wpa3,
+                _ => WifiSecurity::Auto,
+            },
+        })
+        .collect::<Vec<_>>();
+    networks.sort_by_key(|network| -(network.signal_strength as i16));
+    Ok(networks)
+}
+
+/// Makes the station network interface for a network, with its static IP and the hostname
+fn new_sta_netif(network: &WifiNetwork, hostname: &str) -> anyhow::Result<EspNetif> {
+    let mut netif = match network.static_ip {
+        Some(static_ip) => EspNetif::new_with_conf(&NetifConfiguration {
+            ip_configuration: Some(IpConfiguration::Client(ipv4::ClientConfiguration::Fixed(
+                ClientSettings {
+                    ip: static_ip.ip,
+                    subnet: Subnet {
+                        gateway: static_ip.gateway,
+                        mask: Mask(static_ip.prefix_len),
+                    },
+                    dns: static_ip.dns,
+                    secondary_dns: None,
+                },
+            ))),
+            ..NetifConfiguration::wifi_default_client()
+        })?,
+        None => EspNetif::new_with_conf(&NetifConfiguration::wifi_default_client())?,
+    };
+    if !hostname.is_empty() {
+        netif.set_hostname(hostname)?;
+    }
+    Ok(netif)
+}
 
 pub struct WifiLoop<'a> {
     wifi: AsyncWifi<EspWifi<'a>>,
+    /// Saved in NVS by the portal
+    config: ValueReceiver<WifiConfig>,
+    status_tx: ValueSender<WifiStatus>,
+    /// Attempts to connect that failed in a row
+    failed_attempts: u32,
 }
 
 impl<'a> WifiLoop<'a> {
-    pub fn new(wifi: AsyncWifi<EspWifi<'a>>) -> Self {
-        Self { wifi }
+    pub fn new(
+        wifi: AsyncWifi<EspWifi<'a>>,
+        config: ValueReceiver<WifiConfig>,
+    ) -> (Self, ValueReceiver<WifiStatus>) {
+        let (status_tx, status_rx) = value_channel(WifiStatus::default());
+        (
+            Self {
+                wifi,
+                config,
+                status_tx,
+                failed_attempts: 0,
+            },
+            status_rx,
+        )
     }
 
-    pub async fn configure(&mut self) -> Result<(), EspError> {
-        info!("Setting Wi-Fi credentials...");
-        let wifi_configuration: Configuration = Configuration::Client(ClientConfiguration {
-            ssid: WIFI_SSID.parse().unwrap(),
-            password: WIFI_PASSWORD.parse().unwrap(),
-            auth_method: AuthMethod::WPA2Personal,
-            channel: None,
-            ..Default::default()
-        });
-        self.wifi.set_configuration(&wifi_configuration)?;
-
-        info!("Starting Wi-Fi driver...");
-        self.wifi.start().await
+    /// Gives the Wi-Fi back so it can be used for the portal
+    pub fn into_wifi(self) -> AsyncWifi<EspWifi<'a>> {
+        self.wifi
     }
 
-    pub async fn initial_connect(&mut self) -> Result<(), EspError> {
-        self.do_connect_loop(true).await
+    /// Scans, and then tries each saved network once in [`connect_order`]
+    pub async fn initial_connect(&mut self) -> anyhow::Result<()> {
+        let config = self.config.get();
+        if config.networks.is_empty() {
+            return Err(anyhow!("No Wi-Fi networks are saved"));
+        }
+        self.set_status(WifiState::Connecting).await;
+        if !self.wifi.is_started()? {
+            self.wifi
+                .set_configuration(&Configuration::Client(ClientConfiguration::default()))?;
+            self.wifi.start().await?;
+        }
+        info!("Scanning for Wi-Fi networks...");
+        let scanned = match scan_networks(&mut self.wifi).await {
+            Ok(scanned) => scanned,
+            Err(e) => {
+                warn!("Error scanning for Wi-Fi networks: {e:?}");
+                Vec::new()
+            }
+        };
+        for (network, scanned) in connect_order(&config.networks, &scanned) {
+            match self.connect(network, scanned, &config.hostname).await {
+                Ok(()) => {
+                    self.failed_attempts = 0;
+                    let (ip_info, _) = self.get_ip_info();
+                    self.set_status(WifiState::Connected {
+                        ssid: network.ssid.clone(),
+                        ip: ip_info.ip,
+                    })
+                    .await;
+                    return Ok(());
+                }
+                Err(e) => warn!("Couldn't connect to {:?}: {e:?}", network.ssid),
+            }
+        }
+        self.failed_attempts = self.failed_attempts.saturating_add(1);
+        self.set_status(WifiState::WaitingToRetry).await;
+        Err(anyhow!("Couldn't connect to any saved Wi-Fi network"))
     }
 
     pub fn get_ip_info(&self) -> (IpInfo, heapless::String<30>) {
@@ -40,30 +140,82 @@ impl<'a> WifiLoop<'a> {
         (netif.get_ip_info().unwrap(), netif.get_hostname().unwrap())
     }
 
-    pub async fn stay_connected(mut self) -> Result<(), EspError> {
-        self.do_connect_loop(false).await
-    }
-
-    async fn do_connect_loop(&mut self, exit_after_first_connect: bool) -> Result<(), EspError> {
-        let wifi = &mut self.wifi;
+    /// Reconnects whenever the connection is lost, waiting longer after each failed attempt
+    pub async fn stay_connected(mut self) -> anyhow::Result<()> {
         loop {
-            // Wait for disconnect before trying to connect again.  This loop ensures
-            // we stay connected and is commonly missing from trivial examples as it's
-            // way too difficult to showcase the core logic of an example and have
-            // a proper Wi-Fi event loop without a robust async runtime.  Fortunately, we can do it
-            // now!
-            wifi.wifi_wait(|this| this.is_up(), None).await?;
-
-            info!("Connecting to Wi-Fi...");
-            wifi.connect().await?;
+            // Wait for disconnect before trying to connect again
+            self.wifi.wifi_wait(|this| this.is_up(), None).await?;
+            warn!("Wi-Fi disconnected");
+            while let Err(e) = self.initial_connect().await {
+                let delay = reconnect_delay(self.failed_attempts);
+                warn!("{e:?}. Trying again in {delay:?}");
+                tokio::time::sleep(delay).await;
+            }
+        }
+    }
 
-            info!("Waiting for association...");
-            wifi.ip_wait_while(|this| this.is_up().map(|s| !s), None)
-                .await?;
+    async fn set_status(&self, state: WifiState) {
+        self.status_tx
+            .update(WifiStatus {
+                state,
+                failed_attempts: self.failed_attempts,
+                backoff_secs: reconnect_delay(self.failed_attempts).as_secs(),
+            })
+            .await;
+    }
 
-            if exit_after_first_connect {
-                return Ok(());
+    async fn connect(
+        &mut self,
+        network: &WifiNetwork,
+        scanned: Option<&ScannedNetwork>,
+        hostname: &str,
+    ) -> anyhow::Result<()> {
+        info!(
+            "Setting Wi-Fi credentials for {:?} ({})...",
+            network.ssid,
+            match scanned {
+                Some(scanned) => format!("{} dBm", scanned.signal_strength),
+                None => "not found while scanning".into(),
             }
+        );
+        let wifi_configuration = Configuration::Client(ClientConfiguration {
+            ssid: network
+                .ssid
+                .as_str()
+                .try_into()
+                .map_err(|()| anyhow!("SSID is too long"))?,
+            password: network
+                .password
+                .as_str()
+                .try_into()
+                .map_err(|()| anyhow!("Password is too long"))?,
+            auth_method: match network.security_for(scanned) {
+                WifiSecurity::Open => AuthMethod::None,
+                WifiSecurity::Auto | WifiSecurity::Wpa2 => AuthMethod::WPA2Personal,
+                WifiSecurity::Wpa3 => AuthMethod::WPA3Personal,
+            },
+            channel: None,
+            ..Default::default()
+        });
+        if self.wifi.is_started()? {
+            self.wifi.stop().await?;
         }
+        // Each network can have its own static IP
+        self.wifi
+            .wifi_mut()
+            .swap_netif_sta(new_sta_netif(network, hostname)?)?;
+        self.wifi.set_configuration(&wifi_configuration)?;
+
+        info!("Starting Wi-Fi driver...");
+        self.wifi.start().await?;
+
+        info!("Connecting to Wi-Fi...");
+        self.wifi.connect().await?;
+
+        info!("Waiting for association...");
+        self.wifi
+            .ip_wait_while(|this| this.is_up().map(|s| !s), None)
+            .await?;
+        Ok(())
     }
 }
@@ -0,0 +1,49 @@
+use std::time::Duration;
+
+use smart_power_button_common::bluetooth_wake::BleAdvertisement;
+use smart_power_button_esp_core::bluetooth_wake::BleScanner;
+use tokio::time::{sleep, sleep_until, Instant};
+
+/// Pretends that some Bluetooth devices are on and advertising
+pub struct FakeBleScanner {
+    devices: Vec<BleAdvertisement>,
+    advertising_interval: Duration,
+}
+
+impl FakeBleScanner {
+    pub fn new(devices: Vec<BleAdvertisement>) -> Self {
+        Self {
+            devices,
+            advertising_interval: Duration::from_secs(1),
+        }
+    }
+}
+
+impl BleScanner for FakeBleScanner {
+    async fn find_device(
+        &mut self,
+        duration: Duration,
+        is_wakeup_device: impl Fn(&BleAdvertisement) -> bool,
+    ) -> anyhow::Result<Option<BleAdvertisement>> {
+        sleep(self.advertising_interval.min(duration)).await;
+        Ok(self
+            .devices
+            .iter()
+            .find(|&advertisement| is_wakeup_device(advertisement))
+            .cloned())
+    }
+
+    async fn scan(
+        &mut self,
+        duration: Duration,
+        mut on_device: impl FnMut(BleAdvertisement),
+    ) -> anyhow::Result<()> {
+        let end = Instant::now() + duration;
+        while Instant::now() + self.advertising_interval <= end {
+            sleep(self.advertising_interval).await;
+            self.devices.iter().cloned().for_each(&mut on_device);
+        }
+        sleep_until(end).await;
+        Ok(())
+    }
+}
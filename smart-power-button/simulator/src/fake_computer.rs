@@ -0,0 +1,119 @@
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use log::info;
+use smart_power_button_common::Power;
+use smart_power_button_esp_core::button::ButtonPin;
+use smart_power_button_esp_core::value_channel::{value_channel, ValueReceiver, ValueSender};
+use tokio::time::{interval, Instant};
+
+/// Holding the power button for this long turns off the computer, like a real one
+const FORCE_OFF_DURATION: Duration = Duration::from_secs(4);
+
+/// Pretends to be a computer with its power LED, HDD LED, power button, and reset button
+/// connected to the ESP.
+///
+/// Pressing the power button turns it on if it's off or suspended, and suspends it if it's on.
+/// Holding the power button turns it off.
+#[derive(Clone)]
+pub struct FakeComputer {
+    power: Arc<Mutex<Power>>,
+    power_led_tx: Arc<ValueSender<bool>>,
+    power_led_rx: ValueReceiver<bool>,
+    hdd_led_tx: Arc<ValueSender<bool>>,
+    hdd_led_rx: ValueReceiver<bool>,
+}
+
+impl FakeComputer {
+    pub fn new(power: Power) -> Self {
+        let (power_led_tx, power_led_rx) = value_channel(false);
+        let (hdd_led_tx, hdd_led_rx) = value_channel(false);
+        Self {
+            power: Arc::new(Mutex::new(power)),
+            power_led_tx: Arc::new(power_led_tx),
+            power_led_rx,
+            hdd_led_tx: Arc::new(hdd_led_tx),
+            hdd_led_rx,
+        }
+    }
+
+    pub fn power(&self) -> Power {
+        *self.power.lock().unwrap()
+    }
+
+    pub fn power_led_rx(&self) -> ValueReceiver<bool> {
+        self.power_led_rx.clone()
+    }
+
+    pub fn hdd_led_rx(&self) -> ValueReceiver<bool> {
+        self.hdd_led_rx.clone()
+    }
+
+    pub fn power_button(&self) -> FakeButtonPin {
+        FakeButtonPin {
+            computer: self.clone(),
+            is_power_button: true,
+            pressed_at: None,
+        }
+    }
+
+    pub fn reset_button(&self) -> FakeButtonPin {
+        FakeButtonPin {
+            computer: self.clone(),
+            is_power_button: false,
+            pressed_at: None,
+        }
+    }
+
+    fn set_power(&self, power: Power) {
+        info!("Fake computer: {power:?}");
+        *self.power.lock().unwrap() = power;
+    }
+
+    /// Blinks the LEDs like a real computer. The power LED blinks once a second when suspended,
+    /// and the HDD LED flickers when on.
+    pub async fn run(&self) {
+        let mut interval = interval(Duration::from_millis(100));
+        let mut tick = 0_u32;
+        loop {
+            interval.tick().await;
+            tick = tick.wrapping_add(1);
+            let (power_led, hdd_led) = match self.power() {
+                Power::On => (true, tick.is_multiple_of(7)),
+                Power::Suspend => ((tick / 10).is_multiple_of(2), false),
+                Power::Off => (false, false),
+            };
+            self.power_led_tx.update_if_changed(power_led).await;
+            self.hdd_led_tx.update_if_changed(hdd_led).await;
+        }
+    }
+}
+
+/// The wires of a button on the [`FakeComputer`]
+pub struct FakeButtonPin {
+    computer: FakeComputer,
+    is_power_button: bool,
+    pressed_at: Option<Instant>,
+}
+
+impl ButtonPin for FakeButtonPin {
+    fn set_pressed(&mut self, is_pressed: bool) -> anyhow::Result<()> {
+        match (is_pressed, self.pressed_at.take()) {
+            (true, pressed_at) => self.pressed_at = Some(pressed_at.unwrap_or_else(Instant::now)),
+            (false, Some(pressed_at)) => {
+                let computer = &self.computer;
+                match (self.is_power_button, computer.power()) {
+                    (true, _) if pressed_at.elapsed() >= FORCE_OFF_DURATION => {
+                        computer.set_power(Power::Off)
+                    }
+                    (true, Power::Off | Power::Suspend) => computer.set_power(Power::On),
+                    (true, Power::On) => computer.set_power(Power::Suspend),
+                    (false, Power::On) => info!("Fake computer: restarting"),
+                    (false, _) => {}
+                }
+            }
+            (false, None) => {}
+        }
+        Ok(())
+    }
+}
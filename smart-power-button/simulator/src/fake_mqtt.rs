@@ -0,0 +1,199 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use anyhow::anyhow;
+use parking_lot::Mutex;
+use smart_power_button_common::mqtt::{MqttConfig, OFFLINE_PAYLOAD};
+use smart_power_button_esp_core::mqtt::{MqttClient, MqttConnector, MqttMessage, MqttMessages};
+use tokio::sync::mpsc;
+
+/// A broker in memory that works like mosquitto for exact topics. Clones share the same broker.
+#[derive(Clone)]
+pub struct FakeBroker(Arc<Mutex<Broker>>);
+
+struct Broker {
+    retained: HashMap<String, Vec<u8>>,
+    /// The last message on each topic, retained or not
+    last: HashMap<String, Vec<u8>>,
+    sessions: Vec<Session>,
+    connections: u32,
+}
+
+struct Session {
+    id: u32,
+    subscriptions: Vec<String>,
+    sender: mpsc::UnboundedSender<MqttMessage>,
+    will: MqttMessage,
+}
+
+impl Default for FakeBroker {
+    fn default() -> Self {
+        Self(Arc::new(Mutex::new(Broker {
+            retained: HashMap::new(),
+            last: HashMap::new(),
+            sessions: Vec::new(),
+            connections: 0,
+        })))
+    }
+}
+
+impl Broker {
+    fn publish(&mut self, message: MqttMessage, retain: bool) {
+        // Like a real broker, an empty retained message deletes the retained one
+        if retain && message.payload.is_empty() {
+            self.retained.remove(&message.topic);
+        } else if retain {
+            self.retained
+                .insert(message.topic.clone(), message.payload.clone());
+        }
+        self.last
+            .insert(message.topic.clone(), message.payload.clone());
+        for session in &self.sessions {
+            if session.subscriptions.contains(&message.topic) {
+                let _ = session.sender.send(message.clone());
+            }
+        }
+    }
+}
+
+impl FakeBroker {
+    /// Publishes like another client, such as Home Assistant
+    pub fn publish(&self, topic: &str, payload: &[u8]) {
+        self.0.lock().publish(
+            MqttMessage {
+                topic: topic.into(),
+                payload: payload.into(),
+            },
+            false,
+        );
+    }
+
+    /// Publishes a retained message like another client
+    pub fn retain(&self, topic: &str, payload: &[u8]) {
+        self.0.lock().publish(
+            MqttMessage {
+                topic: topic.into(),
+                payload: payload.into(),
+            },
+            true,
+        );
+    }
+
+    pub fn retained(&self, topic: &str) -> Option<Vec<u8>> {
+        self.0.lock().retained.get(topic).cloned()
+    }
+
+    /// The last message on `topic`, even if it wasn't retained
+    pub fn last(&self, topic: &str) -> Option<Vec<u8>> {
+        self.0.lock().last.get(topic).cloned()
+    }
+
+    /// How many times clients connected
+    pub fn connections(&self) -> u32 {
+        self.0.lock().connections
+    }
+
+    /// Drops all clients like the network went down, and publishes their last wills
+    pub fn disconnect_all(&self) {
+        let mut broker = self.0.lock();
+        for session in std::mem::take(&mut broker.sessions) {
+            broker.publish(session.will, true);
+        }
+    }
+}
+
+impl MqttConnector for FakeBroker {
+    type Client = FakeMqttClient;
+    type Messages = FakeMqttMessages;
+
+    async fn connect(
+        &mut self,
+        _config: &MqttConfig,
+        _client_id: &str,
+        availability_topic: &str,
+    ) -> anyhow::Result<(FakeMqttClient, FakeMqttMessages)> {
+        let (sender, receiver) = mpsc::unbounded_channel();
+        let mut broker = self.0.lock();
+        broker.connections += 1;
+        let id = broker.connections;
+        broker.sessions.push(Session {
+            id,
+            subscriptions: Vec::new(),
+            sender,
+            will: MqttMessage {
+                topic: availability_topic.into(),
+                payload: OFFLINE_PAYLOAD.into(),
+            },
+        });
+        Ok((
+            FakeMqttClient {
+                broker: self.clone(),
+                id,
+            },
+            FakeMqttMessages(receiver),
+        ))
+    }
+}
+
+pub struct FakeMqttClient {
+    broker: FakeBroker,
+    id: u32,
+}
+
+impl FakeMqttClient {
+    fn is_connected(&self) -> bool {
+        let broker = self.broker.0.lock();
+        broker.sessions.iter().any(|session| session.id == self.id)
+    }
+}
+
+/// Disconnecting on purpose doesn't publish the last will
+impl Drop for FakeMqttClient {
+    fn drop(&mut self) {
+        let mut broker = self.broker.0.lock();
+        broker.sessions.retain(|session| session.id != self.id);
+    }
+}
+
+impl MqttClient for FakeMqttClient {
+    async fn publish(&mut self, topic: &str, payload: &[u8], retain: bool) -> anyhow::Result<()> {
+        if !self.is_connected() {
+            return Err(anyhow!("Disconnected"));
+        }
+        self.broker.0.lock().publish(
+            MqttMessage {
+                topic: topic.into(),
+                payload: payload.into(),
+            },
+            retain,
+        );
+        Ok(())
+    }
+
+    async fn subscribe(&mut self, topic: &str) -> anyhow::Result<()> {
+        let mut broker = self.broker.0.lock();
+        let retained = broker.retained.get(topic).cloned();
+        let session = broker
+            .sessions
+            .iter_mut()
+            .find(|session| session.id == self.id)
+            .ok_or(anyhow!("Disconnected"))?;
+        session.subscriptions.push(topic.into());
+        // Like a real broker, retained messages are sent when subscribing
+        if let Some(payload) = retained {
+            let _ = session.sender.send(MqttMessage {
+                topic: topic.into(),
+                payload,
+            });
+        }
+        Ok(())
+    }
+}
+
+pub struct FakeMqttMessages(mpsc::UnboundedReceiver<MqttMessage>);
+
+impl MqttMessages for FakeMqttMessages {
+    async fn next(&mut self) -> anyhow::Result<MqttMessage> {
+        self.0.recv().await.ok_or(anyhow!("Disconnected"))
+    }
+}
@@ -0,0 +1,67 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::anyhow;
+use parking_lot::Mutex;
+use smart_power_button_esp_core::ota::{OtaPartition, OtaWriter};
+
+/// The size of each OTA partition in `esp/partitions.csv`
+pub const OTA_PARTITION_SIZE: u64 = 0x1E0000;
+/// About how long the ESP takes to erase the partition, which blocks the thread
+pub const ERASE_DURATION: Duration = Duration::from_secs(1);
+
+/// Keeps the last image that was written in memory. Clones share the same image.
+#[derive(Clone, Default)]
+pub struct FakeOtaPartition {
+    image: Arc<Mutex<Option<Vec<u8>>>>,
+    is_erasing: Arc<AtomicBool>,
+}
+
+impl FakeOtaPartition {
+    /// The image that would be booted after restarting
+    pub fn image(&self) -> Option<Vec<u8>> {
+        self.image.lock().clone()
+    }
+
+    pub fn is_erasing(&self) -> bool {
+        self.is_erasing.load(Ordering::Relaxed)
+    }
+}
+
+impl OtaPartition for FakeOtaPartition {
+    fn max_size(&self) -> u64 {
+        OTA_PARTITION_SIZE
+    }
+
+    fn begin(&self, size: u64) -> anyhow::Result<Box<dyn OtaWriter>> {
+        self.is_erasing.store(true, Ordering::Relaxed);
+        std::thread::sleep(ERASE_DURATION);
+        self.is_erasing.store(false, Ordering::Relaxed);
+        Ok(Box::new(FakeOtaWriter {
+            bytes: Vec::with_capacity(size as usize),
+            image: self.image.clone(),
+        }))
+    }
+}
+
+struct FakeOtaWriter {
+    bytes: Vec<u8>,
+    image: Arc<Mutex<Option<Vec<u8>>>>,
+}
+
+impl OtaWriter for FakeOtaWriter {
+    fn write(&mut self, bytes: &[u8]) -> anyhow::Result<()> {
+        self.bytes.extend_from_slice(bytes);
+        Ok(())
+    }
+
+    fn finish(self: Box<Self>) -> anyhow::Result<()> {
+        // Real images start with this magic byte, which the ESP checks
+        if self.bytes.first() != Some(&0xE9) {
+            return Err(anyhow!("Not a firmware image"));
+        }
+        *self.image.lock() = Some(self.bytes);
+        Ok(())
+    }
+}
@@ -0,0 +1,208 @@
+//! Runs the ESP code on a normal computer, with a fake computer and fake Bluetooth devices instead
+//! of the GPIO pins and Bluetooth. Nothing is saved after it stops.
+use std::future::Future;
+use std::net::Ipv4Addr;
+use std::sync::Arc;
+
+use futures::future::join_all;
+use smart_power_button_common::bluetooth_wake::BleAdvertisement;
+use smart_power_button_common::history::HistoryEventKind;
+use smart_power_button_common::machine::DEFAULT_MACHINE_NAME;
+use smart_power_button_common::ota::OtaStatus;
+use smart_power_button_common::wifi::{WifiState, WifiStatus};
+use smart_power_button_esp_core::bluetooth_wake::{
+    bluetooth_wake, migrate_wakeup_devices, BleDiscovery,
+};
+use smart_power_button_esp_core::button::Button;
+use smart_power_button_esp_core::event_log::EventLog;
+use smart_power_button_esp_core::history::History;
+use smart_power_button_esp_core::intents::IntentRunner;
+use smart_power_button_esp_core::machine::Machine;
+use smart_power_button_esp_core::mqtt::{run_mqtt, MqttDevice};
+use smart_power_button_esp_core::nvs_value::{MachineStorage, MemoryStorage, NvsValue};
+use smart_power_button_esp_core::ota::Ota;
+use smart_power_button_esp_core::power_io::PowerIo;
+use smart_power_button_esp_core::serve::serve;
+use smart_power_button_esp_core::server_state::ServerState;
+use smart_power_button_esp_core::static_assets::StaticAsset;
+use smart_power_button_esp_core::value_channel::{value_channel, ValueReceiver};
+use smart_power_button_esp_core::wake_on_lan::wake_on_lan;
+use tokio::net::{TcpListener, UdpSocket};
+use tokio::sync::Mutex;
+use tokio::{join, select};
+
+use crate::fake_ble::FakeBleScanner;
+use crate::fake_computer::FakeComputer;
+use crate::fake_ota::FakeOtaPartition;
+use crate::rumqttc_connector::RumqttcConnector;
+
+pub mod fake_ble;
+pub mod fake_computer;
+pub mod fake_mqtt;
+pub mod fake_ota;
+pub mod rumqttc_connector;
+
+pub struct SimulatorConfig {
+    pub pairing_password: String,
+    /// Bluetooth devices that are advertising
+    pub ble_devices: Vec<BleAdvertisement>,
+    /// Where firmware updates are written
+    pub ota_partition: FakeOtaPartition,
+    /// Where Wake-on-LAN magic packets are received. The ESP uses ports 7 and 9, which normal
+    /// users usually can't listen on.
+    pub wake_on_lan_sockets: Vec<UdpSocket>,
+    /// More machines on the same ESP, with their names
+    pub other_computers: Vec<(String, FakeComputer)>,
+    /// Served like the web page on the ESP
+    pub static_assets: &'static [StaticAsset],
+}
+
+fn fill_random(bytes: &mut [u8]) {
+    getrandom::getrandom(bytes).expect("Error getting random bytes");
+}
+
+/// Nothing is saved, so starting over is the same as erasing everything
+fn factory_reset() {
+    log::warn!("Factory reset. Stopping the simulator.");
+    std::process::exit(0);
+}
+
+/// There is no new firmware to run, so the simulator keeps going
+fn restart() {
+    log::warn!("Restarting isn't simulated. The firmware update was only saved in memory.");
+}
+
+/// Sets up a machine for `computer`, with its values in `storage`
+async fn start_machine(
+    name: &str,
+    computer: &FakeComputer,
+    storage: MachineStorage<MemoryStorage>,
+    ota_status_rx: ValueReceiver<OtaStatus>,
+) -> anyhow::Result<(Machine, impl Future<Output = ()>)> {
+    let history = History::new(storage.clone())?;
+    let (bluetooth_wakeup_rules_tx, bluetooth_wakeup_rules_rx) =
+        NvsValue::new(storage.clone(), "wakeup_rules")?;
+    let (schedule_tx, schedule_rx) = NvsValue::new(storage.clone(), "schedule")?;
+    let (press_config_tx, press_config_rx) = NvsValue::new(storage.clone(), "press")?;
+    let (blink_config_tx, blink_config_rx) = NvsValue::new(storage.clone(), "power_led")?;
+    let (wake_on_lan_config_tx, wake_on_lan_config_rx) = NvsValue::new(storage, "wake_on_lan")?;
+    let (power_io_future, power_io) = PowerIo::new(
+        computer.power_led_rx(),
+        computer.hdd_led_rx(),
+        Button::new(computer.power_button())?,
+        Button::new(computer.reset_button())?,
+        history.clone(),
+        press_config_rx,
+        blink_config_rx,
+    );
+    history.record(HistoryEventKind::Boot).await?;
+    let intents = IntentRunner::new(power_io.clone());
+    let (event_log_future, event_log) = EventLog::new(
+        power_io.clone(),
+        ota_status_rx,
+        intents.status_rx.clone(),
+        fill_random,
+    );
+    let machine = Machine {
+        name: name.into(),
+        power_io,
+        intents,
+        event_log,
+        bluetooth_wakeup_rules_tx: Arc::new(Mutex::new(bluetooth_wakeup_rules_tx)),
+        bluetooth_wakeup_rules_rx,
+        schedule_tx: Arc::new(Mutex::new(schedule_tx)),
+        schedule_rx,
+        press_config_tx: Arc::new(Mutex::new(press_config_tx)),
+        blink_config_tx: Arc::new(Mutex::new(blink_config_tx)),
+        wake_on_lan_config_tx: Arc::new(Mutex::new(wake_on_lan_config_tx)),
+        wake_on_lan_config_rx,
+    };
+    let computer = computer.clone();
+    let future = async move {
+        join!(computer.run(), power_io_future, event_log_future);
+    };
+    Ok((machine, future))
+}
+
+/// Serves the API on `listener` until it fails. `computer` is the first machine, and
+/// [`SimulatorConfig::other_computers`] are the machines after it.
+pub async fn run_simulator(
+    listener: TcpListener,
+    computer: FakeComputer,
+    config: SimulatorConfig,
+) -> anyhow::Result<()> {
+    let storage = MemoryStorage::default();
+    migrate_wakeup_devices(&mut storage.clone(), "devices", "wakeup_rules")?;
+    let (auth_tokens_tx, auth_tokens_rx) = NvsValue::new(storage.clone(), "tokens")?;
+    let (mqtt_config_tx, mqtt_config_rx) = NvsValue::new(storage.clone(), "mqtt")?;
+    // Only saved, because the simulator's machines come from the config
+    let (machines_config_tx, machines_config_rx) = NvsValue::new(storage.clone(), "machines")?;
+    let ota = Ota::new(config.ota_partition, restart);
+    let computers = std::iter::once((DEFAULT_MACHINE_NAME.into(), computer))
+        .chain(config.other_computers)
+        .collect::<Vec<_>>();
+    let mut machines = Vec::with_capacity(computers.len());
+    let mut machine_futures = Vec::with_capacity(computers.len());
+    for ((name, computer), id) in computers.iter().zip(0..) {
+        let (machine, future) = start_machine(
+            name,
+            computer,
+            MachineStorage::new(storage.clone(), id),
+            ota.status_rx.clone(),
+        )
+        .await?;
+        machines.push(machine);
+        machine_futures.push(future);
+    }
+    let machines: Arc<[Machine]> = machines.into();
+    let (ble_discovery, discovery_requests) = BleDiscovery::new();
+    let server_state = ServerState {
+        machines: machines.clone(),
+        machines_config_tx: Arc::new(Mutex::new(machines_config_tx)),
+        machines_config_rx,
+        ble_discovery,
+        auth_tokens_tx: Arc::new(Mutex::new(auth_tokens_tx)),
+        auth_tokens_rx,
+        // The simulator uses the computer's network
+        wifi_status_rx: value_channel(WifiStatus {
+            state: WifiState::Connected {
+                ssid: "Simulator".into(),
+                ip: Ipv4Addr::LOCALHOST,
+            },
+            failed_attempts: 0,
+            backoff_secs: 0,
+        })
+        .1,
+        mqtt_config_tx: Arc::new(Mutex::new(mqtt_config_tx)),
+        mqtt_config_rx: mqtt_config_rx.clone(),
+        ota,
+        pairing_password: config.pairing_password.into(),
+        random_bytes: fill_random,
+        factory_reset,
+        static_assets: config.static_assets,
+    };
+    let background_future = async {
+        join!(
+            join_all(machine_futures),
+            join_all(machines.iter().zip(0..).map(|(machine, id)| run_mqtt(
+                RumqttcConnector,
+                mqtt_config_rx.clone(),
+                machine.power_io.clone(),
+                MqttDevice {
+                    name: machine.mqtt_name(id, "simulator"),
+                    firmware_version: env!("CARGO_PKG_VERSION").into(),
+                },
+            ))),
+            wake_on_lan(config.wake_on_lan_sockets, machines.clone()),
+            bluetooth_wake(
+                machines.clone(),
+                FakeBleScanner::new(config.ble_devices),
+                discovery_requests,
+            )
+        )
+    };
+    select! {
+        result = serve(listener, server_state) => result,
+        _ = background_future => Ok(()),
+    }
+}
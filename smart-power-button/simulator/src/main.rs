@@ -0,0 +1,83 @@
+use std::net::{Ipv4Addr, SocketAddr};
+
+use anyhow::{anyhow, bail, Context};
+use log::info;
+use smart_power_button_common::bluetooth_wake::{BleAddressType, BleAdvertisement};
+use smart_power_button_common::Power;
+use smart_power_button_esp_core::static_assets::WEB_PAGE;
+use smart_power_button_esp_core::wake_on_lan::bind_wake_on_lan_sockets;
+use smart_power_button_simulator::fake_computer::FakeComputer;
+use smart_power_button_simulator::{run_simulator, SimulatorConfig};
+use tokio::net::TcpListener;
+
+const USAGE: &str = "Usage: smart-power-button-simulator [--port <port>] [--password <pairing password>] [--ble-device <AA:BB:CC:DD:EE:FF>]... [--ble-name <name>]... [--wake-on-lan-port <port>]... [--machine <name>]...";
+
+/// Parses an address the way it's written, like `C8:3F:26:8D:4D:00`
+fn parse_ble_address(address: &str) -> anyhow::Result<[u8; 6]> {
+    let mut bytes = address
+        .split(':')
+        .map(|hex| u8::from_str_radix(hex, 16))
+        .collect::<Result<Vec<_>, _>>()
+        .context(format!("Invalid Bluetooth address: {address:?}"))?;
+    // The ESP keeps the bytes in the opposite order
+    bytes.reverse();
+    bytes
+        .try_into()
+        .map_err(|_| anyhow!("Bluetooth addresses have 6 bytes: {address:?}"))
+}
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("info")).init();
+
+    let mut port = 8080;
+    let mut config = SimulatorConfig {
+        pairing_password: "simulator".into(),
+        ble_devices: Vec::new(),
+        ota_partition: Default::default(),
+        wake_on_lan_sockets: Vec::new(),
+        other_computers: Vec::new(),
+        static_assets: WEB_PAGE,
+    };
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        let mut value = || {
+            args.next()
+                .context(format!("Missing value for {arg}\n{USAGE}"))
+        };
+        match arg.as_str() {
+            "--port" => port = value()?.parse().context("Invalid port")?,
+            "--password" => config.pairing_password = value()?,
+            "--ble-device" => config.ble_devices.push(BleAdvertisement {
+                address: parse_ble_address(&value()?)?,
+                ..Default::default()
+            }),
+            // A device with a random address, which can only be matched by its name
+            "--ble-name" => config.ble_devices.push(BleAdvertisement {
+                address_type: BleAddressType::Random,
+                name: Some(value()?),
+                ..Default::default()
+            }),
+            // Like the ESP, this listens on every interface so that broadcasts are received
+            "--wake-on-lan-port" => {
+                let port = value()?.parse().context("Invalid port")?;
+                config
+                    .wake_on_lan_sockets
+                    .extend(bind_wake_on_lan_sockets(&[port]).await);
+            }
+            // Another computer on the same ESP, after the first one
+            "--machine" => config
+                .other_computers
+                .push((value()?, FakeComputer::new(Power::Off))),
+            _ => bail!("Unknown argument: {arg}\n{USAGE}"),
+        }
+    }
+
+    let listener = TcpListener::bind(SocketAddr::from((Ipv4Addr::LOCALHOST, port))).await?;
+    info!(
+        "Simulator is listening at http://{}. The pairing password is {:?}.",
+        listener.local_addr()?,
+        config.pairing_password
+    );
+    run_simulator(listener, FakeComputer::new(Power::Off), config).await
+}
@@ -0,0 +1,71 @@
+use std::time::Duration;
+
+use rumqttc::{AsyncClient, Event, EventLoop, LastWill, MqttOptions, Packet, QoS};
+use smart_power_button_common::mqtt::{MqttConfig, OFFLINE_PAYLOAD};
+use smart_power_button_esp_core::mqtt::{MqttClient, MqttConnector, MqttMessage, MqttMessages};
+
+/// Connects to a real broker, like mosquitto, so the simulator can be used with Home Assistant
+pub struct RumqttcConnector;
+
+impl MqttConnector for RumqttcConnector {
+    type Client = RumqttcClient;
+    type Messages = RumqttcMessages;
+
+    async fn connect(
+        &mut self,
+        config: &MqttConfig,
+        client_id: &str,
+        availability_topic: &str,
+    ) -> anyhow::Result<(RumqttcClient, RumqttcMessages)> {
+        let mut options = MqttOptions::new(client_id, &config.host, config.port);
+        options.set_keep_alive(Duration::from_secs(30));
+        options.set_last_will(LastWill::new(
+            availability_topic,
+            OFFLINE_PAYLOAD,
+            QoS::AtLeastOnce,
+            true,
+        ));
+        if !config.username.is_empty() {
+            options.set_credentials(&config.username, &config.password);
+        }
+        // Requests wait here until the event loop is polled, which is only while waiting for
+        // messages, so this has room for all the discovery configs
+        let (client, mut event_loop) = AsyncClient::new(options, 64);
+        loop {
+            if let Event::Incoming(Packet::ConnAck(_)) = event_loop.poll().await? {
+                break;
+            }
+        }
+        Ok((RumqttcClient(client), RumqttcMessages(event_loop)))
+    }
+}
+
+pub struct RumqttcClient(AsyncClient);
+
+impl MqttClient for RumqttcClient {
+    async fn publish(&mut self, topic: &str, payload: &[u8], retain: bool) -> anyhow::Result<()> {
+        Ok(self
+            .0
+            .publish(topic, QoS::AtLeastOnce, retain, payload.to_vec())
+            .await?)
+    }
+
+    async fn subscribe(&mut self, topic: &str) -> anyhow::Result<()> {
+        Ok(self.0.subscribe(topic, QoS::AtLeastOnce).await?)
+    }
+}
+
+pub struct RumqttcMessages(EventLoop);
+
+impl MqttMessages for RumqttcMessages {
+    async fn next(&mut self) -> anyhow::Result<MqttMessage> {
+        loop {
+            if let Event::Incoming(Packet::Publish(publish)) = self.0.poll().await? {
+                return Ok(MqttMessage {
+                    topic: publish.topic,
+                    payload: publish.payload.to_vec(),
+                });
+            }
+        }
+    }
+}
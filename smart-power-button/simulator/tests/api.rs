@@ -0,0 +1,490 @@
+//! Runs the simulator and uses its API like the web page and the `computer` crate do
+use std::net::SocketAddr;
+use std::time::Duration;
+
+use futures::{SinkExt, Stream, StreamExt};
+use postcard::{from_bytes, to_allocvec};
+use reqwest::{Client, StatusCode};
+use smart_power_button_common::auth::{LoginRequest, LoginResponse};
+use smart_power_button_common::mqtt::MqttConfig;
+use smart_power_button_common::power_led::{BlinkConfig, Calibration, CalibrationError};
+use smart_power_button_common::press::{
+    ButtonKind, PressConfig, PressError, PressPattern, MAX_HOLD,
+};
+use smart_power_button_common::protocol::{
+    Capability, FrameToEsp, FrameToWeb, Hello, ProtocolError, RequestId,
+};
+use smart_power_button_common::wifi::{WifiState, WifiStatus};
+use smart_power_button_common::{MessageToEsp, MessageToWeb, Power, WakeupReason};
+use smart_power_button_simulator::fake_computer::FakeComputer;
+use smart_power_button_simulator::{run_simulator, SimulatorConfig};
+use tokio::net::TcpListener;
+use tokio::time::timeout;
+use tokio_tungstenite::connect_async;
+use tokio_tungstenite::tungstenite::Message;
+
+const PASSWORD: &str = "test password";
+
+async fn start(computer: FakeComputer) -> SocketAddr {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let address = listener.local_addr().unwrap();
+    tokio::spawn(run_simulator(
+        listener,
+        computer,
+        SimulatorConfig {
+            pairing_password: PASSWORD.into(),
+            ble_devices: vec![],
+            ota_partition: Default::default(),
+            wake_on_lan_sockets: vec![],
+            other_computers: vec![],
+            static_assets: &[],
+        },
+    ));
+    address
+}
+
+async fn login(address: SocketAddr, password: &str) -> reqwest::Response {
+    Client::new()
+        .post(format!("http://{address}/login"))
+        .body(
+            to_allocvec(&LoginRequest {
+                password: password.into(),
+                client_name: "test".into(),
+            })
+            .unwrap(),
+        )
+        .send()
+        .await
+        .unwrap()
+}
+
+async fn get_wakeup_reason(address: SocketAddr, token: &str) -> Option<WakeupReason> {
+    let response = Client::new()
+        .get(format!("http://{address}/wakeup_reason"))
+        .bearer_auth(token)
+        .send()
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+    from_bytes(&response.bytes().await.unwrap()).unwrap()
+}
+
+#[tokio::test]
+async fn api_needs_a_token() {
+    let address = start(FakeComputer::new(Power::Off)).await;
+    let response = Client::new()
+        .get(format!("http://{address}/wakeup_reason"))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    assert_eq!(
+        login(address, "wrong password").await.status(),
+        StatusCode::UNAUTHORIZED
+    );
+    for body in [&b""[..], b"\xff\xff\xff"] {
+        let response = Client::new()
+            .post(format!("http://{address}/login"))
+            .body(body)
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    }
+    let response = Client::new()
+        .get(format!("http://{address}/wakeup_reason"))
+        .bearer_auth("not a token")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+}
+
+/// Waits for a frame that `f` returns `Some` for
+async fn wait_for<T>(
+    frames: &mut (impl Stream<Item = FrameToWeb> + Unpin),
+    mut f: impl FnMut(FrameToWeb) -> Option<T>,
+) -> T {
+    timeout(Duration::from_secs(10), async {
+        loop {
+            if let Some(value) = f(frames.next().await.expect("WebSocket closed")) {
+                break value;
+            }
+        }
+    })
+    .await
+    .expect("Timed out waiting for frame")
+}
+
+fn power_state(frame: FrameToWeb) -> Option<Option<Power>> {
+    match frame {
+        FrameToWeb::Message {
+            message: MessageToWeb::PowerState(state),
+            ..
+        } => Some(state.power),
+        _ => None,
+    }
+}
+
+#[tokio::test]
+async fn pressing_power_button_turns_on_computer() {
+    let computer = FakeComputer::new(Power::Off);
+    let address = start(computer.clone()).await;
+    let response = login(address, PASSWORD).await.error_for_status().unwrap();
+    let LoginResponse { token } = from_bytes(&response.bytes().await.unwrap()).unwrap();
+    assert_eq!(get_wakeup_reason(address, &token).await, None);
+
+    let (mut websocket, _) = connect_async(format!("ws://{address}/?token={token}"))
+        .await
+        .unwrap();
+    websocket
+        .send(Message::Binary(
+            FrameToEsp::Hello(Hello::new(vec![])).encode(),
+        ))
+        .await
+        .unwrap();
+    let (mut w, r) = websocket.split();
+    let mut frames = Box::pin(r.filter_map(|message| async move {
+        match message.unwrap() {
+            Message::Binary(bytes) => Some(FrameToWeb::decode(&bytes).unwrap()),
+            _ => None,
+        }
+    }));
+    match frames.next().await {
+        Some(FrameToWeb::Hello(hello)) => {
+            assert_eq!(hello.check_version(), Ok(()));
+            assert!(hello.has(Capability::PowerState));
+        }
+        frame => panic!("Expected hello, got {frame:?}"),
+    }
+    // The ESP has to watch the power LED for a bit before it knows that the computer is off
+    wait_for(&mut frames, |frame| {
+        (power_state(frame)? == Some(Power::Off)).then_some(())
+    })
+    .await;
+
+    w.send(Message::Binary(
+        FrameToEsp::Request {
+            id: 7,
+            machine: 0,
+            message: MessageToEsp::ShortPressPowerButton(true),
+        }
+        .encode(),
+    ))
+    .await
+    .unwrap();
+    wait_for(&mut frames, |frame| {
+        (frame == FrameToWeb::Ack(7)).then_some(())
+    })
+    .await;
+    assert_eq!(computer.power(), Power::On);
+    wait_for(&mut frames, |frame| {
+        (power_state(frame)? == Some(Power::On)).then_some(())
+    })
+    .await;
+    assert_eq!(
+        get_wakeup_reason(address, &token).await,
+        Some(WakeupReason::Web(true))
+    );
+
+    let history = Client::new()
+        .get(format!("http://{address}/history"))
+        .bearer_auth(&token)
+        .header("Accept", "application/json")
+        .send()
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap()
+        .bytes()
+        .await
+        .unwrap();
+    let history = serde_json::from_slice::<serde_json::Value>(&history).unwrap();
+    assert!(history.as_array().unwrap().len() >= 4, "{history:#}");
+}
+
+#[tokio::test]
+async fn wifi_status_is_reported() {
+    let address = start(FakeComputer::new(Power::Off)).await;
+    let response = login(address, PASSWORD).await.error_for_status().unwrap();
+    let LoginResponse { token } = from_bytes(&response.bytes().await.unwrap()).unwrap();
+    let response = Client::new()
+        .get(format!("http://{address}/wifi_status"))
+        .bearer_auth(&token)
+        .send()
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+    let status = from_bytes::<WifiStatus>(&response.bytes().await.unwrap()).unwrap();
+    assert!(matches!(status.state, WifiState::Connected { .. }));
+    assert_eq!(status.failed_attempts, 0);
+}
+
+#[tokio::test]
+async fn mqtt_config_is_saved() {
+    let address = start(FakeComputer::new(Power::Off)).await;
+    let response = login(address, PASSWORD).await.error_for_status().unwrap();
+    let LoginResponse { token } = from_bytes(&response.bytes().await.unwrap()).unwrap();
+    let put = |config: MqttConfig| {
+        Client::new()
+            .put(format!("http://{address}/mqtt"))
+            .bearer_auth(&token)
+            .body(to_allocvec(&config).unwrap())
+            .send()
+    };
+    let response = put(MqttConfig {
+        host: "127.0.0.1".into(),
+        base_topic: "a/#".into(),
+        ..Default::default()
+    })
+    .await
+    .unwrap();
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+    let config = MqttConfig {
+        // Nothing listens on port 1, so the simulator just keeps retrying
+        host: "127.0.0.1".into(),
+        port: 1,
+        ..Default::default()
+    };
+    put(config.clone())
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+    let response = Client::new()
+        .get(format!("http://{address}/mqtt"))
+        .bearer_auth(&token)
+        .send()
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+    assert_eq!(
+        from_bytes::<MqttConfig>(&response.bytes().await.unwrap()).unwrap(),
+        config
+    );
+}
+
+#[tokio::test]
+async fn press_config_is_validated_and_saved() {
+    let address = start(FakeComputer::new(Power::Off)).await;
+    let response = login(address, PASSWORD).await.error_for_status().unwrap();
+    let LoginResponse { token } = from_bytes(&response.bytes().await.unwrap()).unwrap();
+    let put = |config: PressConfig| {
+        Client::new()
+            .put(format!("http://{address}/press_config"))
+            .bearer_auth(&token)
+            .body(to_allocvec(&config).unwrap())
+            .send()
+    };
+    let response = put(PressConfig {
+        long_press: MAX_HOLD * 2,
+        ..Default::default()
+    })
+    .await
+    .unwrap();
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    assert_eq!(
+        response.text().await.unwrap(),
+        PressError::InvalidHold.to_string()
+    );
+
+    let config = PressConfig {
+        short_press: Duration::from_millis(200),
+        long_press: Duration::from_secs(5),
+        reset_press: Duration::from_millis(300),
+    };
+    put(config).await.unwrap().error_for_status().unwrap();
+    let response = Client::new()
+        .get(format!("http://{address}/press_config"))
+        .bearer_auth(&token)
+        .send()
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+    assert_eq!(
+        from_bytes::<PressConfig>(&response.bytes().await.unwrap()).unwrap(),
+        config
+    );
+}
+
+fn press_power_button(id: RequestId, pattern: PressPattern) -> Message {
+    Message::Binary(
+        FrameToEsp::Request {
+            id,
+            machine: 0,
+            message: MessageToEsp::Press {
+                button: ButtonKind::Power,
+                pattern,
+            },
+        }
+        .encode(),
+    )
+}
+
+#[tokio::test]
+async fn press_patterns_are_checked_and_pressed() {
+    let computer = FakeComputer::new(Power::Off);
+    let address = start(computer.clone()).await;
+    let response = login(address, PASSWORD).await.error_for_status().unwrap();
+    let LoginResponse { token } = from_bytes(&response.bytes().await.unwrap()).unwrap();
+    let (mut websocket, _) = connect_async(format!("ws://{address}/?token={token}"))
+        .await
+        .unwrap();
+    websocket
+        .send(Message::Binary(
+            FrameToEsp::Hello(Hello::new(vec![])).encode(),
+        ))
+        .await
+        .unwrap();
+    let (mut w, r) = websocket.split();
+    let mut frames = Box::pin(r.filter_map(|message| async move {
+        match message.unwrap() {
+            Message::Binary(bytes) => Some(FrameToWeb::decode(&bytes).unwrap()),
+            _ => None,
+        }
+    }));
+    match frames.next().await {
+        Some(FrameToWeb::Hello(hello)) => assert!(hello.has(Capability::PressPatterns)),
+        frame => panic!("Expected hello, got {frame:?}"),
+    }
+    w.send(press_power_button(1, PressPattern::hold(MAX_HOLD * 2)))
+        .await
+        .unwrap();
+    let error = wait_for(&mut frames, |frame| match frame {
+        FrameToWeb::Error { id: Some(1), error } => Some(error),
+        FrameToWeb::Ack(1) => panic!("The pattern was pressed"),
+        _ => None,
+    })
+    .await;
+    assert_eq!(error, ProtocolError::Press(PressError::InvalidHold));
+    assert_eq!(computer.power(), Power::Off);
+    assert_eq!(get_wakeup_reason(address, &token).await, None);
+
+    wait_for(&mut frames, |frame| {
+        (power_state(frame)? == Some(Power::Off)).then_some(())
+    })
+    .await;
+    // The first press turns on the fake computer, and the second one suspends it
+    w.send(press_power_button(
+        2,
+        PressPattern::double_press(Duration::from_millis(200), Duration::from_millis(300)),
+    ))
+    .await
+    .unwrap();
+    wait_for(&mut frames, |frame| {
+        (frame == FrameToWeb::Ack(2)).then_some(())
+    })
+    .await;
+    assert_eq!(computer.power(), Power::Suspend);
+    assert_eq!(
+        get_wakeup_reason(address, &token).await,
+        Some(WakeupReason::Web(false))
+    );
+}
+
+/// A body that can't be read gets 400 Bad Request, instead of a dropped connection
+#[tokio::test]
+async fn bad_bodies_get_400() {
+    let address = start(FakeComputer::new(Power::Off)).await;
+    let response = login(address, PASSWORD).await.error_for_status().unwrap();
+    let LoginResponse { token } = from_bytes(&response.bytes().await.unwrap()).unwrap();
+    for path in [
+        "/schedule",
+        "/mqtt",
+        "/press_config",
+        "/power_led",
+        "/wake_on_lan",
+        "/machines",
+    ] {
+        let response = Client::new()
+            .put(format!("http://{address}{path}"))
+            .bearer_auth(&token)
+            .body(&b"\xff\xff\xff"[..])
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST, "{path}");
+        assert!(!response.text().await.unwrap().is_empty(), "{path}");
+    }
+}
+
+#[tokio::test]
+async fn power_led_is_calibrated_while_suspended() {
+    let address = start(FakeComputer::new(Power::Suspend)).await;
+    let response = login(address, PASSWORD).await.error_for_status().unwrap();
+    let LoginResponse { token } = from_bytes(&response.bytes().await.unwrap()).unwrap();
+    let response = Client::new()
+        .post(format!("http://{address}/power_led/calibrate?secs=6"))
+        .bearer_auth(&token)
+        .send()
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+    let calibration = from_bytes::<Calibration>(&response.bytes().await.unwrap()).unwrap();
+    // The fake computer blinks once a second
+    for half_period in [
+        calibration.shortest_half_period,
+        calibration.longest_half_period,
+    ] {
+        assert!(
+            half_period.abs_diff(Duration::from_secs(1)) < Duration::from_millis(200),
+            "{calibration:?}"
+        );
+    }
+
+    let put = |config: BlinkConfig| {
+        Client::new()
+            .put(format!("http://{address}/power_led"))
+            .bearer_auth(&token)
+            .body(to_allocvec(&config).unwrap())
+            .send()
+    };
+    let response = put(BlinkConfig {
+        debounce: Duration::from_secs(1),
+        ..Default::default()
+    })
+    .await
+    .unwrap();
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    put(calibration.suggested)
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+    let response = Client::new()
+        .get(format!("http://{address}/power_led"))
+        .bearer_auth(&token)
+        .send()
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+    assert_eq!(
+        from_bytes::<BlinkConfig>(&response.bytes().await.unwrap()).unwrap(),
+        calibration.suggested
+    );
+}
+
+#[tokio::test]
+async fn power_led_calibration_needs_a_blink() {
+    let address = start(FakeComputer::new(Power::On)).await;
+    let response = login(address, PASSWORD).await.error_for_status().unwrap();
+    let LoginResponse { token } = from_bytes(&response.bytes().await.unwrap()).unwrap();
+    let response = Client::new()
+        .post(format!("http://{address}/power_led/calibrate?secs=1"))
+        .bearer_auth(&token)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(
+        response.text().await.unwrap(),
+        CalibrationError::NotEnoughEdges.to_string()
+    );
+}
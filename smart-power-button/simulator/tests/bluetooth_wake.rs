@@ -0,0 +1,218 @@
+//! Wakes the simulator's computer with fake Bluetooth devices, and finds them with discovery scans
+use std::net::SocketAddr;
+use std::time::Duration;
+
+use futures::{SinkExt, StreamExt};
+use postcard::{from_bytes, to_allocvec};
+use reqwest::Client;
+use smart_power_button_common::auth::{LoginRequest, LoginResponse};
+use smart_power_button_common::bluetooth_wake::{
+    BleAddressType, BleAdvertisement, BleUuid, DeviceMatcher, WakeupRule,
+};
+use smart_power_button_common::protocol::{Capability, FrameToEsp, FrameToWeb, Hello};
+use smart_power_button_common::{MessageToWeb, Power, WakeupReason};
+use smart_power_button_esp_core::bluetooth_wake::migrate_wakeup_devices;
+use smart_power_button_esp_core::nvs_value::{MemoryStorage, NvsValue, Storage};
+use smart_power_button_simulator::fake_computer::FakeComputer;
+use smart_power_button_simulator::{run_simulator, SimulatorConfig};
+use tokio::net::TcpListener;
+use tokio::time::{sleep, timeout};
+use tokio_tungstenite::connect_async;
+use tokio_tungstenite::tungstenite::Message;
+
+const PASSWORD: &str = "test password";
+
+/// A controller with a random address, like most game controllers use
+fn controller() -> BleAdvertisement {
+    BleAdvertisement {
+        address: [0x12, 0x34, 0x56, 0x78, 0x9A, 0x4B],
+        address_type: BleAddressType::Random,
+        name: Some("DualSense Wireless Controller".into()),
+        service_uuids: vec![BleUuid::Uuid16(0x1812)],
+        manufacturer_data: None,
+        rssi: -80,
+    }
+}
+
+/// Starts the simulator with `controller` advertising, and saves `rules`. Returns the address and
+/// a token.
+async fn start(computer: FakeComputer, rules: Vec<WakeupRule>) -> (SocketAddr, String) {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let address = listener.local_addr().unwrap();
+    tokio::spawn(run_simulator(
+        listener,
+        computer,
+        SimulatorConfig {
+            pairing_password: PASSWORD.into(),
+            ble_devices: vec![controller()],
+            ota_partition: Default::default(),
+            wake_on_lan_sockets: vec![],
+            other_computers: vec![],
+            static_assets: &[],
+        },
+    ));
+    let response = Client::new()
+        .post(format!("http://{address}/login"))
+        .body(
+            to_allocvec(&LoginRequest {
+                password: PASSWORD.into(),
+                client_name: "test".into(),
+            })
+            .unwrap(),
+        )
+        .send()
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+    let LoginResponse { token } = from_bytes(&response.bytes().await.unwrap()).unwrap();
+    Client::new()
+        .put(format!("http://{address}/bluetooth_wakeup_devices"))
+        .bearer_auth(&token)
+        .body(to_allocvec(&rules).unwrap())
+        .send()
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+    (address, token)
+}
+
+async fn wait_until_on(computer: &FakeComputer, max_duration: Duration) -> bool {
+    timeout(max_duration, async {
+        while computer.power() != Power::On {
+            sleep(Duration::from_millis(100)).await;
+        }
+    })
+    .await
+    .is_ok()
+}
+
+#[tokio::test]
+async fn devices_with_random_addresses_are_matched_by_name() {
+    let computer = FakeComputer::new(Power::Off);
+    let (address, token) = start(
+        computer.clone(),
+        vec![
+            WakeupRule::address([1, 2, 3, 4, 5, 6]),
+            WakeupRule {
+                matcher: DeviceMatcher::Name("dualsense*".into()),
+                min_rssi: Some(-90),
+                time_window: None,
+            },
+        ],
+    )
+    .await;
+    assert!(wait_until_on(&computer, Duration::from_secs(20)).await);
+    let response = Client::new()
+        .get(format!("http://{address}/wakeup_reason"))
+        .bearer_auth(&token)
+        .send()
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+    assert_eq!(
+        from_bytes::<Option<WakeupReason>>(&response.bytes().await.unwrap()).unwrap(),
+        Some(WakeupReason::Bluetooth(controller().address))
+    );
+}
+
+#[tokio::test]
+async fn weak_devices_dont_wake_the_computer() {
+    let computer = FakeComputer::new(Power::Off);
+    start(
+        computer.clone(),
+        vec![WakeupRule {
+            matcher: DeviceMatcher::ServiceUuid(BleUuid::Uuid16(0x1812)),
+            min_rssi: Some(-70),
+            time_window: None,
+        }],
+    )
+    .await;
+    assert!(!wait_until_on(&computer, Duration::from_secs(6)).await);
+}
+
+#[tokio::test]
+async fn saved_addresses_become_rules() {
+    let address = [0, 0x4D, 0x8D, 0x26, 0x3F, 0xC8];
+    let mut storage = MemoryStorage::default();
+    storage
+        .set_blob("devices", &to_allocvec(&vec![address]).unwrap())
+        .unwrap();
+    migrate_wakeup_devices(&mut storage, "devices", "rules").unwrap();
+    let (mut rules_tx, rules_rx) =
+        NvsValue::<Vec<WakeupRule>>::new(storage.clone(), "rules").unwrap();
+    assert_eq!(rules_rx.get(), [WakeupRule::address(address)]);
+
+    // Rules that were saved later aren't replaced
+    rules_tx.set(vec![]).await.unwrap();
+    migrate_wakeup_devices(&mut storage, "devices", "rules").unwrap();
+    let (_, rules_rx) = NvsValue::<Vec<WakeupRule>>::new(storage, "rules").unwrap();
+    assert_eq!(rules_rx.get(), []);
+}
+
+#[tokio::test]
+async fn discovery_scans_wait_for_the_wake_scan() {
+    // The computer is off and the rule never matches, so the ESP keeps scanning for it
+    let computer = FakeComputer::new(Power::Off);
+    let (address, token) = start(computer, vec![WakeupRule::address([1, 2, 3, 4, 5, 6])]).await;
+    sleep(Duration::from_secs(3)).await;
+    let response = Client::new()
+        .post(format!("http://{address}/bluetooth_scan?secs=3"))
+        .bearer_auth(&token)
+        .header("Accept", "application/json")
+        .send()
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+    let devices: Vec<BleAdvertisement> =
+        serde_json::from_slice(&response.bytes().await.unwrap()).unwrap();
+    assert_eq!(devices, [controller()]);
+}
+
+#[tokio::test]
+async fn discovered_devices_are_streamed_over_the_websocket() {
+    let (address, token) = start(FakeComputer::new(Power::On), vec![]).await;
+    let (websocket, _) = connect_async(format!("ws://{address}/?token={token}"))
+        .await
+        .unwrap();
+    let (mut w, mut r) = websocket.split();
+    w.send(Message::Binary(
+        FrameToEsp::Hello(Hello::new(vec![])).encode(),
+    ))
+    .await
+    .unwrap();
+    match FrameToWeb::decode(&r.next().await.unwrap().unwrap().into_data()) {
+        Ok(FrameToWeb::Hello(hello)) => assert!(hello.has(Capability::BluetoothDiscovery)),
+        frame => panic!("Expected hello, got {frame:?}"),
+    }
+    w.send(Message::Binary(
+        FrameToEsp::BluetoothScan {
+            id: 7,
+            duration: Duration::from_secs(3),
+        }
+        .encode(),
+    ))
+    .await
+    .unwrap();
+    let devices = timeout(Duration::from_secs(10), async {
+        let mut devices = Vec::new();
+        loop {
+            match FrameToWeb::decode(&r.next().await.unwrap().unwrap().into_data()) {
+                Ok(FrameToWeb::Message {
+                    message: MessageToWeb::BluetoothDevice(device),
+                    ..
+                }) => devices.push(device),
+                Ok(FrameToWeb::Ack(7)) => break devices,
+                Ok(FrameToWeb::Error { id: Some(7), error }) => panic!("Scan failed: {error:?}"),
+                _ => {}
+            }
+        }
+    })
+    .await
+    .expect("Timed out waiting for the scan");
+    // Each device is only sent once
+    assert_eq!(devices, [controller()]);
+}
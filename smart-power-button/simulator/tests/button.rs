@@ -0,0 +1,30 @@
+//! Presses the fake computer's buttons directly, like the ESP's tasks do
+use std::time::Duration;
+
+use smart_power_button_common::press::{PressPattern, PressStep};
+use smart_power_button_common::Power;
+use smart_power_button_esp_core::button::Button;
+use smart_power_button_simulator::fake_computer::FakeComputer;
+use tokio::time::timeout;
+
+#[tokio::test]
+async fn cancelled_patterns_release_the_button() {
+    let computer = FakeComputer::new(Power::Off);
+    let button = Button::new(computer.power_button()).unwrap();
+    let pattern = PressPattern(vec![
+        PressStep::Hold(Duration::from_millis(100)),
+        PressStep::Release(Duration::from_millis(100)),
+        PressStep::Hold(Duration::from_secs(10)),
+    ]);
+    // Dropped in the middle of the 10 second hold, like a request whose client disconnected
+    let result = timeout(Duration::from_millis(500), button.press_pattern(&pattern)).await;
+    assert!(result.is_err());
+    assert!(!button.is_pressed().await);
+    // The first hold turned it on, and releasing the second one early suspended it instead of
+    // forcing it off
+    assert_eq!(computer.power(), Power::Suspend);
+
+    // The pin isn't stuck either
+    button.press(Duration::from_millis(100)).await;
+    assert_eq!(computer.power(), Power::On);
+}
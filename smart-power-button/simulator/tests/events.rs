@@ -0,0 +1,223 @@
+//! Reads `/events` like a dashboard with `EventSource` would
+use std::net::SocketAddr;
+use std::time::Duration;
+
+use postcard::{from_bytes, to_allocvec};
+use reqwest::Client;
+use serde_json::{json, Value};
+use smart_power_button_common::auth::{LoginRequest, LoginResponse};
+use smart_power_button_common::Power;
+use smart_power_button_simulator::fake_computer::FakeComputer;
+use smart_power_button_simulator::{run_simulator, SimulatorConfig};
+use tokio::net::TcpListener;
+use tokio::time::timeout;
+
+const PASSWORD: &str = "test password";
+
+async fn start() -> (SocketAddr, String) {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let address = listener.local_addr().unwrap();
+    tokio::spawn(run_simulator(
+        listener,
+        FakeComputer::new(Power::Off),
+        SimulatorConfig {
+            pairing_password: PASSWORD.into(),
+            ble_devices: vec![],
+            ota_partition: Default::default(),
+            wake_on_lan_sockets: vec![],
+            other_computers: vec![],
+            static_assets: &[],
+        },
+    ));
+    let response = Client::new()
+        .post(format!("http://{address}/login"))
+        .body(
+            to_allocvec(&LoginRequest {
+                password: PASSWORD.into(),
+                client_name: "test".into(),
+            })
+            .unwrap(),
+        )
+        .send()
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+    let LoginResponse { token } = from_bytes(&response.bytes().await.unwrap()).unwrap();
+    (address, token)
+}
+
+#[derive(Debug)]
+struct Event {
+    /// Different after each start of the ESP
+    boot_id: String,
+    seq: u64,
+    data: Value,
+}
+
+impl Event {
+    fn id(&self) -> String {
+        format!("{}-{}", self.boot_id, self.seq)
+    }
+}
+
+/// Parses the events out of an SSE stream. Skips comments and `retry`.
+struct EventStream {
+    response: reqwest::Response,
+    buffer: String,
+}
+
+impl EventStream {
+    async fn connect(address: SocketAddr, token: &str, last_event_id: Option<String>) -> Self {
+        let mut request = Client::new().get(format!("http://{address}/events?token={token}"));
+        if let Some(id) = last_event_id {
+            request = request.header("Last-Event-ID", id);
+        }
+        let response = request.send().await.unwrap().error_for_status().unwrap();
+        assert_eq!(response.headers()["Content-Type"], "text/event-stream");
+        Self {
+            response,
+            buffer: String::new(),
+        }
+    }
+
+    async fn next(&mut self) -> Event {
+        loop {
+            if let Some(end) = self.buffer.find("\n\n") {
+                let block = self.buffer[..end].to_owned();
+                self.buffer.drain(..end + 2);
+                let mut id = None;
+                let mut data = None;
+                for line in block.lines() {
+                    if let Some(value) = line.strip_prefix("id: ") {
+                        let (boot_id, seq) = value.split_once('-').unwrap();
+                        id = Some((boot_id.to_owned(), seq.parse().unwrap()));
+                    } else if let Some(value) = line.strip_prefix("data: ") {
+                        data = Some(serde_json::from_str(value).unwrap());
+                    }
+                }
+                if let (Some((boot_id, seq)), Some(data)) = (id, data) {
+                    return Event { boot_id, seq, data };
+                }
+                continue;
+            }
+            let chunk = timeout(Duration::from_secs(10), self.response.chunk())
+                .await
+                .expect("Timed out waiting for an event")
+                .unwrap()
+                .expect("The stream ended");
+            self.buffer.push_str(std::str::from_utf8(&chunk).unwrap());
+        }
+    }
+
+    /// Waits for an event with `key`, like `"PowerState"`, that `f` returns `true` for
+    async fn wait_for(&mut self, key: &str, f: impl Fn(&Value) -> bool) -> Event {
+        timeout(Duration::from_secs(10), async {
+            loop {
+                let event = self.next().await;
+                if event.data.get(key).is_some_and(&f) {
+                    return event;
+                }
+            }
+        })
+        .await
+        .unwrap_or_else(|_| panic!("Timed out waiting for {key}"))
+    }
+}
+
+#[tokio::test]
+async fn state_is_sent_first() {
+    let (address, token) = start().await;
+    let mut events = EventStream::connect(address, &token, None).await;
+    let mut keys = Vec::new();
+    let mut last_seq = 0;
+    // Each kind of state is sent once, in the order it changed
+    while keys.len() < 7 {
+        let event = events.next().await;
+        assert!(event.seq > last_seq);
+        last_seq = event.seq;
+        let key = event
+            .data
+            .as_object()
+            .unwrap()
+            .keys()
+            .next()
+            .unwrap()
+            .clone();
+        assert!(!keys.contains(&key), "{key} was sent twice");
+        keys.push(key);
+    }
+    for key in ["PowerLedStatus", "PowerState", "WakeupReason", "OtaStatus"] {
+        assert!(keys.iter().any(|k| k == key), "{key} wasn't sent");
+    }
+}
+
+#[tokio::test]
+async fn missed_events_are_resent() {
+    let (address, token) = start().await;
+    let mut events = EventStream::connect(address, &token, None).await;
+    // The ESP has to watch the power LED for a bit before it knows that the computer is off
+    let last = events
+        .wait_for("PowerState", |state| state["power"] == "Off")
+        .await;
+    drop(events);
+
+    Client::new()
+        .post(format!("http://{address}/power/short"))
+        .bearer_auth(&token)
+        .send()
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+
+    let mut events = EventStream::connect(address, &token, Some(last.id())).await;
+    let first = events.next().await;
+    assert_eq!(first.boot_id, last.boot_id);
+    assert_eq!(first.seq, last.seq + 1);
+    if first.data != json!({ "PowerButtonStatus": true }) {
+        events
+            .wait_for("PowerButtonStatus", |is_pressed| is_pressed == true)
+            .await;
+    }
+    events
+        .wait_for("PowerState", |state| state["power"] == "On")
+        .await;
+}
+
+#[tokio::test]
+async fn unknown_ids_get_the_state() {
+    let (address, token) = start().await;
+    let mut events = EventStream::connect(address, &token, None).await;
+    let boot_id = events.next().await.boot_id;
+    drop(events);
+    for id in [format!("{boot_id}-1000000"), "not an id".into()] {
+        let mut events = EventStream::connect(address, &token, Some(id)).await;
+        let event = events.wait_for("PowerLedStatus", |_| true).await;
+        assert!(event.seq < 1_000_000);
+    }
+}
+
+#[tokio::test]
+async fn ids_from_before_a_restart_get_the_state() {
+    let (address, token) = start().await;
+    let mut events = EventStream::connect(address, &token, None).await;
+    let before_restart = events
+        .wait_for("PowerState", |state| state["power"] == "Off")
+        .await;
+    drop(events);
+
+    // Another simulator is like the same ESP after a restart, with the same sequence numbers
+    let (address, token) = start().await;
+    let mut events = EventStream::connect(address, &token, None).await;
+    let after_restart = events
+        .wait_for("PowerState", |state| state["power"] == "Off")
+        .await;
+    drop(events);
+    assert_ne!(after_restart.boot_id, before_restart.boot_id);
+
+    // Resuming after `before_restart` would skip the power LED, which was sent before it
+    let mut events = EventStream::connect(address, &token, Some(before_restart.id())).await;
+    let event = events.wait_for("PowerLedStatus", |_| true).await;
+    assert_eq!(event.boot_id, after_restart.boot_id);
+}
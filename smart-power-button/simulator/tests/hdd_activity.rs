@@ -0,0 +1,123 @@
+//! Checks that the HDD LED is summarized unless the web page asks for every change
+use std::net::SocketAddr;
+use std::time::Duration;
+
+use futures::stream::{SplitSink, SplitStream};
+use futures::{SinkExt, StreamExt};
+use postcard::{from_bytes, to_allocvec};
+use reqwest::Client;
+use smart_power_button_common::auth::{LoginRequest, LoginResponse};
+use smart_power_button_common::hdd::HddActivity;
+use smart_power_button_common::protocol::{Capability, FrameToEsp, FrameToWeb, Hello};
+use smart_power_button_common::{MessageToWeb, Power};
+use smart_power_button_simulator::fake_computer::FakeComputer;
+use smart_power_button_simulator::fake_ota::FakeOtaPartition;
+use smart_power_button_simulator::{run_simulator, SimulatorConfig};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::time::timeout;
+use tokio_tungstenite::tungstenite::Message;
+use tokio_tungstenite::{connect_async, MaybeTlsStream, WebSocketStream};
+
+const PASSWORD: &str = "test password";
+
+type WebSocket = WebSocketStream<MaybeTlsStream<TcpStream>>;
+
+async fn start(computer: FakeComputer) -> SocketAddr {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let address = listener.local_addr().unwrap();
+    tokio::spawn(run_simulator(
+        listener,
+        computer,
+        SimulatorConfig {
+            pairing_password: PASSWORD.into(),
+            ble_devices: vec![],
+            ota_partition: FakeOtaPartition::default(),
+            wake_on_lan_sockets: vec![],
+            other_computers: vec![],
+            static_assets: &[],
+        },
+    ));
+    address
+}
+
+/// Logs in and says hello with `capabilities`
+async fn connect(
+    address: SocketAddr,
+    capabilities: Vec<Capability>,
+) -> (SplitSink<WebSocket, Message>, SplitStream<WebSocket>) {
+    let response = Client::new()
+        .post(format!("http://{address}/login"))
+        .body(
+            to_allocvec(&LoginRequest {
+                password: PASSWORD.into(),
+                client_name: "test".into(),
+            })
+            .unwrap(),
+        )
+        .send()
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+    let LoginResponse { token } = from_bytes(&response.bytes().await.unwrap()).unwrap();
+    let (websocket, _) = connect_async(format!("ws://{address}/?token={token}"))
+        .await
+        .unwrap();
+    let (mut w, mut r) = websocket.split();
+    w.send(Message::Binary(
+        FrameToEsp::Hello(Hello::new(capabilities)).encode(),
+    ))
+    .await
+    .unwrap();
+    match FrameToWeb::decode(&r.next().await.unwrap().unwrap().into_data()) {
+        Ok(FrameToWeb::Hello(hello)) => assert!(hello.has(Capability::HddActivity)),
+        frame => panic!("Expected hello, got {frame:?}"),
+    }
+    (w, r)
+}
+
+#[tokio::test]
+async fn hdd_activity_is_summarized() {
+    let address = start(FakeComputer::new(Power::On)).await;
+    let (_w, mut r) = connect(address, vec![]).await;
+    let activity: HddActivity = timeout(Duration::from_secs(10), async {
+        loop {
+            match FrameToWeb::decode(&r.next().await.unwrap().unwrap().into_data()) {
+                Ok(FrameToWeb::Message {
+                    message: MessageToWeb::HddActivity(activity),
+                    ..
+                }) if activity.last_minute.bursts > 0 => break activity,
+                Ok(FrameToWeb::Message {
+                    message: MessageToWeb::HddLedStatus(_),
+                    ..
+                }) => {
+                    panic!("Got the raw HDD LED without asking for it")
+                }
+                _ => {}
+            }
+        }
+    })
+    .await
+    .expect("Timed out waiting for HDD activity");
+    assert!(activity.last_minute.active_percent > 0);
+    assert!(activity.history.len() <= 60);
+}
+
+#[tokio::test]
+async fn raw_hdd_led_is_sent_when_asked_for() {
+    let address = start(FakeComputer::new(Power::On)).await;
+    let (_w, mut r) = connect(address, vec![Capability::RawHddLed]).await;
+    timeout(Duration::from_secs(10), async {
+        loop {
+            if let Ok(FrameToWeb::Message {
+                message: MessageToWeb::HddLedStatus(true),
+                ..
+            }) = FrameToWeb::decode(&r.next().await.unwrap().unwrap().into_data())
+            {
+                break;
+            }
+        }
+    })
+    .await
+    .expect("Timed out waiting for the HDD LED");
+}
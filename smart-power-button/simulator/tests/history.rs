@@ -0,0 +1,92 @@
+//! Records history events in memory storage, like the ESP does in NVS
+use anyhow::bail;
+use smart_power_button_common::history::{HistoryEventKind, MAX_EVENTS};
+use smart_power_button_common::Power;
+use smart_power_button_esp_core::history::History;
+use smart_power_button_esp_core::nvs_value::{MemoryStorage, Storage};
+
+fn power(i: usize) -> HistoryEventKind {
+    match i % 3 {
+        0 => HistoryEventKind::Power(Power::On),
+        1 => HistoryEventKind::Power(Power::Suspend),
+        _ => HistoryEventKind::Power(Power::Off),
+    }
+}
+
+#[tokio::test]
+async fn events_are_kept_across_restarts() {
+    let storage = MemoryStorage::default();
+    let history = History::new(storage.clone()).unwrap();
+    history.record(HistoryEventKind::Boot).await.unwrap();
+    for i in 0..MAX_EVENTS * 3 {
+        history.record(power(i)).await.unwrap();
+    }
+    let events = history.events();
+    assert_eq!(events.len(), MAX_EVENTS);
+    assert_eq!(events.last().unwrap().kind, power(MAX_EVENTS * 3 - 1));
+
+    // Like the ESP restarting
+    let history = History::new(storage.clone()).unwrap();
+    assert_eq!(history.events(), events);
+    history.record(HistoryEventKind::Boot).await.unwrap();
+    let history = History::new(storage).unwrap();
+    assert_eq!(history.events().len(), MAX_EVENTS);
+    assert_eq!(history.events()[..MAX_EVENTS - 1], events[1..]);
+    assert_eq!(
+        history.events().last().unwrap().kind,
+        HistoryEventKind::Boot
+    );
+}
+
+#[tokio::test]
+async fn recording_only_rewrites_a_small_slot() {
+    let storage = MemoryStorage::default();
+    let history = History::new(storage.clone()).unwrap();
+    for i in 0..MAX_EVENTS * 3 {
+        history.record(power(i)).await.unwrap();
+    }
+    let mut slots = 0;
+    while let Some(bytes) = storage.get_blob(&format!("events{slots}")).unwrap() {
+        assert!(bytes.len() < 256, "Slot {slots} has {} bytes", bytes.len());
+        slots += 1;
+    }
+    assert!(slots * 16 > MAX_EVENTS, "Only {slots} slots");
+    assert_eq!(
+        storage.get_blob(&format!("events{}", slots + 1)).unwrap(),
+        None
+    );
+}
+
+/// Like NVS when it's full
+struct FullStorage;
+
+impl Storage for FullStorage {
+    fn get_blob(&self, _key: &str) -> anyhow::Result<Option<Vec<u8>>> {
+        Ok(None)
+    }
+
+    fn set_blob(&mut self, _key: &str, _value: &[u8]) -> anyhow::Result<()> {
+        bail!("ESP_ERR_NVS_NOT_ENOUGH_SPACE")
+    }
+
+    fn remove(&mut self, _key: &str) -> anyhow::Result<()> {
+        Ok(())
+    }
+}
+
+#[tokio::test]
+async fn save_errors_are_returned() {
+    let history = History::new(FullStorage).unwrap();
+    let mut events = history.subscribe();
+    let error = history
+        .record(HistoryEventKind::Boot)
+        .await
+        .expect_err("The event was saved");
+    assert!(
+        format!("{error:#}").contains("NOT_ENOUGH_SPACE"),
+        "{error:#}"
+    );
+    // It's still sent to clients, and kept until the ESP restarts
+    assert_eq!(events.recv().await.unwrap().kind, HistoryEventKind::Boot);
+    assert_eq!(history.events().len(), 1);
+}
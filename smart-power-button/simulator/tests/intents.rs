@@ -0,0 +1,192 @@
+//! Sends intents to the simulator like the web page does
+use std::net::SocketAddr;
+use std::time::Duration;
+
+use futures::stream::{SplitSink, SplitStream};
+use futures::{SinkExt, StreamExt};
+use postcard::{from_bytes, to_allocvec};
+use reqwest::Client;
+use smart_power_button_common::auth::{LoginRequest, LoginResponse};
+use smart_power_button_common::intent::{Intent, IntentError, IntentOutcome, IntentStatus};
+use smart_power_button_common::protocol::{
+    Capability, FrameToEsp, FrameToWeb, Hello, ProtocolError, RequestId,
+};
+use smart_power_button_common::{MessageToWeb, Power};
+use smart_power_button_simulator::fake_computer::FakeComputer;
+use smart_power_button_simulator::fake_ota::FakeOtaPartition;
+use smart_power_button_simulator::{run_simulator, SimulatorConfig};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::time::timeout;
+use tokio_tungstenite::tungstenite::Message;
+use tokio_tungstenite::{connect_async, MaybeTlsStream, WebSocketStream};
+
+const PASSWORD: &str = "test password";
+
+type WebSocket = WebSocketStream<MaybeTlsStream<TcpStream>>;
+
+async fn start(computer: FakeComputer) -> SocketAddr {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let address = listener.local_addr().unwrap();
+    tokio::spawn(run_simulator(
+        listener,
+        computer,
+        SimulatorConfig {
+            pairing_password: PASSWORD.into(),
+            ble_devices: vec![],
+            ota_partition: FakeOtaPartition::default(),
+            wake_on_lan_sockets: vec![],
+            other_computers: vec![],
+            static_assets: &[],
+        },
+    ));
+    address
+}
+
+/// Logs in and says hello
+async fn connect(address: SocketAddr) -> (SplitSink<WebSocket, Message>, SplitStream<WebSocket>) {
+    let response = Client::new()
+        .post(format!("http://{address}/login"))
+        .body(
+            to_allocvec(&LoginRequest {
+                password: PASSWORD.into(),
+                client_name: "test".into(),
+            })
+            .unwrap(),
+        )
+        .send()
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+    let LoginResponse { token } = from_bytes(&response.bytes().await.unwrap()).unwrap();
+    let (websocket, _) = connect_async(format!("ws://{address}/?token={token}"))
+        .await
+        .unwrap();
+    let (mut w, mut r) = websocket.split();
+    w.send(Message::Binary(
+        FrameToEsp::Hello(Hello::new(vec![])).encode(),
+    ))
+    .await
+    .unwrap();
+    match FrameToWeb::decode(&r.next().await.unwrap().unwrap().into_data()) {
+        Ok(FrameToWeb::Hello(hello)) => assert!(hello.has(Capability::Intents)),
+        frame => panic!("Expected hello, got {frame:?}"),
+    }
+    (w, r)
+}
+
+async fn send_intent(w: &mut SplitSink<WebSocket, Message>, id: RequestId, intent: Intent) {
+    w.send(Message::Binary(
+        FrameToEsp::Intent {
+            id,
+            machine: 0,
+            intent,
+        }
+        .encode(),
+    ))
+    .await
+    .unwrap();
+}
+
+/// Waits for the ack or error for `id`
+async fn wait_for_result(
+    r: &mut SplitStream<WebSocket>,
+    id: RequestId,
+    max_duration: Duration,
+) -> Result<(), ProtocolError> {
+    timeout(max_duration, async {
+        loop {
+            match FrameToWeb::decode(&r.next().await.unwrap().unwrap().into_data()) {
+                Ok(FrameToWeb::Ack(acked)) if acked == id => break Ok(()),
+                Ok(FrameToWeb::Error {
+                    id: Some(failed),
+                    error,
+                }) if failed == id => break Err(error),
+                _ => {}
+            }
+        }
+    })
+    .await
+    .expect("Timed out waiting for the intent")
+}
+
+async fn wait_for_status(
+    r: &mut SplitStream<WebSocket>,
+    status: IntentStatus,
+    max_duration: Duration,
+) {
+    timeout(max_duration, async {
+        loop {
+            if let Ok(FrameToWeb::Message {
+                message: MessageToWeb::IntentStatus(Some(received)),
+                ..
+            }) = FrameToWeb::decode(&r.next().await.unwrap().unwrap().into_data())
+            {
+                if received == status {
+                    break;
+                }
+            }
+        }
+    })
+    .await
+    .unwrap_or_else(|_| panic!("Timed out waiting for {status:?}"))
+}
+
+#[tokio::test]
+async fn ensure_on_turns_on_the_computer_once() {
+    let computer = FakeComputer::new(Power::Off);
+    let address = start(computer.clone()).await;
+    let (mut w, mut r) = connect(address).await;
+
+    send_intent(&mut w, 0, Intent::EnsureOn(false)).await;
+    // A different intent is refused while this one runs
+    send_intent(
+        &mut w,
+        1,
+        Intent::EnsureOff {
+            graceful_timeout: Duration::from_secs(1),
+        },
+    )
+    .await;
+    assert_eq!(
+        wait_for_result(&mut r, 1, Duration::from_secs(5)).await,
+        Err(ProtocolError::Intent(IntentError::Conflict(
+            Intent::EnsureOn(false)
+        )))
+    );
+    assert_eq!(
+        wait_for_result(&mut r, 0, Duration::from_secs(60)).await,
+        Ok(())
+    );
+    assert_eq!(computer.power(), Power::On);
+
+    // Already on, so nothing is pressed
+    send_intent(&mut w, 2, Intent::EnsureOn(false)).await;
+    wait_for_status(
+        &mut r,
+        IntentStatus::Finished(Intent::EnsureOn(false), Ok(IntentOutcome::AlreadyDone)),
+        Duration::from_secs(10),
+    )
+    .await;
+    assert_eq!(computer.power(), Power::On);
+}
+
+#[tokio::test]
+async fn ensure_off_forces_off_when_the_os_doesnt_shut_down() {
+    // A short press suspends the fake computer instead of shutting it down
+    let computer = FakeComputer::new(Power::On);
+    let address = start(computer.clone()).await;
+    let (mut w, mut r) = connect(address).await;
+
+    let intent = Intent::EnsureOff {
+        graceful_timeout: Duration::from_secs(1),
+    };
+    send_intent(&mut w, 0, intent).await;
+    wait_for_status(
+        &mut r,
+        IntentStatus::Finished(intent, Ok(IntentOutcome::ForcedOff)),
+        Duration::from_secs(60),
+    )
+    .await;
+    assert_eq!(computer.power(), Power::Off);
+}
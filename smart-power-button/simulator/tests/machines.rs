@@ -0,0 +1,254 @@
+//! Runs the simulator with two computers on the same ESP
+use std::net::SocketAddr;
+use std::time::Duration;
+
+use futures::{SinkExt, StreamExt};
+use postcard::{from_bytes, to_allocvec};
+use reqwest::{Client, StatusCode};
+use smart_power_button_common::auth::{LoginRequest, LoginResponse};
+use smart_power_button_common::machine::{machine_path, MachineConfig, MachinesConfig};
+use smart_power_button_common::protocol::{FrameToEsp, FrameToWeb, Hello, ProtocolError};
+use smart_power_button_common::provisioning::PinConfig;
+use smart_power_button_common::{MessageToEsp, MessageToWeb, Power, WakeupReason};
+use smart_power_button_simulator::fake_computer::FakeComputer;
+use smart_power_button_simulator::{run_simulator, SimulatorConfig};
+use tokio::net::TcpListener;
+use tokio::time::{sleep, timeout};
+use tokio_tungstenite::connect_async;
+use tokio_tungstenite::tungstenite::Message;
+
+const PASSWORD: &str = "test password";
+
+struct Simulator {
+    address: SocketAddr,
+    token: String,
+}
+
+/// Starts the simulator with `first` as machine 0 and `second` as machine 1
+async fn start(first: FakeComputer, second: FakeComputer) -> Simulator {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let address = listener.local_addr().unwrap();
+    tokio::spawn(run_simulator(
+        listener,
+        first,
+        SimulatorConfig {
+            pairing_password: PASSWORD.into(),
+            ble_devices: vec![],
+            ota_partition: Default::default(),
+            wake_on_lan_sockets: vec![],
+            other_computers: vec![("Server".into(), second)],
+            static_assets: &[],
+        },
+    ));
+    let response = Client::new()
+        .post(format!("http://{address}/login"))
+        .body(
+            to_allocvec(&LoginRequest {
+                password: PASSWORD.into(),
+                client_name: "test".into(),
+            })
+            .unwrap(),
+        )
+        .send()
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+    let LoginResponse { token } = from_bytes(&response.bytes().await.unwrap()).unwrap();
+    Simulator { address, token }
+}
+
+async fn wait_until_on(computer: &FakeComputer) {
+    timeout(Duration::from_secs(5), async {
+        while computer.power() != Power::On {
+            sleep(Duration::from_millis(100)).await;
+        }
+    })
+    .await
+    .expect("Timed out waiting for the computer to turn on");
+}
+
+#[tokio::test]
+async fn rest_endpoints_are_for_one_machine() {
+    let first = FakeComputer::new(Power::Off);
+    let second = FakeComputer::new(Power::Off);
+    let simulator = start(first.clone(), second.clone()).await;
+    // The wakeup reason is only set if the ESP saw that the computer was off
+    sleep(Duration::from_secs(3)).await;
+
+    Client::new()
+        .post(format!(
+            "http://{}{}",
+            simulator.address,
+            machine_path(1, "/power/short")
+        ))
+        .bearer_auth(&simulator.token)
+        .send()
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+    wait_until_on(&second).await;
+    assert_eq!(first.power(), Power::Off);
+
+    let wakeup_reason = |machine| {
+        let simulator = &simulator;
+        async move {
+            let response = Client::new()
+                .get(format!(
+                    "http://{}{}",
+                    simulator.address,
+                    machine_path(machine, "/wakeup_reason")
+                ))
+                .bearer_auth(&simulator.token)
+                .send()
+                .await
+                .unwrap()
+                .error_for_status()
+                .unwrap();
+            from_bytes::<Option<WakeupReason>>(&response.bytes().await.unwrap()).unwrap()
+        }
+    };
+    assert_eq!(wakeup_reason(0).await, None);
+    assert_eq!(wakeup_reason(1).await, Some(WakeupReason::Web(false)));
+
+    let response = Client::new()
+        .get(format!(
+            "http://{}{}",
+            simulator.address,
+            machine_path(2, "/status")
+        ))
+        .bearer_auth(&simulator.token)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn machines_config_is_validated() {
+    let simulator = start(FakeComputer::new(Power::Off), FakeComputer::new(Power::Off)).await;
+    let pins = PinConfig {
+        power_led: 5,
+        hdd_led: 6,
+        power_button: 7,
+        reset_button: 8,
+    };
+    let put = |config: &MachinesConfig| {
+        Client::new()
+            .put(format!("http://{}/machines", simulator.address))
+            .bearer_auth(&simulator.token)
+            .header("Content-Type", "application/json")
+            .body(serde_json::to_vec(config).unwrap())
+            .send()
+    };
+    let mut config = MachinesConfig {
+        first_name: "Desktop".into(),
+        others: vec![
+            MachineConfig {
+                name: "Server".into(),
+                pins,
+            },
+            MachineConfig {
+                name: "NAS".into(),
+                pins,
+            },
+        ],
+    };
+    assert_eq!(
+        put(&config).await.unwrap().status(),
+        StatusCode::BAD_REQUEST
+    );
+    config.others.pop();
+    put(&config).await.unwrap().error_for_status().unwrap();
+    let response = Client::new()
+        .get(format!("http://{}/machines", simulator.address))
+        .bearer_auth(&simulator.token)
+        .header("Accept", "application/json")
+        .send()
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+    assert_eq!(
+        serde_json::from_slice::<MachinesConfig>(&response.bytes().await.unwrap()).unwrap(),
+        config
+    );
+}
+
+#[tokio::test]
+async fn websocket_messages_have_the_machine() {
+    let first = FakeComputer::new(Power::Off);
+    let second = FakeComputer::new(Power::Off);
+    let simulator = start(first.clone(), second.clone()).await;
+
+    let (mut websocket, _) = connect_async(format!(
+        "ws://{}/?token={}",
+        simulator.address, simulator.token
+    ))
+    .await
+    .unwrap();
+    websocket
+        .send(Message::Binary(
+            FrameToEsp::Hello(Hello::new(vec![])).encode(),
+        ))
+        .await
+        .unwrap();
+    let (mut w, r) = websocket.split();
+    let mut frames = Box::pin(r.filter_map(|message| async move {
+        match message.unwrap() {
+            Message::Binary(bytes) => Some(FrameToWeb::decode(&bytes).unwrap()),
+            _ => None,
+        }
+    }));
+    match frames.next().await {
+        Some(FrameToWeb::Hello(hello)) => {
+            assert_eq!(hello.machines, ["Computer", "Server"]);
+        }
+        frame => panic!("Expected hello, got {frame:?}"),
+    }
+
+    w.send(Message::Binary(
+        FrameToEsp::Request {
+            id: 1,
+            machine: 5,
+            message: MessageToEsp::ShortPressPowerButton(false),
+        }
+        .encode(),
+    ))
+    .await
+    .unwrap();
+    w.send(Message::Binary(
+        FrameToEsp::Request {
+            id: 2,
+            machine: 1,
+            message: MessageToEsp::ShortPressPowerButton(false),
+        }
+        .encode(),
+    ))
+    .await
+    .unwrap();
+    let mut unknown_machine = false;
+    timeout(Duration::from_secs(10), async {
+        loop {
+            match frames.next().await.expect("WebSocket closed") {
+                FrameToWeb::Error {
+                    id: Some(1),
+                    error: ProtocolError::UnknownMachine(5),
+                } => unknown_machine = true,
+                FrameToWeb::Message {
+                    machine: Some(machine),
+                    message: MessageToWeb::PowerState(state),
+                } if state.power == Some(Power::On) => {
+                    assert_eq!(machine, 1);
+                    break;
+                }
+                _ => {}
+            }
+        }
+    })
+    .await
+    .expect("Timed out waiting for the second machine to turn on");
+    assert!(unknown_machine);
+    assert_eq!(first.power(), Power::Off);
+}
@@ -0,0 +1,187 @@
+//! Runs the MQTT client against a fake broker, like Home Assistant would use it with mosquitto
+use std::time::Duration;
+
+use smart_power_button_common::mqtt::{
+    MqttConfig, MqttTopics, OFFLINE_PAYLOAD, ONLINE_PAYLOAD, PRESS_PAYLOAD,
+};
+use smart_power_button_common::Power;
+use smart_power_button_esp_core::button::Button;
+use smart_power_button_esp_core::history::History;
+use smart_power_button_esp_core::mqtt::{run_mqtt, MqttDevice};
+use smart_power_button_esp_core::nvs_value::{MemoryStorage, NvsValue};
+use smart_power_button_esp_core::power_io::PowerIo;
+use smart_power_button_simulator::fake_computer::FakeComputer;
+use smart_power_button_simulator::fake_mqtt::FakeBroker;
+use tokio::time::{sleep, timeout};
+
+const DEVICE_NAME: &str = "test-remote";
+
+fn enabled_config() -> MqttConfig {
+    MqttConfig {
+        host: "broker".into(),
+        ..Default::default()
+    }
+}
+
+/// Starts the MQTT client with a fake computer. Returns what sets the config.
+async fn start(
+    computer: FakeComputer,
+    broker: FakeBroker,
+    config: MqttConfig,
+) -> NvsValue<MqttConfig> {
+    let storage = MemoryStorage::default();
+    let (mut config_tx, config_rx) = NvsValue::new(storage.clone(), "mqtt").unwrap();
+    let (_, press_config_rx) = NvsValue::new(storage.clone(), "press").unwrap();
+    let (_, blink_config_rx) = NvsValue::new(storage.clone(), "power_led").unwrap();
+    config_tx.set(config).await.unwrap();
+    let (power_io_future, power_io) = PowerIo::new(
+        computer.power_led_rx(),
+        computer.hdd_led_rx(),
+        Button::new(computer.power_button()).unwrap(),
+        Button::new(computer.reset_button()).unwrap(),
+        History::new(storage).unwrap(),
+        press_config_rx,
+        blink_config_rx,
+    );
+    tokio::spawn(async move { computer.run().await });
+    tokio::spawn(power_io_future);
+    tokio::spawn(run_mqtt(
+        broker,
+        config_rx,
+        power_io,
+        MqttDevice {
+            name: DEVICE_NAME.into(),
+            firmware_version: "1.2.3".into(),
+        },
+    ));
+    config_tx
+}
+
+/// Waits until `topic` has `payload` retained
+async fn wait_for_retained(broker: &FakeBroker, topic: &str, payload: &str) {
+    timeout(Duration::from_secs(10), async {
+        while broker.retained(topic).as_deref() != Some(payload.as_bytes()) {
+            sleep(Duration::from_millis(50)).await;
+        }
+    })
+    .await
+    .unwrap_or_else(|_| {
+        panic!(
+            "Timed out waiting for {payload:?} on {topic}. It is {:?}.",
+            broker.retained(topic).map(String::from_utf8)
+        )
+    });
+}
+
+#[tokio::test]
+async fn states_and_discovery_are_published() {
+    let broker = FakeBroker::default();
+    let _config_tx = start(
+        FakeComputer::new(Power::Off),
+        broker.clone(),
+        enabled_config(),
+    )
+    .await;
+    let topics = MqttTopics::new(&enabled_config(), DEVICE_NAME);
+    wait_for_retained(&broker, &topics.availability(), ONLINE_PAYLOAD).await;
+    wait_for_retained(&broker, &topics.power_led(), "OFF").await;
+    wait_for_retained(&broker, &topics.wakeup_reason(), "none").await;
+    // The ESP has to watch the power LED for a bit before it knows that the computer is off
+    wait_for_retained(&broker, &topics.power(), "off").await;
+
+    let config = broker
+        .retained(&topics.discovery("sensor", "power"))
+        .expect("No discovery config for the power sensor");
+    let config = serde_json::from_slice::<serde_json::Value>(&config).unwrap();
+    assert_eq!(config["state_topic"], topics.power());
+    assert_eq!(config["availability_topic"], topics.availability());
+    assert_eq!(config["device"]["sw_version"], "1.2.3");
+    let config = broker
+        .retained(&topics.discovery("button", "reset"))
+        .expect("No discovery config for the reset button");
+    let config = serde_json::from_slice::<serde_json::Value>(&config).unwrap();
+    assert_eq!(config["command_topic"], topics.reset());
+}
+
+#[tokio::test]
+async fn hdd_activity_is_published_without_retain() {
+    let broker = FakeBroker::default();
+    let topics = MqttTopics::new(&enabled_config(), DEVICE_NAME);
+    // Left over from an older version
+    broker.retain(&topics.discovery("binary_sensor", "hdd_led"), b"{}");
+    broker.retain(&topics.old_hdd_led(), b"ON");
+    let _config_tx = start(
+        FakeComputer::new(Power::On),
+        broker.clone(),
+        enabled_config(),
+    )
+    .await;
+    wait_for_retained(&broker, &topics.availability(), ONLINE_PAYLOAD).await;
+    assert_eq!(
+        broker.retained(&topics.discovery("binary_sensor", "hdd_led")),
+        None
+    );
+    assert_eq!(broker.retained(&topics.old_hdd_led()), None);
+    let config = broker
+        .retained(&topics.discovery("sensor", "hdd_activity"))
+        .expect("No discovery config for the HDD activity sensor");
+    let config = serde_json::from_slice::<serde_json::Value>(&config).unwrap();
+    assert_eq!(config["state_topic"], topics.hdd_activity());
+    assert_eq!(config["unit_of_measurement"], "%");
+
+    // The fake computer's HDD LED flickers while it's on
+    timeout(Duration::from_secs(10), async {
+        loop {
+            let percent = broker
+                .last(&topics.hdd_activity())
+                .map(|payload| String::from_utf8(payload).unwrap().parse::<u8>().unwrap());
+            if percent.is_some_and(|percent| percent > 0) {
+                break;
+            }
+            sleep(Duration::from_millis(50)).await;
+        }
+    })
+    .await
+    .expect("HDD activity was never published");
+    assert_eq!(broker.retained(&topics.hdd_activity()), None);
+}
+
+#[tokio::test]
+async fn commands_press_buttons() {
+    let broker = FakeBroker::default();
+    let computer = FakeComputer::new(Power::Off);
+    let _config_tx = start(computer.clone(), broker.clone(), enabled_config()).await;
+    let topics = MqttTopics::new(&enabled_config(), DEVICE_NAME);
+    wait_for_retained(&broker, &topics.power(), "off").await;
+
+    broker.publish(&topics.short_press(), PRESS_PAYLOAD.as_bytes());
+    wait_for_retained(&broker, &topics.power(), "on").await;
+    assert_eq!(computer.power(), Power::On);
+    wait_for_retained(&broker, &topics.wakeup_reason(), "mqtt").await;
+}
+
+#[tokio::test]
+async fn connects_when_enabled_and_reconnects() {
+    let broker = FakeBroker::default();
+    let mut config_tx = start(
+        FakeComputer::new(Power::Off),
+        broker.clone(),
+        MqttConfig::default(),
+    )
+    .await;
+    sleep(Duration::from_millis(200)).await;
+    assert_eq!(broker.connections(), 0);
+
+    config_tx.set(enabled_config()).await.unwrap();
+    let topics = MqttTopics::new(&enabled_config(), DEVICE_NAME);
+    wait_for_retained(&broker, &topics.availability(), ONLINE_PAYLOAD).await;
+    assert_eq!(broker.connections(), 1);
+
+    broker.disconnect_all();
+    assert_eq!(
+        broker.retained(&topics.availability()).as_deref(),
+        Some(OFFLINE_PAYLOAD.as_bytes())
+    );
+    wait_for_retained(&broker, &topics.availability(), ONLINE_PAYLOAD).await;
+    assert_eq!(broker.connections(), 2);
+}
@@ -0,0 +1,33 @@
+//! Loads saved values like the ESP does when it starts, including ones from other firmwares
+use postcard::to_allocvec;
+use smart_power_button_common::schedule::Schedule;
+use smart_power_button_esp_core::nvs_value::{MemoryStorage, NvsValue, Storage};
+
+fn schedule() -> Schedule {
+    Schedule {
+        time_zone: "CET-1CEST,M3.5.0,M10.5.0/3".into(),
+        rules: vec![],
+    }
+}
+
+#[tokio::test]
+async fn saved_values_are_loaded() {
+    let storage = MemoryStorage::default();
+    let (mut schedule_tx, _) = NvsValue::new(storage.clone(), "schedule").unwrap();
+    schedule_tx.set(schedule()).await.unwrap();
+    let (_, schedule_rx) = NvsValue::<Schedule>::new(storage, "schedule").unwrap();
+    assert_eq!(schedule_rx.get(), schedule());
+}
+
+#[tokio::test]
+async fn unreadable_values_are_erased() {
+    let mut storage = MemoryStorage::default();
+    let saved = to_allocvec(&schedule()).unwrap();
+    // Like a firmware where the type had fewer fields, and bytes that were never a schedule
+    for bytes in [&saved[..saved.len() - 1], &[0xff; 3]] {
+        storage.set_blob("schedule", bytes).unwrap();
+        let (_, schedule_rx) = NvsValue::<Schedule>::new(storage.clone(), "schedule").unwrap();
+        assert_eq!(schedule_rx.get(), Schedule::default());
+        assert_eq!(storage.get_blob("schedule").unwrap(), None);
+    }
+}
@@ -0,0 +1,193 @@
+//! Sends firmware updates to the simulator like the `ota` command and the web page do
+use std::net::SocketAddr;
+use std::time::Duration;
+
+use futures::{SinkExt, StreamExt};
+use postcard::{from_bytes, to_allocvec};
+use reqwest::{Client, StatusCode};
+use sha2::{Digest, Sha256};
+use smart_power_button_common::auth::{LoginRequest, LoginResponse};
+use smart_power_button_common::ota::{format_sha256, OtaError, OtaStatus, SHA256_HEADER};
+use smart_power_button_common::protocol::{Capability, FrameToEsp, FrameToWeb, Hello};
+use smart_power_button_common::{MessageToWeb, Power};
+use smart_power_button_simulator::fake_computer::FakeComputer;
+use smart_power_button_simulator::fake_ota::{FakeOtaPartition, OTA_PARTITION_SIZE};
+use smart_power_button_simulator::{run_simulator, SimulatorConfig};
+use tokio::net::TcpListener;
+use tokio::time::{sleep, timeout};
+use tokio_tungstenite::connect_async;
+use tokio_tungstenite::tungstenite::Message;
+
+const PASSWORD: &str = "test password";
+
+struct Simulator {
+    address: SocketAddr,
+    token: String,
+    ota_partition: FakeOtaPartition,
+}
+
+async fn start() -> Simulator {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let address = listener.local_addr().unwrap();
+    let ota_partition = FakeOtaPartition::default();
+    tokio::spawn(run_simulator(
+        listener,
+        FakeComputer::new(Power::Off),
+        SimulatorConfig {
+            pairing_password: PASSWORD.into(),
+            ble_devices: vec![],
+            ota_partition: ota_partition.clone(),
+            wake_on_lan_sockets: vec![],
+            other_computers: vec![],
+            static_assets: &[],
+        },
+    ));
+    let response = Client::new()
+        .post(format!("http://{address}/login"))
+        .body(
+            to_allocvec(&LoginRequest {
+                password: PASSWORD.into(),
+                client_name: "test".into(),
+            })
+            .unwrap(),
+        )
+        .send()
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+    let LoginResponse { token } = from_bytes(&response.bytes().await.unwrap()).unwrap();
+    Simulator {
+        address,
+        token,
+        ota_partition,
+    }
+}
+
+/// Looks like a firmware image to the fake partition
+fn image(len: usize) -> Vec<u8> {
+    let mut image = (0..len).map(|i| i as u8).collect::<Vec<_>>();
+    image[0] = 0xE9;
+    image
+}
+
+fn sha256(bytes: &[u8]) -> String {
+    format_sha256(&Sha256::digest(bytes).into())
+}
+
+async fn upload(simulator: &Simulator, image: Vec<u8>, sha256: &str) -> reqwest::Response {
+    Client::new()
+        .post(format!("http://{}/ota", simulator.address))
+        .bearer_auth(&simulator.token)
+        .header(SHA256_HEADER, sha256)
+        .body(image)
+        .send()
+        .await
+        .unwrap()
+}
+
+#[tokio::test]
+async fn update_is_saved_and_reported() {
+    let simulator = start().await;
+    let (mut websocket, _) = connect_async(format!(
+        "ws://{}/?token={}",
+        simulator.address, simulator.token
+    ))
+    .await
+    .unwrap();
+    websocket
+        .send(Message::Binary(
+            FrameToEsp::Hello(Hello::new(vec![])).encode(),
+        ))
+        .await
+        .unwrap();
+    let mut statuses = Box::pin(websocket.filter_map(|message| async move {
+        match FrameToWeb::decode(&message.unwrap().into_data()) {
+            Ok(FrameToWeb::Hello(hello)) => {
+                assert!(hello.has(Capability::Ota));
+                None
+            }
+            Ok(FrameToWeb::Message {
+                message: MessageToWeb::OtaStatus(status),
+                ..
+            }) => Some(status),
+            _ => None,
+        }
+    }));
+    assert_eq!(statuses.next().await, Some(OtaStatus::Idle));
+
+    let image = image(300_000);
+    let response = upload(&simulator, image.clone(), &sha256(&image)).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(simulator.ota_partition.image(), Some(image));
+    timeout(Duration::from_secs(10), async {
+        while statuses.next().await != Some(OtaStatus::Restarting) {}
+    })
+    .await
+    .expect("Timed out waiting for the update to be reported");
+}
+
+#[tokio::test]
+async fn requests_are_answered_while_erasing() {
+    let simulator = start().await;
+    let image = image(1000);
+    let upload = tokio::spawn({
+        let simulator = Simulator {
+            address: simulator.address,
+            token: simulator.token.clone(),
+            ota_partition: simulator.ota_partition.clone(),
+        };
+        let sha256 = sha256(&image);
+        async move { upload(&simulator, image, &sha256).await.status() }
+    });
+    // Erasing blocks its thread, so this would only see it if it's not the runtime's thread
+    timeout(Duration::from_secs(10), async {
+        while !simulator.ota_partition.is_erasing() {
+            sleep(Duration::from_millis(10)).await;
+        }
+    })
+    .await
+    .expect("Never saw the partition being erased");
+    let response = Client::new()
+        .get(format!("http://{}/wifi_status", simulator.address))
+        .bearer_auth(&simulator.token)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert!(simulator.ota_partition.is_erasing());
+    assert_eq!(upload.await.unwrap(), StatusCode::OK);
+}
+
+#[tokio::test]
+async fn wrong_hash_is_rejected() {
+    let simulator = start().await;
+    let response = upload(&simulator, image(1000), &sha256(b"something else")).await;
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    assert_eq!(
+        response.text().await.unwrap(),
+        OtaError::HashMismatch.to_string()
+    );
+    assert_eq!(simulator.ota_partition.image(), None);
+}
+
+#[tokio::test]
+async fn invalid_requests_are_rejected() {
+    let simulator = start().await;
+    let image = image(OTA_PARTITION_SIZE as usize + 1);
+    let response = upload(&simulator, image.clone(), &sha256(&image)).await;
+    assert_eq!(response.status(), StatusCode::PAYLOAD_TOO_LARGE);
+
+    let response = upload(&simulator, image[..1000].to_vec(), "not a hash").await;
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+    let response = Client::new()
+        .post(format!("http://{}/ota", simulator.address))
+        .header(SHA256_HEADER, sha256(&image))
+        .body(image)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    assert_eq!(simulator.ota_partition.image(), None);
+}
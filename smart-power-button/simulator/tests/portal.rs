@@ -0,0 +1,206 @@
+//! Uses the Wi-Fi setup portal and its DNS server like a phone that joined the ESP's access point
+use std::net::{Ipv4Addr, SocketAddr};
+use std::sync::Arc;
+use std::time::Duration;
+
+use reqwest::redirect::Policy;
+use reqwest::{Client, StatusCode};
+use smart_power_button_common::provisioning::{
+    PinConfig, ScannedNetwork, StaticIp, WifiConfig, WifiNetwork, WifiSecurity,
+};
+use smart_power_button_esp_core::captive_dns::serve_captive_dns;
+use smart_power_button_esp_core::nvs_value::{MemoryStorage, NvsValue};
+use smart_power_button_esp_core::portal::{serve_portal, PortalState};
+use smart_power_button_esp_core::value_channel::ValueReceiver;
+use tokio::net::{TcpListener, UdpSocket};
+use tokio::sync::{Mutex, Notify};
+use tokio::time::timeout;
+
+const PORTAL_URL: &str = "http://192.168.71.1/";
+
+struct Portal {
+    address: SocketAddr,
+    wifi_config_rx: ValueReceiver<WifiConfig>,
+    pin_config_rx: ValueReceiver<PinConfig>,
+    saved: Arc<Notify>,
+}
+
+async fn start_portal() -> Portal {
+    let storage = MemoryStorage::default();
+    let (wifi_config_tx, wifi_config_rx) = NvsValue::new(storage.clone(), "wifi").unwrap();
+    let (pin_config_tx, pin_config_rx) = NvsValue::new(storage, "pins").unwrap();
+    let saved = Arc::new(Notify::new());
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let address = listener.local_addr().unwrap();
+    tokio::spawn(serve_portal(
+        listener,
+        PortalState {
+            networks: [ScannedNetwork {
+                ssid: "<Neighbor>".into(),
+                signal_strength: -70,
+                security: WifiSecurity::Open,
+            }]
+            .into(),
+            wifi_config_tx: Arc::new(Mutex::new(wifi_config_tx)),
+            wifi_config_rx: wifi_config_rx.clone(),
+            pin_config_tx: Arc::new(Mutex::new(pin_config_tx)),
+            pin_config_rx: pin_config_rx.clone(),
+            saved: saved.clone(),
+            factory_reset: || {},
+            portal_url: PORTAL_URL.into(),
+        },
+    ));
+    Portal {
+        address,
+        wifi_config_rx,
+        pin_config_rx,
+        saved,
+    }
+}
+
+async fn post_form(address: SocketAddr, form: &str) -> reqwest::Response {
+    Client::new()
+        .post(format!("http://{address}/"))
+        .header("Content-Type", "application/x-www-form-urlencoded")
+        .body(form.to_owned())
+        .send()
+        .await
+        .unwrap()
+}
+
+#[tokio::test]
+async fn page_shows_scanned_networks() {
+    let portal = start_portal().await;
+    let response = reqwest::get(format!("http://{}/", portal.address))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let page = response.text().await.unwrap();
+    assert!(page.contains("&lt;Neighbor&gt;"));
+    assert!(!page.contains("<Neighbor>"));
+}
+
+#[tokio::test]
+async fn saving_stores_settings() {
+    let portal = start_portal().await;
+    let response = post_form(
+        portal.address,
+        "ssid=Home&password=hunter22&priority=1&security=auto&ip=&prefix_len=&gateway=&dns=\
+&ssid=Lab&password=&priority=0&security=open&ip=10.0.0.5&prefix_len=24&gateway=10.0.0.1&dns=\
+&ssid=&password=&hostname=gaming-remote&power_led=1&hdd_led=2&power_button=3&reset_button=4",
+    )
+    .await;
+    assert_eq!(response.status(), StatusCode::OK);
+    timeout(Duration::from_secs(1), portal.saved.notified())
+        .await
+        .unwrap();
+    assert_eq!(
+        portal.wifi_config_rx.get(),
+        WifiConfig {
+            networks: vec![
+                WifiNetwork {
+                    ssid: "Home".into(),
+                    password: "hunter22".into(),
+                    priority: 1,
+                    security: WifiSecurity::Auto,
+                    static_ip: None,
+                },
+                WifiNetwork {
+                    ssid: "Lab".into(),
+                    password: "".into(),
+                    priority: 0,
+                    security: WifiSecurity::Open,
+                    static_ip: Some(StaticIp {
+                        ip: Ipv4Addr::new(10, 0, 0, 5),
+                        prefix_len: 24,
+                        gateway: Ipv4Addr::new(10, 0, 0, 1),
+                        dns: None,
+                    }),
+                },
+            ],
+            hostname: "gaming-remote".into(),
+        }
+    );
+    assert_eq!(
+        portal.pin_config_rx.get(),
+        PinConfig {
+            power_led: 1,
+            hdd_led: 2,
+            power_button: 3,
+            reset_button: 4,
+        }
+    );
+}
+
+#[tokio::test]
+async fn invalid_settings_are_not_saved() {
+    let portal = start_portal().await;
+    let response = post_form(
+        portal.address,
+        "ssid=Home&password=short&power_led=1&hdd_led=2&power_button=3&reset_button=4",
+    )
+    .await;
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    assert_eq!(portal.wifi_config_rx.get(), WifiConfig::default());
+    assert_eq!(portal.pin_config_rx.get(), PinConfig::default());
+}
+
+#[tokio::test]
+async fn other_pages_redirect_to_portal() {
+    let portal = start_portal().await;
+    let response = Client::builder()
+        .redirect(Policy::none())
+        .build()
+        .unwrap()
+        .get(format!("http://{}/generate_204", portal.address))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::FOUND);
+    assert_eq!(response.headers()["Location"], PORTAL_URL);
+}
+
+/// A query for `example.com` with the given type
+fn dns_query(query_type: u16) -> Vec<u8> {
+    let mut query = vec![0x12, 0x34, 0x01, 0x00, 0, 1, 0, 0, 0, 0, 0, 0];
+    query.extend_from_slice(b"\x07example\x03com\x00");
+    query.extend_from_slice(&query_type.to_be_bytes());
+    query.extend_from_slice(&1u16.to_be_bytes());
+    query
+}
+
+#[tokio::test]
+async fn dns_answers_with_portal_address() {
+    let ip = Ipv4Addr::new(192, 168, 71, 1);
+    let server = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    let server_address = server.local_addr().unwrap();
+    tokio::spawn(serve_captive_dns(server, ip));
+    let client = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    let mut buffer = [0; 512];
+
+    let query = dns_query(1);
+    client.send_to(&query, server_address).await.unwrap();
+    let len = timeout(Duration::from_secs(1), client.recv(&mut buffer))
+        .await
+        .unwrap()
+        .unwrap();
+    let response = &buffer[..len];
+    // Same id, a response, one answer
+    assert_eq!(response[..2], [0x12, 0x34]);
+    assert_ne!(response[2] & 0x80, 0);
+    assert_eq!(response[6..8], [0, 1]);
+    assert_eq!(response[12..query.len()], query[12..]);
+    assert_eq!(response[len - 4..], ip.octets());
+
+    // AAAA gets no answers
+    client
+        .send_to(&dns_query(28), server_address)
+        .await
+        .unwrap();
+    let len = timeout(Duration::from_secs(1), client.recv(&mut buffer))
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(buffer[6..8], [0, 0]);
+    assert_eq!(len, query.len());
+}
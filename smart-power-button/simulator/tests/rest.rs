@@ -0,0 +1,239 @@
+//! Uses the JSON REST API like a script with `curl` would
+use std::net::SocketAddr;
+use std::time::Duration;
+
+use postcard::from_bytes;
+use reqwest::{Client, Method, StatusCode};
+use serde_json::{json, Value};
+use smart_power_button_common::auth::LoginResponse;
+use smart_power_button_common::bluetooth_wake::{DeviceMatcher, WakeupRule};
+use smart_power_button_common::rest::Status;
+use smart_power_button_common::Power;
+use smart_power_button_simulator::fake_computer::FakeComputer;
+use smart_power_button_simulator::{run_simulator, SimulatorConfig};
+use tokio::net::TcpListener;
+use tokio::time::{sleep, timeout};
+
+const PASSWORD: &str = "test password";
+
+struct Simulator {
+    address: SocketAddr,
+    token: String,
+}
+
+impl Simulator {
+    fn request(&self, method: Method, path: &str) -> reqwest::RequestBuilder {
+        Client::new()
+            .request(method, format!("http://{}{path}", self.address))
+            .bearer_auth(&self.token)
+    }
+
+    async fn status(&self) -> Status {
+        let response = self
+            .request(Method::GET, "/status")
+            .send()
+            .await
+            .unwrap()
+            .error_for_status()
+            .unwrap();
+        assert_eq!(response.headers()["Content-Type"], "application/json");
+        serde_json::from_value(json_body(response).await).unwrap()
+    }
+}
+
+async fn json_body(response: reqwest::Response) -> Value {
+    serde_json::from_slice(&response.bytes().await.unwrap()).unwrap()
+}
+
+async fn start(computer: FakeComputer) -> Simulator {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let address = listener.local_addr().unwrap();
+    tokio::spawn(run_simulator(
+        listener,
+        computer,
+        SimulatorConfig {
+            pairing_password: PASSWORD.into(),
+            ble_devices: vec![],
+            ota_partition: Default::default(),
+            wake_on_lan_sockets: vec![],
+            other_computers: vec![],
+            static_assets: &[],
+        },
+    ));
+    let response = Client::new()
+        .post(format!("http://{address}/login"))
+        .header("Content-Type", "application/json")
+        .header("Accept", "application/json")
+        .body(json!({ "password": PASSWORD, "client_name": "test" }).to_string())
+        .send()
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+    assert_eq!(response.headers()["Content-Type"], "application/json");
+    let LoginResponse { token } = serde_json::from_value(json_body(response).await).unwrap();
+    Simulator { address, token }
+}
+
+#[tokio::test]
+async fn short_press_turns_on_computer() {
+    let computer = FakeComputer::new(Power::Off);
+    let simulator = start(computer.clone()).await;
+    // The ESP has to watch the power LED for a bit before it knows that the computer is off
+    timeout(Duration::from_secs(10), async {
+        while simulator.status().await.power_state.power != Some(Power::Off) {
+            sleep(Duration::from_millis(100)).await;
+        }
+    })
+    .await
+    .expect("Timed out waiting for the computer to be off");
+
+    simulator
+        .request(Method::POST, "/power/short")
+        .header("Content-Type", "application/json")
+        .body(r#"{"turn_on_tv":true}"#)
+        .send()
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+    assert_eq!(computer.power(), Power::On);
+    let status = simulator.status().await;
+    assert!(!status.power_button_pressed);
+
+    let wakeup_reason = simulator
+        .request(Method::DELETE, "/wakeup_reason")
+        .header("Accept", "application/json")
+        .send()
+        .await
+        .unwrap();
+    let wakeup_reason = json_body(wakeup_reason).await;
+    assert_eq!(wakeup_reason, json!({ "Web": true }));
+    let wakeup_reason = simulator
+        .request(Method::GET, "/wakeup_reason")
+        .header("Accept", "application/json")
+        .send()
+        .await
+        .unwrap();
+    let wakeup_reason = json_body(wakeup_reason).await;
+    assert_eq!(wakeup_reason, Value::Null);
+}
+
+#[tokio::test]
+async fn invalid_press_requests_are_rejected() {
+    let computer = FakeComputer::new(Power::Off);
+    let simulator = start(computer.clone()).await;
+    let response = simulator
+        .request(Method::POST, "/power/short")
+        .body("not json")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    let response = simulator
+        .request(Method::GET, "/reset")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::METHOD_NOT_ALLOWED);
+    assert_eq!(computer.power(), Power::Off);
+}
+
+#[tokio::test]
+async fn button_is_released_when_the_client_disconnects() {
+    let computer = FakeComputer::new(Power::On);
+    let simulator = start(computer.clone()).await;
+    // Like `curl -m 1`, which gives up long before the 6 second long press is done
+    let result = simulator
+        .request(Method::POST, "/power/long")
+        .timeout(Duration::from_secs(1))
+        .send()
+        .await;
+    assert!(result.unwrap_err().is_timeout());
+    assert!(simulator.status().await.power_button_pressed);
+
+    timeout(Duration::from_secs(10), async {
+        while simulator.status().await.power_button_pressed {
+            sleep(Duration::from_millis(100)).await;
+        }
+    })
+    .await
+    .expect("The power button is still pressed");
+    assert_eq!(computer.power(), Power::Off);
+}
+
+#[tokio::test]
+async fn bluetooth_wakeup_devices_can_be_json() {
+    let simulator = start(FakeComputer::new(Power::Off)).await;
+    let devices = vec![
+        WakeupRule::address([0, 0x4D, 0x8D, 0x26, 0x3F, 0xC8]),
+        WakeupRule {
+            matcher: DeviceMatcher::Name("DualSense*".into()),
+            min_rssi: Some(-70),
+            time_window: None,
+        },
+    ];
+    simulator
+        .request(Method::PUT, "/bluetooth_wakeup_devices")
+        .header("Content-Type", "application/json")
+        .body(serde_json::to_vec(&devices).unwrap())
+        .send()
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+    let response = simulator
+        .request(Method::GET, "/bluetooth_wakeup_devices")
+        .header("Accept", "application/json")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(json_body(response).await, json!(devices));
+    // The web page still gets postcard
+    let response = simulator
+        .request(Method::GET, "/bluetooth_wakeup_devices")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(
+        from_bytes::<Vec<WakeupRule>>(&response.bytes().await.unwrap()).unwrap(),
+        devices
+    );
+    let response = simulator
+        .request(Method::PUT, "/bluetooth_wakeup_devices")
+        .header("Content-Type", "application/json")
+        .body(r#"[{"matcher": {"Name": ""}, "min_rssi": null, "time_window": null}]"#)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    let response = simulator
+        .request(Method::PUT, "/bluetooth_wakeup_devices")
+        .header("Content-Type", "application/json")
+        .body(r#"[{"matcher": "#)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    assert!(response.text().await.unwrap().contains("EOF"));
+}
+
+/// Every path in the OpenAPI description is routed and needs a token, except logging in
+#[tokio::test]
+async fn openapi_paths_need_a_token() {
+    let simulator = start(FakeComputer::new(Power::Off)).await;
+    let openapi = include_str!("../../openapi.yaml");
+    let paths = openapi
+        .lines()
+        .filter_map(|line| line.strip_prefix("  /")?.strip_suffix(':'))
+        .collect::<Vec<_>>();
+    assert!(paths.contains(&"status"), "{paths:?}");
+    for path in paths.into_iter().filter(|&path| path != "login") {
+        let response = Client::new()
+            .get(format!("http://{}/{path}", simulator.address))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED, "/{path}");
+    }
+}
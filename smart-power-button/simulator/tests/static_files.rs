@@ -0,0 +1,258 @@
+//! Runs the simulator with a few fake files instead of the web page
+use std::net::SocketAddr;
+
+use reqwest::header::{HeaderValue, CONTENT_ENCODING, CONTENT_RANGE, CONTENT_TYPE, ETAG};
+use reqwest::{Client, Response, StatusCode};
+use smart_power_button_common::Power;
+use smart_power_button_esp_core::static_assets::StaticAsset;
+use smart_power_button_simulator::fake_computer::FakeComputer;
+use smart_power_button_simulator::{run_simulator, SimulatorConfig};
+use tokio::net::TcpListener;
+
+/// Seconds since the Unix epoch for 2024-01-01 00:00:00 UTC
+const MODIFIED: u64 = 1_704_067_200;
+
+/// The compressed contents are fake, because the ESP doesn't look inside them
+static ASSETS: &[StaticAsset] = &[
+    StaticAsset {
+        path: "index.html",
+        contents: b"<!DOCTYPE html>",
+        gzip: Some(b"gzip index"),
+        brotli: Some(b"br index"),
+        hash: "0123abcd",
+        modified: MODIFIED,
+    },
+    StaticAsset {
+        path: "app.wasm",
+        contents: b"0123456789",
+        gzip: None,
+        brotli: None,
+        hash: "4567cdef",
+        modified: MODIFIED,
+    },
+    StaticAsset {
+        path: "icons/icon.svg",
+        contents: b"<svg/>",
+        gzip: None,
+        brotli: None,
+        hash: "89ab0123",
+        modified: MODIFIED,
+    },
+    StaticAsset {
+        path: "notes.unknown",
+        contents: b"?",
+        gzip: None,
+        brotli: None,
+        hash: "cdef4567",
+        modified: MODIFIED,
+    },
+];
+
+async fn start() -> SocketAddr {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let address = listener.local_addr().unwrap();
+    tokio::spawn(run_simulator(
+        listener,
+        FakeComputer::new(Power::Off),
+        SimulatorConfig {
+            pairing_password: "test password".into(),
+            ble_devices: vec![],
+            ota_partition: Default::default(),
+            wake_on_lan_sockets: vec![],
+            other_computers: vec![],
+            static_assets: ASSETS,
+        },
+    ));
+    address
+}
+
+async fn get(address: SocketAddr, path: &str, headers: &[(&str, &str)]) -> Response {
+    let mut request = Client::new().get(format!("http://{address}{path}"));
+    for (name, value) in headers {
+        request = request.header(*name, *value);
+    }
+    request.send().await.unwrap()
+}
+
+fn header(response: &Response, name: impl reqwest::header::AsHeaderName) -> &str {
+    response.headers().get(name).unwrap().to_str().unwrap()
+}
+
+#[tokio::test]
+async fn picks_the_encoding_from_accept_encoding() {
+    let address = start().await;
+    for (accept_encoding, encoding, body) in [
+        (Some("gzip, deflate, br"), Some("br"), "br index"),
+        (Some("gzip"), Some("gzip"), "gzip index"),
+        (Some("br;q=0, gzip;q=0.5"), Some("gzip"), "gzip index"),
+        (Some("*"), Some("br"), "br index"),
+        (Some("identity"), None, "<!DOCTYPE html>"),
+        (None, None, "<!DOCTYPE html>"),
+    ] {
+        let headers = accept_encoding
+            .map(|value| vec![("Accept-Encoding", value)])
+            .unwrap_or_default();
+        let response = get(address, "/", &headers).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(header(&response, CONTENT_TYPE), "text/html");
+        assert_eq!(header(&response, "Vary"), "Accept-Encoding");
+        assert_eq!(
+            response
+                .headers()
+                .get(CONTENT_ENCODING)
+                .map(|value| value.to_str().unwrap()),
+            encoding
+        );
+        let etag = match encoding {
+            Some(encoding) => format!("\"0123abcd-{encoding}\""),
+            None => "\"0123abcd\"".into(),
+        };
+        assert_eq!(header(&response, ETAG), etag);
+        assert_eq!(response.text().await.unwrap(), body);
+    }
+}
+
+#[tokio::test]
+async fn unchanged_files_are_not_sent_again() {
+    let address = start().await;
+    let response = get(address, "/app.wasm", &[]).await;
+    let etag = header(&response, ETAG).to_owned();
+    assert_eq!(
+        header(&response, "Last-Modified"),
+        "Mon, 01 Jan 2024 00:00:00 GMT"
+    );
+
+    for if_none_match in [
+        etag.clone(),
+        format!("W/{etag}"),
+        format!("\"other\", {etag}"),
+        "*".into(),
+    ] {
+        let response = get(address, "/app.wasm", &[("If-None-Match", &if_none_match)]).await;
+        assert_eq!(response.status(), StatusCode::NOT_MODIFIED);
+        assert_eq!(header(&response, ETAG), etag);
+        assert!(response.bytes().await.unwrap().is_empty());
+    }
+    let response = get(address, "/app.wasm", &[("If-None-Match", "\"other\"")]).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    // The ETag of the uncompressed file doesn't match the compressed one
+    let response = get(
+        address,
+        "/",
+        &[("If-None-Match", "\"0123abcd\""), ("Accept-Encoding", "br")],
+    )
+    .await;
+    assert_eq!(response.status(), StatusCode::OK);
+
+    // All three HTTP-date formats
+    for if_modified_since in [
+        "Tue, 02 Jan 2024 00:00:00 GMT",
+        "Tuesday, 02-Jan-24 00:00:00 GMT",
+        "Tue Jan  2 00:00:00 2024",
+    ] {
+        let response = get(
+            address,
+            "/app.wasm",
+            &[("If-Modified-Since", if_modified_since)],
+        )
+        .await;
+        assert_eq!(
+            response.status(),
+            StatusCode::NOT_MODIFIED,
+            "{if_modified_since}"
+        );
+    }
+    let response = get(
+        address,
+        "/app.wasm",
+        &[("If-Modified-Since", "Sun, 31 Dec 2023 00:00:00 GMT")],
+    )
+    .await;
+    assert_eq!(response.status(), StatusCode::OK);
+}
+
+#[tokio::test]
+async fn sends_byte_ranges() {
+    let address = start().await;
+    for (range, content_range, body) in [
+        ("bytes=2-4", "bytes 2-4/10", "234"),
+        ("bytes=8-", "bytes 8-9/10", "89"),
+        ("bytes=-3", "bytes 7-9/10", "789"),
+        ("bytes=5-100", "bytes 5-9/10", "56789"),
+    ] {
+        let response = get(address, "/app.wasm", &[("Range", range)]).await;
+        assert_eq!(response.status(), StatusCode::PARTIAL_CONTENT, "{range}");
+        assert_eq!(header(&response, CONTENT_RANGE), content_range);
+        assert_eq!(response.text().await.unwrap(), body);
+    }
+
+    let response = get(address, "/app.wasm", &[("Range", "bytes=10-")]).await;
+    assert_eq!(response.status(), StatusCode::RANGE_NOT_SATISFIABLE);
+    assert_eq!(header(&response, CONTENT_RANGE), "bytes */10");
+
+    // Several ranges and ranges of another version get the whole file
+    for headers in [
+        [("Range", "bytes=0-1,4-5"), ("If-Range", "\"4567cdef\"")],
+        [("Range", "bytes=0-1"), ("If-Range", "\"old\"")],
+    ] {
+        let response = get(address, "/app.wasm", &headers).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.text().await.unwrap(), "0123456789");
+    }
+}
+
+#[tokio::test]
+async fn headers_that_cant_be_understood_are_ignored() {
+    let address = start().await;
+    for (name, value) in [
+        ("If-Modified-Since", "yesterday"),
+        ("If-None-Match", "not quoted"),
+        ("Range", "pages=1-2"),
+        ("Range", "bytes=5-2"),
+        ("Range", "bytes=a-b"),
+        ("Range", "bytes=-"),
+    ] {
+        let response = get(address, "/app.wasm", &[(name, value)]).await;
+        assert_eq!(response.status(), StatusCode::OK, "{name}: {value}");
+        assert_eq!(response.text().await.unwrap(), "0123456789");
+    }
+}
+
+#[tokio::test]
+async fn headers_that_arent_ascii_get_400() {
+    let address = start().await;
+    let response = Client::new()
+        .get(format!("http://{address}/app.wasm"))
+        .header(
+            "Accept-Encoding",
+            HeaderValue::from_bytes(b"gzip\xff").unwrap(),
+        )
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+async fn content_types_come_from_the_extension() {
+    let address = start().await;
+    for (path, content_type) in [
+        ("/app.wasm", "application/wasm"),
+        ("/icons/icon.svg", "image/svg+xml"),
+        ("/notes.unknown", "application/octet-stream"),
+    ] {
+        let response = get(address, path, &[]).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(header(&response, CONTENT_TYPE), content_type);
+    }
+    assert_eq!(
+        get(address, "/missing.js", &[]).await.status(),
+        StatusCode::NOT_FOUND
+    );
+    let response = Client::new()
+        .post(format!("http://{address}/app.wasm"))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::METHOD_NOT_ALLOWED);
+}
@@ -0,0 +1,160 @@
+//! Wakes the simulator's computer with Wake-on-LAN magic packets
+use std::net::SocketAddr;
+use std::time::Duration;
+
+use postcard::{from_bytes, to_allocvec};
+use reqwest::Client;
+use smart_power_button_common::auth::{LoginRequest, LoginResponse};
+use smart_power_button_common::wake_on_lan::WakeOnLanConfig;
+use smart_power_button_common::{Power, WakeupReason};
+use smart_power_button_simulator::fake_computer::FakeComputer;
+use smart_power_button_simulator::{run_simulator, SimulatorConfig};
+use tokio::net::{TcpListener, UdpSocket};
+use tokio::time::{sleep, timeout};
+
+const PASSWORD: &str = "test password";
+const MAC: [u8; 6] = [0x00, 0x1A, 0x2B, 0x3C, 0x4D, 0x5E];
+
+struct Simulator {
+    address: SocketAddr,
+    token: String,
+    wake_on_lan_address: SocketAddr,
+}
+
+/// Starts the simulator and saves [`MAC`] as the computer's MAC address
+async fn start(computer: FakeComputer) -> Simulator {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let address = listener.local_addr().unwrap();
+    let wake_on_lan_socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    let wake_on_lan_address = wake_on_lan_socket.local_addr().unwrap();
+    tokio::spawn(run_simulator(
+        listener,
+        computer,
+        SimulatorConfig {
+            pairing_password: PASSWORD.into(),
+            ble_devices: vec![],
+            ota_partition: Default::default(),
+            wake_on_lan_sockets: vec![wake_on_lan_socket],
+            other_computers: vec![],
+            static_assets: &[],
+        },
+    ));
+    let response = Client::new()
+        .post(format!("http://{address}/login"))
+        .body(
+            to_allocvec(&LoginRequest {
+                password: PASSWORD.into(),
+                client_name: "test".into(),
+            })
+            .unwrap(),
+        )
+        .send()
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+    let LoginResponse { token } = from_bytes(&response.bytes().await.unwrap()).unwrap();
+    Client::new()
+        .put(format!("http://{address}/wake_on_lan"))
+        .bearer_auth(&token)
+        .header("Content-Type", "application/json")
+        .body(serde_json::to_vec(&WakeOnLanConfig { mac: Some(MAC) }).unwrap())
+        .send()
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+    Simulator {
+        address,
+        token,
+        wake_on_lan_address,
+    }
+}
+
+async fn send_magic_packet(to: SocketAddr, mac: [u8; 6]) {
+    let mut packet = vec![0xFF; 6];
+    for _ in 0..16 {
+        packet.extend_from_slice(&mac);
+    }
+    let socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    socket.send_to(&packet, to).await.unwrap();
+}
+
+async fn wait_until_on(computer: &FakeComputer, max_duration: Duration) -> bool {
+    timeout(max_duration, async {
+        while computer.power() != Power::On {
+            sleep(Duration::from_millis(100)).await;
+        }
+    })
+    .await
+    .is_ok()
+}
+
+#[tokio::test]
+async fn magic_packets_wake_the_computer() {
+    let computer = FakeComputer::new(Power::Off);
+    let simulator = start(computer.clone()).await;
+    // Wait for the ESP to see that the computer is off
+    sleep(Duration::from_secs(3)).await;
+
+    send_magic_packet(simulator.wake_on_lan_address, [1, 2, 3, 4, 5, 6]).await;
+    assert!(!wait_until_on(&computer, Duration::from_secs(2)).await);
+
+    send_magic_packet(simulator.wake_on_lan_address, MAC).await;
+    assert!(wait_until_on(&computer, Duration::from_secs(5)).await);
+    let response = Client::new()
+        .get(format!("http://{}/wakeup_reason", simulator.address))
+        .bearer_auth(&simulator.token)
+        .send()
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+    assert_eq!(
+        from_bytes::<Option<WakeupReason>>(&response.bytes().await.unwrap()).unwrap(),
+        Some(WakeupReason::WakeOnLan([127, 0, 0, 1].into()))
+    );
+}
+
+#[tokio::test]
+async fn magic_packets_dont_suspend_the_computer() {
+    let computer = FakeComputer::new(Power::On);
+    let simulator = start(computer.clone()).await;
+    sleep(Duration::from_secs(3)).await;
+
+    send_magic_packet(simulator.wake_on_lan_address, MAC).await;
+    sleep(Duration::from_secs(3)).await;
+    assert_eq!(computer.power(), Power::On);
+}
+
+#[tokio::test]
+async fn invalid_macs_are_rejected() {
+    let simulator = start(FakeComputer::new(Power::Off)).await;
+    let response = Client::new()
+        .put(format!("http://{}/wake_on_lan", simulator.address))
+        .bearer_auth(&simulator.token)
+        .header("Content-Type", "application/json")
+        .body(
+            serde_json::to_vec(&WakeOnLanConfig {
+                mac: Some([0xFF; 6]),
+            })
+            .unwrap(),
+        )
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 400);
+    let response = Client::new()
+        .get(format!("http://{}/wake_on_lan", simulator.address))
+        .bearer_auth(&simulator.token)
+        .header("Accept", "application/json")
+        .send()
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+    assert_eq!(
+        serde_json::from_slice::<WakeOnLanConfig>(&response.bytes().await.unwrap()).unwrap(),
+        WakeOnLanConfig { mac: Some(MAC) }
+    );
+}
@@ -1,13 +1,27 @@
 #![feature(iter_intersperse)]
 
 use postcard::{from_bytes, to_allocvec};
-use reqwest::{Client, Method};
+use std::time::Duration;
+
+use reqwest::{Client, Method, RequestBuilder};
+use smart_power_button_common::bluetooth_wake::{BleAdvertisement, DeviceMatcher, WakeupRule};
 use smart_power_button_common::WakeupReason;
+use smart_power_button_computer::discovery::find_device;
+
+/// Like `gaming-computer-remote`. `None` finds the remote with mDNS.
+const ADDRESS: Option<&str> = None;
+/// Token from `POST /login`
+const TOKEN: Option<&str> = None;
 
-const ADDRESS: &str = "gaming-computer-remote";
+fn authorize(request: RequestBuilder) -> RequestBuilder {
+    match TOKEN {
+        Some(token) => request.bearer_auth(token),
+        None => request,
+    }
+}
 
-async fn set_bluetooth_wakeup_devices() {
-    let ids = [
+async fn set_bluetooth_wakeup_devices(address: &str) {
+    let rules = [
         "C8:3F:26:8D:4D:00",
         "5C:BA:37:1D:74:5C",
         "28:EA:0B:D7:85:1D",
@@ -24,12 +38,11 @@ async fn set_bluetooth_wakeup_devices() {
         }
         .try_into()
         .unwrap();
-        id
+        WakeupRule::address(id)
     })
     .collect::<Vec<_>>();
-    Client::new()
-        .put(format!("http://{ADDRESS}/bluetooth_wakeup_devices"))
-        .body(to_allocvec(&ids).unwrap())
+    authorize(Client::new().put(format!("http://{address}/bluetooth_wakeup_devices")))
+        .body(to_allocvec(&rules).unwrap())
         .send()
         .await
         .unwrap()
@@ -37,9 +50,10 @@ async fn set_bluetooth_wakeup_devices() {
         .unwrap();
 }
 
-async fn get_bluetooth_wakeup_devices() {
-    let ids: Vec<[u8; 6]> = from_bytes(
-        &reqwest::get(format!("http://{ADDRESS}/bluetooth_wakeup_devices"))
+async fn get_bluetooth_wakeup_devices(address: &str) {
+    let rules: Vec<WakeupRule> = from_bytes(
+        &authorize(Client::new().get(format!("http://{address}/bluetooth_wakeup_devices")))
+            .send()
             .await
             .unwrap()
             .bytes()
@@ -47,30 +61,26 @@ async fn get_bluetooth_wakeup_devices() {
             .unwrap(),
     )
     .unwrap();
-    let ids = ids
+    let rules = rules
         .iter()
-        .map(|id| {
-            id.iter()
+        .map(|rule| match &rule.matcher {
+            DeviceMatcher::Address(id) => id
+                .iter()
                 // The string version's numbers need to be reversed
                 .rev()
                 .map(|id| format!("{id:02X}"))
                 .intersperse(":".into())
-                .collect::<String>()
+                .collect::<String>(),
+            matcher => format!("{matcher:?}"),
         })
         .collect::<Vec<_>>();
-    println!("{ids:#?}");
+    println!("{rules:#?}");
 }
 
-async fn get_wakeup_reason(delete: bool) {
-    let reason: Option<WakeupReason> = from_bytes(
-        &Client::new()
-            .request(
-                match delete {
-                    true => Method::DELETE,
-                    false => Method::GET,
-                },
-                format!("http://{ADDRESS}/wakeup_reason"),
-            )
+/// Prints the devices near the ESP, so their addresses don't have to be typed by hand
+async fn find_bluetooth_devices(address: &str) {
+    let devices: Vec<BleAdvertisement> = from_bytes(
+        &authorize(Client::new().post(format!("http://{address}/bluetooth_scan?secs=10")))
             .send()
             .await
             .unwrap()
@@ -81,12 +91,39 @@ async fn get_wakeup_reason(delete: bool) {
             .unwrap(),
     )
     .unwrap();
+    println!("{devices:#?}");
+}
+
+async fn get_wakeup_reason(address: &str, delete: bool) {
+    let reason: Option<WakeupReason> = from_bytes(
+        &authorize(Client::new().request(
+            match delete {
+                true => Method::DELETE,
+                false => Method::GET,
+            },
+            format!("http://{address}/wakeup_reason"),
+        ))
+        .send()
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap()
+        .bytes()
+        .await
+        .unwrap(),
+    )
+    .unwrap();
     println!("Wakup reason: {reason:?}");
 }
 
 #[tokio::main]
 async fn main() {
-    set_bluetooth_wakeup_devices().await;
-    get_bluetooth_wakeup_devices().await;
-    get_wakeup_reason(true).await;
+    let address = match ADDRESS {
+        Some(address) => address.to_owned(),
+        None => find_device(Duration::from_secs(5)).await.unwrap().address(),
+    };
+    find_bluetooth_devices(&address).await;
+    set_bluetooth_wakeup_devices(&address).await;
+    get_bluetooth_wakeup_devices(&address).await;
+    get_wakeup_reason(&address, true).await;
 }
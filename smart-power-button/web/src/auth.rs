@@ -0,0 +1,109 @@
+use async_ui_web::event_traits::EmitElementEvent;
+use async_ui_web::html::{Br, Button, Input};
+use async_ui_web::join;
+use async_ui_web::shortcut_traits::{ShortcutRenderStr, UiFutureExt};
+use gloo_net::http::Request;
+use js_sys::Uint8Array;
+use postcard::{from_bytes, to_allocvec};
+use smart_power_button_common::auth::{LoginRequest, LoginResponse};
+use web_sys::window;
+
+/// Where the token is kept in local storage
+const TOKEN_KEY: &str = "token";
+
+pub fn stored_token() -> Option<String> {
+    window()?.local_storage().ok()??.get_item(TOKEN_KEY).ok()?
+}
+
+fn store_token(token: &str) {
+    if let Some(storage) = window().unwrap().local_storage().unwrap() {
+        storage.set_item(TOKEN_KEY, token).unwrap();
+    }
+}
+
+pub fn clear_token() {
+    if let Some(storage) = window().unwrap().local_storage().unwrap() {
+        storage.remove_item(TOKEN_KEY).unwrap();
+    }
+}
+
+/// Returns `Ok(false)` if the ESP doesn't accept the token anymore
+pub async fn check_token(http_url: &str, token: &str) -> Result<bool, String> {
+    let response = Request::get(&format!("{http_url}/login"))
+        .header("Authorization", &format!("Bearer {token}"))
+        .send()
+        .await
+        .map_err(|e| e.to_string())?;
+    match response.status() {
+        204 => Ok(true),
+        401 => Ok(false),
+        status => Err(format!("Unexpected status checking login: {status}")),
+    }
+}
+
+pub async fn logout(http_url: &str, token: &str) {
+    // Even if this fails, the token is forgotten by the browser
+    let _ = Request::delete(&format!("{http_url}/login"))
+        .header("Authorization", &format!("Bearer {token}"))
+        .send()
+        .await;
+    clear_token();
+}
+
+async fn request_token(http_url: &str, password: String) -> Result<String, String> {
+    let body = to_allocvec(&LoginRequest {
+        password,
+        client_name: window()
+            .unwrap()
+            .navigator()
+            .user_agent()
+            .unwrap_or_else(|_| "Web browser".into()),
+    })
+    .unwrap();
+    let response = Request::post(&format!("{http_url}/login"))
+        .body(Uint8Array::from(body.as_slice()))
+        .map_err(|e| e.to_string())?
+        .send()
+        .await
+        .map_err(|e| e.to_string())?;
+    match response.status() {
+        200 => {
+            let bytes = response.binary().await.map_err(|e| e.to_string())?;
+            let LoginResponse { token } = from_bytes(&bytes).map_err(|e| e.to_string())?;
+            Ok(token)
+        }
+        401 => Err("Wrong password".into()),
+        status => Err(format!("Error logging in: {status}")),
+    }
+}
+
+/// Shows a login form until the ESP gives us a token. The token is saved in local storage.
+pub async fn login(http_url: &str) -> String {
+    let mut message = None::<String>;
+    loop {
+        let password_input = Input::new();
+        password_input.set_type("password");
+        let login_button = Button::new();
+        let attempt = async {
+            login_button.until_click().await;
+            login_button.set_disabled(true);
+            request_token(http_url, password_input.value()).await
+        };
+        let result = attempt
+            .meanwhile(join((
+                "Pairing password: ".render(),
+                password_input.render(),
+                login_button.render("Log in".render()),
+                Br::new().render(),
+                message.clone().unwrap_or_default().render(),
+            )))
+            .await;
+        match result {
+            Ok(token) => {
+                store_token(&token);
+                break token;
+            }
+            Err(e) => message = Some(e),
+        }
+    }
+}
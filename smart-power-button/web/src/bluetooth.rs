@@ -0,0 +1,152 @@
+use async_ui_web::event_traits::EmitElementEvent;
+use async_ui_web::html::{Br, Button};
+use async_ui_web::join;
+use async_ui_web::shortcut_traits::{ShortcutRenderStr, UiFutureExt};
+use futures::{stream, StreamExt};
+use gloo_console::error;
+use gloo_net::http::Request;
+use js_sys::Uint8Array;
+use postcard::{from_bytes, to_allocvec};
+use smart_power_button_common::bluetooth_wake::{BleAddressType, BleAdvertisement, WakeupRule};
+use web_sys::window;
+
+use crate::history::describe_address;
+use crate::stream_render_ext::StreamRenderExt;
+
+/// How long the ESP scans for
+const SCAN_SECS: u32 = 10;
+
+async fn scan(http_url: &str, token: &str) -> Result<Vec<BleAdvertisement>, String> {
+    let response = Request::post(&format!("{http_url}/bluetooth_scan?secs={SCAN_SECS}"))
+        .header("Authorization", &format!("Bearer {token}"))
+        .send()
+        .await
+        .map_err(|e| e.to_string())?;
+    match response.status() {
+        200 => {
+            let bytes = response.binary().await.map_err(|e| e.to_string())?;
+            from_bytes(&bytes).map_err(|e| e.to_string())
+        }
+        status => Err(format!(
+            "Error scanning ({status}): {}",
+            response.text().await.unwrap_or_default()
+        )),
+    }
+}
+
+/// Adds a rule for `device` to the machine's rules that are saved on the ESP
+async fn add_wakeup_device(
+    machine_url: &str,
+    token: &str,
+    device: &BleAdvertisement,
+) -> Result<(), String> {
+    let url = format!("{machine_url}/bluetooth_wakeup_devices");
+    let response = Request::get(&url)
+        .header("Authorization", &format!("Bearer {token}"))
+        .send()
+        .await
+        .map_err(|e| e.to_string())?;
+    let mut rules: Vec<WakeupRule> = match response.status() {
+        200 => {
+            let bytes = response.binary().await.map_err(|e| e.to_string())?;
+            from_bytes(&bytes).map_err(|e| e.to_string())?
+        }
+        status => return Err(format!("Error getting wakeup devices: {status}")),
+    };
+    let rule = WakeupRule::for_device(device);
+    if rules.contains(&rule) {
+        return Ok(());
+    }
+    rules.push(rule);
+    let response = Request::put(&url)
+        .header("Authorization", &format!("Bearer {token}"))
+        .body(Uint8Array::from(to_allocvec(&rules).unwrap().as_slice()))
+        .map_err(|e| e.to_string())?
+        .send()
+        .await
+        .map_err(|e| e.to_string())?;
+    match response.status() {
+        200 => Ok(()),
+        status => Err(format!("Error saving wakeup devices: {status}")),
+    }
+}
+
+fn describe_device(device: &BleAdvertisement) -> String {
+    let name = device.name.as_deref().unwrap_or("Unknown name");
+    let address_type = match device.address_type {
+        BleAddressType::Public => "",
+        BleAddressType::Random => ", random address",
+    };
+    format!(
+        "{name} ({}{address_type}, {} dBm) ",
+        describe_address(device.address),
+        device.rssi
+    )
+}
+
+async fn device_line(machine_url: &str, token: &str, device: BleAdvertisement) {
+    let add_button = Button::new();
+    join((
+        describe_device(&device).render(),
+        add_button.render("Wake up the computer with this".render()),
+        Br::new().render(),
+        async {
+            loop {
+                add_button.until_click().await;
+                add_button.set_disabled(true);
+                match add_wakeup_device(machine_url, token, &device).await {
+                    // Stays disabled, because it was added
+                    Ok(()) => {}
+                    Err(e) => {
+                        error!(e.clone());
+                        let _ = window().unwrap().alert_with_message(&e);
+                        add_button.set_disabled(false);
+                    }
+                }
+            }
+        },
+    ))
+    .await;
+}
+
+/// Finds Bluetooth devices that are near the ESP, so that the user can pick the ones that wake up
+/// the computer instead of typing their addresses. The ESP scans for all machines, and the devices
+/// are added to the machine at `machine_url`.
+pub async fn bluetooth_view(http_url: &str, machine_url: &str, token: &str) {
+    let scan_button = &Button::new();
+    let scans = stream::unfold((), move |()| async move {
+        scan_button.until_click().await;
+        scan_button.set_disabled(true);
+        let result = scan(http_url, token)
+            .meanwhile("Scanning...".render())
+            .await;
+        scan_button.set_disabled(false);
+        Some((result, ()))
+    });
+    join((
+        scan_button.render("Find Bluetooth devices (turn them on first)".render()),
+        Br::new().render(),
+        scans
+            .map(move |result| async move {
+                match result {
+                    Ok(devices) if devices.is_empty() => "No devices found".render().await,
+                    Ok(devices) => {
+                        join(
+                            devices
+                                .into_iter()
+                                .map(|device| device_line(machine_url, token, device))
+                                .collect::<Vec<_>>(),
+                        )
+                        .await;
+                    }
+                    Err(e) => {
+                        error!(e.clone());
+                        e.render().await
+                    }
+                }
+            })
+            .boxed_local()
+            .render(),
+    ))
+    .await;
+}
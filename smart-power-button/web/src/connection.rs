@@ -0,0 +1,482 @@
+//! Keeps a web socket to the ESP open, reconnecting with a growing delay when it closes
+use std::cell::RefCell;
+use std::mem::discriminant;
+use std::pin::Pin;
+use std::time::Duration;
+
+use futures::future::ready;
+use futures::stream::{self, LocalBoxStream};
+use futures::{Sink, SinkExt, StreamExt};
+use gloo_console::{error, log, warn};
+use js_sys::Promise;
+use tokio::sync::broadcast::error::RecvError;
+use tokio::sync::{broadcast, watch, Mutex};
+use wasm_bindgen_futures::JsFuture;
+use web_sys::window;
+use ws_stream_wasm::{WsErr, WsMessage, WsMeta};
+
+use smart_power_button_common::protocol::{FrameToEsp, FrameToWeb, Hello, ProtocolError};
+use smart_power_button_common::wifi::reconnect_delay;
+use smart_power_button_common::MessageToWeb;
+
+/// The longest time the page waits between attempts, so it comes back soon after the ESP does
+pub const MAX_RECONNECT_DELAY: Duration = Duration::from_secs(30);
+
+pub type Writer = Pin<Box<dyn Sink<Vec<u8>, Error = ConnectionError>>>;
+pub type Reader = LocalBoxStream<'static, Vec<u8>>;
+
+/// Opens web sockets to the ESP. Tests use a mock instead.
+pub trait Connector {
+    async fn connect(&self) -> Result<(Writer, Reader), ConnectionError>;
+    /// Waits before the next attempt
+    async fn sleep(&self, duration: Duration);
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ConnectionError {
+    /// The web socket couldn't be opened
+    Connect(String),
+    /// The ESP closed the web socket
+    Closed,
+    Send(String),
+    /// There is no web socket right now
+    Offline,
+    /// The ESP answered the hello with an error
+    Handshake(ProtocolError),
+    /// The ESP sent something else before its hello
+    ExpectedHello,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ConnectionState {
+    Connecting,
+    Connected(Hello),
+    /// The connection failed, and it is tried again after `retry_in`
+    Offline {
+        error: ConnectionError,
+        retry_in: Duration,
+    },
+    /// Trying again won't help, for example because the ESP has a different protocol version
+    Failed(ConnectionError),
+}
+
+impl ConnectionState {
+    pub fn is_connected(&self) -> bool {
+        matches!(self, ConnectionState::Connected(_))
+    }
+}
+
+/// A web socket to the ESP that is opened again whenever it closes. Frames from every socket go
+/// to the same streams, so the page doesn't have to know about reconnecting.
+pub struct Connection {
+    state_tx: watch::Sender<ConnectionState>,
+    writer: Mutex<Option<Writer>>,
+    frames_tx: broadcast::Sender<FrameToWeb>,
+    /// The last message of each kind from the current socket, for streams that start late
+    latest: RefCell<Vec<FrameToWeb>>,
+}
+
+impl Connection {
+    pub fn new() -> Self {
+        Self {
+            state_tx: watch::channel(ConnectionState::Connecting).0,
+            writer: Mutex::new(None),
+            frames_tx: broadcast::channel(64).0,
+            latest: RefCell::new(Vec::new()),
+        }
+    }
+
+    /// The current state, and then every change to it
+    pub fn states(&self) -> LocalBoxStream<'static, ConnectionState> {
+        stream::unfold(
+            (self.state_tx.subscribe(), true),
+            |(mut rx, is_first)| async move {
+                if !is_first {
+                    rx.changed().await.ok()?;
+                }
+                let state = rx.borrow_and_update().clone();
+                Some((state, (rx, false)))
+            },
+        )
+        .boxed_local()
+    }
+
+    /// Waits for the first hello from the ESP, or for the connection to fail for good
+    pub async fn until_connected(&self) -> Result<Hello, ConnectionError> {
+        let mut states = self.states();
+        while let Some(state) = states.next().await {
+            match state {
+                ConnectionState::Connected(hello) => return Ok(hello),
+                ConnectionState::Failed(error) => return Err(error),
+                _ => {}
+            }
+        }
+        Err(ConnectionError::Closed)
+    }
+
+    /// The latest state messages from the ESP, and then every frame it sends, including ones
+    /// from later web sockets
+    pub fn frames(&self) -> LocalBoxStream<'static, FrameToWeb> {
+        let rx = self.frames_tx.subscribe();
+        let latest = self.latest.borrow().clone();
+        stream::iter(latest)
+            .chain(stream::unfold(rx, |mut rx| async move {
+                loop {
+                    match rx.recv().await {
+                        Ok(frame) => return Some((frame, rx)),
+                        Err(RecvError::Lagged(skipped)) => {
+                            warn!(format!("Skipped {skipped} frames from the ESP"));
+                        }
+                        Err(RecvError::Closed) => return None,
+                    }
+                }
+            }))
+            .boxed_local()
+    }
+
+    pub async fn send(&self, frame: FrameToEsp) -> Result<(), ConnectionError> {
+        let mut writer = self.writer.lock().await;
+        let writer = writer.as_mut().ok_or(ConnectionError::Offline)?;
+        writer.send(frame.encode()).await
+    }
+
+    /// Connects and reconnects until the ESP has a protocol version the page can't use
+    pub async fn run(&self, connector: impl Connector, client_hello: Hello) {
+        let mut failed_attempts = 0;
+        loop {
+            self.state_tx.send_replace(ConnectionState::Connecting);
+            let error = match handshake(&connector, &client_hello).await {
+                Ok((writer, reader, esp_hello)) => {
+                    failed_attempts = 0;
+                    self.latest.borrow_mut().clear();
+                    *self.writer.lock().await = Some(writer);
+                    log!(format!("Connected to ESP: {esp_hello:?}"));
+                    self.state_tx
+                        .send_replace(ConnectionState::Connected(esp_hello));
+                    self.read_frames(reader).await;
+                    *self.writer.lock().await = None;
+                    ConnectionError::Closed
+                }
+                Err(error @ ConnectionError::Handshake(ProtocolError::VersionMismatch { .. })) => {
+                    error!(format!("Not reconnecting: {error:?}"));
+                    self.state_tx.send_replace(ConnectionState::Failed(error));
+                    return;
+                }
+                Err(error) => error,
+            };
+            failed_attempts += 1;
+            let retry_in = reconnect_delay(failed_attempts).min(MAX_RECONNECT_DELAY);
+            warn!(format!(
+                "Disconnected ({error:?}), retrying in {retry_in:?}"
+            ));
+            self.state_tx
+                .send_replace(ConnectionState::Offline { error, retry_in });
+            connector.sleep(retry_in).await;
+        }
+    }
+
+    /// Passes on frames until the web socket closes
+    async fn read_frames(&self, mut reader: Reader) {
+        while let Some(bytes) = reader.next().await {
+            match FrameToWeb::decode(&bytes) {
+                Ok(frame) => {
+                    // This line is just to debug messages
+                    log!(format!("{frame:?}"));
+                    self.remember(&frame);
+                    let _ = self.frames_tx.send(frame);
+                }
+                Err(e) => error!(format!("Error decoding frame: {e:?}")),
+            }
+        }
+    }
+
+    fn remember(&self, frame: &FrameToWeb) {
+        let FrameToWeb::Message { machine, message } = frame else {
+            return;
+        };
+        if matches!(
+            message,
+            MessageToWeb::HistoryEvent(_) | MessageToWeb::BluetoothDevice(_)
+        ) {
+            return;
+        }
+        let mut latest = self.latest.borrow_mut();
+        let old = latest.iter_mut().find(|old| {
+            matches!(old, FrameToWeb::Message { machine: old_machine, message: old_message }
+                if old_machine == machine && discriminant(old_message) == discriminant(message))
+        });
+        match old {
+            Some(old) => *old = frame.clone(),
+            None => latest.push(frame.clone()),
+        }
+    }
+}
+
+/// Opens a web socket and exchanges hellos
+async fn handshake(
+    connector: &impl Connector,
+    client_hello: &Hello,
+) -> Result<(Writer, Reader, Hello), ConnectionError> {
+    let (mut writer, mut reader) = connector.connect().await?;
+    writer
+        .send(FrameToEsp::Hello(client_hello.clone()).encode())
+        .await?;
+    let bytes = reader.next().await.ok_or(ConnectionError::Closed)?;
+    match FrameToWeb::decode(&bytes) {
+        Ok(FrameToWeb::Hello(esp_hello)) => {
+            esp_hello
+                .check_version()
+                .map_err(ConnectionError::Handshake)?;
+            Ok((writer, reader, esp_hello))
+        }
+        Ok(FrameToWeb::Error { error, .. }) | Err(error) => Err(ConnectionError::Handshake(error)),
+        Ok(_) => Err(ConnectionError::ExpectedHello),
+    }
+}
+
+/// Connects to the ESP with a browser web socket
+pub struct WsConnector {
+    pub url: String,
+}
+
+impl Connector for WsConnector {
+    async fn connect(&self) -> Result<(Writer, Reader), ConnectionError> {
+        let (ws_meta, ws_stream) = WsMeta::connect(&self.url, None)
+            .await
+            .map_err(|e| ConnectionError::Connect(e.to_string()))?;
+        let (w, r) = ws_stream.split();
+        let writer = w
+            .with(|bytes: Vec<u8>| ready(Ok::<_, WsErr>(WsMessage::Binary(bytes))))
+            .sink_map_err(|e| ConnectionError::Send(e.to_string()));
+        let reader = r.filter_map(move |message| {
+            // The socket stays open as long as the reader
+            let _ws_meta = &ws_meta;
+            ready(match message {
+                WsMessage::Binary(bytes) => Some(bytes),
+                WsMessage::Text(text) => {
+                    warn!(format!("Ignoring text message from the ESP: {text}"));
+                    None
+                }
+            })
+        });
+        Ok((Box::pin(writer), reader.boxed_local()))
+    }
+
+    async fn sleep(&self, duration: Duration) {
+        sleep(duration).await;
+    }
+}
+
+pub async fn sleep(duration: Duration) {
+    let promise = Promise::new(&mut |resolve, _reject| {
+        window()
+            .unwrap()
+            .set_timeout_with_callback_and_timeout_and_arguments_0(
+                &resolve,
+                duration.as_millis() as i32,
+            )
+            .unwrap();
+    });
+    let _ = JsFuture::from(promise).await;
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+    use std::collections::VecDeque;
+    use std::future::{pending, Future};
+    use std::pin::pin;
+    use std::time::Duration;
+
+    use futures::channel::mpsc::{self, UnboundedReceiver, UnboundedSender};
+    use futures::future::{ready, select, Either};
+    use futures::{SinkExt, StreamExt};
+    use wasm_bindgen_test::wasm_bindgen_test;
+
+    use smart_power_button_common::protocol::{
+        FrameToEsp, FrameToWeb, Hello, ProtocolError, PROTOCOL_VERSION,
+    };
+    use smart_power_button_common::{MessageToWeb, Power, PowerState};
+
+    use super::{Connection, ConnectionError, Connector, Reader, Writer};
+
+    /// The ESP's end of a mock web socket
+    struct MockEsp {
+        to_web: UnboundedSender<Vec<u8>>,
+        from_web: UnboundedReceiver<Vec<u8>>,
+    }
+
+    impl MockEsp {
+        fn send(&self, frame: FrameToWeb) {
+            self.to_web.unbounded_send(frame.encode()).unwrap();
+        }
+
+        async fn receive(&mut self) -> FrameToEsp {
+            FrameToEsp::decode(&self.from_web.next().await.unwrap()).unwrap()
+        }
+    }
+
+    fn mock_socket() -> (MockEsp, Result<(Writer, Reader), ConnectionError>) {
+        let (to_web, reader) = mpsc::unbounded();
+        let (writer, from_web) = mpsc::unbounded();
+        let writer = writer.sink_map_err(|e| ConnectionError::Send(e.to_string()));
+        (
+            MockEsp { to_web, from_web },
+            Ok((Box::pin(writer), reader.boxed_local())),
+        )
+    }
+
+    /// Hands out the sockets in order, then never connects again
+    #[derive(Default)]
+    struct MockConnector {
+        sockets: RefCell<VecDeque<Result<(Writer, Reader), ConnectionError>>>,
+        sleeps: RefCell<Vec<Duration>>,
+    }
+
+    impl Connector for &MockConnector {
+        async fn connect(&self) -> Result<(Writer, Reader), ConnectionError> {
+            let socket = self.sockets.borrow_mut().pop_front();
+            match socket {
+                Some(socket) => socket,
+                None => pending().await,
+            }
+        }
+
+        async fn sleep(&self, duration: Duration) {
+            self.sleeps.borrow_mut().push(duration);
+        }
+    }
+
+    fn esp_hello() -> Hello {
+        Hello {
+            machines: vec!["Computer".into()],
+            ..Hello::new(vec![])
+        }
+    }
+
+    fn power_state(power: Power) -> FrameToWeb {
+        FrameToWeb::Message {
+            machine: Some(0),
+            message: MessageToWeb::PowerState(PowerState {
+                power: Some(power),
+                since: None,
+            }),
+        }
+    }
+
+    /// Runs `connection` until `test` finishes
+    async fn run_until<T>(
+        connection: &Connection,
+        connector: &MockConnector,
+        test: impl Future<Output = T>,
+    ) -> T {
+        let run = pin!(connection.run(connector, Hello::new(vec![])));
+        match select(run, pin!(test)).await {
+            Either::Left(_) => panic!("The connection stopped"),
+            Either::Right((output, _)) => output,
+        }
+    }
+
+    #[wasm_bindgen_test]
+    async fn backs_off_until_connected() {
+        let connection = Connection::new();
+        let connector = MockConnector::default();
+        let (mut esp, socket) = mock_socket();
+        esp.send(FrameToWeb::Hello(esp_hello()));
+        connector.sockets.borrow_mut().extend([
+            Err(ConnectionError::Connect("refused".into())),
+            Err(ConnectionError::Connect("refused".into())),
+            socket,
+        ]);
+
+        let hello = run_until(&connection, &connector, connection.until_connected()).await;
+        assert_eq!(hello, Ok(esp_hello()));
+        assert_eq!(
+            *connector.sleeps.borrow(),
+            [Duration::from_secs(1), Duration::from_secs(2)]
+        );
+        assert_eq!(esp.receive().await, FrameToEsp::Hello(Hello::new(vec![])));
+    }
+
+    #[wasm_bindgen_test]
+    async fn reconnects_and_replays_the_new_state() {
+        let connection = Connection::new();
+        let connector = MockConnector::default();
+        let (first_esp, first_socket) = mock_socket();
+        first_esp.send(FrameToWeb::Hello(esp_hello()));
+        first_esp.send(power_state(Power::Off));
+        drop(first_esp);
+        let (second_esp, second_socket) = mock_socket();
+        second_esp.send(FrameToWeb::Hello(esp_hello()));
+        second_esp.send(power_state(Power::On));
+        connector
+            .sockets
+            .borrow_mut()
+            .extend([first_socket, second_socket]);
+
+        let frame = run_until(&connection, &connector, async {
+            connection
+                .states()
+                .filter(|state| {
+                    ready(state.is_connected() && connector.sockets.borrow().is_empty())
+                })
+                .next()
+                .await;
+            connection.frames().next().await
+        })
+        .await;
+        assert_eq!(frame, Some(power_state(Power::On)));
+        assert_eq!(*connector.sleeps.borrow(), [Duration::from_secs(1)]);
+    }
+
+    #[wasm_bindgen_test]
+    async fn late_streams_get_the_latest_state() {
+        let connection = Connection::new();
+        let connector = MockConnector::default();
+        let (esp, socket) = mock_socket();
+        esp.send(FrameToWeb::Hello(esp_hello()));
+        connector.sockets.borrow_mut().push_back(socket);
+
+        run_until(&connection, &connector, async {
+            let mut frames = connection.frames();
+            esp.send(power_state(Power::Off));
+            esp.send(power_state(Power::On));
+            frames.next().await;
+            frames.next().await;
+            let mut late_frames = connection.frames();
+            assert_eq!(late_frames.next().await, Some(power_state(Power::On)));
+        })
+        .await;
+    }
+
+    #[wasm_bindgen_test]
+    async fn sending_while_offline_fails() {
+        let connection = Connection::new();
+        assert_eq!(
+            connection.send(FrameToEsp::Hello(Hello::new(vec![]))).await,
+            Err(ConnectionError::Offline)
+        );
+    }
+
+    #[wasm_bindgen_test]
+    async fn stops_on_version_mismatch() {
+        let connection = Connection::new();
+        let connector = MockConnector::default();
+        let (esp, socket) = mock_socket();
+        esp.send(FrameToWeb::Hello(Hello {
+            protocol_version: PROTOCOL_VERSION + 1,
+            ..esp_hello()
+        }));
+        connector.sockets.borrow_mut().push_back(socket);
+
+        connection.run(&connector, Hello::new(vec![])).await;
+        assert_eq!(
+            connection.until_connected().await,
+            Err(ConnectionError::Handshake(ProtocolError::VersionMismatch {
+                expected: PROTOCOL_VERSION,
+                actual: PROTOCOL_VERSION + 1,
+            }))
+        );
+        assert!(connector.sleeps.borrow().is_empty());
+    }
+}
@@ -0,0 +1,33 @@
+use smart_power_button_common::hdd::{HddActivity, HddStats};
+
+const SPARKLINE_BARS: [char; 8] = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+
+fn describe_stats(stats: HddStats) -> String {
+    format!(
+        "{}% ({} burst{})",
+        stats.active_percent,
+        stats.bursts,
+        if stats.bursts == 1 { "" } else { "s" }
+    )
+}
+
+/// One bar for each second, oldest first
+pub fn sparkline(history: &[u8]) -> String {
+    history
+        .iter()
+        .map(|&percent| {
+            let index = usize::from(percent.min(100)) * (SPARKLINE_BARS.len() - 1) / 100;
+            SPARKLINE_BARS[index]
+        })
+        .collect()
+}
+
+pub fn describe_hdd_activity(activity: &HddActivity) -> String {
+    format!(
+        "1s: {}, 10s: {}, 1 min: {} {}",
+        describe_stats(activity.last_second),
+        describe_stats(activity.last_10_seconds),
+        describe_stats(activity.last_minute),
+        sparkline(&activity.history)
+    )
+}
@@ -0,0 +1,157 @@
+use std::future::ready;
+
+use async_ui_web::html::Br;
+use async_ui_web::join;
+use async_ui_web::shortcut_traits::{ShortcutRenderStr, UiFutureExt};
+use futures::{stream, Stream, StreamExt};
+use gloo_console::error;
+use gloo_net::http::Request;
+use js_sys::{Date, Object};
+use postcard::from_bytes;
+use smart_power_button_common::history::{
+    add_event, last_wakeup, HistoryEvent, HistoryEventKind, PressSource,
+};
+use smart_power_button_common::press::ButtonKind;
+use smart_power_button_common::{MessageToEsp, Power, WakeupReason};
+
+use crate::stream_render_ext::StreamRenderExt;
+
+async fn fetch_history(http_url: &str, token: &str) -> Result<Vec<HistoryEvent>, String> {
+    let response = Request::get(&format!("{http_url}/history"))
+        .header("Authorization", &format!("Bearer {token}"))
+        .send()
+        .await
+        .map_err(|e| e.to_string())?;
+    match response.status() {
+        200 => {
+            let bytes = response.binary().await.map_err(|e| e.to_string())?;
+            from_bytes(&bytes).map_err(|e| e.to_string())
+        }
+        status => Err(format!("Error getting history: {status}")),
+    }
+}
+
+pub fn describe_time(time: Option<u64>) -> String {
+    match time {
+        Some(seconds) => Date::new(&(seconds as f64 * 1000.0).into())
+            .to_locale_string("default", &Object::new())
+            .into(),
+        None => "Unknown time".into(),
+    }
+}
+
+pub fn describe_address(address: [u8; 6]) -> String {
+    address
+        .iter()
+        // The ESP keeps the bytes in the opposite order
+        .rev()
+        .map(|byte| format!("{byte:02X}"))
+        .collect::<Vec<_>>()
+        .join(":")
+}
+
+pub fn describe_wakeup_reason(reason: WakeupReason) -> String {
+    match reason {
+        WakeupReason::Web(_) => "Web page".into(),
+        WakeupReason::Bluetooth(address) => {
+            format!("Bluetooth device {}", describe_address(address))
+        }
+        WakeupReason::Schedule(_) => "Schedule".into(),
+        WakeupReason::Mqtt(_) => "MQTT".into(),
+        WakeupReason::WakeOnLan(address) => format!("Wake-on-LAN from {address}"),
+    }
+}
+
+fn describe_event(event: &HistoryEvent) -> String {
+    let what = match &event.kind {
+        HistoryEventKind::Boot => "ESP started".into(),
+        HistoryEventKind::Power(power) => match power {
+            Power::On => "Computer turned on",
+            Power::Suspend => "Computer suspended",
+            Power::Off => "Computer turned off",
+        }
+        .into(),
+        HistoryEventKind::HddActivity {
+            active_millis,
+            period_secs,
+        } => format!(
+            "HDD LED was on for {:.1}s in {} minutes",
+            *active_millis as f64 / 1000.0,
+            period_secs / 60
+        ),
+        HistoryEventKind::ButtonPress { message, source } => format!(
+            "{} ({})",
+            match message {
+                MessageToEsp::ShortPressPowerButton(_) => "Pressed power button",
+                MessageToEsp::LongPressPowerButton => "Pressed power button for a long time",
+                MessageToEsp::ShortPressResetButton => "Pressed reset button",
+                MessageToEsp::Press {
+                    button: ButtonKind::Power,
+                    ..
+                } => "Pressed power button with a pattern",
+                MessageToEsp::Press {
+                    button: ButtonKind::Reset,
+                    ..
+                } => "Pressed reset button with a pattern",
+            },
+            match source {
+                PressSource::Web => "web page".into(),
+                PressSource::Bluetooth(address) =>
+                    format!("Bluetooth device {}", describe_address(*address)),
+                PressSource::Schedule => "schedule".into(),
+                PressSource::Mqtt => "MQTT".into(),
+                PressSource::WakeOnLan(address) => format!("Wake-on-LAN packet from {address}"),
+            }
+        ),
+        HistoryEventKind::WakeupReason(reason) => {
+            format!("Wakeup reason: {}", describe_wakeup_reason(*reason))
+        }
+    };
+    format!("{}: {what}", describe_time(event.time))
+}
+
+async fn render_history(events: Vec<HistoryEvent>) {
+    let last_wakeup = match last_wakeup(&events) {
+        Some((event, reason)) => format!(
+            "Last woke up: {} ({})",
+            describe_time(event.time),
+            reason.map_or("not by the ESP".into(), describe_wakeup_reason)
+        ),
+        None => "Last woke up: not in history".into(),
+    };
+    let lines = events
+        .iter()
+        .rev()
+        .map(describe_event)
+        .map(|line| async move { join((line.render(), Br::new().render())).await })
+        .collect::<Vec<_>>();
+    join((last_wakeup.render(), Br::new().render(), join(lines))).await;
+}
+
+/// Shows the history from the ESP, newest first, and adds events as they happen
+pub async fn history_view(
+    http_url: &str,
+    token: &str,
+    new_events: impl Stream<Item = HistoryEvent> + Unpin,
+) {
+    let events = match fetch_history(http_url, token)
+        .meanwhile("Loading history".render())
+        .await
+    {
+        Ok(events) => events,
+        Err(e) => {
+            error!(e.clone());
+            format!("Error loading history: {e}").render().await;
+            return;
+        }
+    };
+    stream::once(ready(events.clone()))
+        .chain(new_events.scan(events, |events, event| {
+            add_event(events, event);
+            ready(Some(events.clone()))
+        }))
+        .map(render_history)
+        .boxed_local()
+        .render()
+        .await;
+}
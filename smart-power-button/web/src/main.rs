@@ -1,205 +1,777 @@
 #![warn(unused_crate_dependencies)]
 
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
 use std::panic;
+use std::time::Duration;
 
 use async_ui_web::event_traits::EmitElementEvent;
 use async_ui_web::html::{Br, Button, Input, Label};
 use async_ui_web::shortcut_traits::{ShortcutRenderStr, UiFutureExt};
-use async_ui_web::{join, mount};
+use async_ui_web::{join, mount, race};
 use dotenvy_macro::option_dotenv;
-use futures::{SinkExt, StreamExt};
-use gloo_console::{error, log};
-use postcard::to_allocvec;
-use stream_broadcast::StreamBroadcastExt;
-use tokio::sync::Mutex;
+use futures::channel::mpsc;
+use futures::future::{ready, Either};
+use futures::{stream, Stream, StreamExt};
+use gloo_console::error;
 use web_sys::window;
-use ws_stream_wasm::{WsMessage, WsMeta};
 
-use smart_power_button_common::{MessageToEsp, MessageToWeb};
+use smart_power_button_common::auth::TOKEN_QUERY_PARAMETER;
+use smart_power_button_common::intent::{
+    Intent, IntentError, IntentOutcome, IntentPhase, IntentStatus, DEFAULT_GRACEFUL_TIMEOUT,
+};
+use smart_power_button_common::machine::{machine_path, MachineId};
+use smart_power_button_common::protocol::{
+    Capability, FrameToEsp, FrameToWeb, Hello, ProtocolError, RequestId,
+};
+use smart_power_button_common::{MessageToEsp, MessageToWeb, Power, PowerState};
 
+use crate::auth::{check_token, clear_token, login, logout, stored_token};
+use crate::bluetooth::bluetooth_view;
+use crate::connection::{sleep, Connection, ConnectionError, ConnectionState, WsConnector};
+use crate::hdd::describe_hdd_activity;
+use crate::history::{describe_time, describe_wakeup_reason, history_view};
+use crate::notifications::notifications_view;
+use crate::ota::ota_view;
+use crate::power_led::power_led_view;
+use crate::press_config::press_config_view;
 use crate::stream_render_ext::StreamRenderExt;
 
+mod auth;
+mod bluetooth;
+mod connection;
+mod hdd;
+mod history;
+mod notifications;
+mod ota;
+mod power_led;
+mod press_config;
 mod stream_render_ext;
 mod web_socket_ext;
 
+/// How long to wait before checking the login again when the ESP can't be reached
+const LOGIN_RETRY_DELAY: Duration = Duration::from_secs(5);
+
 fn main() {
     panic::set_hook(Box::new(console_error_panic_hook::hook));
     mount(app());
 }
 
 async fn app() {
-    let ws_host = option_dotenv!("WS_HOST")
+    let host = option_dotenv!("WS_HOST")
         .map_or(window().unwrap().location().host().unwrap(), |s: &str| {
             s.to_owned()
         });
-    let ws_protocol = match window().unwrap().location().protocol().unwrap().as_str() {
-        "http:" => "ws",
-        "https:" => "wss",
-        _ => unreachable!("Unknown protocol"),
+    let (http_protocol, ws_protocol) =
+        match window().unwrap().location().protocol().unwrap().as_str() {
+            "http:" => ("http", "ws"),
+            "https:" => ("https", "wss"),
+            _ => unreachable!("Unknown protocol"),
+        };
+    let http_url = format!("{http_protocol}://{host}");
+    let token = loop {
+        let token = match stored_token() {
+            Some(token) => token,
+            None => login(&http_url).await,
+        };
+        match check_token(&http_url, &token)
+            .meanwhile("Checking login".render())
+            .await
+        {
+            Ok(true) => break token,
+            Ok(false) => clear_token(),
+            Err(e) => {
+                error!(e.clone());
+                // The page can come from the service worker while the ESP can't be reached
+                race((
+                    format!("Can't reach the ESP ({e}). Trying again.").render(),
+                    sleep(LOGIN_RETRY_DELAY),
+                ))
+                .await;
+            }
+        }
+    };
+    let ws_url = format!("{ws_protocol}://{host}/?{TOKEN_QUERY_PARAMETER}={token}");
+    let connection = Connection::new();
+    join((
+        connection.run(WsConnector { url: ws_url }, Hello::new(vec![])),
+        connection_view(&connection),
+        async {
+            // If it fails, the connection view shows why
+            if let Ok(esp_hello) = connection.until_connected().await {
+                controls(&http_url, &token, &connection, esp_hello).await;
+            }
+        },
+    ))
+    .await;
+}
+
+/// Everything for the selected machine, once the ESP said hello
+async fn controls(http_url: &str, token: &str, connection: &Connection, esp_hello: Hello) {
+    let machine = selected_machine(&esp_hello);
+    let machine_url = format!("{http_url}{}", machine_path(machine, ""));
+    let next_request_id = Cell::new(0);
+    // Names of requests that we are waiting for an ack for
+    let pending_requests = RefCell::new(HashMap::<RequestId, &'static str>::new());
+    // Requests that couldn't be sent
+    let (send_errors_tx, send_errors_rx) = mpsc::unbounded::<String>();
+    let new_request = |name: &'static str| {
+        let id = next_request_id.get();
+        next_request_id.set(id.wrapping_add(1));
+        pending_requests.borrow_mut().insert(id, name);
+        id
+    };
+    let send = |id: RequestId, frame: FrameToEsp, name: &'static str| {
+        let pending_requests = &pending_requests;
+        let send_errors_tx = &send_errors_tx;
+        async move {
+            if let Err(error) = connection.send(frame).await {
+                pending_requests.borrow_mut().remove(&id);
+                let _ = send_errors_tx
+                    .unbounded_send(format!("{name}: {}", describe_connection_error(&error)));
+            }
+        }
+    };
+    let send_request = |message: MessageToEsp, name: &'static str| {
+        let id = new_request(name);
+        send(
+            id,
+            FrameToEsp::Request {
+                id,
+                machine,
+                message,
+            },
+            name,
+        )
+    };
+    let send_intent = |intent: Intent, name: &'static str| {
+        let id = new_request(name);
+        send(
+            id,
+            FrameToEsp::Intent {
+                id,
+                machine,
+                intent,
+            },
+            name,
+        )
     };
-    let ws_url = format!("{ws_protocol}://{ws_host}");
-    match WsMeta::connect(ws_url, None)
-        .meanwhile("Opening web socket".render())
-        .await
-    {
-        Ok((_ws_meta, ws_stream)) => {
-            let (w, r) = ws_stream.split();
-            let w = Mutex::new(w);
-            let message_to_web_stream = r
-                .map(|message| match message {
-                    WsMessage::Binary(data) => postcard::from_bytes::<MessageToWeb>(&data).unwrap(),
-                    WsMessage::Text(_) => unreachable!(),
+
+    join((
+        machine_picker(&esp_hello.machines, machine),
+        async {
+            if esp_hello.has(Capability::PowerState) {
+                join((
+                    "Computer: ".render(),
+                    machine_frames(connection, machine)
+                        .filter_map(|frame| {
+                            Box::pin(async move {
+                                match frame {
+                                    FrameToWeb::Message {
+                                        message: MessageToWeb::PowerState(power_state),
+                                        ..
+                                    } => Some(power_state),
+                                    _ => None,
+                                }
+                            })
+                        })
+                        .map(|power_state| describe_power_state(power_state).render())
+                        .render(),
+                    Br::new().render(),
+                    "Wakeup reason: ".render(),
+                    machine_frames(connection, machine)
+                        .filter_map(|frame| {
+                            Box::pin(async move {
+                                match frame {
+                                    FrameToWeb::Message {
+                                        message: MessageToWeb::WakeupReason(reason),
+                                        ..
+                                    } => Some(reason),
+                                    _ => None,
+                                }
+                            })
+                        })
+                        .map(|reason| {
+                            reason
+                                .map_or("None".into(), describe_wakeup_reason)
+                                .render()
+                        })
+                        .render(),
+                    Br::new().render(),
+                    notifications_view(
+                        esp_hello
+                            .machines
+                            .get(machine as usize)
+                            .map_or("The computer", String::as_str),
+                        machine_frames(connection, machine).filter_map(|frame| {
+                            Box::pin(async move {
+                                match frame {
+                                    FrameToWeb::Message {
+                                        message: MessageToWeb::PowerState(power_state),
+                                        ..
+                                    } => Some(power_state),
+                                    _ => None,
+                                }
+                            })
+                        }),
+                    ),
+                ))
+                .await;
+            }
+        },
+        "Power LED Status: ".render(),
+        machine_frames(connection, machine)
+            .filter_map(|frame| {
+                Box::pin(async move {
+                    match frame {
+                        FrameToWeb::Message {
+                            message: MessageToWeb::PowerLedStatus(is_on),
+                            ..
+                        } => Some(is_on),
+                        _ => None,
+                    }
+                })
+            })
+            .map(|is_on| {
+                match is_on {
+                    true => "On",
+                    false => "Off",
+                }
+                .render()
+            })
+            .render(),
+        Br::new().render(),
+        "HDD Activity: ".render(),
+        machine_frames(connection, machine)
+            .filter_map(|frame| {
+                Box::pin(async move {
+                    match frame {
+                        FrameToWeb::Message {
+                            message: MessageToWeb::HddActivity(activity),
+                            ..
+                        } => Some(activity),
+                        _ => None,
+                    }
                 })
-                // This line is just to debug messages
-                .inspect(|message| log!(format!("{message:?}")))
-                .fuse()
-                .broadcast(16);
+            })
+            .map(|activity| describe_hdd_activity(&activity).render())
+            .render(),
+        Br::new().render(),
+        async {
+            if esp_hello.has(Capability::Intents) {
+                let turn_on_button = Button::new();
+                let shut_down_button = Button::new();
+                let restart_button = Button::new();
+                join((
+                    turn_on_button.render("Turn on".render()),
+                    shut_down_button.render("Shut down".render()),
+                    restart_button.render("Restart".render()),
+                    Br::new().render(),
+                    "Action: ".render(),
+                    machine_frames(connection, machine)
+                        .filter_map(|frame| {
+                            Box::pin(async move {
+                                match frame {
+                                    FrameToWeb::Message {
+                                        message: MessageToWeb::IntentStatus(status),
+                                        ..
+                                    } => Some(status),
+                                    _ => None,
+                                }
+                            })
+                        })
+                        .map(|status| {
+                            status
+                                .map_or("None".into(), describe_intent_status)
+                                .render()
+                        })
+                        .render(),
+                    Br::new().render(),
+                    disable_buttons(
+                        &[&turn_on_button, &shut_down_button, &restart_button],
+                        connection,
+                        stream::empty(),
+                    ),
+                    async {
+                        loop {
+                            turn_on_button.until_click().await;
+                            send_intent(Intent::EnsureOn(false), "Turn on").await;
+                        }
+                    },
+                    async {
+                        loop {
+                            shut_down_button.until_click().await;
+                            send_intent(
+                                Intent::EnsureOff {
+                                    graceful_timeout: DEFAULT_GRACEFUL_TIMEOUT,
+                                },
+                                "Shut down",
+                            )
+                            .await;
+                        }
+                    },
+                    async {
+                        loop {
+                            restart_button.until_click().await;
+                            send_intent(
+                                Intent::Restart {
+                                    graceful_timeout: DEFAULT_GRACEFUL_TIMEOUT,
+                                },
+                                "Restart",
+                            )
+                            .await;
+                        }
+                    },
+                ))
+                .await;
+            }
+        },
+        async {
+            let short_press_button = Button::new();
+            let long_press_button = Button::new();
+            let should_turn_on_tv_input = Input::new_checkbox();
 
             join((
-                "Power LED Status: ".render(),
-                message_to_web_stream
-                    .clone()
-                    .filter_map(|(_, message)| {
+                short_press_button.render("Press power button".render()),
+                Label::new().render(join((
+                    "Turn on TV (if turning on computer)".render(),
+                    should_turn_on_tv_input.render(),
+                ))),
+                Br::new().render(),
+                long_press_button.render("Press power button for a long time".render()),
+                disable_buttons(
+                    &[&short_press_button, &long_press_button],
+                    connection,
+                    machine_frames(connection, machine).filter_map(|frame| {
                         Box::pin(async move {
-                            match message {
-                                MessageToWeb::PowerLedStatus(is_on) => Some(is_on),
+                            match frame {
+                                FrameToWeb::Message {
+                                    message: MessageToWeb::PowerButtonStatus(is_on),
+                                    ..
+                                } => Some(is_on),
                                 _ => None,
                             }
                         })
-                    })
-                    .map(|is_on| {
-                        match is_on {
-                            true => "On",
-                            false => "Off",
+                    }),
+                ),
+                async {
+                    loop {
+                        short_press_button.until_click().await;
+                        short_press_button.set_disabled(true);
+                        long_press_button.set_disabled(true);
+                        send_request(
+                            MessageToEsp::ShortPressPowerButton(should_turn_on_tv_input.checked()),
+                            "Press power button",
+                        )
+                        .await;
+                    }
+                },
+                async {
+                    loop {
+                        long_press_button.until_click().await;
+                        if !confirm("Force off the computer? Unsaved work will be lost.") {
+                            continue;
                         }
-                        .render()
-                    })
-                    .render(),
-                Br::new().render(),
-                "HDD LED Status: ".render(),
-                message_to_web_stream
-                    .clone()
-                    .filter_map(|(_, message)| {
+                        short_press_button.set_disabled(true);
+                        long_press_button.set_disabled(true);
+                        send_request(
+                            MessageToEsp::LongPressPowerButton,
+                            "Press power button for a long time",
+                        )
+                        .await;
+                    }
+                },
+            ))
+            .await;
+        },
+        Br::new().render(),
+        async {
+            let reset_button = Button::new();
+
+            join((
+                reset_button.render("Press reset button".render()),
+                disable_buttons(
+                    &[&reset_button],
+                    connection,
+                    machine_frames(connection, machine).filter_map(|frame| {
                         Box::pin(async move {
-                            match message {
-                                MessageToWeb::HddLedStatus(is_on) => Some(is_on),
+                            match frame {
+                                FrameToWeb::Message {
+                                    message: MessageToWeb::ResetButtonStatus(is_on),
+                                    ..
+                                } => Some(is_on),
                                 _ => None,
                             }
                         })
-                    })
-                    .map(|is_on| {
-                        match is_on {
-                            true => "On",
-                            false => "Off",
-                        }
-                        .render()
-                    })
-                    .render(),
-                Br::new().render(),
-                async {
-                    let short_press_button = Button::new();
-                    let long_press_button = Button::new();
-                    let should_turn_on_tv_input = Input::new_checkbox();
-
-                    join((
-                        short_press_button.render("Press power button".render()),
-                        Label::new().render(join((
-                            "Turn on TV (if turning on computer)".render(),
-                            should_turn_on_tv_input.render(),
-                        ))),
-                        Br::new().render(),
-                        long_press_button.render("Press power button for a long time".render()),
-                        async {
-                            let mut stream =
-                                message_to_web_stream.clone().filter_map(|(_, message)| {
-                                    Box::pin(async move {
-                                        match message {
-                                            MessageToWeb::PowerButtonStatus(is_on) => Some(is_on),
-                                            _ => None,
-                                        }
-                                    })
-                                });
-                            while let Some(is_on) = stream.next().await {
-                                short_press_button.set_disabled(is_on);
-                                long_press_button.set_disabled(is_on);
-                            }
-                        },
-                        async {
-                            loop {
-                                short_press_button.until_click().await;
-                                short_press_button.set_disabled(true);
-                                long_press_button.set_disabled(true);
-                                w.lock()
-                                    .await
-                                    .send(WsMessage::Binary(
-                                        to_allocvec(&MessageToEsp::ShortPressPowerButton(
-                                            should_turn_on_tv_input.checked(),
-                                        ))
-                                        .unwrap(),
-                                    ))
-                                    .await
-                                    .unwrap();
-                            }
-                        },
-                        async {
-                            loop {
-                                long_press_button.until_click().await;
-                                short_press_button.set_disabled(true);
-                                long_press_button.set_disabled(true);
-                                w.lock()
-                                    .await
-                                    .send(WsMessage::Binary(
-                                        to_allocvec(&MessageToEsp::LongPressPowerButton).unwrap(),
-                                    ))
-                                    .await
-                                    .unwrap();
-                            }
-                        },
-                    ))
-                    .await;
-                },
-                Br::new().render(),
+                    }),
+                ),
                 async {
-                    let reset_button = Button::new();
-
-                    join((
-                        reset_button.render("Press reset button".render()),
-                        async {
-                            let mut stream =
-                                message_to_web_stream.clone().filter_map(|(_, message)| {
-                                    Box::pin(async move {
-                                        match message {
-                                            MessageToWeb::ResetButtonStatus(is_on) => Some(is_on),
-                                            _ => None,
-                                        }
-                                    })
-                                });
-                            while let Some(is_on) = stream.next().await {
-                                reset_button.set_disabled(is_on);
-                            }
-                        },
-                        async {
-                            loop {
-                                reset_button.until_click().await;
-                                reset_button.set_disabled(true);
-                                w.lock()
-                                    .await
-                                    .send(WsMessage::Binary(
-                                        to_allocvec(&MessageToEsp::ShortPressResetButton).unwrap(),
-                                    ))
-                                    .await
-                                    .unwrap();
-                            }
-                        },
-                    ))
-                    .await;
+                    loop {
+                        reset_button.until_click().await;
+                        if !confirm("Reset the computer? Unsaved work will be lost.") {
+                            continue;
+                        }
+                        reset_button.set_disabled(true);
+                        send_request(MessageToEsp::ShortPressResetButton, "Press reset button")
+                            .await;
+                    }
                 },
             ))
             .await;
+        },
+        Br::new().render(),
+        "Last command: ".render(),
+        stream::select_all([
+            machine_frames(connection, machine)
+                .filter_map(|frame| {
+                    let pending_requests = &pending_requests;
+                    Box::pin(async move {
+                        match frame {
+                            FrameToWeb::Ack(id) => pending_requests
+                                .borrow_mut()
+                                .remove(&id)
+                                .map(|name| format!("{name}: done")),
+                            FrameToWeb::Error {
+                                id: Some(id),
+                                error,
+                            } => pending_requests
+                                .borrow_mut()
+                                .remove(&id)
+                                .map(|name| format!("{name}: {}", describe_protocol_error(error))),
+                            FrameToWeb::Error { id: None, error } => {
+                                Some(describe_protocol_error(error))
+                            }
+                            _ => None,
+                        }
+                    })
+                })
+                .boxed_local(),
+            // Nothing will answer requests that were sent before the connection was lost
+            connection
+                .states()
+                .filter_map(|state| {
+                    ready(match state {
+                        ConnectionState::Offline { .. } | ConnectionState::Failed(_) => {
+                            let names = pending_requests
+                                .borrow_mut()
+                                .drain()
+                                .map(|(_, name)| format!("{name}: connection lost"))
+                                .collect::<Vec<_>>();
+                            (!names.is_empty()).then(|| names.join(", "))
+                        }
+                        _ => None,
+                    })
+                })
+                .boxed_local(),
+            send_errors_rx.boxed_local(),
+        ])
+        .map(|text| text.render())
+        .render(),
+        Br::new().render(),
+        async {
+            if esp_hello.has(Capability::History) {
+                let machine_url = machine_url.as_str();
+                // Events are missed while offline, so the history is loaded again after reconnecting
+                let history_views = connection
+                    .states()
+                    .filter(|state| ready(state.is_connected()))
+                    .map(move |_| {
+                        let new_events = machine_frames(connection, machine).filter_map(|frame| {
+                            Box::pin(async move {
+                                match frame {
+                                    FrameToWeb::Message {
+                                        message: MessageToWeb::HistoryEvent(event),
+                                        ..
+                                    } => Some(event),
+                                    _ => None,
+                                }
+                            })
+                        });
+                        history_view(machine_url, token, new_events)
+                    });
+                join((
+                    "History".render(),
+                    Br::new().render(),
+                    history_views.render(),
+                ))
+                .await;
+            }
+        },
+        Br::new().render(),
+        async {
+            if esp_hello.has(Capability::Ota) {
+                let statuses = machine_frames(connection, machine).filter_map(|frame| {
+                    Box::pin(async move {
+                        match frame {
+                            FrameToWeb::Message {
+                                message: MessageToWeb::OtaStatus(status),
+                                ..
+                            } => Some(status),
+                            _ => None,
+                        }
+                    })
+                });
+                join((
+                    "Firmware update".render(),
+                    Br::new().render(),
+                    ota_view(http_url, token, statuses),
+                    Br::new().render(),
+                ))
+                .await;
+            }
+        },
+        async {
+            if esp_hello.has(Capability::PressPatterns) {
+                join((
+                    "Press timings".render(),
+                    Br::new().render(),
+                    press_config_view(&machine_url, token),
+                    Br::new().render(),
+                ))
+                .await;
+            }
+        },
+        async {
+            if esp_hello.has(Capability::PowerLedCalibration) {
+                join((
+                    "Power LED".render(),
+                    Br::new().render(),
+                    power_led_view(&machine_url, token),
+                    Br::new().render(),
+                ))
+                .await;
+            }
+        },
+        async {
+            if esp_hello.has(Capability::BluetoothDiscovery) {
+                join((
+                    "Bluetooth wakeup devices".render(),
+                    Br::new().render(),
+                    bluetooth_view(http_url, &machine_url, token),
+                    Br::new().render(),
+                ))
+                .await;
+            }
+        },
+        async {
+            let logout_button = Button::new();
+            join((logout_button.render("Log out".render()), async {
+                logout_button.until_click().await;
+                logout(http_url, token).await;
+                window().unwrap().location().reload().unwrap();
+            }))
+            .await;
+        },
+    ))
+    .await;
+}
+
+/// Frames about the selected machine, and about the whole ESP
+fn machine_frames(
+    connection: &Connection,
+    machine: MachineId,
+) -> impl Stream<Item = FrameToWeb> + Unpin {
+    connection.frames().filter(move |frame| {
+        // Messages for the other machines aren't shown
+        ready(match frame {
+            FrameToWeb::Message {
+                machine: Some(frame_machine),
+                ..
+            } => *frame_machine == machine,
+            _ => true,
+        })
+    })
+}
+
+/// Keeps `buttons` disabled while the ESP is offline, and while the last value of `is_busy` is
+/// true
+async fn disable_buttons(
+    buttons: &[&Button],
+    connection: &Connection,
+    is_busy: impl Stream<Item = bool> + Unpin,
+) {
+    let mut changes = stream::select(
+        connection
+            .states()
+            .map(|state| Either::Left(state.is_connected())),
+        is_busy.map(Either::Right),
+    );
+    let (mut is_connected, mut is_busy) = (false, false);
+    while let Some(change) = changes.next().await {
+        match change {
+            Either::Left(connected) => is_connected = connected,
+            Either::Right(busy) => is_busy = busy,
+        }
+        for button in buttons {
+            button.set_disabled(!is_connected || is_busy);
+        }
+    }
+}
+
+/// Shows if the page is connected to the ESP
+async fn connection_view(connection: &Connection) {
+    let mut first_hello = None;
+    join((
+        "ESP: ".render(),
+        connection
+            .states()
+            .map(move |state| {
+                match &state {
+                    ConnectionState::Connected(hello)
+                        if first_hello.get_or_insert_with(|| hello.clone()) != hello =>
+                    {
+                        "Connected, but the ESP changed. Reload the page.".into()
+                    }
+                    state => describe_connection_state(state),
+                }
+                .render()
+            })
+            .render(),
+        Br::new().render(),
+    ))
+    .await;
+}
+
+/// The machine in the page's `?machine=<id>` query. The first machine is shown if there is no
+/// query or the machine doesn't exist.
+fn selected_machine(esp_hello: &Hello) -> MachineId {
+    let search = window().unwrap().location().search().unwrap_or_default();
+    search
+        .strip_prefix("?machine=")
+        .and_then(|id| id.parse::<MachineId>().ok())
+        .filter(|&id| (id as usize) < esp_hello.machines.len())
+        .unwrap_or(0)
+}
+
+/// A button for each machine, which opens the page for it. Nothing is shown if there is only one
+/// machine.
+async fn machine_picker(names: &[String], selected: MachineId) {
+    if names.len() < 2 {
+        return;
+    }
+    let buttons = names
+        .iter()
+        .zip(0..)
+        .map(|(name, id)| async move {
+            let button = Button::new();
+            button.set_disabled(id == selected);
+            join((button.render(name.as_str().render()), async {
+                button.until_click().await;
+                window()
+                    .unwrap()
+                    .location()
+                    .set_search(&format!("?machine={id}"))
+                    .unwrap();
+            }))
+            .await;
+        })
+        .collect::<Vec<_>>();
+    join(("Machine: ".render(), join(buttons), Br::new().render())).await;
+}
+
+fn describe_power_state(PowerState { power, since }: PowerState) -> String {
+    let power = match power {
+        Some(Power::On) => "On",
+        Some(Power::Suspend) => "Suspend",
+        Some(Power::Off) => "Off",
+        None => "Unknown",
+    };
+    match since {
+        Some(_) => format!("{power} (since {})", describe_time(since)),
+        None => power.into(),
+    }
+}
+
+/// Asks the user with a dialog, so dangerous buttons can't be pressed by accident
+fn confirm(message: &str) -> bool {
+    window()
+        .unwrap()
+        .confirm_with_message(message)
+        .unwrap_or(false)
+}
+
+fn describe_intent(intent: Intent) -> &'static str {
+    match intent {
+        Intent::EnsureOn(_) => "Turn on",
+        Intent::EnsureOff { .. } => "Shut down",
+        Intent::Restart { .. } => "Restart",
+    }
+}
+
+fn describe_intent_status(status: IntentStatus) -> String {
+    match status {
+        IntentStatus::Running(intent, phase) => {
+            let phase = match phase {
+                IntentPhase::WaitingForPowerState => "waiting for the power state",
+                IntentPhase::TurningOn => "turning on",
+                IntentPhase::ShuttingDown => "waiting for the OS to shut down",
+                IntentPhase::ForcingOff => "forcing off",
+            };
+            format!("{}: {phase}", describe_intent(intent))
+        }
+        IntentStatus::Finished(intent, result) => {
+            let result = match result {
+                Ok(IntentOutcome::AlreadyDone) => "already done".into(),
+                Ok(IntentOutcome::Done) => "done".into(),
+                Ok(IntentOutcome::ForcedOff) => "done, but it had to be forced off".into(),
+                Err(error) => describe_intent_error(error),
+            };
+            format!("{}: {result}", describe_intent(intent))
+        }
+    }
+}
+
+fn describe_intent_error(error: IntentError) -> String {
+    match error {
+        IntentError::Conflict(intent) => {
+            format!("{} is still running", describe_intent(intent))
+        }
+        IntentError::UnknownPowerState => "The ESP doesn't know if the computer is on".into(),
+        IntentError::DidntTurnOn => "The computer didn't turn on".into(),
+        IntentError::DidntTurnOff => "The computer didn't turn off".into(),
+    }
+}
+
+fn describe_connection_state(state: &ConnectionState) -> String {
+    match state {
+        ConnectionState::Connecting => "Connecting".into(),
+        ConnectionState::Connected(_) => "Connected".into(),
+        ConnectionState::Offline { error, retry_in } => format!(
+            "Offline ({}). Trying again in {} s.",
+            describe_connection_error(error),
+            retry_in.as_secs()
+        ),
+        ConnectionState::Failed(error) => describe_connection_error(error),
+    }
+}
+
+fn describe_connection_error(error: &ConnectionError) -> String {
+    match error {
+        ConnectionError::Connect(e) => format!("Couldn't connect: {e}"),
+        ConnectionError::Closed => "The connection closed".into(),
+        ConnectionError::Send(e) => format!("Couldn't send: {e}"),
+        ConnectionError::Offline => "Not connected to the ESP".into(),
+        ConnectionError::Handshake(error) => describe_protocol_error(*error),
+        ConnectionError::ExpectedHello => "The ESP didn't start with a hello".into(),
+    }
+}
+
+fn describe_protocol_error(error: ProtocolError) -> String {
+    match error {
+        ProtocolError::VersionMismatch { expected, actual } => format!(
+            "Protocol version mismatch (expected {expected}, got {actual}). Reload the page or update the ESP."
+        ),
+        ProtocolError::HandshakeRequired => "The ESP expected a hello first".into(),
+        ProtocolError::UnexpectedHello => "The ESP didn't expect another hello".into(),
+        ProtocolError::Malformed => "The ESP couldn't understand a message".into(),
+        ProtocolError::Unsupported(capability) => {
+            format!("The ESP doesn't support {capability:?}")
         }
-        Err(e) => {
-            error!(format!("{e:#?}"));
-            format!("Error connecting web socket: {e}").render().await;
+        ProtocolError::Intent(error) => describe_intent_error(error),
+        ProtocolError::Press(error) => error.to_string(),
+        ProtocolError::BluetoothScanFailed => "The ESP couldn't scan for Bluetooth devices".into(),
+        ProtocolError::UnknownMachine(machine) => {
+            format!("The ESP doesn't have machine {machine}. Reload the page.")
         }
     }
 }
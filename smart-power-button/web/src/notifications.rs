@@ -0,0 +1,153 @@
+//! Browser notifications when the computer turns on or off while the page is in the background
+use async_ui_web::html::{Br, Button};
+use async_ui_web::shortcut_traits::ShortcutRenderStr;
+use async_ui_web::{join, race};
+use futures::{Stream, StreamExt};
+use gloo_console::error;
+use wasm_bindgen_futures::JsFuture;
+use web_sys::wasm_bindgen::JsCast;
+use web_sys::{
+    window, Notification, NotificationOptions, NotificationPermission, ServiceWorkerRegistration,
+};
+
+use smart_power_button_common::{Power, PowerState};
+
+/// Where the notification setting is kept in local storage
+const NOTIFY_KEY: &str = "notify_power";
+
+fn is_enabled() -> bool {
+    let stored = window()
+        .and_then(|window| window.local_storage().ok()?)
+        .and_then(|storage| storage.get_item(NOTIFY_KEY).ok()?);
+    stored.as_deref() == Some("true")
+        && Notification::permission() == NotificationPermission::Granted
+}
+
+fn set_enabled(enabled: bool) {
+    if let Some(storage) = window().unwrap().local_storage().unwrap() {
+        storage.set_item(NOTIFY_KEY, &enabled.to_string()).unwrap();
+    }
+}
+
+/// Asks the browser if the page may show notifications
+async fn request_permission() -> bool {
+    match Notification::request_permission() {
+        Ok(promise) => {
+            let _ = JsFuture::from(promise).await;
+            Notification::permission() == NotificationPermission::Granted
+        }
+        Err(e) => {
+            error!(e);
+            false
+        }
+    }
+}
+
+/// Shows a notification from the service worker, because some phones can't show notifications
+/// from the page
+async fn notify(text: &str) {
+    let Ok(ready) = window().unwrap().navigator().service_worker().ready() else {
+        return;
+    };
+    let registration = match JsFuture::from(ready).await {
+        Ok(registration) => registration.unchecked_into::<ServiceWorkerRegistration>(),
+        Err(e) => {
+            error!(e);
+            return;
+        }
+    };
+    let mut options = NotificationOptions::new();
+    // Replaces the last notification instead of piling them up
+    options.icon("icon.svg").tag("power");
+    if let Err(e) = registration.show_notification_with_options(text, &options) {
+        error!(e);
+    }
+}
+
+/// What to tell the user when the power changes from `old` to `new`, if anything
+pub fn describe_power_change(name: &str, old: Option<Power>, new: Option<Power>) -> Option<String> {
+    match (old?, new?) {
+        (old, new) if old == new => None,
+        (_, Power::On) => Some(format!("{name} turned on")),
+        (_, Power::Suspend) => Some(format!("{name} went to sleep")),
+        (_, Power::Off) => Some(format!("{name} turned off")),
+    }
+}
+
+/// A button to turn notifications on or off, and the notifications themselves. They are only
+/// shown while the page is hidden, because otherwise the user can already see the power state.
+pub async fn notifications_view(
+    name: &str,
+    mut power_states: impl Stream<Item = PowerState> + Unpin,
+) {
+    join((
+        async {
+            loop {
+                let button = Button::new();
+                let enabled = is_enabled();
+                race((
+                    button.render(
+                        if enabled {
+                            "Stop notifications"
+                        } else {
+                            "Notify when the computer turns on or off"
+                        }
+                        .render(),
+                    ),
+                    button.until_click(),
+                ))
+                .await;
+                set_enabled(!enabled && request_permission().await);
+            }
+        },
+        Br::new().render(),
+        async {
+            let mut last_power = None;
+            while let Some(PowerState { power, .. }) = power_states.next().await {
+                let is_hidden = window()
+                    .and_then(|window| window.document())
+                    .is_some_and(|document| document.hidden());
+                if let Some(text) = describe_power_change(name, last_power, power) {
+                    if is_hidden && is_enabled() {
+                        notify(&text).await;
+                    }
+                }
+                last_power = power;
+            }
+        },
+    ))
+    .await;
+}
+
+#[cfg(test)]
+mod tests {
+    use smart_power_button_common::Power;
+    use wasm_bindgen_test::wasm_bindgen_test;
+
+    use super::describe_power_change;
+
+    #[wasm_bindgen_test]
+    fn only_changes_are_described() {
+        assert_eq!(
+            describe_power_change("Computer", Some(Power::Off), Some(Power::On)),
+            Some("Computer turned on".into())
+        );
+        assert_eq!(
+            describe_power_change("Server", Some(Power::On), Some(Power::Suspend)),
+            Some("Server went to sleep".into())
+        );
+        assert_eq!(
+            describe_power_change("Computer", Some(Power::On), Some(Power::On)),
+            None
+        );
+        // Nothing is said when the page first learns the power state
+        assert_eq!(
+            describe_power_change("Computer", None, Some(Power::Off)),
+            None
+        );
+        assert_eq!(
+            describe_power_change("Computer", Some(Power::On), None),
+            None
+        );
+    }
+}
@@ -0,0 +1,92 @@
+use async_ui_web::event_traits::EmitElementEvent;
+use async_ui_web::html::{Br, Button, Input};
+use async_ui_web::join;
+use async_ui_web::shortcut_traits::ShortcutRenderStr;
+use futures::{Stream, StreamExt};
+use gloo_console::error;
+use gloo_net::http::Request;
+use js_sys::Uint8Array;
+use sha2::{Digest, Sha256};
+use smart_power_button_common::ota::{format_sha256, OtaStatus, SHA256_HEADER};
+use wasm_bindgen_futures::JsFuture;
+use web_sys::window;
+
+use crate::stream_render_ext::StreamRenderExt;
+
+async fn read_file(input: &Input) -> Result<Vec<u8>, String> {
+    let file = input
+        .files()
+        .and_then(|files| files.get(0))
+        .ok_or("Choose a firmware image first")?;
+    let buffer = JsFuture::from(file.array_buffer())
+        .await
+        .map_err(|e| format!("Error reading file: {e:?}"))?;
+    Ok(Uint8Array::new(&buffer).to_vec())
+}
+
+async fn upload(http_url: &str, token: &str, image: Vec<u8>) -> Result<(), String> {
+    let sha256 = format_sha256(&Sha256::digest(&image).into());
+    let response = Request::post(&format!("{http_url}/ota"))
+        .header("Authorization", &format!("Bearer {token}"))
+        .header(SHA256_HEADER, &sha256)
+        .body(Uint8Array::from(image.as_slice()))
+        .map_err(|e| e.to_string())?
+        .send()
+        .await
+        .map_err(|e| e.to_string())?;
+    match response.status() {
+        200 => Ok(()),
+        status => Err(format!(
+            "Update failed ({status}): {}",
+            response.text().await.unwrap_or_default()
+        )),
+    }
+}
+
+fn describe_ota_status(status: OtaStatus) -> String {
+    match status {
+        OtaStatus::Idle => "No update in progress".into(),
+        OtaStatus::Receiving { received, total } => format!(
+            "Receiving: {}% ({received} of {total} bytes)",
+            received * 100 / total.max(1)
+        ),
+        OtaStatus::Restarting => "Update saved. The ESP is restarting.".into(),
+        OtaStatus::Failed(e) => format!("Update failed: {e}"),
+    }
+}
+
+/// Uploads a firmware image made by `espflash save-image`, and shows how the ESP is doing with it
+pub async fn ota_view(
+    http_url: &str,
+    token: &str,
+    statuses: impl Stream<Item = OtaStatus> + Unpin,
+) {
+    let file_input = Input::new();
+    file_input.set_type("file");
+    file_input.set_accept(".bin");
+    let upload_button = Button::new();
+    join((
+        file_input.render(),
+        upload_button.render("Update firmware".render()),
+        Br::new().render(),
+        statuses
+            .map(|status| describe_ota_status(status).render())
+            .render(),
+        async {
+            loop {
+                upload_button.until_click().await;
+                upload_button.set_disabled(true);
+                let result = match read_file(&file_input).await {
+                    Ok(image) => upload(http_url, token, image).await,
+                    Err(e) => Err(e),
+                };
+                if let Err(e) = result {
+                    error!(e.clone());
+                    let _ = window().unwrap().alert_with_message(&e);
+                }
+                upload_button.set_disabled(false);
+            }
+        },
+    ))
+    .await;
+}
@@ -0,0 +1,190 @@
+use async_ui_web::event_traits::EmitElementEvent;
+use async_ui_web::html::{Br, Button, Input, Label};
+use async_ui_web::join;
+use async_ui_web::shortcut_traits::{ShortcutRenderStr, UiFutureExt};
+use gloo_console::error;
+use gloo_net::http::Request;
+use js_sys::Uint8Array;
+use postcard::{from_bytes, to_allocvec};
+use smart_power_button_common::power_led::{BlinkConfig, Calibration};
+use web_sys::window;
+
+use crate::press_config::{millis_input, read_millis};
+
+/// How long the ESP records the LED for when calibrating
+const CALIBRATION_SECS: u32 = 10;
+
+async fn fetch_blink_config(http_url: &str, token: &str) -> Result<BlinkConfig, String> {
+    let response = Request::get(&format!("{http_url}/power_led"))
+        .header("Authorization", &format!("Bearer {token}"))
+        .send()
+        .await
+        .map_err(|e| e.to_string())?;
+    match response.status() {
+        200 => {
+            let bytes = response.binary().await.map_err(|e| e.to_string())?;
+            from_bytes(&bytes).map_err(|e| e.to_string())
+        }
+        status => Err(format!("Error getting power LED settings: {status}")),
+    }
+}
+
+async fn save_blink_config(http_url: &str, token: &str, config: BlinkConfig) -> Result<(), String> {
+    let response = Request::put(&format!("{http_url}/power_led"))
+        .header("Authorization", &format!("Bearer {token}"))
+        .body(Uint8Array::from(to_allocvec(&config).unwrap().as_slice()))
+        .map_err(|e| e.to_string())?
+        .send()
+        .await
+        .map_err(|e| e.to_string())?;
+    match response.status() {
+        200 => Ok(()),
+        status => Err(format!("Error saving power LED settings: {status}")),
+    }
+}
+
+async fn calibrate(http_url: &str, token: &str) -> Result<Calibration, String> {
+    let response = Request::post(&format!(
+        "{http_url}/power_led/calibrate?secs={CALIBRATION_SECS}"
+    ))
+    .header("Authorization", &format!("Bearer {token}"))
+    .send()
+    .await
+    .map_err(|e| e.to_string())?;
+    match response.status() {
+        200 => {
+            let bytes = response.binary().await.map_err(|e| e.to_string())?;
+            from_bytes(&bytes).map_err(|e| e.to_string())
+        }
+        status => Err(format!(
+            "Calibration failed ({status}): {}",
+            response.text().await.unwrap_or_default()
+        )),
+    }
+}
+
+struct BlinkConfigInputs {
+    debounce: Input,
+    min_half_period: Input,
+    max_half_period: Input,
+    blinks_for_suspend: Input,
+}
+
+impl BlinkConfigInputs {
+    fn new(config: BlinkConfig) -> Self {
+        let blinks_for_suspend = Input::new();
+        blinks_for_suspend.set_type("number");
+        blinks_for_suspend.set_min("1");
+        blinks_for_suspend.set_value(&config.blinks_for_suspend.to_string());
+        Self {
+            debounce: millis_input(config.debounce),
+            min_half_period: millis_input(config.min_half_period),
+            max_half_period: millis_input(config.max_half_period),
+            blinks_for_suspend,
+        }
+    }
+
+    fn set(&self, config: BlinkConfig) {
+        for (input, duration) in [
+            (&self.debounce, config.debounce),
+            (&self.min_half_period, config.min_half_period),
+            (&self.max_half_period, config.max_half_period),
+        ] {
+            input.set_value(&duration.as_millis().to_string());
+        }
+        self.blinks_for_suspend
+            .set_value(&config.blinks_for_suspend.to_string());
+    }
+
+    fn read(&self) -> Result<BlinkConfig, String> {
+        let config = BlinkConfig {
+            debounce: read_millis(&self.debounce, "Debounce")?,
+            min_half_period: read_millis(&self.min_half_period, "Shortest blink")?,
+            max_half_period: read_millis(&self.max_half_period, "Longest blink")?,
+            blinks_for_suspend: self
+                .blinks_for_suspend
+                .value()
+                .trim()
+                .parse()
+                .map_err(|_| "Blinks for suspend must be a number")?,
+        };
+        match config.is_valid() {
+            true => Ok(config),
+            false => Err(
+                "The debounce must be shorter than the shortest blink, which must be shorter than the longest blink. The longest blink can be at most 10000 ms."
+                    .into(),
+            ),
+        }
+    }
+}
+
+/// Lets the user change how the ESP tells suspend apart from on and off, or have the ESP suggest
+/// settings by watching the LED while the computer is suspended
+pub async fn power_led_view(http_url: &str, token: &str) {
+    let config = match fetch_blink_config(http_url, token)
+        .meanwhile("Loading power LED settings".render())
+        .await
+    {
+        Ok(config) => config,
+        Err(e) => {
+            error!(e.clone());
+            e.render().await;
+            return;
+        }
+    };
+    let inputs = BlinkConfigInputs::new(config);
+    let save_button = Button::new();
+    let calibrate_button = Button::new();
+    join((
+        Label::new().render(join(("Debounce (ms) ".render(), inputs.debounce.render()))),
+        Br::new().render(),
+        Label::new().render(join((
+            "Shortest blink (ms) ".render(),
+            inputs.min_half_period.render(),
+        ))),
+        Br::new().render(),
+        Label::new().render(join((
+            "Longest blink (ms) ".render(),
+            inputs.max_half_period.render(),
+        ))),
+        Br::new().render(),
+        Label::new().render(join((
+            "Blinks for suspend ".render(),
+            inputs.blinks_for_suspend.render(),
+        ))),
+        Br::new().render(),
+        save_button.render("Save power LED settings".render()),
+        calibrate_button.render("Calibrate (suspend the computer first)".render()),
+        async {
+            loop {
+                save_button.until_click().await;
+                save_button.set_disabled(true);
+                let result = match inputs.read() {
+                    Ok(config) => save_blink_config(http_url, token, config).await,
+                    Err(e) => Err(e),
+                };
+                if let Err(e) = result {
+                    error!(e.clone());
+                    let _ = window().unwrap().alert_with_message(&e);
+                }
+                save_button.set_disabled(false);
+            }
+        },
+        async {
+            loop {
+                calibrate_button.until_click().await;
+                calibrate_button.set_disabled(true);
+                // Filled in, but not saved until the user saves it
+                match calibrate(http_url, token).await {
+                    Ok(calibration) => inputs.set(calibration.suggested),
+                    Err(e) => {
+                        error!(e.clone());
+                        let _ = window().unwrap().alert_with_message(&e);
+                    }
+                }
+                calibrate_button.set_disabled(false);
+            }
+        },
+    ))
+    .await;
+}
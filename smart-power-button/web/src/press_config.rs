@@ -0,0 +1,120 @@
+use std::time::Duration;
+
+use async_ui_web::event_traits::EmitElementEvent;
+use async_ui_web::html::{Br, Button, Input, Label};
+use async_ui_web::join;
+use async_ui_web::shortcut_traits::{ShortcutRenderStr, UiFutureExt};
+use gloo_console::error;
+use gloo_net::http::Request;
+use js_sys::Uint8Array;
+use postcard::{from_bytes, to_allocvec};
+use smart_power_button_common::press::PressConfig;
+use web_sys::window;
+
+async fn fetch_press_config(http_url: &str, token: &str) -> Result<PressConfig, String> {
+    let response = Request::get(&format!("{http_url}/press_config"))
+        .header("Authorization", &format!("Bearer {token}"))
+        .send()
+        .await
+        .map_err(|e| e.to_string())?;
+    match response.status() {
+        200 => {
+            let bytes = response.binary().await.map_err(|e| e.to_string())?;
+            from_bytes(&bytes).map_err(|e| e.to_string())
+        }
+        status => Err(format!("Error getting press timings: {status}")),
+    }
+}
+
+async fn save_press_config(http_url: &str, token: &str, config: PressConfig) -> Result<(), String> {
+    let response = Request::put(&format!("{http_url}/press_config"))
+        .header("Authorization", &format!("Bearer {token}"))
+        .body(Uint8Array::from(to_allocvec(&config).unwrap().as_slice()))
+        .map_err(|e| e.to_string())?
+        .send()
+        .await
+        .map_err(|e| e.to_string())?;
+    match response.status() {
+        200 => Ok(()),
+        status => Err(format!(
+            "Error saving press timings ({status}): {}",
+            response.text().await.unwrap_or_default()
+        )),
+    }
+}
+
+pub fn millis_input(duration: Duration) -> Input {
+    let input = Input::new();
+    input.set_type("number");
+    input.set_min("1");
+    input.set_value(&duration.as_millis().to_string());
+    input
+}
+
+pub fn read_millis(input: &Input, name: &str) -> Result<Duration, String> {
+    input
+        .value()
+        .trim()
+        .parse()
+        .map(Duration::from_millis)
+        .map_err(|_| format!("{name} must be a number of milliseconds"))
+}
+
+/// Lets the user change how long the buttons are held, for motherboards that need other timings
+pub async fn press_config_view(http_url: &str, token: &str) {
+    let config = match fetch_press_config(http_url, token)
+        .meanwhile("Loading press timings".render())
+        .await
+    {
+        Ok(config) => config,
+        Err(e) => {
+            error!(e.clone());
+            e.render().await;
+            return;
+        }
+    };
+    let short_press_input = millis_input(config.short_press);
+    let long_press_input = millis_input(config.long_press);
+    let reset_press_input = millis_input(config.reset_press);
+    let save_button = Button::new();
+    join((
+        Label::new().render(join((
+            "Short press (ms) ".render(),
+            short_press_input.render(),
+        ))),
+        Br::new().render(),
+        Label::new().render(join((
+            "Long press (ms) ".render(),
+            long_press_input.render(),
+        ))),
+        Br::new().render(),
+        Label::new().render(join((
+            "Reset press (ms) ".render(),
+            reset_press_input.render(),
+        ))),
+        Br::new().render(),
+        save_button.render("Save press timings".render()),
+        async {
+            loop {
+                save_button.until_click().await;
+                save_button.set_disabled(true);
+                let result = async {
+                    let config = PressConfig {
+                        short_press: read_millis(&short_press_input, "Short press")?,
+                        long_press: read_millis(&long_press_input, "Long press")?,
+                        reset_press: read_millis(&reset_press_input, "Reset press")?,
+                    };
+                    config.validate().map_err(|e| e.to_string())?;
+                    save_press_config(http_url, token, config).await
+                }
+                .await;
+                if let Err(e) = result {
+                    error!(e.clone());
+                    let _ = window().unwrap().alert_with_message(&e);
+                }
+                save_button.set_disabled(false);
+            }
+        },
+    ))
+    .await;
+}
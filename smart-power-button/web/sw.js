@@ -0,0 +1,76 @@
+// Keeps the page's files in the browser, because the ESP takes a long time to serve them. The
+// cached files are shown right away, and then updated from the ESP for the next time.
+const CACHE = "smart-power-button-v1";
+// Trunk's `filehash = false` keeps these names the same between builds
+const SHELL = [
+  "./",
+  "index.html",
+  "smart-power-button-web.js",
+  "smart-power-button-web_bg.wasm",
+  "manifest.webmanifest",
+  "icon.svg",
+];
+
+self.addEventListener("install", (event) => {
+  event.waitUntil(caches.open(CACHE).then((cache) => cache.addAll(SHELL)));
+  self.skipWaiting();
+});
+
+self.addEventListener("activate", (event) => {
+  event.waitUntil(
+    caches
+      .keys()
+      .then((keys) =>
+        Promise.all(keys.filter((key) => key !== CACHE).map((key) => caches.delete(key))),
+      )
+      .then(() => self.clients.claim()),
+  );
+});
+
+function isShell(url) {
+  const scope = new URL(self.registration.scope);
+  return (
+    url.origin === scope.origin &&
+    SHELL.some((path) => new URL(path, scope).pathname === url.pathname)
+  );
+}
+
+// Asks the ESP if the file changed, without using the browser's year-long HTTP cache
+async function update(request) {
+  const response = await fetch(request, { cache: "no-cache" });
+  if (response.ok) {
+    const cache = await caches.open(CACHE);
+    await cache.put(request, response.clone());
+  }
+  return response;
+}
+
+self.addEventListener("fetch", (event) => {
+  const url = new URL(event.request.url);
+  // The API and the web socket always go to the ESP
+  if (event.request.method !== "GET" || !isShell(url)) {
+    return;
+  }
+  const request = event.request.mode === "navigate" ? "index.html" : event.request;
+  event.respondWith(
+    caches.match(request).then((cached) => {
+      const updated = update(request);
+      if (cached) {
+        event.waitUntil(updated.catch(() => {}));
+        return cached;
+      }
+      return updated;
+    }),
+  );
+});
+
+// Opens the page when a power notification is clicked
+self.addEventListener("notificationclick", (event) => {
+  event.notification.close();
+  event.waitUntil(
+    self.clients.matchAll({ type: "window" }).then((windows) => {
+      const open = windows.find((client) => "focus" in client);
+      return open ? open.focus() : self.clients.openWindow(self.registration.scope);
+    }),
+  );
+});